@@ -0,0 +1,189 @@
+//! File validation
+//!
+//! Loads every file it can identify and reports the problems found, e.g.
+//! to fail an asset pipeline on malformed files. The type of a file is
+//! detected with `RoseFileType::detect`, files of an unknown type are
+//! skipped.
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+use failure::Error;
+
+use roselib::files::filetype::{RoseFileData, RoseFileType};
+
+/// Problems of a single file, see `check_path`
+///
+/// `file_type` is `None` for files and directories that couldn't be read.
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct CheckReport {
+    pub path: PathBuf,
+    pub file_type: Option<RoseFileType>,
+    pub problems: Vec<String>,
+}
+
+impl CheckReport {
+    /// Whether the file has no problems
+    pub fn is_valid(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    /// Report of a file or directory that couldn't be read
+    fn unreadable(path: &Path, problem: String) -> CheckReport {
+        CheckReport {
+            path: path.to_path_buf(),
+            file_type: None,
+            problems: vec![problem],
+        }
+    }
+}
+
+/// Reports of all the files of a tree, see `validate_tree`
+///
+/// Serializes to JSON with `serde_json`, e.g. to keep as a CI artifact.
+#[derive(Debug, Serialize, PartialEq)]
+pub struct ValidationReport {
+    pub files: Vec<CheckReport>,
+}
+
+impl ValidationReport {
+    /// Whether no file has problems
+    pub fn is_valid(&self) -> bool {
+        self.files.iter().all(|r| r.is_valid())
+    }
+
+    /// Reports of the files with problems
+    pub fn failures(&self) -> impl Iterator<Item = &CheckReport> {
+        self.files.iter().filter(|r| !r.is_valid())
+    }
+}
+
+/// A file to check, or the report of a directory that couldn't be listed
+type Listed = Result<PathBuf, CheckReport>;
+
+/// Check a file, or every file of a directory recursively
+///
+/// Returns one report per identified file, in path order. A file that fails
+/// to load is reported with the load error as its problem, meshes are also
+/// validated with `Mesh::validate`. Files and directories below `path` that
+/// can't be read are reported with the read error as their problem. Fails
+/// only if `path` can't be read.
+pub fn check_path(path: &Path) -> Result<Vec<CheckReport>, Error> {
+    let mut reports = Vec::new();
+    for listed in list_files(path)? {
+        reports.extend(check_listed(&listed));
+    }
+    Ok(reports)
+}
+
+/// Check every file of a directory tree in parallel
+///
+/// Like `check_path`, with the files checked on one thread per CPU. The
+/// reports are in path order.
+pub fn validate_tree(root: &Path) -> Result<ValidationReport, Error> {
+    let files = list_files(root)?;
+    let threads = thread::available_parallelism().map_or(1, |n| n.get()).min(files.len().max(1));
+    let next = AtomicUsize::new(0);
+
+    let mut results: Vec<(usize, Option<CheckReport>)> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut results = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::SeqCst);
+                        match files.get(i) {
+                            Some(listed) => results.push((i, check_listed(listed))),
+                            None => return results,
+                        }
+                    }
+                })
+            })
+            .collect();
+        workers.into_iter().flat_map(|w| w.join().unwrap()).collect()
+    });
+    results.sort_by_key(|&(i, _)| i);
+
+    let reports = results.into_iter().filter_map(|(_, report)| report).collect();
+    Ok(ValidationReport { files: reports })
+}
+
+/// `path` if it is a file, else the files of the directory recursively in
+/// path order
+///
+/// Fails if `path` can't be read, directories and entries below it that
+/// can't be read are listed as their report.
+fn list_files(path: &Path) -> Result<Vec<Listed>, Error> {
+    let mut files = Vec::new();
+    if fs::metadata(path)?.is_dir() {
+        list_directory(path, fs::read_dir(path)?, &mut files);
+    } else {
+        files.push(Ok(path.to_path_buf()));
+    }
+    Ok(files)
+}
+
+fn list_directory(path: &Path, read_dir: fs::ReadDir, files: &mut Vec<Listed>) {
+    let mut entries = Vec::new();
+    for entry in read_dir {
+        match entry {
+            Ok(entry) => entries.push(entry.path()),
+            Err(e) => {
+                let problem = format!("Failed to read directory entry: {}", e);
+                files.push(Err(CheckReport::unreadable(path, problem)));
+            }
+        }
+    }
+    entries.sort();
+
+    for entry in entries {
+        if !entry.is_dir() {
+            files.push(Ok(entry));
+            continue;
+        }
+
+        match fs::read_dir(&entry) {
+            Ok(read_dir) => list_directory(&entry, read_dir, files),
+            Err(e) => {
+                let problem = format!("Failed to read directory: {}", e);
+                files.push(Err(CheckReport::unreadable(&entry, problem)));
+            }
+        }
+    }
+}
+
+/// Check a listed file, reporting it as unreadable if it can't be read
+fn check_listed(listed: &Listed) -> Option<CheckReport> {
+    match *listed {
+        Ok(ref path) => {
+            match check_file(path) {
+                Ok(report) => report,
+                Err(e) => Some(CheckReport::unreadable(path, format!("Failed to read: {}", e))),
+            }
+        }
+        Err(ref report) => Some(report.clone()),
+    }
+}
+
+/// Check a single file, `None` if its type is unknown
+pub fn check_file(path: &Path) -> Result<Option<CheckReport>, Error> {
+    let bytes = fs::read(path)?;
+    let file_type = match RoseFileType::detect(&bytes, path) {
+        Some(file_type) => file_type,
+        None => return Ok(None),
+    };
+
+    let problems = match file_type.load(&mut Cursor::new(bytes)) {
+        Ok(RoseFileData::ZMS(zms)) => zms.validate(),
+        Ok(_) => Vec::new(),
+        Err(e) => vec![format!("Failed to load: {}", e)],
+    };
+
+    Ok(Some(CheckReport {
+        path: path.to_path_buf(),
+        file_type: Some(file_type),
+        problems,
+    }))
+}
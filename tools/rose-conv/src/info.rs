@@ -0,0 +1,139 @@
+//! File summaries
+//!
+//! Human readable summaries of the contents of any supported file, the type
+//! of the file is detected with `RoseFileData::from_path`.
+use std::fmt::Write;
+use std::path::Path;
+
+use failure::Error;
+
+use roselib::files::filetype::RoseFileData;
+use roselib::files::ZMS;
+
+/// Load the file at `path` and summarize it
+pub fn file_summary(path: &Path) -> Result<String, Error> {
+    let data = RoseFileData::from_path(path)?;
+    Ok(format!("{}\n{}", path.display(), summary(&data)))
+}
+
+/// Summarize a loaded file, one property per line
+pub fn summary(data: &RoseFileData) -> String {
+    let mut fields: Vec<(&str, String)> = Vec::new();
+
+    match *data {
+        RoseFileData::AIP(ref aip) => {
+            fields.push(("Name", aip.name.clone()));
+            fields.push(("Triggers", aip.triggers.len().to_string()));
+            fields.push(("Idle interval", aip.idle_interval.to_string()));
+            fields.push(("Damage rate", aip.damage_rate.to_string()));
+        }
+        RoseFileData::DDS(ref dds) => {
+            fields.push(("Dimensions", format!("{}x{}", dds.width, dds.height)));
+            fields.push(("Format", format!("{:?}", dds.format)));
+            fields.push(("Mipmaps", dds.mipmap_count.to_string()));
+        }
+        RoseFileData::HIM(ref him) => {
+            fields.push(("Dimensions", format!("{}x{}", him.width, him.height)));
+            fields.push(("Grid count", him.grid_count.to_string()));
+            fields.push(("Scale", him.scale.to_string()));
+            fields.push(("Min height", him.min_height.to_string()));
+            fields.push(("Max height", him.max_height.to_string()));
+        }
+        RoseFileData::IDX(ref idx) => {
+            let entries: usize = idx.file_systems.iter().map(|vfs| vfs.files.len()).sum();
+            fields.push(("Version", format!("{} (base {})", idx.current_version, idx.base_version)));
+            fields.push(("File systems", idx.file_systems.len().to_string()));
+            fields.push(("Entries", entries.to_string()));
+            for vfs in &idx.file_systems {
+                fields.push(("File system",
+                             format!("{} ({} entries)", vfs.filename.display(), vfs.files.len())));
+            }
+        }
+        RoseFileData::IFO(ref ifo) => {
+            fields.push(("Name", ifo.name.clone()));
+            fields.push(("Position", format!("{}_{}", ifo.map_position.x, ifo.map_position.y)));
+            fields.push(("Deco objects", ifo.deco_objects.len().to_string()));
+            fields.push(("Construction objects", ifo.construction_objects.len().to_string()));
+            fields.push(("NPCs", ifo.npcs.len().to_string()));
+            fields.push(("Monster spawns", ifo.monster_spawns.len().to_string()));
+            fields.push(("Warps", ifo.warps.len().to_string()));
+            fields.push(("Events", ifo.events.len().to_string()));
+        }
+        RoseFileData::LIT(ref lit) => {
+            fields.push(("Objects", lit.objects.len().to_string()));
+            fields.push(("Filenames", lit.filenames.len().to_string()));
+        }
+        RoseFileData::STB(ref stb) => {
+            fields.push(("Identifier", stb.identifier.clone()));
+            fields.push(("Rows", stb.rows.len().to_string()));
+            fields.push(("Columns", stb.column_names.len().to_string()));
+        }
+        RoseFileData::STL(ref stl) => {
+            fields.push(("Identifier", stl.identifier.clone()));
+            fields.push(("Keys", stl.keys.len().to_string()));
+            fields.push(("Languages", stl.languages.len().to_string()));
+        }
+        RoseFileData::TIL(ref til) => {
+            fields.push(("Dimensions", format!("{}x{}", til.width, til.height)));
+        }
+        RoseFileData::TSI(ref tsi) => {
+            let sprites: usize = tsi.textures.iter().map(|t| t.sprites.len()).sum();
+            fields.push(("Textures", tsi.textures.len().to_string()));
+            fields.push(("Sprites", sprites.to_string()));
+        }
+        RoseFileData::ZMD(ref zmd) => {
+            fields.push(("Identifier", zmd.identifier.clone()));
+            fields.push(("Bones", zmd.bones.len().to_string()));
+            fields.push(("Dummies", zmd.dummies.len().to_string()));
+        }
+        RoseFileData::ZMO(ref zmo) => {
+            fields.push(("Identifier", zmo.identifier.clone()));
+            fields.push(("FPS", zmo.fps.to_string()));
+            fields.push(("Frames", zmo.frame_count.to_string()));
+            fields.push(("Channels", zmo.channels.len().to_string()));
+        }
+        RoseFileData::ZMS(ref zms) => {
+            fields.push(("Identifier", zms.identifier.clone()));
+            fields.push(("Vertices", zms.vertices.len().to_string()));
+            fields.push(("Indices", zms.indices.len().to_string()));
+            fields.push(("Materials", zms.materials.len().to_string()));
+            fields.push(("Bones", zms.bones.len().to_string()));
+            fields.push(("Attributes", mesh_attributes(zms).join(", ")));
+        }
+        RoseFileData::ZON(ref zon) => {
+            fields.push(("Name", zon.name.clone()));
+            fields.push(("Type", format!("{:?}", zon.zone_type)));
+            fields.push(("Dimensions", format!("{}x{}", zon.width, zon.height)));
+            fields.push(("Event points", zon.event_points.len().to_string()));
+            fields.push(("Textures", zon.textures.len().to_string()));
+            fields.push(("Tiles", zon.tiles.len().to_string()));
+        }
+        RoseFileData::ZSC(ref zsc) => {
+            fields.push(("Meshes", zsc.meshes.len().to_string()));
+            fields.push(("Materials", zsc.materials.len().to_string()));
+            fields.push(("Effects", zsc.effects.len().to_string()));
+            fields.push(("Objects", zsc.objects.len().to_string()));
+        }
+    }
+
+    let mut s = format!("Type: {:?}\n", data.file_type());
+    for (name, value) in fields {
+        let _ = writeln!(s, "{}: {}", name, value);
+    }
+    s
+}
+
+/// Names of the vertex attributes enabled in a mesh
+fn mesh_attributes(zms: &ZMS) -> Vec<&'static str> {
+    let attributes = [(zms.positions_enabled(), "position"),
+                      (zms.normals_enabled(), "normal"),
+                      (zms.colors_enabled(), "color"),
+                      (zms.bones_enabled(), "bones"),
+                      (zms.tangents_enabled(), "tangent"),
+                      (zms.uv1_enabled(), "uv1"),
+                      (zms.uv2_enabled(), "uv2"),
+                      (zms.uv3_enabled(), "uv3"),
+                      (zms.uv4_enabled(), "uv4")];
+
+    attributes.iter().filter(|a| a.0).map(|a| a.1).collect()
+}
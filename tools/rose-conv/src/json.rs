@@ -0,0 +1,87 @@
+//! JSON output
+//!
+//! The converters write JSON through `to_json_writer` so the formatting can
+//! be chosen from the command line, e.g. compact files to save space or
+//! rounded floats for readable diffs under version control.
+use std::io::Write;
+
+use failure::Error;
+use serde::Serialize;
+use serde_json::{self, Number, Value};
+
+/// Formatting of the JSON files
+#[derive(Clone, Debug, PartialEq)]
+pub struct JsonOptions {
+    /// Indent the output instead of writing it on a single line
+    pub pretty: bool,
+    /// Round floats to this many decimals, `None` keeps their full precision
+    pub float_precision: Option<u32>,
+}
+
+impl JsonOptions {
+    /// Pretty printed output with floats at full precision
+    pub fn new() -> JsonOptions {
+        JsonOptions {
+            pretty: true,
+            float_precision: None,
+        }
+    }
+}
+
+impl Default for JsonOptions {
+    fn default() -> JsonOptions {
+        JsonOptions::new()
+    }
+}
+
+/// Write `value` as JSON formatted with `options`
+///
+/// Rounding the floats goes through a `serde_json::Value`, so the keys of
+/// objects are then written sorted instead of in the field order.
+pub fn to_json_writer<W: Write, T: Serialize>(writer: W,
+                                              value: &T,
+                                              options: &JsonOptions)
+                                              -> Result<(), Error> {
+    match options.float_precision {
+        Some(precision) => {
+            let mut json = serde_json::to_value(value)?;
+            round_floats(&mut json, precision);
+            write_json(writer, &json, options.pretty)
+        }
+        None => write_json(writer, value, options.pretty),
+    }
+}
+
+fn write_json<W: Write, T: Serialize>(writer: W, value: &T, pretty: bool) -> Result<(), Error> {
+    if pretty {
+        serde_json::to_writer_pretty(writer, value)?;
+    } else {
+        serde_json::to_writer(writer, value)?;
+    }
+    Ok(())
+}
+
+/// Round the floats of a JSON value to `precision` decimals, integers are
+/// left as they are
+fn round_floats(value: &mut Value, precision: u32) {
+    match *value {
+        Value::Number(ref mut n) if n.is_f64() => {
+            let scale = 10f64.powi(precision as i32);
+            let rounded = (n.as_f64().unwrap() * scale).round() / scale;
+            if let Some(rounded) = Number::from_f64(rounded) {
+                *n = rounded;
+            }
+        }
+        Value::Array(ref mut values) => {
+            for v in values.iter_mut() {
+                round_floats(v, precision);
+            }
+        }
+        Value::Object(ref mut map) => {
+            for (_, v) in map.iter_mut() {
+                round_floats(v, precision);
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,14 @@
+//! ROSE Online converters
+//!
+//! Conversions used by the `roseconv` command line tool.
+#[macro_use] extern crate failure;
+#[macro_use] extern crate serde_derive;
+extern crate serde;
+extern crate serde_json;
+extern crate image;
+extern crate roselib;
+
+pub mod check;
+pub mod info;
+pub mod json;
+pub mod zone;
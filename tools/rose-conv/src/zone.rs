@@ -0,0 +1,295 @@
+//! Zone (map) conversion
+//!
+//! A zone directory contains a `.ZON` file named after the directory and a
+//! set of tiles named `<x>_<y>` with a `.HIM` heightmap and a `.TIL` tilemap
+//! each. The tiles are stitched together in a single heightmap image and
+//! tilemap file.
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use failure::Error;
+use image::GrayImage;
+use serde::Serialize;
+
+use roselib::files::{HIM, IFO, TIL, ZON};
+use roselib::files::zon::ZoneTileRotation;
+use roselib::io::RoseFile;
+use roselib::map::{self, MapHeightmap};
+use roselib::utils;
+
+use json::{JsonOptions, to_json_writer};
+
+pub use roselib::map::TileCoordinates;
+
+/// Number of height samples on each side of a HIM tile
+pub const HEIGHTMAP_SIZE: u32 = map::HIM_GRID_SIZE as u32;
+
+/// Number of tiles on each side of a TIL tile
+pub const TILEMAP_SIZE: u32 = map::TIL_GRID_SIZE as u32;
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TilemapTile {
+    pub layer1: i32,
+    pub layer2: i32,
+    pub rotation: ZoneTileRotation,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TilemapFile {
+    pub textures: Vec<String>,
+    pub tiles: Vec<TilemapTile>,
+    pub tilemap: Vec<Vec<i32>>,
+}
+
+/// Heights of all the tiles of a zone
+///
+/// `min_height` and `max_height` are computed over all the tiles so the
+/// heights are normalized consistently across the zone.
+#[derive(Debug)]
+pub struct Heightfield {
+    pub width: u32,
+    pub height: u32,
+    pub heights: Vec<Vec<f32>>,
+    pub min_height: f32,
+    pub max_height: f32,
+    pub warnings: Vec<String>,
+}
+
+impl Heightfield {
+    /// Render the heights as a greyscale image, from black at `min_height`
+    /// to white at `max_height`
+    pub fn to_image(&self) -> GrayImage {
+        utils::heights_to_gray_image(&self.heights, self.min_height, self.max_height)
+    }
+}
+
+/// Find the tile coordinates of a zone from the `.HIM` files in a directory
+///
+/// Returns the coordinates sorted by row then column along with the path of
+/// each heightmap.
+pub fn tile_coordinates(map_dir: &Path) -> Result<Vec<(TileCoordinates, PathBuf)>, Error> {
+    let tiles = map::tile_files(map_dir, "him")?;
+    if tiles.is_empty() {
+        bail!("No HIM files found in {}", map_dir.display());
+    }
+    Ok(tiles)
+}
+
+/// Files written by `export_map`
+#[derive(Debug)]
+pub struct MapExport {
+    /// Paths of the written files
+    pub files: Vec<PathBuf>,
+
+    /// Problems found while stitching that did not prevent the export, see
+    /// `Heightfield::warnings`
+    pub warnings: Vec<String>,
+}
+
+/// Stitch the heightmaps of a zone together
+///
+/// Tiles are placed relative to the lowest tile coordinates. The dimensions
+/// are padded to a multiple of `TIL_CELL_GRID_SIZE` (plus the shared edge)
+/// for the terrain importer, padding is filled with zeros.
+pub fn stitch_heightmaps(tiles: &[(TileCoordinates, HIM)]) -> Result<Heightfield, Error> {
+    let map = MapHeightmap::from_heightmaps(tiles)?;
+
+    let width = padded_size(map.width as u32);
+    let height = padded_size(map.height as u32);
+    let mut heights = vec![vec![0.0; width as usize]; height as usize];
+    for (row, map_row) in heights.iter_mut().zip(map.heights.iter()) {
+        row[..map_row.len()].copy_from_slice(map_row);
+    }
+
+    Ok(Heightfield {
+        width,
+        height,
+        heights,
+        min_height: map.min_height,
+        max_height: map.max_height,
+        warnings: map.warnings,
+    })
+}
+
+/// Stitch the tile ids of the tilemaps of a zone together
+///
+/// Tilemaps are placed by their actual dimensions like the heightmaps: each
+/// column of tiles is as wide as its widest tilemap and each row as high as
+/// its highest tilemap. Columns and rows without a tilemap are
+/// `TILEMAP_SIZE` tiles. The result has one column per `TIL_CELL_GRID_SIZE`
+/// heights of the stitched heightmap, cells not covered by a tilemap are
+/// zero.
+pub fn stitch_tilemaps(tiles: &[(TileCoordinates, TIL)]) -> Result<Vec<Vec<i32>>, Error> {
+    let (x_min, x_max, y_min, y_max) = tile_bounds(tiles)?;
+
+    let mut column_widths = vec![0; (x_max - x_min + 1) as usize];
+    let mut row_heights = vec![0; (y_max - y_min + 1) as usize];
+    for &((x, y), ref til) in tiles {
+        let (width, height) = tilemap_size(til);
+        let column = &mut column_widths[(x - x_min) as usize];
+        *column = (*column).max(width);
+        let row = &mut row_heights[(y - y_min) as usize];
+        *row = (*row).max(height);
+    }
+    for size in column_widths.iter_mut().chain(row_heights.iter_mut()) {
+        if *size == 0 {
+            *size = TILEMAP_SIZE;
+        }
+    }
+
+    let width = stitched_tilemap_size(&column_widths);
+    let height = stitched_tilemap_size(&row_heights);
+    let mut tilemap = vec![vec![0; width as usize]; height as usize];
+
+    let column_offsets = offsets(&column_widths);
+    let row_offsets = offsets(&row_heights);
+    for &((x, y), ref til) in tiles {
+        let (tile_width, tile_height) = tilemap_size(til);
+        let offset_x = column_offsets[(x - x_min) as usize] as usize;
+        let offset_y = row_offsets[(y - y_min) as usize] as usize;
+        for (h, row) in til.tiles.iter().take(tile_height as usize).enumerate() {
+            for (w, tile) in row.iter().take(tile_width as usize).enumerate() {
+                tilemap[offset_y + h][offset_x + w] = tile.tile_id;
+            }
+        }
+    }
+
+    Ok(tilemap)
+}
+
+/// Convert a zone directory
+///
+/// Writes the following files to `out_dir`:
+/// - `<map>.png`: Combined greyscale heightmap
+/// - `<map>_tilemap.json`: Combined tilemap
+/// - `<map>_zon.json`: ZON data
+/// - `<x>_<y>_til.json`: TIL data of each tile
+/// - `<x>_<y>_ifo.json`: IFO data (objects) of each tile with an `.IFO` file
+///
+/// The JSON files are formatted with `options`. Returns the paths of the
+/// written files along with the stitching warnings.
+pub fn export_map(map_dir: &Path, out_dir: &Path, options: &JsonOptions) -> Result<MapExport, Error> {
+    let map_name = match map_dir.file_name().and_then(|n| n.to_str()) {
+        Some(name) => name.to_string(),
+        None => bail!("Invalid map directory: {}", map_dir.display()),
+    };
+
+    let til_paths: HashMap<TileCoordinates, PathBuf> =
+        map::tile_files(map_dir, "til")?.into_iter().collect();
+    let ifo_paths: HashMap<TileCoordinates, PathBuf> =
+        map::tile_files(map_dir, "ifo")?.into_iter().collect();
+
+    let mut hims = Vec::new();
+    let mut tils = Vec::new();
+    let mut ifos = Vec::new();
+    for (coords, him_path) in tile_coordinates(map_dir)? {
+        let til_path = match til_paths.get(&coords) {
+            Some(path) => path,
+            None => bail!("Missing TIL file for tile {}_{} in {}", coords.0, coords.1, map_dir.display()),
+        };
+        hims.push((coords, HIM::from_path(&him_path)?));
+        tils.push((coords, TIL::from_path(til_path)?));
+        if let Some(ifo_path) = ifo_paths.get(&coords) {
+            ifos.push((coords, IFO::from_path(ifo_path)?));
+        }
+    }
+
+    let zon = ZON::from_path(&map_dir.join(format!("{}.ZON", map_name)))?;
+
+    let mut written = Vec::new();
+
+    let height_file = out_dir.join(format!("{}.png", map_name));
+    let heightfield = stitch_heightmaps(&hims)?;
+    heightfield.to_image().save(&height_file)?;
+    written.push(height_file);
+
+    let zon_file = out_dir.join(format!("{}_zon.json", map_name));
+    write_json(&zon_file, &zon, options)?;
+    written.push(zon_file);
+
+    let tilemap = TilemapFile {
+        tiles: zon.tiles.into_iter()
+            .map(|t| TilemapTile {
+                layer1: t.layer1 + t.offset1,
+                layer2: t.layer2 + t.offset2,
+                rotation: t.rotation,
+            })
+            .collect(),
+        textures: zon.textures,
+        tilemap: stitch_tilemaps(&tils)?,
+    };
+
+    let tilemap_file = out_dir.join(format!("{}_tilemap.json", map_name));
+    write_json(&tilemap_file, &tilemap, options)?;
+    written.push(tilemap_file);
+
+    for &((x, y), ref til) in &tils {
+        let til_file = out_dir.join(format!("{}_{}_til.json", x, y));
+        write_json(&til_file, til, options)?;
+        written.push(til_file);
+    }
+
+    for &((x, y), ref ifo) in &ifos {
+        let ifo_file = out_dir.join(format!("{}_{}_ifo.json", x, y));
+        write_json(&ifo_file, ifo, options)?;
+        written.push(ifo_file);
+    }
+
+    Ok(MapExport {
+        files: written,
+        warnings: heightfield.warnings,
+    })
+}
+
+fn write_json<T: Serialize>(path: &Path, value: &T, options: &JsonOptions) -> Result<(), Error> {
+    let f = File::create(path)?;
+    to_json_writer(f, value, options)
+}
+
+/// Lowest and highest tile coordinates as `(x_min, x_max, y_min, y_max)`
+fn tile_bounds<T>(tiles: &[(TileCoordinates, T)]) -> Result<(u32, u32, u32, u32), Error> {
+    if tiles.is_empty() {
+        bail!("No tiles to stitch");
+    }
+
+    let xs = || tiles.iter().map(|&((x, _), _)| x);
+    let ys = || tiles.iter().map(|&((_, y), _)| y);
+
+    Ok((xs().min().unwrap(), xs().max().unwrap(), ys().min().unwrap(), ys().max().unwrap()))
+}
+
+/// Size of the tiles actually present in a tilemap as `(width, height)`
+fn tilemap_size(til: &TIL) -> (u32, u32) {
+    let height = til.tiles.len() as u32;
+    let width = til.tiles.iter().map(|row| row.len()).min().unwrap_or(0) as u32;
+    (width, height)
+}
+
+/// Number of tiles on one side of the stitched tilemap
+///
+/// A slot of `size` tiles matches the `size * TIL_CELL_GRID_SIZE + 1`
+/// heights of its heightmap, the total is padded like the heightmap.
+fn stitched_tilemap_size(sizes: &[u32]) -> u32 {
+    let cell_size = map::TIL_CELL_GRID_SIZE as u32;
+    let heights = sizes.iter().map(|size| size * cell_size + 1).sum();
+    padded_size(heights) / cell_size
+}
+
+/// Starting offset of each slot from the size of the slots
+fn offsets(sizes: &[u32]) -> Vec<u32> {
+    sizes.iter()
+        .scan(0, |offset, &size| {
+            let start = *offset;
+            *offset += size;
+            Some(start)
+        })
+        .collect()
+}
+
+/// Round a size up to a multiple of `TIL_CELL_GRID_SIZE` plus the shared
+/// edge
+fn padded_size(size: u32) -> u32 {
+    let cell_size = map::TIL_CELL_GRID_SIZE as u32;
+    (size + cell_size - 1) / cell_size * cell_size + 1
+}
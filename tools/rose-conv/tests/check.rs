@@ -0,0 +1,105 @@
+extern crate roselib;
+extern crate roseconv;
+extern crate serde_json;
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use roseconv::check;
+use roselib::files::ZMS;
+use roselib::files::filetype::RoseFileType;
+use roselib::io::RoseFile;
+use roselib::utils::Vector3;
+
+fn data_dir() -> PathBuf {
+    let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    root.push("..");
+    root.push("rose-lib");
+    root.push("tests");
+    root.push("data");
+    root
+}
+
+#[test]
+fn check_directory() {
+    let dir = env::temp_dir().join("roseconv_check_directory");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("models")).unwrap();
+
+    fs::copy(data_dir().join("STONE014.ZMS"), dir.join("STONE014.ZMS")).unwrap();
+    fs::write(dir.join("README.txt"), b"not a ROSE file").unwrap();
+
+    // A triangle referencing a missing vertex
+    let mut zms = ZMS::from_path(&data_dir().join("STONE014.ZMS")).unwrap();
+    let vertex_count = zms.vertices.len() as i16;
+    zms.indices[0] = Vector3 { x: 0, y: 1, z: vertex_count };
+    fs::write(dir.join("models").join("BROKEN.ZMS"), zms.to_bytes().unwrap()).unwrap();
+
+    let reports = check::check_path(&dir).unwrap();
+    assert_eq!(reports.len(), 2);
+    assert!(reports.iter().all(|r| r.file_type == Some(RoseFileType::ZMS)));
+
+    let failures: Vec<_> = reports.iter().filter(|r| !r.is_valid()).collect();
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].path, dir.join("models").join("BROKEN.ZMS"));
+    assert_eq!(failures[0].problems.len(), 1);
+    assert!(failures[0].problems[0].starts_with("1 triangle indices out of range"));
+
+    for name in &["HEADBAD01.ZMS", "STONE014.ZMS"] {
+        let zms = ZMS::from_path(&data_dir().join(name)).unwrap();
+        assert_eq!(zms.validate(), Vec::<String>::new());
+    }
+}
+
+#[test]
+fn validate_directory_tree() {
+    let dir = env::temp_dir().join("roseconv_validate_tree");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("3DDATA").join("NPC")).unwrap();
+
+    let npc = dir.join("3DDATA").join("NPC");
+    fs::copy(data_dir().join("STONE014.ZMS"), dir.join("3DDATA").join("STONE014.ZMS")).unwrap();
+    fs::copy(data_dir().join("HEADBAD01.ZMS"), npc.join("HEADBAD01.ZMS")).unwrap();
+    fs::write(npc.join("TRUNCATED.ZMS"), b"ZMS0008\0\x02").unwrap();
+    fs::write(npc.join("NOTES.TXT"), b"not a ROSE file").unwrap();
+
+    let report = check::validate_tree(&dir).unwrap();
+    assert_eq!(report.files, check::check_path(&dir).unwrap());
+    assert_eq!(report.files.len(), 3);
+    assert!(!report.is_valid());
+
+    let failures: Vec<_> = report.failures().collect();
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].path, npc.join("TRUNCATED.ZMS"));
+    assert!(failures[0].problems[0].starts_with("Failed to load"));
+
+    let json: serde_json::Value = serde_json::to_value(&report).unwrap();
+    assert_eq!(json["files"].as_array().unwrap().len(), 3);
+    assert_eq!(json["files"][1]["file_type"], "ZMS");
+}
+
+#[cfg(unix)]
+#[test]
+fn validate_unreadable_file() {
+    use std::os::unix::fs::symlink;
+
+    let dir = env::temp_dir().join("roseconv_validate_unreadable");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    fs::copy(data_dir().join("STONE014.ZMS"), dir.join("STONE014.ZMS")).unwrap();
+    symlink(dir.join("MISSING.ZMS"), dir.join("DANGLING.ZMS")).unwrap();
+
+    // The other files are still checked
+    let report = check::validate_tree(&dir).unwrap();
+    assert_eq!(report.files.len(), 2);
+    let failures: Vec<_> = report.failures().collect();
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0].path, dir.join("DANGLING.ZMS"));
+    assert_eq!(failures[0].file_type, None);
+    assert!(failures[0].problems[0].starts_with("Failed to read"));
+    assert_eq!(report.files, check::check_path(&dir).unwrap());
+
+    assert!(check::validate_tree(&dir.join("MISSING")).is_err());
+}
@@ -0,0 +1,40 @@
+extern crate roselib;
+extern crate roseconv;
+
+use std::path::PathBuf;
+
+use roseconv::info;
+use roselib::files::filetype::RoseFileData;
+
+fn data_dir() -> PathBuf {
+    let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    root.push("..");
+    root.push("rose-lib");
+    root.push("tests");
+    root.push("data");
+    root
+}
+
+#[test]
+fn zms_summary() {
+    let path = data_dir().join("HEADBAD01.ZMS");
+    let zms = match RoseFileData::from_path(&path).unwrap() {
+        RoseFileData::ZMS(zms) => zms,
+        d => panic!("Expected a ZMS, found {:?}", d.file_type()),
+    };
+
+    let summary = info::file_summary(&path).unwrap();
+    assert!(summary.contains("Type: ZMS"));
+    assert!(summary.contains(&format!("Vertices: {}", zms.vertices.len())));
+    assert!(summary.contains(&format!("Indices: {}", zms.indices.len())));
+    assert!(summary.contains(&format!("Materials: {}", zms.materials.len())));
+    assert!(summary.contains("Attributes: position"));
+}
+
+#[test]
+fn him_summary() {
+    let summary = info::file_summary(&data_dir().join("33_30.HIM")).unwrap();
+    assert!(summary.contains("Type: HIM"));
+    assert!(summary.contains("Dimensions: 65x65"));
+    assert!(summary.contains("Scale: 250"));
+}
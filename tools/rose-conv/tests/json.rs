@@ -0,0 +1,41 @@
+extern crate roseconv;
+#[macro_use]
+extern crate serde_derive;
+
+use roseconv::json::{JsonOptions, to_json_writer};
+
+#[derive(Serialize)]
+struct Sample {
+    name: String,
+    count: i32,
+    heights: Vec<f32>,
+}
+
+fn sample_json(options: &JsonOptions) -> String {
+    let sample = Sample {
+        name: String::from("31_30"),
+        count: 7,
+        heights: vec![5463.6577, 0.1, -2.0],
+    };
+
+    let mut bytes = Vec::new();
+    to_json_writer(&mut bytes, &sample, options).unwrap();
+    String::from_utf8(bytes).unwrap()
+}
+
+#[test]
+fn json_float_precision() {
+    let mut options = JsonOptions::new();
+    options.pretty = false;
+    assert_eq!(sample_json(&options),
+               r#"{"name":"31_30","count":7,"heights":[5463.6577,0.1,-2.0]}"#);
+
+    options.float_precision = Some(3);
+    assert_eq!(sample_json(&options),
+               r#"{"count":7,"heights":[5463.658,0.1,-2.0],"name":"31_30"}"#);
+
+    options.pretty = true;
+    let json = sample_json(&options);
+    assert!(json.contains("\n  \"count\": 7,"));
+    assert!(json.contains("5463.658"));
+}
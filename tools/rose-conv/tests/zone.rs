@@ -0,0 +1,190 @@
+extern crate image;
+extern crate roseconv;
+extern crate roselib;
+extern crate serde_json;
+
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use roseconv::json::JsonOptions;
+use roseconv::zone;
+use roseconv::zone::TilemapFile;
+use roselib::files::TIL;
+use roselib::files::til::Tile;
+use roselib::io::RoseFile;
+
+const TILES: [(u32, u32); 4] = [(30, 30), (31, 30), (30, 31), (31, 31)];
+
+fn write_him(path: &Path, size: i32, height: f32) {
+    let mut data: Vec<u8> = Vec::new();
+    for v in &[size, size, 4] {
+        data.extend_from_slice(&v.to_le_bytes());
+    }
+    data.extend_from_slice(&250.0f32.to_le_bytes());
+    for _ in 0..size * size {
+        data.extend_from_slice(&height.to_le_bytes());
+    }
+    fs::write(path, data.as_slice()).unwrap();
+}
+
+fn write_til(path: &Path, tile_id: i32) {
+    let mut data: Vec<u8> = Vec::new();
+    for v in &[16i32, 16] {
+        data.extend_from_slice(&v.to_le_bytes());
+    }
+    for _ in 0..16 * 16 {
+        data.extend_from_slice(&[0, 0, 0]);
+        data.extend_from_slice(&tile_id.to_le_bytes());
+    }
+    fs::write(path, data.as_slice()).unwrap();
+}
+
+fn synthetic_map(root: &Path) -> PathBuf {
+    let map_dir = root.join("TEST");
+    fs::create_dir_all(&map_dir).unwrap();
+
+    for (i, &(x, y)) in TILES.iter().enumerate() {
+        write_him(&map_dir.join(format!("{}_{}.HIM", x, y)), 65, i as f32 * 100.0);
+        write_til(&map_dir.join(format!("{}_{}.TIL", x, y)), i as i32);
+    }
+
+    let data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../rose-lib/tests/data");
+    fs::copy(data_dir.join("JGT01.ZON"), map_dir.join("TEST.ZON")).unwrap();
+    for &(x, y) in TILES.iter() {
+        fs::copy(data_dir.join("31_30.IFO"), map_dir.join(format!("{}_{}.IFO", x, y))).unwrap();
+    }
+
+    map_dir
+}
+
+#[test]
+fn export_map() {
+    let root = env::temp_dir().join("roseconv_export_map");
+    let _ = fs::remove_dir_all(&root);
+    let map_dir = synthetic_map(&root);
+    let out_dir = root.join("out");
+    fs::create_dir_all(&out_dir).unwrap();
+
+    let export = zone::export_map(&map_dir, &out_dir, &JsonOptions::new()).unwrap();
+    assert!(export.warnings.is_empty());
+    assert_eq!(export.files.len(), 3 + 2 * TILES.len());
+    for path in &export.files {
+        assert!(path.is_file(), "{} was not written", path.display());
+    }
+    for &(x, y) in TILES.iter() {
+        assert!(out_dir.join(format!("{}_{}_til.json", x, y)).is_file());
+        assert!(out_dir.join(format!("{}_{}_ifo.json", x, y)).is_file());
+    }
+    assert!(out_dir.join("TEST_zon.json").is_file());
+
+    // 2x2 tiles of 65 heights padded to a multiple of 4 plus the edge
+    let image = image::open(out_dir.join("TEST.png")).unwrap().to_luma();
+    assert_eq!((image.width(), image.height()), (133, 133));
+
+    // Heights are normalized across all the tiles
+    assert_eq!(image.get_pixel(0, 0).data[0], 0);
+    assert_eq!(image.get_pixel(65, 0).data[0], 85);
+    assert_eq!(image.get_pixel(0, 65).data[0], 170);
+    assert_eq!(image.get_pixel(129, 129).data[0], 255);
+
+    let tilemap: TilemapFile = serde_json::from_reader(
+        File::open(out_dir.join("TEST_tilemap.json")).unwrap()
+    ).unwrap();
+    assert_eq!(tilemap.tilemap.len(), 33);
+    assert!(tilemap.tilemap.iter().all(|row| row.len() == 33));
+    assert_eq!(tilemap.tilemap[0][0], 0);
+    assert_eq!(tilemap.tilemap[0][16], 1);
+    assert_eq!(tilemap.tilemap[16][0], 2);
+    assert_eq!(tilemap.tilemap[31][31], 3);
+
+    let ifo: serde_json::Value = serde_json::from_reader(
+        File::open(out_dir.join("30_30_ifo.json")).unwrap()
+    ).unwrap();
+    assert!(ifo["deco_objects"].as_array().map_or(false, |objects| !objects.is_empty()));
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+fn tilemap(size: usize, tile_id: i32) -> TIL {
+    let tile = Tile { brush_id: 0, tile_idx: 0, tile_set: 0, tile_id };
+    let mut til = TIL::new();
+    til.width = size as i32;
+    til.height = size as i32;
+    til.tiles = vec![vec![tile; size]; size];
+    til
+}
+
+#[test]
+fn stitch_tilemaps_sizes() {
+    let tiles = [((30, 30), tilemap(16, 1)), ((31, 30), tilemap(8, 2))];
+    let stitched = zone::stitch_tilemaps(&tiles).unwrap();
+
+    // Sized like the heightmap: 65 + 33 heights padded to 101 by 65 heights
+    // padded to 69
+    assert_eq!(stitched.len(), 17);
+    assert!(stitched.iter().all(|row| row.len() == 25));
+    assert_eq!(stitched[15][15], 1);
+    assert_eq!(stitched[0][16], 2);
+    assert_eq!(stitched[7][23], 2);
+    assert_eq!(stitched[8][16], 0);
+}
+
+#[test]
+fn export_map_warnings() {
+    let root = env::temp_dir().join("roseconv_export_map_warnings");
+    let _ = fs::remove_dir_all(&root);
+    let map_dir = synthetic_map(&root);
+    let out_dir = root.join("out");
+    fs::create_dir_all(&out_dir).unwrap();
+
+    // Smaller than the other tiles of its row and column
+    write_him(&map_dir.join("31_31.HIM"), 33, 300.0);
+
+    let export = zone::export_map(&map_dir, &out_dir, &JsonOptions::new()).unwrap();
+    assert_eq!(export.warnings.len(), 1);
+    assert!(export.warnings[0].contains("(31, 31)"));
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn stitch_heightmaps_empty() {
+    assert!(zone::stitch_heightmaps(&[]).is_err());
+}
+
+#[test]
+fn tile_coordinates_skip_junk() {
+    let root = env::temp_dir().join("roseconv_tile_coordinates_junk");
+    let _ = fs::remove_dir_all(&root);
+    let map_dir = synthetic_map(&root);
+
+    fs::write(map_dir.join("README"), b"not a tile").unwrap();
+    fs::write(map_dir.join("notes.txt"), b"not a tile").unwrap();
+    fs::write(map_dir.join("backup.HIM"), b"not a tile").unwrap();
+    fs::write(map_dir.join("30_30_old.HIM"), b"not a tile").unwrap();
+    fs::write(map_dir.join("x_30.him"), b"not a tile").unwrap();
+    fs::create_dir_all(map_dir.join("32_32.HIM")).unwrap();
+
+    let coords: Vec<_> = zone::tile_coordinates(&map_dir).unwrap()
+        .into_iter()
+        .map(|(coords, _)| coords)
+        .collect();
+    assert_eq!(coords, vec![(30, 30), (31, 30), (30, 31), (31, 31)]);
+
+    // Lowercase TIL extensions are found
+    let out_dir = root.join("out");
+    fs::create_dir_all(&out_dir).unwrap();
+    fs::rename(map_dir.join("31_31.TIL"), map_dir.join("31_31.til")).unwrap();
+    zone::export_map(&map_dir, &out_dir, &JsonOptions::new()).unwrap();
+
+    // Tiles without an IFO file are exported without IFO data
+    fs::remove_file(map_dir.join("31_31.IFO")).unwrap();
+    let export = zone::export_map(&map_dir, &out_dir, &JsonOptions::new()).unwrap();
+    assert_eq!(export.files.len(), 3 + 2 * TILES.len() - 1);
+
+    // Missing TIL files are reported instead of panicking
+    fs::remove_file(map_dir.join("31_31.til")).unwrap();
+    assert!(zone::export_map(&map_dir, &out_dir, &JsonOptions::new()).is_err());
+}
@@ -0,0 +1,66 @@
+//! Error types
+//!
+//! Readers and writers return `failure::Error`. Errors that callers may want
+//! to handle are raised as a `RoseError` and can be recovered with
+//! `downcast_ref`.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use std::path::Path;
+//! use roselib::errors::RoseError;
+//! use roselib::files::ZMS;
+//! use roselib::io::RoseFile;
+//!
+//! match ZMS::from_path(Path::new("/path/to/mesh.zms")) {
+//!     Ok(_) => {}
+//!     Err(e) => match e.downcast_ref::<RoseError>() {
+//!         Some(&RoseError::UnsupportedVersion { ref found, .. }) => {
+//!             println!("Unsupported version: {}", found);
+//!         }
+//!         _ => println!("Error: {}", e),
+//!     },
+//! }
+//! ```
+
+/// Errors raised by the file readers and writers
+#[derive(Debug, Fail, PartialEq)]
+pub enum RoseError {
+    /// The file is of a known format but an unsupported version
+    #[fail(display = "Unsupported {} version: {}", format, found)]
+    UnsupportedVersion { format: &'static str, found: String },
+
+    /// The file does not start with the expected magic identifier
+    #[fail(display = "Invalid {} magic: {}", format, found)]
+    InvalidMagic { format: &'static str, found: String },
+
+    /// The file ended before all the data could be read
+    #[fail(display = "Unexpected end of {} file", format)]
+    TruncatedFile { format: &'static str },
+
+    /// A read went past the end of the stream, `offset` is the position of
+    /// the value being read
+    #[fail(display = "Unexpected end of file reading at offset {:#X}", offset)]
+    UnexpectedEof { offset: u64 },
+
+    /// The file contains a value that is not valid for the format
+    #[fail(display = "Invalid {} data: {}", format, reason)]
+    InvalidData { format: &'static str, reason: String },
+
+    /// An index refers to an element that does not exist
+    #[fail(display = "{} index {} out of range (count {})", kind, index, count)]
+    IndexOutOfRange { kind: &'static str, index: i64, count: usize },
+
+    /// A file looked up by path is not part of an archive or index
+    #[fail(display = "File not found in {}: {}", format, path)]
+    FileNotFound { format: &'static str, path: String },
+
+    /// No file exists at a path or any of its case variants, see
+    /// `io::resolve_path`
+    #[fail(display = "File not found, tried: {:?}", tried)]
+    PathNotFound { tried: Vec<String> },
+
+    /// The data of one or more entries does not match their checksum
+    #[fail(display = "{} checksum mismatch: {:?}", format, files)]
+    ChecksumMismatch { format: &'static str, files: Vec<String> },
+}
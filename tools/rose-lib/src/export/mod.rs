@@ -0,0 +1,29 @@
+//! A module for exporting ROSE data to common interchange formats
+
+mod obj;
+mod ply;
+mod stl;
+
+pub use self::obj::{write_obj, write_obj_with_materials};
+pub use self::ply::write_ply;
+pub use self::stl::write_stl;
+
+use files::ZMS;
+
+/// Iterate over the triangles of a mesh as vertex indices
+///
+/// Triangles are in strip order for meshes with a strip, see
+/// `Mesh::draw_triangles`. Triangles referencing vertices outside of the
+/// mesh are skipped
+fn triangles(mesh: &ZMS) -> impl Iterator<Item = [usize; 3]> {
+    let vertex_count = mesh.vertices.len();
+    mesh.draw_triangles()
+        .into_iter()
+        .map(|i| [i.x as usize, i.y as usize, i.z as usize])
+        .filter(move |t| t.iter().all(|&i| i < vertex_count))
+}
+
+/// Comment identifying the exporter
+fn header_comment() -> String {
+    format!("Exported using {} v{}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))
+}
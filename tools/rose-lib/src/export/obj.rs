@@ -0,0 +1,154 @@
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::Path;
+
+use failure::Error;
+use files::ZMS;
+use files::zsc::SceneMaterial;
+use utils::Transform;
+
+/// Write a mesh as a Wavefront OBJ
+///
+/// Only the positions, normals and first UV channel are exported. Positions
+/// and normals are converted with `transform` and the V coordinate is
+/// flipped as OBJ uses a bottom-left UV origin.
+pub fn write_obj<W: Write>(mesh: &ZMS, writer: &mut W, transform: &Transform) -> Result<(), Error> {
+    writeln!(writer, "# {}", super::header_comment())?;
+    write_vertices(mesh, writer, transform)?;
+
+    for t in super::triangles(mesh) {
+        write_face(mesh, writer, t)?;
+    }
+
+    Ok(())
+}
+
+/// Write a mesh as a Wavefront OBJ with its materials in a companion MTL
+///
+/// Like `write_obj`, with the faces of each material group of the mesh
+/// under a `usemtl` statement. `mtl_filename` is the name of the MTL file
+/// referenced by the OBJ, written to `mtl_writer`.
+///
+/// `materials` are the model list materials of the groups in order, as
+/// returned by `Scene::resolve_materials`. A group gets the material of the
+/// same index, named after its texture and with the texture as `map_Kd`.
+/// Groups without a material get a placeholder named `material_<index>`.
+/// A mesh without material groups is exported as a single group, triangles
+/// past the material counts are added to the last group. Material groups are
+/// ranges of the triangle list, so the strip of the mesh is not used.
+pub fn write_obj_with_materials<W: Write, M: Write>(mesh: &ZMS,
+                                                    writer: &mut W,
+                                                    mtl_writer: &mut M,
+                                                    mtl_filename: &str,
+                                                    materials: &[&SceneMaterial],
+                                                    transform: &Transform)
+                                                    -> Result<(), Error> {
+    let groups = material_groups(mesh);
+    let names = material_names(groups.len(), materials);
+
+    writeln!(writer, "# {}", super::header_comment())?;
+    writeln!(writer, "mtllib {}", mtl_filename)?;
+    write_vertices(mesh, writer, transform)?;
+
+    let vertex_count = mesh.vertices.len();
+    for (&(start, end), name) in groups.iter().zip(&names) {
+        writeln!(writer, "usemtl {}", name)?;
+        for i in &mesh.indices[start..end] {
+            let t = [i.x as usize, i.y as usize, i.z as usize];
+            if t.iter().all(|&i| i < vertex_count) {
+                write_face(mesh, writer, t)?;
+            }
+        }
+    }
+
+    writeln!(mtl_writer, "# {}", super::header_comment())?;
+    for (i, name) in names.iter().enumerate() {
+        writeln!(mtl_writer)?;
+        writeln!(mtl_writer, "newmtl {}", name)?;
+        writeln!(mtl_writer, "Kd 1 1 1")?;
+        if let Some(material) = materials.get(i) {
+            if material.alpha_enabled || material.alpha < 1.0 {
+                writeln!(mtl_writer, "d {}", material.alpha)?;
+            }
+            writeln!(mtl_writer, "map_Kd {}", material.path.replace('\\', "/"))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_vertices<W: Write>(mesh: &ZMS, writer: &mut W, transform: &Transform) -> Result<(), Error> {
+    for v in &mesh.vertices {
+        let p = transform.position(v.position);
+        writeln!(writer, "v {} {} {}", p.x, p.y, p.z)?;
+    }
+
+    if mesh.uv1_enabled() {
+        for v in &mesh.vertices {
+            writeln!(writer, "vt {} {}", v.uv1.x, 1.0 - v.uv1.y)?;
+        }
+    }
+
+    if mesh.normals_enabled() {
+        for v in &mesh.vertices {
+            let n = transform.normal(v.normal);
+            writeln!(writer, "vn {} {} {}", n.x, n.y, n.z)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_face<W: Write>(mesh: &ZMS, writer: &mut W, t: [usize; 3]) -> Result<(), Error> {
+    write!(writer, "f")?;
+    for &i in t.iter() {
+        let i = i + 1;
+        match (mesh.uv1_enabled(), mesh.normals_enabled()) {
+            (true, true) => write!(writer, " {i}/{i}/{i}", i = i)?,
+            (true, false) => write!(writer, " {i}/{i}", i = i)?,
+            (false, true) => write!(writer, " {i}//{i}", i = i)?,
+            (false, false) => write!(writer, " {}", i)?,
+        }
+    }
+    writeln!(writer)?;
+    Ok(())
+}
+
+/// Range of `indices` of each material group
+fn material_groups(mesh: &ZMS) -> Vec<(usize, usize)> {
+    let triangle_count = mesh.indices.len();
+    let mut groups = Vec::with_capacity(mesh.materials.len().max(1));
+    let mut start = 0;
+    for &count in &mesh.materials {
+        let end = (start + count.max(0) as usize).min(triangle_count);
+        groups.push((start, end));
+        start = end;
+    }
+
+    match groups.last_mut() {
+        Some(last) => last.1 = triangle_count,
+        None => groups.push((0, triangle_count)),
+    }
+    groups
+}
+
+/// Unique MTL name of each material group
+fn material_names(count: usize, materials: &[&SceneMaterial]) -> Vec<String> {
+    let mut used = HashSet::new();
+    (0..count)
+        .map(|i| {
+            let stem = materials.get(i).and_then(|m| {
+                let path = m.path.replace('\\', "/");
+                Path::new(&path).file_stem().and_then(|s| s.to_str()).map(|s| s.replace(' ', "_"))
+            });
+
+            let name = match stem {
+                Some(ref stem) if !stem.is_empty() && !used.contains(stem) => stem.clone(),
+                Some(stem) => format!("{}_{}", stem, i),
+                None => format!("material_{}", i),
+            };
+            used.insert(name.clone());
+            name
+        })
+        .collect()
+}
@@ -0,0 +1,98 @@
+use std::io::Write;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use failure::Error;
+use files::ZMS;
+use utils::Transform;
+
+/// Write a mesh as a Stanford PLY
+///
+/// Positions, normals, vertex colors and the first UV channel are exported
+/// when enabled on the mesh, positions and normals are converted with
+/// `transform`. The V coordinate is flipped like `write_obj` as PLY viewers
+/// use a bottom-left UV origin. Set `ascii` to write the human readable
+/// variant instead of binary little endian.
+pub fn write_ply<W: Write>(mesh: &ZMS,
+                           writer: &mut W,
+                           ascii: bool,
+                           transform: &Transform)
+                           -> Result<(), Error> {
+    let triangles: Vec<[usize; 3]> = super::triangles(mesh).collect();
+
+    writeln!(writer, "ply")?;
+    if ascii {
+        writeln!(writer, "format ascii 1.0")?;
+    } else {
+        writeln!(writer, "format binary_little_endian 1.0")?;
+    }
+    writeln!(writer, "comment {}", super::header_comment())?;
+    writeln!(writer, "element vertex {}", mesh.vertices.len())?;
+    writeln!(writer, "property float x")?;
+    writeln!(writer, "property float y")?;
+    writeln!(writer, "property float z")?;
+    if mesh.normals_enabled() {
+        writeln!(writer, "property float nx")?;
+        writeln!(writer, "property float ny")?;
+        writeln!(writer, "property float nz")?;
+    }
+    if mesh.colors_enabled() {
+        writeln!(writer, "property uchar red")?;
+        writeln!(writer, "property uchar green")?;
+        writeln!(writer, "property uchar blue")?;
+        writeln!(writer, "property uchar alpha")?;
+    }
+    if mesh.uv1_enabled() {
+        writeln!(writer, "property float s")?;
+        writeln!(writer, "property float t")?;
+    }
+    writeln!(writer, "element face {}", triangles.len())?;
+    writeln!(writer, "property list uchar int vertex_indices")?;
+    writeln!(writer, "end_header")?;
+
+    for v in &mesh.vertices {
+        let p = transform.position(v.position);
+        let mut floats = vec![p.x, p.y, p.z];
+        if mesh.normals_enabled() {
+            let n = transform.normal(v.normal);
+            floats.extend_from_slice(&[n.x, n.y, n.z]);
+        }
+
+        let color = v.color_rgba8();
+
+        if ascii {
+            let mut fields: Vec<String> = floats.iter().map(|f| f.to_string()).collect();
+            if mesh.colors_enabled() {
+                fields.extend(color.iter().map(|c| c.to_string()));
+            }
+            if mesh.uv1_enabled() {
+                fields.push(v.uv1.x.to_string());
+                fields.push((1.0 - v.uv1.y).to_string());
+            }
+            writeln!(writer, "{}", fields.join(" "))?;
+        } else {
+            for f in floats {
+                writer.write_f32::<LittleEndian>(f)?;
+            }
+            if mesh.colors_enabled() {
+                writer.write_all(&color)?;
+            }
+            if mesh.uv1_enabled() {
+                writer.write_f32::<LittleEndian>(v.uv1.x)?;
+                writer.write_f32::<LittleEndian>(1.0 - v.uv1.y)?;
+            }
+        }
+    }
+
+    for t in triangles {
+        if ascii {
+            writeln!(writer, "3 {} {} {}", t[0], t[1], t[2])?;
+        } else {
+            writer.write_u8(3)?;
+            for &i in t.iter() {
+                writer.write_i32::<LittleEndian>(i as i32)?;
+            }
+        }
+    }
+
+    Ok(())
+}
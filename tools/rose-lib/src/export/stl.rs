@@ -0,0 +1,53 @@
+use std::io::Write;
+
+use byteorder::{LittleEndian, WriteBytesExt};
+use failure::Error;
+use files::ZMS;
+use utils::{Transform, Vector3};
+
+/// Write a mesh as a binary STL
+///
+/// STL only stores positions, facet normals are computed from the triangle
+/// positions after converting them with `transform`.
+pub fn write_stl<W: Write>(mesh: &ZMS, writer: &mut W, transform: &Transform) -> Result<(), Error> {
+    let triangles: Vec<[usize; 3]> = super::triangles(mesh).collect();
+
+    let mut header = super::header_comment().into_bytes();
+    header.resize(80, 0);
+    writer.write_all(&header)?;
+    writer.write_u32::<LittleEndian>(triangles.len() as u32)?;
+
+    for t in triangles {
+        let a = transform.position(mesh.vertices[t[0]].position);
+        let b = transform.position(mesh.vertices[t[1]].position);
+        let c = transform.position(mesh.vertices[t[2]].position);
+
+        for f in facet_normal(&a, &b, &c).iter() {
+            writer.write_f32::<LittleEndian>(*f)?;
+        }
+        for p in [a, b, c].iter() {
+            writer.write_f32::<LittleEndian>(p.x)?;
+            writer.write_f32::<LittleEndian>(p.y)?;
+            writer.write_f32::<LittleEndian>(p.z)?;
+        }
+        writer.write_u16::<LittleEndian>(0)?;
+    }
+
+    Ok(())
+}
+
+fn facet_normal(a: &Vector3<f32>, b: &Vector3<f32>, c: &Vector3<f32>) -> [f32; 3] {
+    let u = [b.x - a.x, b.y - a.y, b.z - a.z];
+    let v = [c.x - a.x, c.y - a.y, c.z - a.z];
+    let n = [
+        u[1] * v[2] - u[2] * v[1],
+        u[2] * v[0] - u[0] * v[2],
+        u[0] * v[1] - u[1] * v[0],
+    ];
+
+    let length = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+    if length == 0.0 {
+        return [0.0, 0.0, 0.0];
+    }
+    [n[0] / length, n[1] / length, n[2] / length]
+}
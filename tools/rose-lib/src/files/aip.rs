@@ -0,0 +1,298 @@
+//! ROSE Online AI Patterns
+//!
+//! NPCs and monsters are driven by AI patterns. A pattern file holds a fixed
+//! set of triggers (e.g. on idle, on damaged, on death) and each trigger
+//! holds a list of events. An event is a list of conditions that must all
+//! pass followed by a list of actions to execute.
+//!
+//! Conditions and actions are stored as opcode records:
+//!
+//! | Field   | Type  | Notes                                   |
+//! |---------|-------|-----------------------------------------|
+//! | size    | `u32` | Size of the whole record in bytes       |
+//! | opcode  | `u32` | `0x04000000` (condition) or `0x0B000000` (action) + id |
+//! | payload | bytes | `size - 8` bytes of opcode specific data |
+//!
+//! Only a handful of common opcodes are decoded, everything else is kept as
+//! raw bytes so files round-trip without losing data.
+use byteorder::{ByteOrder, LittleEndian};
+use errors::RoseError;
+use failure::Error;
+use io::{RoseFile, ReadRoseExt, WriteRoseExt};
+
+/// AI Pattern File
+pub type AIP = AiPattern;
+
+const NAME_SIZE: u64 = 32;
+const RECORD_HEADER_SIZE: u32 = 8;
+
+const CONDITION_OPCODE: u32 = 0x0400_0000;
+const ACTION_OPCODE: u32 = 0x0B00_0000;
+
+/// AI Pattern
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct AiPattern {
+    pub idle_interval: i32,
+    pub damage_rate: i32,
+    pub name: String,
+    pub triggers: Vec<AiTrigger>,
+}
+
+/// AI Pattern trigger
+///
+/// A named list of events evaluated when the trigger fires
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct AiTrigger {
+    pub name: String,
+    pub events: Vec<AiEvent>,
+}
+
+/// AI Pattern event
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct AiEvent {
+    pub name: String,
+    pub conditions: Vec<AiCondition>,
+    pub actions: Vec<AiAction>,
+}
+
+/// AI Pattern condition
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub enum AiCondition {
+    /// Always passes
+    FightOrDelay,
+    /// Damage dealt or received crosses a threshold
+    Damage { damage: i32, receive: u8 },
+    /// Undecoded condition
+    Unknown { opcode: u32, data: Vec<u8> },
+}
+
+/// AI Pattern action
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub enum AiAction {
+    /// Stop all movement
+    Stop,
+    /// Say a message from the AI string table
+    Say { string_id: i32 },
+    /// Undecoded action
+    Unknown { opcode: u32, data: Vec<u8> },
+}
+
+impl AiPattern {
+    /// Iterate over the events of every trigger
+    pub fn events(&self) -> impl Iterator<Item = &AiEvent> {
+        self.triggers.iter().flat_map(|t| t.events.iter())
+    }
+}
+
+impl AiTrigger {
+    fn new() -> AiTrigger {
+        AiTrigger {
+            name: String::new(),
+            events: Vec::new(),
+        }
+    }
+}
+
+impl AiEvent {
+    fn new() -> AiEvent {
+        AiEvent {
+            name: String::new(),
+            conditions: Vec::new(),
+            actions: Vec::new(),
+        }
+    }
+}
+
+impl AiCondition {
+    fn decode(opcode: u32, data: Vec<u8>) -> AiCondition {
+        let decoded = match (opcode, data.len()) {
+            (0x0400_0001, 0) => AiCondition::FightOrDelay,
+            (0x0400_0002, 8) => AiCondition::Damage {
+                damage: LittleEndian::read_i32(&data[0..4]),
+                receive: data[4],
+            },
+            _ => return AiCondition::Unknown { opcode, data },
+        };
+
+        // Only keep the decoded form if it serializes back to the same bytes
+        if decoded.encode().1 == data {
+            decoded
+        } else {
+            AiCondition::Unknown { opcode, data }
+        }
+    }
+
+    fn encode(&self) -> (u32, Vec<u8>) {
+        match *self {
+            AiCondition::FightOrDelay => (CONDITION_OPCODE | 1, Vec::new()),
+            AiCondition::Damage { damage, receive } => {
+                let mut data = le_bytes(damage);
+                data.extend_from_slice(&[receive, 0, 0, 0]);
+                (CONDITION_OPCODE | 2, data)
+            }
+            AiCondition::Unknown { opcode, ref data } => (opcode, data.clone()),
+        }
+    }
+}
+
+impl AiAction {
+    fn decode(opcode: u32, data: Vec<u8>) -> AiAction {
+        let decoded = match (opcode, data.len()) {
+            (0x0B00_0001, 0) => AiAction::Stop,
+            (0x0B00_0003, 4) => AiAction::Say {
+                string_id: LittleEndian::read_i32(&data[0..4]),
+            },
+            _ => return AiAction::Unknown { opcode, data },
+        };
+
+        if decoded.encode().1 == data {
+            decoded
+        } else {
+            AiAction::Unknown { opcode, data }
+        }
+    }
+
+    fn encode(&self) -> (u32, Vec<u8>) {
+        match *self {
+            AiAction::Stop => (ACTION_OPCODE | 1, Vec::new()),
+            AiAction::Say { string_id } => (ACTION_OPCODE | 3, le_bytes(string_id)),
+            AiAction::Unknown { opcode, ref data } => (opcode, data.clone()),
+        }
+    }
+}
+
+impl RoseFile for AiPattern {
+    fn new() -> AiPattern {
+        AiPattern {
+            idle_interval: 0,
+            damage_rate: 0,
+            name: String::new(),
+            triggers: Vec::new(),
+        }
+    }
+
+    fn read<R: ReadRoseExt>(&mut self, reader: &mut R) -> Result<(), Error> {
+        let trigger_count = reader.read_i32()?;
+        self.idle_interval = reader.read_i32()?;
+        self.damage_rate = reader.read_i32()?;
+        self.name = reader.read_string_u32()?;
+        let trigger_count = reader.check_count("AIP", trigger_count as i64, NAME_SIZE + 4)?;
+
+        for _ in 0..trigger_count {
+            let mut trigger = AiTrigger::new();
+            trigger.name = reader.read_string(NAME_SIZE)?;
+
+            let event_count = reader.read_i32()?;
+            let event_count = reader.check_count("AIP", event_count as i64, NAME_SIZE + 8)?;
+            for _ in 0..event_count {
+                let mut event = AiEvent::new();
+                event.name = reader.read_string(NAME_SIZE)?;
+
+                let condition_count = reader.read_i32()?;
+                let condition_count = reader.check_count("AIP",
+                                                         condition_count as i64,
+                                                         RECORD_HEADER_SIZE as u64)?;
+                for _ in 0..condition_count {
+                    let (opcode, data) = read_record(reader)?;
+                    event.conditions.push(AiCondition::decode(opcode, data));
+                }
+
+                let action_count = reader.read_i32()?;
+                let action_count = reader.check_count("AIP",
+                                                      action_count as i64,
+                                                      RECORD_HEADER_SIZE as u64)?;
+                for _ in 0..action_count {
+                    let (opcode, data) = read_record(reader)?;
+                    event.actions.push(AiAction::decode(opcode, data));
+                }
+
+                trigger.events.push(event);
+            }
+
+            self.triggers.push(trigger);
+        }
+
+        Ok(())
+    }
+
+    fn write<W: WriteRoseExt>(&mut self, writer: &mut W) -> Result<(), Error> {
+        writer.write_i32(self.triggers.len() as i32)?;
+        writer.write_i32(self.idle_interval)?;
+        writer.write_i32(self.damage_rate)?;
+        writer.write_string_u32(&self.name)?;
+
+        for trigger in &self.triggers {
+            writer.write_string_fixed(&trigger.name, NAME_SIZE as usize)?;
+            writer.write_i32(trigger.events.len() as i32)?;
+
+            for event in &trigger.events {
+                writer.write_string_fixed(&event.name, NAME_SIZE as usize)?;
+
+                writer.write_i32(event.conditions.len() as i32)?;
+                for condition in &event.conditions {
+                    let (opcode, data) = condition.encode();
+                    write_record(writer, opcode, &data)?;
+                }
+
+                writer.write_i32(event.actions.len() as i32)?;
+                for action in &event.actions {
+                    let (opcode, data) = action.encode();
+                    write_record(writer, opcode, &data)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn size_hint(&self) -> usize {
+        let mut size = 16 + self.name.len();
+
+        for trigger in &self.triggers {
+            size += NAME_SIZE as usize + 4;
+
+            for event in &trigger.events {
+                size += NAME_SIZE as usize + 8;
+                for condition in &event.conditions {
+                    size += RECORD_HEADER_SIZE as usize + condition.encode().1.len();
+                }
+                for action in &event.actions {
+                    size += RECORD_HEADER_SIZE as usize + action.encode().1.len();
+                }
+            }
+        }
+
+        size
+    }
+}
+
+fn read_record<R: ReadRoseExt>(reader: &mut R) -> Result<(u32, Vec<u8>), Error> {
+    let start = reader.stream_position()?;
+    let size = reader.read_u32()?;
+    let opcode = reader.read_u32()?;
+
+    if size < RECORD_HEADER_SIZE {
+        return Err(RoseError::InvalidData {
+            format: "AIP",
+            reason: format!("Invalid record size {} at offset {}", size, start),
+        }.into());
+    }
+
+    let len = reader.check_count("AIP", (size - RECORD_HEADER_SIZE) as i64, 1)?;
+    let mut data = vec![0u8; len];
+    reader.read_exact(&mut data)?;
+    Ok((opcode, data))
+}
+
+fn write_record<W: WriteRoseExt>(writer: &mut W, opcode: u32, data: &[u8]) -> Result<(), Error> {
+    writer.write_u32(data.len() as u32 + RECORD_HEADER_SIZE)?;
+    writer.write_u32(opcode)?;
+    writer.write_all(data)?;
+    Ok(())
+}
+
+fn le_bytes(n: i32) -> Vec<u8> {
+    let mut bytes = vec![0u8; 4];
+    LittleEndian::write_i32(&mut bytes, n);
+    bytes
+}
@@ -0,0 +1,223 @@
+//! DirectDraw Surface Textures
+//!
+//! Textures referenced by lightmaps and models are stored as `.DDS` files.
+//! Only the header is read, which is enough to learn the dimensions and the
+//! pixel format of a texture without decoding it.
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use errors::RoseError;
+use failure::Error;
+use io::{RoseFile, ReadRoseExt, WriteRoseExt};
+
+/// DDS File
+pub type DDS = DdsInfo;
+
+const HEADER_SIZE: u32 = 124;
+const PIXEL_FORMAT_SIZE: u32 = 32;
+
+// Header flags
+const DDSD_CAPS: u32 = 0x1;
+const DDSD_HEIGHT: u32 = 0x2;
+const DDSD_WIDTH: u32 = 0x4;
+const DDSD_PIXELFORMAT: u32 = 0x1000;
+const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+
+// Pixel format flags
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+
+const DDSCAPS_TEXTURE: u32 = 0x1000;
+
+// DXGI formats of the block compressed textures
+const DXGI_FORMAT_BC1_UNORM: u32 = 71;
+const DXGI_FORMAT_BC1_UNORM_SRGB: u32 = 72;
+const DXGI_FORMAT_BC2_UNORM: u32 = 74;
+const DXGI_FORMAT_BC2_UNORM_SRGB: u32 = 75;
+const DXGI_FORMAT_BC3_UNORM: u32 = 77;
+const DXGI_FORMAT_BC3_UNORM_SRGB: u32 = 78;
+
+/// DDS pixel format
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum DdsFormat {
+    DXT1,
+    DXT3,
+    DXT5,
+    /// Other DXGI format from a `DX10` extended header
+    Dxgi(u32),
+    /// Other FourCC code
+    FourCC(String),
+    /// Uncompressed pixels with the given number of bits per pixel
+    Uncompressed(u32),
+}
+
+/// DDS header information
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct DdsInfo {
+    pub width: u32,
+    pub height: u32,
+    pub mipmap_count: u32,
+    pub format: DdsFormat,
+}
+
+/// Read the header information of the DDS file at `path`
+///
+/// # Example
+/// ```rust,no_run
+/// use std::path::Path;
+/// use roselib::files::dds;
+///
+/// let info = dds::info(Path::new("Object_256_1.dds")).unwrap();
+/// println!("{}x{} {:?}", info.width, info.height, info.format);
+/// ```
+pub fn info(path: &Path) -> Result<DdsInfo, Error> {
+    let mut reader = BufReader::new(File::open(path)?);
+    DdsInfo::from_reader(&mut reader)
+}
+
+impl RoseFile for DdsInfo {
+    fn new() -> DdsInfo {
+        DdsInfo {
+            width: 0,
+            height: 0,
+            mipmap_count: 1,
+            format: DdsFormat::DXT1,
+        }
+    }
+
+    fn read<R: ReadRoseExt>(&mut self, reader: &mut R) -> Result<(), Error> {
+        let magic = reader.read_string(4)?;
+        if magic != "DDS " {
+            return Err(RoseError::InvalidMagic { format: "DDS", found: magic }.into());
+        }
+
+        let header_size = reader.read_u32()?;
+        if header_size != HEADER_SIZE {
+            return Err(RoseError::InvalidData {
+                format: "DDS",
+                reason: format!("Header size {}, expected {}", header_size, HEADER_SIZE),
+            }.into());
+        }
+
+        let flags = reader.read_u32()?;
+        self.height = reader.read_u32()?;
+        self.width = reader.read_u32()?;
+        let _pitch_or_linear_size = reader.read_u32()?;
+        let _depth = reader.read_u32()?;
+        let mipmap_count = reader.read_u32()?;
+        self.mipmap_count = if flags & DDSD_MIPMAPCOUNT != 0 {
+            mipmap_count.max(1)
+        } else {
+            1
+        };
+
+        for _ in 0..11 {
+            reader.read_u32()?;
+        }
+
+        let _pixel_format_size = reader.read_u32()?;
+        let pixel_format_flags = reader.read_u32()?;
+        let mut four_cc = [0u8; 4];
+        reader.read_exact(&mut four_cc)?;
+        let bit_count = reader.read_u32()?;
+        for _ in 0..4 {
+            reader.read_u32()?; // Channel masks
+        }
+
+        for _ in 0..5 {
+            reader.read_u32()?; // Caps and reserved
+        }
+
+        self.format = if pixel_format_flags & DDPF_FOURCC != 0 {
+            match &four_cc {
+                b"DXT1" => DdsFormat::DXT1,
+                b"DXT3" => DdsFormat::DXT3,
+                b"DXT5" => DdsFormat::DXT5,
+                b"DX10" => {
+                    let dxgi_format = reader.read_u32()?;
+                    for _ in 0..4 {
+                        reader.read_u32()?;
+                    }
+
+                    match dxgi_format {
+                        DXGI_FORMAT_BC1_UNORM | DXGI_FORMAT_BC1_UNORM_SRGB => DdsFormat::DXT1,
+                        DXGI_FORMAT_BC2_UNORM | DXGI_FORMAT_BC2_UNORM_SRGB => DdsFormat::DXT3,
+                        DXGI_FORMAT_BC3_UNORM | DXGI_FORMAT_BC3_UNORM_SRGB => DdsFormat::DXT5,
+                        f => DdsFormat::Dxgi(f),
+                    }
+                }
+                code => DdsFormat::FourCC(String::from_utf8_lossy(code).into_owned()),
+            }
+        } else {
+            DdsFormat::Uncompressed(bit_count)
+        };
+
+        Ok(())
+    }
+
+    /// Header information is read-only, a header alone is not a valid
+    /// texture. See `write_header` to write the header of a new texture.
+    fn write<W: WriteRoseExt>(&mut self, _writer: &mut W) -> Result<(), Error> {
+        bail!("DDS header information can not be written as a texture, see DdsInfo::write_header")
+    }
+}
+
+impl DdsInfo {
+    /// Write the DDS header of a texture with this information
+    ///
+    /// Only the header is written, the pixel data must follow it for the
+    /// file to be a valid texture.
+    pub fn write_header<W: WriteRoseExt>(&self, writer: &mut W) -> Result<(), Error> {
+        let (pixel_format_flags, four_cc, bit_count, dxgi_format) = match self.format {
+            DdsFormat::DXT1 => (DDPF_FOURCC, *b"DXT1", 0, None),
+            DdsFormat::DXT3 => (DDPF_FOURCC, *b"DXT3", 0, None),
+            DdsFormat::DXT5 => (DDPF_FOURCC, *b"DXT5", 0, None),
+            DdsFormat::Dxgi(f) => (DDPF_FOURCC, *b"DX10", 0, Some(f)),
+            DdsFormat::FourCC(ref code) => {
+                if code.len() != 4 {
+                    bail!("Invalid DDS FourCC code: {}", code);
+                }
+                let mut four_cc = [0u8; 4];
+                four_cc.copy_from_slice(code.as_bytes());
+                (DDPF_FOURCC, four_cc, 0, None)
+            }
+            DdsFormat::Uncompressed(bits) => (DDPF_RGB, [0; 4], bits, None),
+        };
+
+        writer.write_all(b"DDS ")?;
+        writer.write_u32(HEADER_SIZE)?;
+        writer.write_u32(DDSD_CAPS | DDSD_HEIGHT | DDSD_WIDTH | DDSD_PIXELFORMAT | DDSD_MIPMAPCOUNT)?;
+        writer.write_u32(self.height)?;
+        writer.write_u32(self.width)?;
+        writer.write_u32(0)?; // Pitch or linear size
+        writer.write_u32(0)?; // Depth
+        writer.write_u32(self.mipmap_count)?;
+        for _ in 0..11 {
+            writer.write_u32(0)?;
+        }
+
+        writer.write_u32(PIXEL_FORMAT_SIZE)?;
+        writer.write_u32(pixel_format_flags)?;
+        writer.write_all(&four_cc)?;
+        writer.write_u32(bit_count)?;
+        for _ in 0..4 {
+            writer.write_u32(0)?;
+        }
+
+        writer.write_u32(DDSCAPS_TEXTURE)?;
+        for _ in 0..4 {
+            writer.write_u32(0)?;
+        }
+
+        if let Some(dxgi_format) = dxgi_format {
+            writer.write_u32(dxgi_format)?;
+            writer.write_u32(3)?; // Texture 2D
+            writer.write_u32(0)?;
+            writer.write_u32(1)?; // Array size
+            writer.write_u32(0)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,190 @@
+//! File type detection
+//!
+//! Files extracted from a virtual file system can be identified by their
+//! leading bytes for formats that start with a magic identifier, or by their
+//! extension for the others.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! use std::path::Path;
+//! use roselib::files::filetype::RoseFileData;
+//!
+//! let data = RoseFileData::from_path(Path::new("/path/to/unknown.zms")).unwrap();
+//! println!("{:?}", data.file_type());
+//! ```
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use failure::Error;
+use files::{AIP, DDS, HIM, IDX, IFO, LIT, STB, STL, TIL, TSI, ZMD, ZMO, ZMS, ZON, ZSC};
+use io::{RoseFile, ReadRoseExt};
+
+/// Supported file types
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
+pub enum RoseFileType {
+    AIP,
+    DDS,
+    HIM,
+    IDX,
+    IFO,
+    LIT,
+    STB,
+    STL,
+    TIL,
+    TSI,
+    ZMD,
+    ZMO,
+    ZMS,
+    ZON,
+    ZSC,
+}
+
+/// A file loaded as its concrete type
+#[derive(Debug, Serialize)]
+pub enum RoseFileData {
+    AIP(AIP),
+    DDS(DDS),
+    HIM(HIM),
+    IDX(IDX),
+    IFO(IFO),
+    LIT(LIT),
+    STB(STB),
+    STL(STL),
+    TIL(TIL),
+    TSI(TSI),
+    ZMD(ZMD),
+    ZMO(ZMO),
+    ZMS(ZMS),
+    ZON(ZON),
+    ZSC(ZSC),
+}
+
+/// Number of leading bytes needed by `detect_type`
+pub const DETECT_SIZE: usize = 8;
+
+/// Detect the type of a file from its leading bytes
+///
+/// Only formats with a magic identifier can be detected, use
+/// `RoseFileType::from_extension` for the others.
+pub fn detect_type(bytes: &[u8]) -> Option<RoseFileType> {
+    if bytes.starts_with(b"ZMS000") {
+        return Some(RoseFileType::ZMS);
+    }
+    if bytes.starts_with(b"ZMD0") {
+        return Some(RoseFileType::ZMD);
+    }
+    if bytes.starts_with(b"ZMO0") {
+        return Some(RoseFileType::ZMO);
+    }
+    if bytes.starts_with(b"STB1") {
+        return Some(RoseFileType::STB);
+    }
+    if bytes.starts_with(b"DDS ") {
+        return Some(RoseFileType::DDS);
+    }
+
+    // STL identifiers are length prefixed strings
+    if bytes.len() >= 7 && bytes[0] == 6 {
+        match &bytes[1..7] {
+            b"NRST01" | b"ITST01" | b"QEST01" => return Some(RoseFileType::STL),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+impl RoseFileType {
+    /// Get the type of a file from its extension, ignoring case
+    pub fn from_extension(extension: &str) -> Option<RoseFileType> {
+        match extension.to_uppercase().as_str() {
+            "AIP" => Some(RoseFileType::AIP),
+            "DDS" => Some(RoseFileType::DDS),
+            "HIM" => Some(RoseFileType::HIM),
+            "IDX" => Some(RoseFileType::IDX),
+            "IFO" => Some(RoseFileType::IFO),
+            "LIT" => Some(RoseFileType::LIT),
+            "STB" => Some(RoseFileType::STB),
+            "STL" => Some(RoseFileType::STL),
+            "TIL" => Some(RoseFileType::TIL),
+            "TSI" => Some(RoseFileType::TSI),
+            "ZMD" => Some(RoseFileType::ZMD),
+            "ZMO" => Some(RoseFileType::ZMO),
+            "ZMS" => Some(RoseFileType::ZMS),
+            "ZON" => Some(RoseFileType::ZON),
+            "ZSC" => Some(RoseFileType::ZSC),
+            _ => None,
+        }
+    }
+
+    /// Get the type of a file from its leading bytes, falling back to the
+    /// extension of `path`
+    pub fn detect(bytes: &[u8], path: &Path) -> Option<RoseFileType> {
+        detect_type(bytes).or_else(|| {
+            path.extension()
+                .and_then(|e| e.to_str())
+                .and_then(RoseFileType::from_extension)
+        })
+    }
+
+    /// Load a file of this type from a reader
+    pub fn load<R: ReadRoseExt>(self, reader: &mut R) -> Result<RoseFileData, Error> {
+        Ok(match self {
+            RoseFileType::AIP => RoseFileData::AIP(AIP::from_reader(reader)?),
+            RoseFileType::DDS => RoseFileData::DDS(DDS::from_reader(reader)?),
+            RoseFileType::HIM => RoseFileData::HIM(HIM::from_reader(reader)?),
+            RoseFileType::IDX => RoseFileData::IDX(IDX::from_reader(reader)?),
+            RoseFileType::IFO => RoseFileData::IFO(IFO::from_reader(reader)?),
+            RoseFileType::LIT => RoseFileData::LIT(LIT::from_reader(reader)?),
+            RoseFileType::STB => RoseFileData::STB(STB::from_reader(reader)?),
+            RoseFileType::STL => RoseFileData::STL(STL::from_reader(reader)?),
+            RoseFileType::TIL => RoseFileData::TIL(TIL::from_reader(reader)?),
+            RoseFileType::TSI => RoseFileData::TSI(TSI::from_reader(reader)?),
+            RoseFileType::ZMD => RoseFileData::ZMD(ZMD::from_reader(reader)?),
+            RoseFileType::ZMO => RoseFileData::ZMO(ZMO::from_reader(reader)?),
+            RoseFileType::ZMS => RoseFileData::ZMS(ZMS::from_reader(reader)?),
+            RoseFileType::ZON => RoseFileData::ZON(ZON::from_reader(reader)?),
+            RoseFileType::ZSC => RoseFileData::ZSC(ZSC::from_reader(reader)?),
+        })
+    }
+}
+
+impl RoseFileData {
+    /// Load the file at `path`, detecting its type with
+    /// `RoseFileType::detect`
+    pub fn from_path(path: &Path) -> Result<RoseFileData, Error> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let mut magic = Vec::with_capacity(DETECT_SIZE);
+        (&mut reader).take(DETECT_SIZE as u64).read_to_end(&mut magic)?;
+        reader.seek(SeekFrom::Start(0))?;
+
+        match RoseFileType::detect(&magic, path) {
+            Some(file_type) => file_type.load(&mut reader),
+            None => bail!("Unknown file type: {}", path.display()),
+        }
+    }
+
+    /// Type of the loaded file
+    pub fn file_type(&self) -> RoseFileType {
+        match *self {
+            RoseFileData::AIP(_) => RoseFileType::AIP,
+            RoseFileData::DDS(_) => RoseFileType::DDS,
+            RoseFileData::HIM(_) => RoseFileType::HIM,
+            RoseFileData::IDX(_) => RoseFileType::IDX,
+            RoseFileData::IFO(_) => RoseFileType::IFO,
+            RoseFileData::LIT(_) => RoseFileType::LIT,
+            RoseFileData::STB(_) => RoseFileType::STB,
+            RoseFileData::STL(_) => RoseFileType::STL,
+            RoseFileData::TIL(_) => RoseFileType::TIL,
+            RoseFileData::TSI(_) => RoseFileType::TSI,
+            RoseFileData::ZMD(_) => RoseFileType::ZMD,
+            RoseFileData::ZMO(_) => RoseFileType::ZMO,
+            RoseFileData::ZMS(_) => RoseFileType::ZMS,
+            RoseFileData::ZON(_) => RoseFileType::ZON,
+            RoseFileData::ZSC(_) => RoseFileType::ZSC,
+        }
+    }
+}
@@ -1,14 +1,46 @@
 //! ROSE Online Heightmaps
 use std::f32;
+use std::io::SeekFrom;
+
 use failure::Error;
+use serde::{Deserialize, Deserializer};
 use io::{RoseFile, ReadRoseExt, WriteRoseExt};
+use utils::{self, ApproxEq, Vector3};
+#[cfg(feature = "image")]
+use image::GrayImage;
 
 
 /// Heightmap File
 pub type HIM = Heightmap;
 
+/// Name of the patch data block following the heights
+const PATCH_BLOCK_NAME: &str = "quad";
+
+/// Cells per side of a patch in client heightmaps
+pub const DEFAULT_GRID_COUNT: i32 = 4;
+
 /// Heightmap
-#[derive(Debug, Serialize, Deserialize)]
+///
+/// `min_height` and `max_height` are derived from `heights` and are `NaN`
+/// when there are no heights. They are serialized as `null` when `NaN` and
+/// recalculated from the heights when deserialized as `null`.
+///
+/// The heights are followed by the height range of the patches of the
+/// heightmap, used by the client for culling. The heights are split in
+/// patches of `grid_count` cells per side (16x16 patches for a 65x65
+/// heightmap), a patch covers the heights on its edges. `patches` are in row
+/// order starting from the last row of `heights` (the bottom of the map).
+///
+/// `quad_patches` is a quad tree of the patches in breadth first order: the
+/// root covering the whole heightmap, then the 4 children of each node of
+/// the previous level, down to the nodes covering 2x2 patches (85 nodes for
+/// 16x16 patches). The children of a node are listed bottom-left,
+/// bottom-right, top-right then top-left, bottom being towards the last row
+/// of `heights`.
+///
+/// The patches are not updated when the heights change, call
+/// `rebuild_patches` before writing an edited heightmap.
+#[derive(Debug, Serialize)]
 pub struct Heightmap {
     pub width: i32,
     pub height: i32,
@@ -17,8 +49,248 @@ pub struct Heightmap {
 
     pub heights: Vec<Vec<f32>>,
 
+    #[serde(with = "::utils::nan_as_null")]
     pub min_height: f32,
+    #[serde(with = "::utils::nan_as_null")]
     pub max_height: f32,
+
+    pub patches: Vec<HeightmapPatch>,
+    pub quad_patches: Vec<HeightmapPatch>,
+}
+
+/// Height range of a heightmap patch, see `Heightmap`
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub struct HeightmapPatch {
+    pub max: f32,
+    pub min: f32,
+}
+
+/// Deserialized fields of a `Heightmap` before the height range is restored
+#[derive(Deserialize)]
+struct HeightmapFields {
+    width: i32,
+    height: i32,
+    grid_count: i32,
+    scale: f32,
+    heights: Vec<Vec<f32>>,
+    #[serde(with = "::utils::nan_as_null")]
+    min_height: f32,
+    #[serde(with = "::utils::nan_as_null")]
+    max_height: f32,
+    #[serde(default)]
+    patches: Vec<HeightmapPatch>,
+    #[serde(default)]
+    quad_patches: Vec<HeightmapPatch>,
+}
+
+impl<'de> Deserialize<'de> for Heightmap {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Heightmap, D::Error> {
+        let fields = HeightmapFields::deserialize(deserializer)?;
+        let mut him = Heightmap {
+            width: fields.width,
+            height: fields.height,
+            grid_count: fields.grid_count,
+            scale: fields.scale,
+            heights: fields.heights,
+            min_height: fields.min_height,
+            max_height: fields.max_height,
+            patches: fields.patches,
+            quad_patches: fields.quad_patches,
+        };
+
+        if him.min_height.is_nan() || him.max_height.is_nan() {
+            him.recalculate_height_range();
+        }
+        Ok(him)
+    }
+}
+
+impl Heightmap {
+    /// Build a heightmap from rows of heights, `scale` being the distance
+    /// between two grid points
+    ///
+    /// `grid_count` is set to `DEFAULT_GRID_COUNT`, the height range and
+    /// the patches are calculated from the heights. Fails if the rows don't
+    /// all have the same length.
+    pub fn from_heights(heights: Vec<Vec<f32>>, scale: f32) -> Result<Heightmap, Error> {
+        let width = heights.first().map_or(0, |row| row.len());
+        if let Some(row) = heights.iter().position(|row| row.len() != width) {
+            bail!("Heightmap row {} has {} heights, expected {}",
+                  row,
+                  heights[row].len(),
+                  width);
+        }
+
+        let mut him = Heightmap::new();
+        him.width = width as i32;
+        him.height = heights.len() as i32;
+        him.grid_count = DEFAULT_GRID_COUNT;
+        him.scale = scale;
+        him.heights = heights;
+        him.recalculate_height_range();
+        him.rebuild_patches();
+        Ok(him)
+    }
+
+    /// Set `min_height` and `max_height` to the range of the heights, `NaN`
+    /// if there are no heights
+    pub fn recalculate_height_range(&mut self) {
+        let heights = self.heights.iter().flat_map(|row| row.iter().cloned());
+        let (min, max) = utils::min_max(heights).unwrap_or((f32::NAN, f32::NAN));
+        self.min_height = min;
+        self.max_height = max;
+    }
+
+    /// Recalculate `patches` and `quad_patches` from the heights
+    ///
+    /// The patches are cleared if `grid_count` doesn't split the heights in
+    /// whole patches. The quad tree is built for a square power of two
+    /// number of patches, it is cleared otherwise.
+    pub fn rebuild_patches(&mut self) {
+        self.patches.clear();
+        self.quad_patches.clear();
+
+        let (columns, rows) = match self.patch_counts() {
+            Some(counts) => counts,
+            None => return,
+        };
+
+        // Patches are stored from the bottom row up
+        for row in (0..rows).rev() {
+            for column in 0..columns {
+                self.patches.push(self.patch_range(column, row, 1));
+            }
+        }
+
+        if columns != rows || !columns.is_power_of_two() || columns < 2 {
+            return;
+        }
+
+        // Nodes as the bottom-left patch (counted from the bottom) and size
+        let mut level = vec![(0, 0, columns)];
+        while level[0].2 >= 2 {
+            let mut next = Vec::with_capacity(level.len() * 4);
+            for &(x, y, size) in &level {
+                self.quad_patches.push(self.patch_range(x, rows - y - size, size));
+
+                let half = size / 2;
+                next.push((x, y, half));
+                next.push((x + half, y, half));
+                next.push((x + half, y + half, half));
+                next.push((x, y + half, half));
+            }
+            level = next;
+        }
+    }
+
+    /// Number of patch columns and rows, `None` if `grid_count` doesn't
+    /// split the heights in whole patches
+    fn patch_counts(&self) -> Option<(usize, usize)> {
+        let grid = self.grid_count;
+        if grid <= 0 || self.width < 2 || self.height < 2
+            || (self.width - 1) % grid != 0 || (self.height - 1) % grid != 0
+            || self.heights.len() != self.height as usize
+            || self.heights.iter().any(|row| row.len() != self.width as usize) {
+            return None;
+        }
+        Some((((self.width - 1) / grid) as usize, ((self.height - 1) / grid) as usize))
+    }
+
+    /// Height range of `size` x `size` patches, `row` counted from the top
+    fn patch_range(&self, column: usize, row: usize, size: usize) -> HeightmapPatch {
+        let grid = self.grid_count as usize;
+        let heights = self.heights[row * grid..=(row + size) * grid]
+            .iter()
+            .flat_map(|heights| heights[column * grid..=(column + size) * grid].iter().cloned());
+        let (min, max) = utils::min_max(heights).unwrap_or((f32::NAN, f32::NAN));
+        HeightmapPatch { max, min }
+    }
+
+    /// Height at a position in grid units, interpolated bilinearly
+    ///
+    /// `x` is the column and `y` the row of the heights grid, positions
+    /// outside the grid are clamped to its edges. Returns `0.0` for an empty
+    /// heightmap.
+    pub fn height_at(&self, x: f32, y: f32) -> f32 {
+        if self.width <= 0 || self.height <= 0 || self.heights.is_empty() {
+            return 0.0;
+        }
+
+        let x = x.max(0.0).min((self.width - 1) as f32);
+        let y = y.max(0.0).min((self.height - 1) as f32);
+
+        let x0 = x.floor() as usize;
+        let y0 = y.floor() as usize;
+        let x1 = (x0 + 1).min(self.width as usize - 1);
+        let y1 = (y0 + 1).min(self.height as usize - 1);
+        let tx = x - x0 as f32;
+        let ty = y - y0 as f32;
+
+        let top = self.heights[y0][x0] * (1.0 - tx) + self.heights[y0][x1] * tx;
+        let bottom = self.heights[y1][x0] * (1.0 - tx) + self.heights[y1][x1] * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+
+    /// Surface normal at a position in grid units
+    ///
+    /// Computed from the slope between the neighbouring heights, `scale`
+    /// being the distance between two grid points. The normal is in ROSE
+    /// space (Z up) and has a unit length.
+    pub fn normal_at(&self, x: f32, y: f32) -> Vector3<f32> {
+        let scale = if self.scale > 0.0 { self.scale } else { 1.0 };
+
+        let dx = (self.height_at(x + 1.0, y) - self.height_at(x - 1.0, y)) / (2.0 * scale);
+        let dy = (self.height_at(x, y + 1.0) - self.height_at(x, y - 1.0)) / (2.0 * scale);
+
+        let length = (dx * dx + dy * dy + 1.0).sqrt();
+        Vector3 {
+            x: -dx / length,
+            y: -dy / length,
+            z: 1.0 / length,
+        }
+    }
+}
+
+#[cfg(feature = "image")]
+impl Heightmap {
+    /// Render the heights as a greyscale image, from black at `min_height`
+    /// to white at `max_height`
+    pub fn to_gray_image(&self) -> GrayImage {
+        utils::heights_to_gray_image(&self.heights, self.min_height, self.max_height)
+    }
+
+    /// Build a heightmap from a greyscale image, mapping black to
+    /// `min_height` and white to `max_height`
+    ///
+    /// `grid_count` and `scale` are left at zero.
+    pub fn from_gray_image(image: &GrayImage, min_height: f32, max_height: f32) -> Heightmap {
+        let mut him = Heightmap::new();
+        him.width = image.width() as i32;
+        him.height = image.height() as i32;
+        him.heights = utils::gray_image_to_heights(image, min_height, max_height);
+        him.recalculate_height_range();
+        him
+    }
+}
+
+impl ApproxEq for Heightmap {
+    fn approx_eq(&self, other: &Heightmap, epsilon: f32) -> bool {
+        self.width == other.width &&
+        self.height == other.height &&
+        self.grid_count == other.grid_count &&
+        self.scale.approx_eq(&other.scale, epsilon) &&
+        self.heights.approx_eq(&other.heights, epsilon) &&
+        self.min_height.approx_eq(&other.min_height, epsilon) &&
+        self.max_height.approx_eq(&other.max_height, epsilon) &&
+        self.patches.approx_eq(&other.patches, epsilon) &&
+        self.quad_patches.approx_eq(&other.quad_patches, epsilon)
+    }
+}
+
+impl ApproxEq for HeightmapPatch {
+    fn approx_eq(&self, other: &HeightmapPatch, epsilon: f32) -> bool {
+        self.max.approx_eq(&other.max, epsilon) && self.min.approx_eq(&other.min, epsilon)
+    }
 }
 
 impl RoseFile for Heightmap {
@@ -32,6 +304,9 @@ impl RoseFile for Heightmap {
 
             min_height: f32::NAN,
             max_height: f32::NAN,
+
+            patches: Vec::new(),
+            quad_patches: Vec::new(),
         }
     }
 
@@ -41,30 +316,73 @@ impl RoseFile for Heightmap {
         self.grid_count = reader.read_i32()?;
         self.scale = reader.read_f32()?;
 
-        self.heights = vec![vec![0.0; self.width as usize]; self.height as usize];
-        for h in 0..self.height {
-            for w in 0..self.width {
-                let height = reader.read_f32()?;
+        let width = reader.check_count("HIM", self.width as i64, 4)?;
+        let height = reader.check_count("HIM", self.height as i64, width as u64 * 4)?;
+        self.heights = vec![vec![0.0; width]; height];
+        for row in &mut self.heights {
+            for h in row.iter_mut() {
+                *h = reader.read_f32()?;
+            }
+        }
+        self.recalculate_height_range();
 
-                self.heights[h as usize][w as usize] = height;
+        // Heightmaps without patch data end after the heights
+        if reader.bytes_remaining()? > 0 {
+            let start = reader.stream_position()?;
+            if reader.read_string_u8()? == PATCH_BLOCK_NAME {
+                self.patches = read_patches(reader)?;
+                self.quad_patches = read_patches(reader)?;
+            } else {
+                reader.seek(SeekFrom::Start(start))?;
+            }
+        }
 
-                if self.min_height.is_nan() || height < self.min_height {
-                    self.min_height = height;
-                }
+        if log_enabled!(::log::Level::Warn) {
+            let remaining = reader.bytes_remaining()?;
+            if remaining > 0 {
+                warn!("HIM: {} trailing bytes not parsed", remaining);
+            }
+        }
 
-                if self.max_height.is_nan() || height > self.max_height {
-                    self.max_height = height;
-                }
+        Ok(())
+    }
+
+    fn write<W: WriteRoseExt>(&mut self, writer: &mut W) -> Result<(), Error> {
+        writer.write_i32(self.width)?;
+        writer.write_i32(self.height)?;
+        writer.write_i32(self.grid_count)?;
+        writer.write_f32(self.scale)?;
+
+        for row in &self.heights {
+            for &height in row {
+                writer.write_f32(height)?;
             }
         }
 
-        // TODO: File contains more data
+        writer.write_string_u8(PATCH_BLOCK_NAME)?;
+        for patches in &[&self.patches, &self.quad_patches] {
+            writer.write_i32(patches.len() as i32)?;
+            for patch in patches.iter() {
+                writer.write_f32(patch.max)?;
+                writer.write_f32(patch.min)?;
+            }
+        }
 
         Ok(())
     }
 
-    fn write<W: WriteRoseExt>(&mut self, writer: &mut W) -> Result<(), Error> {
-        // TODO: Implement writer
-        unimplemented!();
+    fn size_hint(&self) -> usize {
+        16 + self.heights.iter().map(|row| row.len() * 4).sum::<usize>()
+            + 1 + PATCH_BLOCK_NAME.len()
+            + 8 + (self.patches.len() + self.quad_patches.len()) * 8
     }
 }
+
+fn read_patches<R: ReadRoseExt>(reader: &mut R) -> Result<Vec<HeightmapPatch>, Error> {
+    reader.read_vec_i32("HIM", 8, |reader| {
+        Ok(HeightmapPatch {
+            max: reader.read_f32()?,
+            min: reader.read_f32()?,
+        })
+    })
+}
@@ -27,32 +27,53 @@
 //!     }
 //! }
 //! ```
-use std::io::SeekFrom;
-use std::path::PathBuf;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::{File, create_dir_all};
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
+#[cfg(feature = "mmap")]
+use std::io::Cursor;
+
+use errors::RoseError;
 use failure::Error;
 use io::{RoseFile, ReadRoseExt, WriteRoseExt, PathRoseExt};
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+use miniz_oxide::deflate::compress_to_vec_zlib;
+use miniz_oxide::inflate::decompress_to_vec_zlib;
 
 /// Virtual file system index file
 pub type IDX = VfsIndex;
 
+/// Buffer size for reading whole `.vfs` blobs, e.g. when extracting them
+pub const VFS_BUFFER_CAPACITY: usize = 1 << 20;
+
 /// Virtual file system index
 ///
 /// An index of the virtual file systems, usually suffixed with `.idx`.
 /// The index does not contain any actual asset data, only meta data about
 /// the file systems. Each file system in the index usually maps to a single
 /// `.vfs` file on disk.
-#[derive(Debug, Serialize, Deserialize)]
+///
+/// Any bytes after the last file system are kept in `trailing_data` as-is
+/// and written back after the file systems.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct VfsIndex {
     pub base_version: i32,
     pub current_version: i32,
     pub file_systems: Vec<VfsMetadata>,
+    #[serde(default)]
+    pub trailing_data: Vec<u8>,
 }
 
 /// Virtual file system
 ///
 /// Contains the metadata for a single file system.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct VfsMetadata {
     pub filename: PathBuf,
     pub files: Vec<VfsFileMetadata>,
@@ -62,6 +83,12 @@ pub struct VfsMetadata {
 /// Virtual file system file entry
 ///
 /// Contains the metadata for a single file in the file system
+///
+/// Indices store each flag as a byte where any non-zero value is set.
+/// `flag_bytes` keeps the bytes of `is_deleted`, `is_compressed` and
+/// `is_encrypted` as read so indices are written back byte for byte, a flag
+/// is written as `0` or `1` once changed. Entries compare equal when they
+/// would be written the same.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct VfsFileMetadata {
     pub filepath: PathBuf,
@@ -73,6 +100,8 @@ pub struct VfsFileMetadata {
     pub is_encrypted: bool,
     pub version: i32,
     pub checksum: i32,
+    #[serde(default)]
+    pub flag_bytes: [u8; 3],
 }
 
 impl VfsMetadata {
@@ -83,6 +112,88 @@ impl VfsMetadata {
             files: Vec::new(),
         }
     }
+
+    /// Extract all files in the file system to `out_dir`
+    ///
+    /// Returns the number of files extracted, deleted files are skipped.
+    /// Encrypted files are written as stored, see
+    /// `VfsFileMetadata::read_data_or_raw`.
+    pub fn extract<R: Read + Seek>(&self, reader: &mut R, out_dir: &Path) -> Result<usize, Error> {
+        self.extract_with_progress(reader, out_dir, |_, _| {})
+    }
+
+    /// Extract all files in the file system to `out_dir`, reporting progress
+    ///
+    /// `progress` is called with `(done, total)` after each file is
+    /// processed.
+    pub fn extract_with_progress<R, F>(&self,
+                                       reader: &mut R,
+                                       out_dir: &Path,
+                                       mut progress: F)
+                                       -> Result<usize, Error>
+        where R: Read + Seek,
+              F: FnMut(usize, usize)
+    {
+        let total = self.files.len();
+        let mut extracted = 0;
+
+        for (i, file) in self.files.iter().enumerate() {
+            if !file.is_deleted {
+                let out_path = out_dir.join(&file.filepath);
+                if let Some(parent) = out_path.parent() {
+                    create_dir_all(parent)?;
+                }
+
+                let data = file.read_data_or_raw(reader)?;
+                File::create(&out_path)?.write_all(&data)?;
+                extracted += 1;
+            } else {
+                debug!("VFS: skipping deleted entry {}", file.filepath.display());
+            }
+
+            progress(i + 1, total);
+        }
+
+        Ok(extracted)
+    }
+
+    /// Re-read each file and compare its data with its checksum
+    ///
+    /// Deleted files are skipped. All the files are checked before
+    /// returning, files that do not match their checksum or could not be
+    /// read are reported together as a `RoseError::ChecksumMismatch`.
+    pub fn verify<R: Read + Seek>(&self, reader: &mut R) -> Result<(), Error> {
+        let mut mismatches = Vec::new();
+        for file in self.files.iter().filter(|f| !f.is_deleted) {
+            let valid = match file.read_data(reader) {
+                Ok(data) => checksum(&data) == file.checksum,
+                Err(_) => false,
+            };
+
+            if !valid {
+                mismatches.push(file.filepath.to_str().unwrap_or("").to_string());
+            }
+        }
+
+        if !mismatches.is_empty() {
+            return Err(RoseError::ChecksumMismatch {
+                format: "VFS",
+                files: mismatches,
+            }.into());
+        }
+
+        Ok(())
+    }
+
+    /// Sort the entries case insensitively by their ROSE path
+    ///
+    /// Deleted entries are moved after the other entries and sorted the same
+    /// way. The sort is stable, entries with the same path keep their order.
+    pub fn sort_files(&mut self) {
+        self.files.sort_by_cached_key(|f| {
+            (f.is_deleted, path_key(f.filepath.to_str().unwrap_or("")))
+        });
+    }
 }
 
 impl VfsFileMetadata {
@@ -98,60 +209,1025 @@ impl VfsFileMetadata {
             is_encrypted: false,
             version: 0,
             checksum: 0,
+            flag_bytes: [0; 3],
+        }
+    }
+
+    /// Bytes of the deleted, compressed and encrypted flags as written to an
+    /// index, see `flag_bytes`
+    fn flag_bytes_to_write(&self) -> [u8; 3] {
+        let flags = [self.is_deleted, self.is_compressed, self.is_encrypted];
+        let mut bytes = [0; 3];
+        for ((byte, &flag), &original) in bytes.iter_mut().zip(&flags).zip(&self.flag_bytes) {
+            *byte = if (original != 0) == flag { original } else { flag as u8 };
+        }
+        bytes
+    }
+
+    /// Compact summary of the entry flags
+    ///
+    /// One character per flag in the order deleted, compressed, encrypted:
+    /// `D`, `C` and `E` when set, `-` otherwise. E.g. `-C-` for a compressed
+    /// entry that is not deleted or encrypted.
+    pub fn flags_string(&self) -> String {
+        let flags = [(self.is_deleted, 'D'), (self.is_compressed, 'C'), (self.is_encrypted, 'E')];
+        flags.iter().map(|&(set, c)| if set { c } else { '-' }).collect()
+    }
+
+    /// Read the file's data from its `.vfs` blob
+    ///
+    /// The raw bytes are read at `offset` and decompressed if
+    /// `is_compressed` is set, compressed data is a zlib stream, see
+    /// `Compression`. Encrypted entries fail as decryption is not
+    /// implemented, see `decrypt_data`.
+    pub fn read_data<R: Read + Seek>(&self, reader: &mut R) -> Result<Vec<u8>, Error> {
+        let mut data = self.read_raw(reader)?;
+
+        if self.is_encrypted {
+            data = decrypt_data(self, data)?;
+        }
+
+        if self.is_compressed {
+            data = match decompress_to_vec_zlib(&data) {
+                Ok(data) => data,
+                Err(e) => {
+                    return Err(RoseError::InvalidData {
+                        format: "VFS",
+                        reason: format!("Invalid compressed data in {}: {}",
+                                        self.filepath.to_str().unwrap_or(""),
+                                        e),
+                    }.into());
+                }
+            };
+        }
+
+        Ok(data)
+    }
+
+    /// Read the file's data like `read_data`, encrypted entries are read as
+    /// stored
+    ///
+    /// Decryption is not implemented, so instead of failing the data of an
+    /// encrypted entry is returned as `read_raw` does and a warning is
+    /// logged.
+    pub fn read_data_or_raw<R: Read + Seek>(&self, reader: &mut R) -> Result<Vec<u8>, Error> {
+        if self.is_encrypted {
+            warn!("VFS: {} is encrypted, reading it as stored", self.filepath.display());
+            return self.read_raw(reader);
+        }
+        self.read_data(reader)
+    }
+
+    /// Read the file's data as stored in its `.vfs` blob
+    ///
+    /// The data is neither decrypted nor decompressed. `size` comes from the
+    /// index and is not trusted, the buffer only grows as data is read so a
+    /// corrupt size fails with `RoseError::TruncatedFile` at the end of the
+    /// blob instead of allocating it up front.
+    pub fn read_raw<R: Read + Seek>(&self, reader: &mut R) -> Result<Vec<u8>, Error> {
+        if self.offset < 0 || self.size < 0 {
+            return Err(RoseError::InvalidData {
+                format: "VFS",
+                reason: format!("Invalid offset {} or size {} of {}",
+                                self.offset,
+                                self.size,
+                                self.filepath.to_str().unwrap_or("")),
+            }.into());
+        }
+
+        // Seeking discards the buffer of a `BufReader`, only seek when the
+        // data isn't next so files stored in order are read through it
+        if reader.stream_position()? != self.offset as u64 {
+            reader.seek(SeekFrom::Start(self.offset as u64))?;
         }
+
+        let size = self.size as usize;
+        let mut data = Vec::with_capacity(size.min(VFS_BUFFER_CAPACITY));
+        reader.by_ref().take(size as u64).read_to_end(&mut data)?;
+        if data.len() < size {
+            return Err(RoseError::TruncatedFile { format: "VFS" }.into());
+        }
+
+        Ok(data)
     }
 }
 
-impl RoseFile for VfsIndex {
-    fn new() -> VfsIndex {
-        VfsIndex {
-            base_version: 0,
-            current_version: 0,
-            file_systems: Vec::new(),
+impl PartialEq for VfsFileMetadata {
+    fn eq(&self, other: &VfsFileMetadata) -> bool {
+        self.filepath == other.filepath && self.offset == other.offset &&
+        self.size == other.size && self.block_size == other.block_size &&
+        self.is_deleted == other.is_deleted && self.is_compressed == other.is_compressed &&
+        self.is_encrypted == other.is_encrypted && self.version == other.version &&
+        self.checksum == other.checksum &&
+        self.flag_bytes_to_write() == other.flag_bytes_to_write()
+    }
+}
+
+impl fmt::Display for VfsFileMetadata {
+    /// Path, size and flags of the entry, e.g. `3DDATA/A.TXT (12 bytes) [---]`
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "{} ({} bytes) [{}]",
+               self.filepath.to_str().unwrap_or(""),
+               self.size,
+               self.flags_string())
+    }
+}
+
+/// Memory mapped `.vfs` blob
+///
+/// Maps the whole blob into memory so file data can be sliced directly
+/// instead of seeking and reading through a `File`. Requires the `mmap`
+/// feature.
+///
+/// The blob must not be modified or truncated while it is mapped, doing so
+/// is undefined behavior. This is usually not a problem for client files but
+/// avoid using it on blobs that are written to by another process.
+#[cfg(feature = "mmap")]
+pub struct VfsArchiveReader {
+    mmap: Mmap,
+}
+
+#[cfg(feature = "mmap")]
+impl VfsArchiveReader {
+    /// Memory map the `.vfs` blob at `path`
+    pub fn open(path: &Path) -> Result<VfsArchiveReader, Error> {
+        let file = File::open(path)?;
+
+        // The mapping is only valid as long as the file is not modified, see
+        // the type level documentation.
+        let mmap = unsafe { Mmap::map(&file)? };
+        Ok(VfsArchiveReader { mmap })
+    }
+
+    /// Get the file's data as a slice of the mapped blob
+    ///
+    /// Only plain entries can be sliced directly, compressed and encrypted
+    /// entries should be read with `VfsFileMetadata::read_data` using
+    /// `cursor()`.
+    pub fn read_file(&self, file: &VfsFileMetadata) -> Result<&[u8], Error> {
+        if file.is_compressed || file.is_encrypted {
+            bail!("Unable to slice a compressed or encrypted VFS entry: {}",
+                  file.filepath.to_str().unwrap_or(""));
+        }
+
+        if file.offset < 0 || file.size < 0 {
+            return Err(RoseError::InvalidData {
+                format: "VFS",
+                reason: format!("Invalid offset {} or size {} of {}",
+                                file.offset,
+                                file.size,
+                                file.filepath.to_str().unwrap_or("")),
+            }.into());
+        }
+
+        let start = file.offset as usize;
+        match start.checked_add(file.size as usize) {
+            Some(end) if end <= self.mmap.len() => Ok(&self.mmap[start..end]),
+            _ => Err(RoseError::TruncatedFile { format: "VFS" }.into()),
         }
     }
 
-    /// Load a `VfsIndex` from a reader
-    fn read<R: ReadRoseExt>(&mut self, reader: &mut R) -> Result<(), Error> {
+    /// Read part of the file's data, e.g. to sniff the header of a file
+    ///
+    /// Reads up to `len` bytes from `start` bytes into the entry, fewer if
+    /// the entry ends first. Like `read_file` only plain entries are
+    /// supported.
+    pub fn read_range(&self, file: &VfsFileMetadata, start: usize, len: usize) -> Result<Vec<u8>, Error> {
+        let data = self.read_file(file)?;
+        if start > data.len() {
+            bail!("Range starts past the end of VFS entry {}: {} > {}",
+                  file.filepath.to_str().unwrap_or(""),
+                  start,
+                  data.len());
+        }
+
+        let end = start.saturating_add(len).min(data.len());
+        Ok(data[start..end].to_vec())
+    }
+
+    /// Get a reader over the mapped blob
+    ///
+    /// Can be used with `VfsFileMetadata::read_data` and
+    /// `VfsMetadata::extract` without a syscall per read.
+    pub fn cursor(&self) -> Cursor<&[u8]> {
+        Cursor::new(&self.mmap[..])
+    }
+
+    /// Size of the mapped blob in bytes
+    pub fn len(&self) -> usize {
+        self.mmap.len()
+    }
+
+    /// Whether the mapped blob is empty
+    pub fn is_empty(&self) -> bool {
+        self.mmap.is_empty()
+    }
+}
+
+/// Compression of the files added to a `.vfs` blob
+///
+/// Files are compressed as zlib streams, the level goes from `0` (no
+/// compression) to `10` (best compression), see `DEFAULT_COMPRESSION_LEVEL`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Compression {
+    /// Store the files as-is
+    None,
+    /// Always compress the files
+    Always(u8),
+    /// Compress the files only if the compressed data is smaller, small or
+    /// already compressed files (e.g. `.DDS`) are usually stored as-is
+    Auto(u8),
+}
+
+/// Default zlib compression level
+pub const DEFAULT_COMPRESSION_LEVEL: u8 = 6;
+
+/// Size of the chunks streamed into a `.vfs` blob
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// `.vfs` blob writer
+///
+/// Files are streamed into the blob in chunks and their checksum is computed
+/// along the way. Once all the files are added, `finish` returns the file
+/// system metadata to store in the `VfsIndex`.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::io::Cursor;
+/// use std::path::Path;
+/// use roselib::files::idx::VfsArchiveWriter;
+///
+/// let mut archive = VfsArchiveWriter::new(Cursor::new(Vec::new()), Path::new("DATA.VFS"));
+/// archive.add_file(Path::new("3DDATA/README.TXT"), &mut &b"Hello"[..]).unwrap();
+/// let vfs = archive.finish_verified().unwrap();
+/// assert_eq!(vfs.files[0].size, 5);
+/// ```
+pub struct VfsArchiveWriter<W: Write + Seek> {
+    writer: W,
+    vfs: VfsMetadata,
+}
+
+impl<W: Write + Seek> VfsArchiveWriter<W> {
+    /// Start writing a `.vfs` blob named `filename` in the index
+    ///
+    /// Files are written from the current position of `writer`.
+    pub fn new(writer: W, filename: &Path) -> VfsArchiveWriter<W> {
+        let mut vfs = VfsMetadata::new();
+        vfs.filename = filename.to_path_buf();
+        VfsArchiveWriter { writer, vfs }
+    }
+
+    /// Stream a file into the blob
+    ///
+    /// Returns the metadata of the new entry.
+    pub fn add_file<R: Read>(&mut self, filepath: &Path, reader: &mut R) -> Result<&VfsFileMetadata, Error> {
+        let offset = self.writer.stream_position()?;
+
+        let mut crc = Crc32::new();
+        let mut size: u64 = 0;
+        let mut chunk = vec![0u8; CHUNK_SIZE];
+        loop {
+            let n = reader.read(&mut chunk)?;
+            if n == 0 {
+                break;
+            }
+            self.writer.write_all(&chunk[..n])?;
+            crc.update(&chunk[..n]);
+            size += n as u64;
+        }
+
+        if offset + size > i32::max_value() as u64 {
+            bail!("VFS blob is too large to add {}", filepath.display());
+        }
+
+        let mut file = VfsFileMetadata::new();
+        file.filepath = filepath.to_path_buf();
+        file.offset = offset as i32;
+        file.size = size as i32;
+        file.block_size = size as i32;
+        file.checksum = crc.finish();
+
+        self.vfs.files.push(file);
+        Ok(&self.vfs.files[self.vfs.files.len() - 1])
+    }
+
+    /// Add a file to the blob with the given compression
+    ///
+    /// Unless `compression` is `Compression::None` the whole file is read
+    /// into memory to be compressed. `is_compressed` is set on the entry if
+    /// the stored data is compressed, the checksum is always the checksum of
+    /// the uncompressed data.
+    pub fn add_file_compressed<R: Read>(&mut self,
+                                        filepath: &Path,
+                                        reader: &mut R,
+                                        compression: Compression)
+                                        -> Result<&VfsFileMetadata, Error> {
+        let level = match compression {
+            Compression::None => return self.add_file(filepath, reader),
+            Compression::Always(level) | Compression::Auto(level) => level,
+        };
+
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+
+        let compressed = compress_to_vec_zlib(&data, level);
+        let is_compressed = match compression {
+            Compression::Auto(_) => compressed.len() < data.len(),
+            _ => true,
+        };
+
+        let checksum = checksum(&data);
+        let index = {
+            let stored = if is_compressed { &compressed } else { &data };
+            self.add_file(filepath, &mut &stored[..])?;
+            self.vfs.files.len() - 1
+        };
+
+        let file = &mut self.vfs.files[index];
+        file.is_compressed = is_compressed;
+        file.checksum = checksum;
+        Ok(file)
+    }
+
+    /// Stream several files into the blob
+    ///
+    /// When `sorted`, the files are written in the order of
+    /// `VfsMetadata::sort_files` instead of the order of `files`, so the same
+    /// set of files always produces the same blob and entries.
+    pub fn add_files<R: Read>(&mut self, mut files: Vec<(PathBuf, R)>, sorted: bool) -> Result<(), Error> {
+        if sorted {
+            files.sort_by_cached_key(|f| path_key(f.0.to_str().unwrap_or("")));
+        }
+
+        for (filepath, mut reader) in files {
+            self.add_file(&filepath, &mut reader)?;
+        }
+        Ok(())
+    }
+
+    /// Flush the blob and return the file system metadata
+    pub fn finish(mut self) -> Result<VfsMetadata, Error> {
+        self.writer.flush()?;
+        Ok(self.vfs)
+    }
+}
+
+impl<W: Read + Write + Seek> VfsArchiveWriter<W> {
+    /// Flush the blob, then re-read each entry and check its checksum
+    ///
+    /// See `VfsMetadata::verify`.
+    pub fn finish_verified(mut self) -> Result<VfsMetadata, Error> {
+        self.writer.flush()?;
+        self.vfs.verify(&mut self.writer)?;
+        Ok(self.vfs)
+    }
+}
+
+/// Checksum of a file's data as stored in `VfsFileMetadata::checksum`
+///
+/// Computed as the CRC-32 (IEEE) of the data. The algorithm used by
+/// the official packer is unknown so only checksums written by
+/// `VfsArchiveWriter` can be verified.
+pub fn checksum(data: &[u8]) -> i32 {
+    let mut crc = Crc32::new();
+    crc.update(data);
+    crc.finish()
+}
+
+/// Hash of a file's data as computed by `VfsIndex::content_hashes`
+///
+/// Computed as the 64-bit FNV-1a of the data, it is stable across platforms
+/// and versions so hashes can be stored and compared later.
+pub fn content_hash(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for &b in data {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+/// Streaming CRC-32 (IEEE)
+struct Crc32 {
+    table: [u32; 256],
+    crc: u32,
+}
+
+impl Crc32 {
+    fn new() -> Crc32 {
+        let mut table = [0u32; 256];
+        for (i, entry) in table.iter_mut().enumerate() {
+            let mut c = i as u32;
+            for _ in 0..8 {
+                c = if c & 1 != 0 { 0xEDB8_8320 ^ (c >> 1) } else { c >> 1 };
+            }
+            *entry = c;
+        }
+
+        Crc32 { table, crc: 0xFFFF_FFFF }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        for &b in data {
+            self.crc = self.table[((self.crc ^ b as u32) & 0xFF) as usize] ^ (self.crc >> 8);
+        }
+    }
+
+    fn finish(&self) -> i32 {
+        (self.crc ^ 0xFFFF_FFFF) as i32
+    }
+}
+
+/// Decrypt the data of an encrypted VFS entry
+///
+/// No decryption is implemented: data of entries without the encryption
+/// flag is returned as-is and encrypted entries fail. The 129 client never
+/// sets the flag. Use `VfsFileMetadata::read_raw` or
+/// `VfsFileMetadata::read_data_or_raw` to get the data of an encrypted entry
+/// as stored.
+pub fn decrypt_data(file: &VfsFileMetadata, data: Vec<u8>) -> Result<Vec<u8>, Error> {
+    if !file.is_encrypted {
+        return Ok(data);
+    }
+
+    bail!("Encrypted VFS entries are not supported: {}",
+          file.filepath.to_str().unwrap_or(""));
+}
+
+impl VfsIndex {
+    /// Load a `VfsIndex` from a reader, reporting progress
+    ///
+    /// `progress` is called with `(done, total)` after each file entry is
+    /// read, where `total` is the number of files across all file systems.
+    pub fn read_with_progress<R, F>(&mut self, reader: &mut R, progress: F) -> Result<(), Error>
+        where R: ReadRoseExt,
+              F: FnMut(usize, usize)
+    {
+        self.read_entries(reader, false, progress)
+    }
+
+    /// Load the readable part of a damaged `VfsIndex`
+    ///
+    /// Reads like `read` but stops at the first error instead of failing,
+    /// keeping the file systems and files read until then. File counts are
+    /// not checked against the size of the index so the files before a
+    /// truncation are kept.
+    ///
+    /// Returns the index and, if reading stopped early, a warning saying
+    /// where and why.
+    pub fn load_lenient<R: ReadRoseExt>(reader: &mut R) -> (VfsIndex, Option<String>) {
+        let mut idx = VfsIndex::new();
+        let warning = match idx.read_entries(reader, true, |_, _| {}) {
+            Ok(()) => None,
+            Err(e) => {
+                let files: usize = idx.file_systems.iter().map(|vfs| vfs.files.len()).sum();
+                let warning = format!("Stopped reading IDX after {} files in {} file systems: {}",
+                                      files,
+                                      idx.file_systems.len(),
+                                      e);
+                warn!("{}", warning);
+                Some(warning)
+            }
+        };
+        (idx, warning)
+    }
+
+    /// Read the index, adding each file system and file as soon as it is
+    /// read so a lenient read keeps them on errors
+    fn read_entries<R, F>(&mut self, reader: &mut R, lenient: bool, mut progress: F) -> Result<(), Error>
+        where R: ReadRoseExt,
+              F: FnMut(usize, usize)
+    {
         self.base_version = reader.read_i32()?;
         self.current_version = reader.read_i32()?;
 
-        let vfs_count = reader.read_i32()?;
-        for i in 0..vfs_count {
-            let mut vfs = VfsMetadata::new();
-            vfs.filename = PathBuf::from(reader.read_string_u16()?);
+        let headers = reader.read_vec_i32("IDX", 6, |r| {
+            let filename = PathBuf::from(r.read_string_u16_euc_kr()?);
+            let offset = r.read_i32()?;
+            Ok((filename, offset))
+        })?;
+
+        let file_size = reader.stream_position()? + reader.bytes_remaining()?;
+        let entry_size = if lenient { 0 } else { 25 };
 
-            let offset = reader.read_i32()?;
-            let next_filesystem = reader.seek(SeekFrom::Current(0))?; // seek(0) returns current position
-            let _ = reader.seek(SeekFrom::Start(offset as u64))?;
+        // Count the files up front so progress can report a total
+        let mut total = 0;
+        if !lenient {
+            for &(_, offset) in &headers {
+                seek_vfs_header(reader, offset, file_size)?;
+                let file_count = reader.read_i32()?;
+                total += reader.check_count("IDX", file_count as i64, entry_size)?;
+            }
+        }
 
+        let mut end = reader.stream_position()?;
+        let mut done = 0;
+        for (filename, offset) in headers {
+            seek_vfs_header(reader, offset, file_size)?;
             let file_count = reader.read_i32()?;
             let _delete_count = reader.read_i32()?;
             let _start_offset = reader.read_i32()?;
+            let file_count = reader.check_count("IDX", file_count as i64, entry_size)?;
+            trace!("IDX: {} files in {}", file_count, filename.display());
+
+            let mut vfs = VfsMetadata::new();
+            vfs.filename = filename;
+            self.file_systems.push(vfs);
+            let vfs = self.file_systems.last_mut().unwrap();
 
             for _ in 0..file_count {
                 let mut vfs_file = VfsFileMetadata::new();
-                vfs_file.filepath = PathBuf::from_rose_path(&reader.read_string_u16()?);
+                vfs_file.filepath = PathBuf::from_rose_path(&reader.read_string_u16_euc_kr()?);
                 vfs_file.offset = reader.read_i32()?;
                 vfs_file.size = reader.read_i32()?;
                 vfs_file.block_size = reader.read_i32()?;
-                vfs_file.is_deleted = reader.read_bool()?;
-                vfs_file.is_compressed = reader.read_bool()?;
-                vfs_file.is_encrypted = reader.read_bool()?;
+                for byte in vfs_file.flag_bytes.iter_mut() {
+                    *byte = reader.read_u8()?;
+                }
+                vfs_file.is_deleted = vfs_file.flag_bytes[0] != 0;
+                vfs_file.is_compressed = vfs_file.flag_bytes[1] != 0;
+                vfs_file.is_encrypted = vfs_file.flag_bytes[2] != 0;
                 vfs_file.version = reader.read_i32()?;
                 vfs_file.checksum = reader.read_i32()?;
 
                 vfs.files.push(vfs_file);
+
+                done += 1;
+                progress(done, total);
             }
+            end = end.max(reader.stream_position()?);
+        }
 
-            self.file_systems.push(vfs);
-            if i < vfs_count - 1 {
-                let _ = reader.seek(SeekFrom::Start(next_filesystem as u64));
+        reader.seek(SeekFrom::Start(end))?;
+        self.trailing_data.clear();
+        reader.read_to_end(&mut self.trailing_data)?;
+        Ok(())
+    }
+
+    /// Update a file by appending its new data to the end of a `.vfs` blob
+    ///
+    /// `vfs` is the blob of the file system named `vfs_filename`. Instead of
+    /// repacking the blob, previous entries of `filepath` in all the file
+    /// systems are marked deleted and a new entry pointing at the appended data is added, versioned
+    /// with the incremented `current_version`. Paths are matched case
+    /// insensitively. The file is added if it doesn't exist yet.
+    ///
+    /// Returns the metadata of the new entry.
+    pub fn update_file<W: Write + Seek>(&mut self,
+                                        vfs_filename: &Path,
+                                        vfs: &mut W,
+                                        filepath: &Path,
+                                        data: &[u8])
+                                        -> Result<&VfsFileMetadata, Error> {
+        let index = match self.file_systems.iter().position(|f| f.filename == vfs_filename) {
+            Some(index) => index,
+            None => bail!("File system not found in index: {}", vfs_filename.display()),
+        };
+
+        vfs.seek(SeekFrom::End(0))?;
+        let mut archive = VfsArchiveWriter::new(&mut *vfs, vfs_filename);
+        archive.add_file(filepath, &mut &data[..])?;
+        let mut file = archive.finish()?.files.remove(0);
+
+        self.current_version += 1;
+        file.version = self.current_version;
+
+        let key = path_key(filepath.to_str().unwrap_or(""));
+        for old in self.file_systems.iter_mut().flat_map(|f| f.files.iter_mut()) {
+            if path_key(old.filepath.to_str().unwrap_or("")) == key {
+                old.is_deleted = true;
+            }
+        }
+
+        let files = &mut self.file_systems[index].files;
+        files.push(file);
+        Ok(&files[files.len() - 1])
+    }
+
+    /// Sort the entries of every file system, see `VfsMetadata::sort_files`
+    ///
+    /// The order of the file systems is kept.
+    pub fn sort(&mut self) {
+        for vfs in &mut self.file_systems {
+            vfs.sort_files();
+        }
+    }
+
+    /// Find the entry of a file by its path, deleted entries are skipped
+    ///
+    /// `filepath` is a ROSE path, matched case insensitively with either `/`
+    /// or `\` separators. Returns the file system of the entry along with
+    /// the entry.
+    pub fn find_file(&self, filepath: &str) -> Option<(&VfsMetadata, &VfsFileMetadata)> {
+        let key = path_key(filepath);
+        for vfs in &self.file_systems {
+            let file = vfs.files
+                .iter()
+                .filter(|f| !f.is_deleted)
+                .find(|f| path_key(f.filepath.to_str().unwrap_or("")) == key);
+            if let Some(file) = file {
+                return Some((vfs, file));
+            }
+        }
+        None
+    }
+
+    /// Rename the entry of a file, moving it to another virtual path
+    ///
+    /// Paths are matched like `find_file` and `to` may use either separator.
+    /// Only the path of the entry changes, its data stays where it is in the
+    /// blob. Fails with `RoseError::FileNotFound` if `from` is not in the
+    /// index, and if another entry is already at `to`.
+    pub fn rename(&mut self, from: &str, to: &str) -> Result<(), Error> {
+        let (from_key, to_key) = (path_key(from), path_key(to));
+        if from_key != to_key && self.find_file(to).is_some() {
+            bail!("File already exists in the index: {}", to);
+        }
+
+        let file = self.file_systems
+            .iter_mut()
+            .flat_map(|vfs| vfs.files.iter_mut())
+            .filter(|f| !f.is_deleted)
+            .find(|f| path_key(f.filepath.to_str().unwrap_or("")) == from_key);
+        match file {
+            Some(file) => {
+                file.filepath = PathBuf::from_rose_path(to);
+                Ok(())
+            }
+            None => {
+                Err(RoseError::FileNotFound {
+                    format: "IDX",
+                    path: from.to_string(),
+                }.into())
+            }
+        }
+    }
+
+    /// Read the data of a file from the `.vfs` blobs in `vfs_dir`
+    ///
+    /// Fails with `RoseError::FileNotFound` if the file is not in the index.
+    /// Encrypted files are read as stored, see
+    /// `VfsFileMetadata::read_data_or_raw`.
+    pub fn read_file(&self, vfs_dir: &Path, filepath: &str) -> Result<Vec<u8>, Error> {
+        let (vfs, file) = match self.find_file(filepath) {
+            Some(found) => found,
+            None => {
+                return Err(RoseError::FileNotFound {
+                    format: "IDX",
+                    path: filepath.to_string(),
+                }.into());
+            }
+        };
+
+        let mut blob = BufReader::new(File::open(vfs_dir.join(&vfs.filename))?);
+        file.read_data_or_raw(&mut blob)
+    }
+
+    /// Whether the entries of `other` can be merged into this index
+    ///
+    /// `base_version` is the client build an index was created for and
+    /// `current_version` is bumped by every patch, entries are stamped with
+    /// the `current_version` they were written at so a higher entry
+    /// `version` is a newer file. Versions only compare between indices of
+    /// the same build, so indices are compatible when their base versions
+    /// are equal.
+    pub fn is_compatible_with(&self, other: &VfsIndex) -> bool {
+        self.base_version == other.base_version
+    }
+
+    /// Merge the file systems of another index into this index
+    ///
+    /// File systems are matched by filename, the ones not in this index are
+    /// added. For a path in both indices the entry with the higher `version`
+    /// wins, the losing entry of this index is marked deleted and the losing
+    /// entry of `other` is dropped; on equal versions this index wins.
+    /// Entries keep pointing at the blob of their file system. Deleted
+    /// entries of `other` are dropped. `current_version` is bumped above the
+    /// version of both indices.
+    ///
+    /// Fails if the indices are not compatible, see `is_compatible_with`.
+    pub fn merge(&mut self, other: VfsIndex) -> Result<(), Error> {
+        if !self.is_compatible_with(&other) {
+            bail!("Cannot merge indices of base versions {} and {}",
+                  self.base_version,
+                  other.base_version);
+        }
+
+        // Live entry of each path, as (file system, file) indices
+        let mut live: HashMap<String, (usize, usize)> = HashMap::new();
+        for (i, vfs) in self.file_systems.iter().enumerate() {
+            for (j, file) in vfs.files.iter().enumerate().filter(|&(_, f)| !f.is_deleted) {
+                live.entry(path_key(file.filepath.to_str().unwrap_or(""))).or_insert((i, j));
+            }
+        }
+
+        self.current_version = self.current_version.max(other.current_version) + 1;
+
+        for other_vfs in other.file_systems {
+            let index = match self.file_systems.iter().position(|f| f.filename == other_vfs.filename) {
+                Some(index) => index,
+                None => {
+                    let mut vfs = VfsMetadata::new();
+                    vfs.filename = other_vfs.filename.clone();
+                    self.file_systems.push(vfs);
+                    self.file_systems.len() - 1
+                }
+            };
+
+            for file in other_vfs.files.into_iter().filter(|f| !f.is_deleted) {
+                let key = path_key(file.filepath.to_str().unwrap_or(""));
+                if let Some(&(i, j)) = live.get(&key) {
+                    let existing = &mut self.file_systems[i].files[j];
+                    if existing.version >= file.version {
+                        continue;
+                    }
+                    existing.is_deleted = true;
+                }
+
+                let files = &mut self.file_systems[index].files;
+                files.push(file);
+                live.insert(key, (index, files.len() - 1));
             }
         }
+
         Ok(())
     }
 
+    /// Hash the data of every file, keyed by path
+    ///
+    /// The data is read from the `.vfs` blobs in `vfs_dir` and hashed after
+    /// decompression with `content_hash`. Deleted entries are skipped. Keys
+    /// are normalized to uppercase with `/` separators so paths compare case
+    /// insensitively, when a path is in the index more than once the entry
+    /// found by `find_file` is hashed.
+    pub fn content_hashes(&self, vfs_dir: &Path) -> Result<HashMap<String, u64>, Error> {
+        let mut hashes = HashMap::new();
+        for vfs in &self.file_systems {
+            let mut blob = None;
+            for file in vfs.files.iter().filter(|f| !f.is_deleted) {
+                let key = path_key(file.filepath.to_str().unwrap_or(""));
+                if hashes.contains_key(&key) {
+                    continue;
+                }
+
+                if blob.is_none() {
+                    let file = File::open(vfs_dir.join(&vfs.filename))?;
+                    blob = Some(BufReader::with_capacity(VFS_BUFFER_CAPACITY, file));
+                }
+                let data = file.read_data(blob.as_mut().unwrap())?;
+                hashes.insert(key, content_hash(&data));
+            }
+        }
+        Ok(hashes)
+    }
+}
+
+/// Write a file system name or file path, stored null terminated with the
+/// terminator counted in the length
+fn write_vfs_path<W: WriteRoseExt>(writer: &mut W, path: &str) -> Result<(), Error> {
+    writer.write_string_u16_euc_kr(&format!("{}\0", path))
+}
+
+/// Seek to the header of a file system, failing if the 12 header bytes at
+/// `offset` are not inside the file
+fn seek_vfs_header<R: ReadRoseExt>(reader: &mut R, offset: i32, file_size: u64) -> Result<(), Error> {
+    if offset < 0 || offset as u64 + 12 > file_size {
+        return Err(RoseError::InvalidData {
+            format: "IDX",
+            reason: format!("index offset {} beyond file size {}", offset, file_size),
+        }.into());
+    }
+    reader.seek(SeekFrom::Start(offset as u64))?;
+    Ok(())
+}
+
+/// Handle of an open `.vfs` blob, shared between threads
+type SharedBlob = Arc<Mutex<BufReader<File>>>;
+
+/// Reader for the files of a `VfsIndex`, e.g. to back an asset server
+///
+/// Keeps a handle open per `.vfs` blob instead of opening the blob on each
+/// read like `VfsIndex::read_file`. Blobs are opened on first use. With a
+/// cache capacity, recently read files are kept decoded in memory until the
+/// total size of the cached files exceeds the capacity, the least recently
+/// used files are evicted first.
+///
+/// The reader is `Send` and `Sync`, reads from the same blob are serialized.
+pub struct VfsReader {
+    index: VfsIndex,
+    vfs_dir: PathBuf,
+    blobs: Mutex<HashMap<PathBuf, SharedBlob>>,
+    cache: Mutex<VfsCache>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+/// Least recently used cache of file data, keyed by `path_key`
+struct VfsCache {
+    capacity: usize,
+    size: usize,
+    tick: u64,
+    entries: HashMap<String, (Arc<Vec<u8>>, u64)>,
+    order: BTreeMap<u64, String>,
+}
+
+impl VfsReader {
+    /// Read the files of `index` from the `.vfs` blobs in `vfs_dir`
+    ///
+    /// Files are not cached, see `with_cache`.
+    pub fn new(index: VfsIndex, vfs_dir: &Path) -> VfsReader {
+        VfsReader::with_cache(index, vfs_dir, 0)
+    }
+
+    /// Read the files of `index`, caching up to `capacity` bytes of files
+    ///
+    /// Files larger than `capacity` are never cached.
+    pub fn with_cache(index: VfsIndex, vfs_dir: &Path, capacity: usize) -> VfsReader {
+        VfsReader {
+            index,
+            vfs_dir: vfs_dir.to_path_buf(),
+            blobs: Mutex::new(HashMap::new()),
+            cache: Mutex::new(VfsCache::new(capacity)),
+            hits: AtomicUsize::new(0),
+            misses: AtomicUsize::new(0),
+        }
+    }
+
+    /// The index files are read from
+    pub fn index(&self) -> &VfsIndex {
+        &self.index
+    }
+
+    /// Get the data of a file, from the cache if it's in there
+    ///
+    /// `filepath` is matched like `VfsIndex::find_file`. Fails with
+    /// `RoseError::FileNotFound` if the file is not in the index. Encrypted
+    /// files are read as stored, see `VfsFileMetadata::read_data_or_raw`.
+    pub fn get(&self, filepath: &str) -> Result<Arc<Vec<u8>>, Error> {
+        let key = path_key(filepath);
+        if let Some(data) = lock(&self.cache).get(&key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(data);
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        let (vfs, file) = match self.index.find_file(filepath) {
+            Some(found) => found,
+            None => {
+                return Err(RoseError::FileNotFound {
+                    format: "IDX",
+                    path: filepath.to_string(),
+                }.into());
+            }
+        };
+
+        let blob = self.blob(&vfs.filename)?;
+        let data = Arc::new(file.read_data_or_raw(&mut *lock(&blob))?);
+        lock(&self.cache).insert(key, data.clone());
+        Ok(data)
+    }
+
+    /// Number of `get` calls served from the cache
+    pub fn cache_hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Number of `get` calls that read from a blob
+    pub fn cache_misses(&self) -> usize {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Total size of the cached files in bytes
+    pub fn cache_size(&self) -> usize {
+        lock(&self.cache).size
+    }
+
+    /// Remove all files from the cache, open blobs are kept
+    pub fn clear_cache(&self) {
+        lock(&self.cache).clear();
+    }
+
+    fn blob(&self, filename: &Path) -> Result<SharedBlob, Error> {
+        let mut blobs = lock(&self.blobs);
+        if let Some(blob) = blobs.get(filename) {
+            return Ok(blob.clone());
+        }
+
+        let file = File::open(self.vfs_dir.join(filename))?;
+        let blob = Arc::new(Mutex::new(BufReader::new(file)));
+        blobs.insert(filename.to_path_buf(), blob.clone());
+        Ok(blob)
+    }
+}
+
+impl VfsCache {
+    fn new(capacity: usize) -> VfsCache {
+        VfsCache {
+            capacity,
+            size: 0,
+            tick: 0,
+            entries: HashMap::new(),
+            order: BTreeMap::new(),
+        }
+    }
+
+    fn get(&mut self, key: &str) -> Option<Arc<Vec<u8>>> {
+        self.tick += 1;
+        let tick = self.tick;
+        let entry = self.entries.get_mut(key)?;
+        self.order.remove(&entry.1);
+        self.order.insert(tick, key.to_string());
+        entry.1 = tick;
+        Some(entry.0.clone())
+    }
+
+    fn insert(&mut self, key: String, data: Arc<Vec<u8>>) {
+        if data.len() > self.capacity || self.entries.contains_key(&key) {
+            return;
+        }
+
+        while self.size + data.len() > self.capacity {
+            let oldest = match self.order.keys().next() {
+                Some(&tick) => tick,
+                None => break,
+            };
+            if let Some(evicted) = self.order.remove(&oldest) {
+                if let Some((evicted, _)) = self.entries.remove(&evicted) {
+                    self.size -= evicted.len();
+                }
+            }
+        }
+
+        self.tick += 1;
+        self.size += data.len();
+        self.order.insert(self.tick, key.clone());
+        self.entries.insert(key, (data, self.tick));
+    }
+
+    fn clear(&mut self) {
+        self.size = 0;
+        self.entries.clear();
+        self.order.clear();
+    }
+}
+
+/// Lock a mutex, a panic while it was held doesn't leave the cache or a blob
+/// handle in an unusable state so poisoning is ignored
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Paths that differ between two sets of content hashes, see `diff`
+#[derive(Debug, Default, PartialEq)]
+pub struct VfsDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+}
+
+/// Compare the content hashes of two versions of an index
+///
+/// Returns the paths only in `new` as added, the paths only in `old` as
+/// removed and the paths with a different hash as changed, each sorted.
+pub fn diff(old: &HashMap<String, u64>, new: &HashMap<String, u64>) -> VfsDiff {
+    let mut diff = VfsDiff::default();
+    for (path, hash) in new {
+        match old.get(path) {
+            None => diff.added.push(path.clone()),
+            Some(old_hash) if old_hash != hash => diff.changed.push(path.clone()),
+            _ => {}
+        }
+    }
+    diff.removed = old.keys().filter(|path| !new.contains_key(*path)).cloned().collect();
+
+    diff.added.sort();
+    diff.removed.sort();
+    diff.changed.sort();
+    diff
+}
+
+/// Normalized ROSE path used to compare paths
+fn path_key(path: &str) -> String {
+    path.replace('\\', "/").to_uppercase()
+}
+
+impl RoseFile for VfsIndex {
+    fn new() -> VfsIndex {
+        VfsIndex {
+            base_version: 0,
+            current_version: 0,
+            file_systems: Vec::new(),
+            trailing_data: Vec::new(),
+        }
+    }
+
+    /// Load a `VfsIndex` from a reader
+    fn read<R: ReadRoseExt>(&mut self, reader: &mut R) -> Result<(), Error> {
+        self.read_with_progress(reader, |_, _| {})
+    }
+
     /// Save a `VfsIndex` to a writer
     fn write<W: WriteRoseExt>(&mut self, writer: &mut W) -> Result<(), Error> {
         writer.write_i32(self.base_version)?;
@@ -162,7 +1238,7 @@ impl RoseFile for VfsIndex {
 
         for i in 0..self.file_systems.len() {
             let fname = &self.file_systems[i].filename.to_str().unwrap_or("");
-            writer.write_string_u16(fname)?;
+            write_vfs_path(writer, fname)?;
 
             file_system_offsets.push(writer.seek(SeekFrom::Current(0))?);
             writer.write_i32(0)?; // Reserve to be written later
@@ -185,25 +1261,47 @@ impl RoseFile for VfsIndex {
                 }
             }
 
+            // Start of the data in the blob, 0 for empty file systems
+            let start_offset = vfs.files.iter().map(|f| f.offset).min().unwrap_or(0);
+
             writer.write_i32(vfs.files.len() as i32)?;
             writer.write_i32(deleted_count)?;
-            writer.write_i32(vfs.files[0].offset)?;
+            writer.write_i32(start_offset)?;
 
             for file in &vfs.files {
-                let fname = &file.filepath.to_str().unwrap_or("");
-                writer.write_string_u16(fname)?;
+                // The client uses `\` separators
+                let fname = file.filepath.to_str().unwrap_or("").replace('/', "\\");
+                write_vfs_path(writer, &fname)?;
                 writer.write_i32(file.offset)?;
                 writer.write_i32(file.size)?;
                 writer.write_i32(file.block_size)?;
-                writer.write_bool(file.is_deleted)?;
-                writer.write_bool(file.is_compressed)?;
-                writer.write_bool(file.is_encrypted)?;
+                for &byte in &file.flag_bytes_to_write() {
+                    writer.write_u8(byte)?;
+                }
                 writer.write_i32(file.version)?;
                 writer.write_i32(file.checksum)?;
             }
         }
+
+        writer.write_all(&self.trailing_data)?;
         Ok(())
     }
+
+    fn size_hint(&self) -> usize {
+        // Versions and file system count
+        let mut size = 12;
+
+        for vfs in &self.file_systems {
+            // Name, offset and file/delete/start offset counts
+            size += 2 + vfs.filename.to_str().unwrap_or("").len() + 1 + 4 + 12;
+
+            for file in &vfs.files {
+                size += 2 + file.filepath.to_str().unwrap_or("").len() + 1 + 12 + 3 + 8;
+            }
+        }
+
+        size + self.trailing_data.len()
+    }
 }
 
 
@@ -0,0 +1,573 @@
+//! ROSE Online Map Blocks
+//!
+//! A `.IFO` file lists everything placed on one block of a map, next to the
+//! `.HIM` heightmap and `.TIL` tiles of the block: decoration and
+//! construction objects, NPCs, sounds, effects, monster spawns, water, warp
+//! gates, collision objects and event objects.
+//!
+//! The file starts with a list of block type/offset pairs, each block holds a
+//! count followed by its entries. All objects start with the same
+//! `MapObject` fields.
+use std::io::SeekFrom;
+
+use errors::RoseError;
+use failure::Error;
+use io::{RoseFile, ReadRoseExt, WriteRoseExt};
+use num::FromPrimitive as num_from;
+use utils::{Vector2, Vector3, Vector4};
+
+
+/// Map Block File
+pub type IFO = MapData;
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, FromPrimitive, PartialEq)]
+pub enum MapBlockType {
+    MapInfo = 0,
+    DecoObjects = 1,
+    Npcs = 2,
+    ConstructionObjects = 3,
+    SoundObjects = 4,
+    EffectObjects = 5,
+    AnimatedObjects = 6,
+    WaterPatches = 7,
+    MonsterSpawns = 8,
+    WaterPlanes = 9,
+    Warps = 10,
+    CollisionObjects = 11,
+    EventObjects = 12,
+}
+
+/// Object placed on a map block
+///
+/// `object_id` is the row of the object in the table of its block (e.g. the
+/// deco `.ZSC` or `LIST_NPC.STB`). `map_position` is the position of the
+/// object on the minimap grid and `rotation` a quaternion.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct MapObject {
+    pub name: String,
+    pub warp_id: u16,
+    pub event_id: u16,
+    pub object_type: u32,
+    pub object_id: u32,
+    pub map_position: Vector2<u32>,
+    pub rotation: Vector4<f32>,
+    pub position: Vector3<f32>,
+    pub scale: Vector3<f32>,
+}
+
+/// NPC placed on a map block
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct MapNpc {
+    pub object: MapObject,
+    pub ai_id: u32,
+    pub quest_file: String,
+}
+
+/// Sound emitter placed on a map block
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct MapSound {
+    pub object: MapObject,
+    pub path: String,
+    pub range: u32,
+    pub interval: u32,
+}
+
+/// Effect placed on a map block
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct MapEffect {
+    pub object: MapObject,
+    pub path: String,
+}
+
+/// Monsters of a spawn point
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct MapSpawnMonster {
+    pub name: String,
+    pub monster_id: u32,
+    pub count: u32,
+}
+
+/// Monster spawn point
+///
+/// `basic_monsters` are spawned regularly, `tactic_monsters` once
+/// `tactic_points` are reached.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct MapMonsterSpawn {
+    pub object: MapObject,
+    pub name: String,
+    pub basic_monsters: Vec<MapSpawnMonster>,
+    pub tactic_monsters: Vec<MapSpawnMonster>,
+    pub interval: u32,
+    pub limit: u32,
+    pub range: u32,
+    pub tactic_points: u32,
+}
+
+/// Water of one patch of the block, superseded by `MapWaterPlane`
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct MapWaterPatch {
+    pub has_water: bool,
+    pub height: f32,
+    pub water_type: u32,
+    pub texture_index: u32,
+    pub reserved: u32,
+}
+
+/// Water plane between two corners
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct MapWaterPlane {
+    pub start: Vector3<f32>,
+    pub end: Vector3<f32>,
+}
+
+/// Event object, e.g. a quest trigger area
+///
+/// `quest_trigger` is the name of the quest trigger run by the server and
+/// `script_function` the name of the client script function.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct MapEvent {
+    pub object: MapObject,
+    pub quest_trigger: String,
+    pub script_function: String,
+}
+
+/// Map Block
+///
+/// `map_position` is the position of the block in the map, e.g. `(31, 30)`
+/// for `31_30.IFO`. `world_matrix` is stored in file order, with the
+/// translation in elements 12 to 14.
+///
+/// `warps` are warp gates, `object.warp_id` is their row in `WARP.STB` which
+/// holds the destination of the warp.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct MapData {
+    pub width: i32,
+    pub height: i32,
+    pub map_position: Vector2<i32>,
+    pub world_matrix: [f32; 16],
+    pub name: String,
+
+    pub deco_objects: Vec<MapObject>,
+    pub npcs: Vec<MapNpc>,
+    pub construction_objects: Vec<MapObject>,
+    pub sounds: Vec<MapSound>,
+    pub effects: Vec<MapEffect>,
+    pub animated_objects: Vec<MapObject>,
+    pub water_patches: Vec<Vec<MapWaterPatch>>,
+    pub monster_spawns: Vec<MapMonsterSpawn>,
+    pub water_size: f32,
+    pub water_planes: Vec<MapWaterPlane>,
+    pub warps: Vec<MapObject>,
+    pub collision_objects: Vec<MapObject>,
+    pub events: Vec<MapEvent>,
+}
+
+/// Block types in the order they are written
+const BLOCK_TYPES: [MapBlockType; 13] = [
+    MapBlockType::MapInfo,
+    MapBlockType::DecoObjects,
+    MapBlockType::Npcs,
+    MapBlockType::ConstructionObjects,
+    MapBlockType::SoundObjects,
+    MapBlockType::EffectObjects,
+    MapBlockType::AnimatedObjects,
+    MapBlockType::WaterPatches,
+    MapBlockType::MonsterSpawns,
+    MapBlockType::WaterPlanes,
+    MapBlockType::Warps,
+    MapBlockType::CollisionObjects,
+    MapBlockType::EventObjects,
+];
+
+impl MapObject {
+    pub fn new() -> MapObject {
+        MapObject {
+            name: String::new(),
+            warp_id: 0,
+            event_id: 0,
+            object_type: 0,
+            object_id: 0,
+            map_position: Vector2 { x: 0, y: 0 },
+            rotation: Vector4::<f32>::new(),
+            position: Vector3::<f32>::new(),
+            scale: Vector3 { x: 1.0, y: 1.0, z: 1.0 },
+        }
+    }
+
+    fn read<R: ReadRoseExt>(reader: &mut R) -> Result<MapObject, Error> {
+        Ok(MapObject {
+            name: reader.read_string_u8()?,
+            warp_id: reader.read_u16()?,
+            event_id: reader.read_u16()?,
+            object_type: reader.read_u32()?,
+            object_id: reader.read_u32()?,
+            map_position: reader.read_vector2_u32()?,
+            // Unlike ZMO and ZSC, IFO quaternions are stored `w` last
+            rotation: Vector4 {
+                x: reader.read_f32()?,
+                y: reader.read_f32()?,
+                z: reader.read_f32()?,
+                w: reader.read_f32()?,
+            },
+            position: reader.read_vector3_f32()?,
+            scale: reader.read_vector3_f32()?,
+        })
+    }
+
+    fn write<W: WriteRoseExt>(&self, writer: &mut W) -> Result<(), Error> {
+        writer.write_string_u8(&self.name)?;
+        writer.write_u16(self.warp_id)?;
+        writer.write_u16(self.event_id)?;
+        writer.write_u32(self.object_type)?;
+        writer.write_u32(self.object_id)?;
+        writer.write_vector2_u32(&self.map_position)?;
+        writer.write_f32(self.rotation.x)?;
+        writer.write_f32(self.rotation.y)?;
+        writer.write_f32(self.rotation.z)?;
+        writer.write_f32(self.rotation.w)?;
+        writer.write_vector3_f32(&self.position)?;
+        writer.write_vector3_f32(&self.scale)?;
+        Ok(())
+    }
+}
+
+impl Default for MapObject {
+    fn default() -> MapObject {
+        MapObject::new()
+    }
+}
+
+impl MapSpawnMonster {
+    fn read<R: ReadRoseExt>(reader: &mut R) -> Result<MapSpawnMonster, Error> {
+        Ok(MapSpawnMonster {
+            name: reader.read_string_u8()?,
+            monster_id: reader.read_u32()?,
+            count: reader.read_u32()?,
+        })
+    }
+
+    fn write<W: WriteRoseExt>(&self, writer: &mut W) -> Result<(), Error> {
+        writer.write_string_u8(&self.name)?;
+        writer.write_u32(self.monster_id)?;
+        writer.write_u32(self.count)?;
+        Ok(())
+    }
+}
+
+/// Read a count prefixed list of entries
+fn read_list<R, T, F>(reader: &mut R, element_size: u64, mut read: F) -> Result<Vec<T>, Error>
+    where R: ReadRoseExt,
+          F: FnMut(&mut R) -> Result<T, Error>
+{
+    let count = reader.read_i32()?;
+    let count = reader.check_count("IFO", count as i64, element_size)?;
+
+    let mut list = Vec::with_capacity(count);
+    for _ in 0..count {
+        list.push(read(reader)?);
+    }
+    Ok(list)
+}
+
+/// Write a count prefixed list of entries
+fn write_list<W, T, F>(writer: &mut W, list: &[T], mut write: F) -> Result<(), Error>
+    where W: WriteRoseExt,
+          F: FnMut(&mut W, &T) -> Result<(), Error>
+{
+    writer.write_i32(list.len() as i32)?;
+    for item in list {
+        write(writer, item)?;
+    }
+    Ok(())
+}
+
+/// Smallest size of a `MapObject` in bytes
+const OBJECT_SIZE: u64 = 61;
+
+impl MapData {
+    fn read_block<R: ReadRoseExt>(&mut self, reader: &mut R, block_type: MapBlockType) -> Result<(), Error> {
+        match block_type {
+            MapBlockType::MapInfo => {
+                self.width = reader.read_i32()?;
+                self.height = reader.read_i32()?;
+                self.map_position = reader.read_vector2_i32()?;
+                for value in self.world_matrix.iter_mut() {
+                    *value = reader.read_f32()?;
+                }
+                self.name = reader.read_string_u8()?;
+            }
+            MapBlockType::DecoObjects => {
+                self.deco_objects = read_list(reader, OBJECT_SIZE, MapObject::read)?;
+            }
+            MapBlockType::Npcs => {
+                self.npcs = read_list(reader, OBJECT_SIZE + 5, |r| {
+                    Ok(MapNpc {
+                        object: MapObject::read(r)?,
+                        ai_id: r.read_u32()?,
+                        quest_file: r.read_string_u8()?,
+                    })
+                })?;
+            }
+            MapBlockType::ConstructionObjects => {
+                self.construction_objects = read_list(reader, OBJECT_SIZE, MapObject::read)?;
+            }
+            MapBlockType::SoundObjects => {
+                self.sounds = read_list(reader, OBJECT_SIZE + 9, |r| {
+                    Ok(MapSound {
+                        object: MapObject::read(r)?,
+                        path: r.read_string_u8()?,
+                        range: r.read_u32()?,
+                        interval: r.read_u32()?,
+                    })
+                })?;
+            }
+            MapBlockType::EffectObjects => {
+                self.effects = read_list(reader, OBJECT_SIZE + 1, |r| {
+                    Ok(MapEffect {
+                        object: MapObject::read(r)?,
+                        path: r.read_string_u8()?,
+                    })
+                })?;
+            }
+            MapBlockType::AnimatedObjects => {
+                self.animated_objects = read_list(reader, OBJECT_SIZE, MapObject::read)?;
+            }
+            MapBlockType::WaterPatches => {
+                let width = reader.read_i32()?;
+                let height = reader.read_i32()?;
+                let width = reader.check_count("IFO", width as i64, 17)?;
+                let height = reader.check_count("IFO", height as i64, 17 * width as u64)?;
+
+                self.water_patches = Vec::with_capacity(height);
+                for _ in 0..height {
+                    let mut row = Vec::with_capacity(width);
+                    for _ in 0..width {
+                        row.push(MapWaterPatch {
+                            has_water: reader.read_bool()?,
+                            height: reader.read_f32()?,
+                            water_type: reader.read_u32()?,
+                            texture_index: reader.read_u32()?,
+                            reserved: reader.read_u32()?,
+                        });
+                    }
+                    self.water_patches.push(row);
+                }
+            }
+            MapBlockType::MonsterSpawns => {
+                self.monster_spawns = read_list(reader, OBJECT_SIZE + 25, |r| {
+                    Ok(MapMonsterSpawn {
+                        object: MapObject::read(r)?,
+                        name: r.read_string_u8()?,
+                        basic_monsters: read_list(r, 9, MapSpawnMonster::read)?,
+                        tactic_monsters: read_list(r, 9, MapSpawnMonster::read)?,
+                        interval: r.read_u32()?,
+                        limit: r.read_u32()?,
+                        range: r.read_u32()?,
+                        tactic_points: r.read_u32()?,
+                    })
+                })?;
+            }
+            MapBlockType::WaterPlanes => {
+                self.water_size = reader.read_f32()?;
+                self.water_planes = read_list(reader, 24, |r| {
+                    Ok(MapWaterPlane {
+                        start: r.read_vector3_f32()?,
+                        end: r.read_vector3_f32()?,
+                    })
+                })?;
+            }
+            MapBlockType::Warps => {
+                self.warps = read_list(reader, OBJECT_SIZE, MapObject::read)?;
+            }
+            MapBlockType::CollisionObjects => {
+                self.collision_objects = read_list(reader, OBJECT_SIZE, MapObject::read)?;
+            }
+            MapBlockType::EventObjects => {
+                self.events = read_list(reader, OBJECT_SIZE + 2, |r| {
+                    Ok(MapEvent {
+                        object: MapObject::read(r)?,
+                        quest_trigger: r.read_string_u8()?,
+                        script_function: r.read_string_u8()?,
+                    })
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_block<W: WriteRoseExt>(&self, writer: &mut W, block_type: MapBlockType) -> Result<(), Error> {
+        match block_type {
+            MapBlockType::MapInfo => {
+                writer.write_i32(self.width)?;
+                writer.write_i32(self.height)?;
+                writer.write_vector2_i32(&self.map_position)?;
+                for &value in self.world_matrix.iter() {
+                    writer.write_f32(value)?;
+                }
+                writer.write_string_u8(&self.name)?;
+            }
+            MapBlockType::DecoObjects => {
+                write_list(writer, &self.deco_objects, |w, o| o.write(w))?;
+            }
+            MapBlockType::Npcs => {
+                write_list(writer, &self.npcs, |w, npc| {
+                    npc.object.write(w)?;
+                    w.write_u32(npc.ai_id)?;
+                    w.write_string_u8(&npc.quest_file)
+                })?;
+            }
+            MapBlockType::ConstructionObjects => {
+                write_list(writer, &self.construction_objects, |w, o| o.write(w))?;
+            }
+            MapBlockType::SoundObjects => {
+                write_list(writer, &self.sounds, |w, sound| {
+                    sound.object.write(w)?;
+                    w.write_string_u8(&sound.path)?;
+                    w.write_u32(sound.range)?;
+                    w.write_u32(sound.interval)
+                })?;
+            }
+            MapBlockType::EffectObjects => {
+                write_list(writer, &self.effects, |w, effect| {
+                    effect.object.write(w)?;
+                    w.write_string_u8(&effect.path)
+                })?;
+            }
+            MapBlockType::AnimatedObjects => {
+                write_list(writer, &self.animated_objects, |w, o| o.write(w))?;
+            }
+            MapBlockType::WaterPatches => {
+                let width = self.water_patches.first().map(|row| row.len()).unwrap_or(0);
+                if self.water_patches.iter().any(|row| row.len() != width) {
+                    bail!("IFO water patch rows have different lengths");
+                }
+
+                writer.write_i32(width as i32)?;
+                writer.write_i32(self.water_patches.len() as i32)?;
+                for patch in self.water_patches.iter().flat_map(|row| row.iter()) {
+                    writer.write_bool(patch.has_water)?;
+                    writer.write_f32(patch.height)?;
+                    writer.write_u32(patch.water_type)?;
+                    writer.write_u32(patch.texture_index)?;
+                    writer.write_u32(patch.reserved)?;
+                }
+            }
+            MapBlockType::MonsterSpawns => {
+                write_list(writer, &self.monster_spawns, |w, spawn| {
+                    spawn.object.write(w)?;
+                    w.write_string_u8(&spawn.name)?;
+                    write_list(w, &spawn.basic_monsters, |w, m| m.write(w))?;
+                    write_list(w, &spawn.tactic_monsters, |w, m| m.write(w))?;
+                    w.write_u32(spawn.interval)?;
+                    w.write_u32(spawn.limit)?;
+                    w.write_u32(spawn.range)?;
+                    w.write_u32(spawn.tactic_points)
+                })?;
+            }
+            MapBlockType::WaterPlanes => {
+                writer.write_f32(self.water_size)?;
+                write_list(writer, &self.water_planes, |w, plane| {
+                    w.write_vector3_f32(&plane.start)?;
+                    w.write_vector3_f32(&plane.end)
+                })?;
+            }
+            MapBlockType::Warps => {
+                write_list(writer, &self.warps, |w, o| o.write(w))?;
+            }
+            MapBlockType::CollisionObjects => {
+                write_list(writer, &self.collision_objects, |w, o| o.write(w))?;
+            }
+            MapBlockType::EventObjects => {
+                write_list(writer, &self.events, |w, event| {
+                    event.object.write(w)?;
+                    w.write_string_u8(&event.quest_trigger)?;
+                    w.write_string_u8(&event.script_function)
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl RoseFile for MapData {
+    fn new() -> MapData {
+        MapData {
+            width: 0,
+            height: 0,
+            map_position: Vector2::<i32>::new(),
+            world_matrix: [0.0; 16],
+            name: String::new(),
+            deco_objects: Vec::new(),
+            npcs: Vec::new(),
+            construction_objects: Vec::new(),
+            sounds: Vec::new(),
+            effects: Vec::new(),
+            animated_objects: Vec::new(),
+            water_patches: Vec::new(),
+            monster_spawns: Vec::new(),
+            water_size: 0.0,
+            water_planes: Vec::new(),
+            warps: Vec::new(),
+            collision_objects: Vec::new(),
+            events: Vec::new(),
+        }
+    }
+
+    fn read<R: ReadRoseExt>(&mut self, reader: &mut R) -> Result<(), Error> {
+        let block_count = reader.read_i32()?;
+        let block_count = reader.check_count("IFO", block_count as i64, 8)?;
+
+        // Map block type/offset pairs
+        let mut blocks: Vec<(i32, i32)> = Vec::with_capacity(block_count);
+        for _ in 0..block_count {
+            let block_type = reader.read_i32()?;
+            let offset = reader.read_i32()?;
+            blocks.push((block_type, offset));
+        }
+
+        for (block_type, offset) in blocks {
+            let block_type = match num_from::from_i32(block_type) {
+                Some(t) => t,
+                None => {
+                    return Err(RoseError::InvalidData {
+                        format: "IFO",
+                        reason: format!("Invalid block type: {}", block_type),
+                    }.into());
+                }
+            };
+
+            reader.seek(SeekFrom::Start(offset as u64))?;
+            self.read_block(reader, block_type)?;
+        }
+
+        Ok(())
+    }
+
+    fn write<W: WriteRoseExt>(&mut self, writer: &mut W) -> Result<(), Error> {
+        let start = writer.stream_position()?;
+
+        // Offsets are filled in once the blocks are written
+        writer.write_i32(BLOCK_TYPES.len() as i32)?;
+        for &block_type in BLOCK_TYPES.iter() {
+            writer.write_i32(block_type as i32)?;
+            writer.write_i32(0)?;
+        }
+
+        let mut offsets = Vec::with_capacity(BLOCK_TYPES.len());
+        for &block_type in BLOCK_TYPES.iter() {
+            offsets.push(writer.stream_position()? - start);
+            self.write_block(writer, block_type)?;
+        }
+        let end = writer.stream_position()?;
+
+        for (i, &offset) in offsets.iter().enumerate() {
+            writer.seek(SeekFrom::Start(start + 4 + i as u64 * 8 + 4))?;
+            writer.write_i32(offset as i32)?;
+        }
+        writer.seek(SeekFrom::Start(end))?;
+
+        Ok(())
+    }
+}
@@ -3,7 +3,15 @@
 //! ROSE Online uses pre-baked lights that get rendered to a lightmap texture
 //! for blending with terrain/object textures.
 //!
+//! The format has no version marker. Only one part layout is known, files
+//! with bytes left after the filename list are reported as a warning with
+//! the `logging` feature.
+#[cfg(feature = "image")]
+use std::collections::HashMap;
+
 use failure::Error;
+#[cfg(feature = "image")]
+use image::RgbaImage;
 use io::{RoseFile, ReadRoseExt, WriteRoseExt};
 
 /// Lightmap file
@@ -41,13 +49,13 @@ impl RoseFile for Lightmap {
     }
 
     fn read<R: ReadRoseExt>(&mut self, reader: &mut R) -> Result<(), Error> {
-        let object_count = reader.read_i32()?;
-
-        for _ in 0..object_count {
+        self.objects = reader.read_vec_i32("LIT", 8, |reader| {
             let mut object = LightmapObject::new();
 
+            // The part count comes before the object id
             let part_count = reader.read_i32()?;
             object.id = reader.read_i32()?;
+            let part_count = reader.check_count("LIT", part_count as i64, 22)?;
 
             for _ in 0..part_count {
                 let mut part = LightmapPart::new();
@@ -62,13 +70,16 @@ impl RoseFile for Lightmap {
                 object.parts.push(part);
             }
 
-            self.objects.push(object);
-        }
+            Ok(object)
+        })?;
 
-        let file_count = reader.read_i32()?;
+        self.filenames = reader.read_vec_i32("LIT", 1, |r| r.read_string_u8())?;
 
-        for _ in 0..file_count {
-            self.filenames.push(reader.read_string_u8()?);
+        if log_enabled!(::log::Level::Warn) {
+            let remaining = reader.bytes_remaining()?;
+            if remaining > 0 {
+                warn!("LIT: {} trailing bytes not parsed, unknown layout", remaining);
+            }
         }
 
         Ok(())
@@ -100,6 +111,24 @@ impl RoseFile for Lightmap {
 
         Ok(())
     }
+
+    fn size_hint(&self) -> usize {
+        let mut size = 4;
+
+        for object in &self.objects {
+            size += 8;
+            for part in &object.parts {
+                size += 1 + part.name.len() + 4 + 1 + part.filename.len() + 16;
+            }
+        }
+
+        size += 4;
+        for filename in &self.filenames {
+            size += 1 + filename.len();
+        }
+
+        size
+    }
 }
 
 impl LightmapObject {
@@ -123,4 +152,108 @@ impl LightmapPart {
             part_position: -1,
         }
     }
+
+    /// Rectangle of the part in its lightmap texture in pixels, as
+    /// `(x, y, width, height)`
+    ///
+    /// Textures are split in rows of `parts_per_width` squares of
+    /// `pixels_per_part` pixels, `part_position` being the index of the
+    /// square of the part. Returns `None` if the layout is invalid.
+    pub fn atlas_rect(&self) -> Option<(u32, u32, u32, u32)> {
+        if self.pixels_per_part <= 0 || self.parts_per_width <= 0 || self.part_position < 0 {
+            return None;
+        }
+
+        let size = self.pixels_per_part as u32;
+        let columns = self.parts_per_width as u32;
+        let position = self.part_position as u32;
+        Some(((position % columns) * size, (position / columns) * size, size, size))
+    }
+}
+
+impl Lightmap {
+    /// Filename of the lightmap texture of a part
+    ///
+    /// The texture is looked up by `lightmap_index` in `filenames`, falling
+    /// back to the part's own `filename`.
+    pub fn filename_for<'a>(&'a self, part: &'a LightmapPart) -> Option<&'a str> {
+        if part.lightmap_index >= 0 {
+            if let Some(filename) = self.filenames.get(part.lightmap_index as usize) {
+                return Some(filename);
+            }
+        }
+
+        if part.filename.is_empty() {
+            None
+        } else {
+            Some(&part.filename)
+        }
+    }
+}
+
+#[cfg(feature = "image")]
+impl Lightmap {
+    /// Composite the lightmaps of all parts into a single debug image
+    ///
+    /// Each lightmap texture gets a square slot sized to fit the parts that
+    /// use it, slots are placed left to right in the order of `filenames`.
+    /// Each part is copied from its texture at `atlas_rect` to the same rect
+    /// in the slot of the texture.
+    ///
+    /// `dds_loader` loads a texture given its filename, it is called once per
+    /// texture. The rects of textures that can't be loaded are left blank
+    /// (transparent), as are pixels outside of a loaded texture.
+    pub fn composite<F>(&self, dds_loader: F) -> RgbaImage
+        where F: Fn(&str) -> Option<RgbaImage>
+    {
+        // Texture filenames in slot order along with the size of their slot
+        let mut slots: Vec<(&str, u32)> = self.filenames.iter().map(|f| (f.as_str(), 0)).collect();
+        let mut parts = Vec::new();
+        for part in self.objects.iter().flat_map(|o| o.parts.iter()) {
+            let (filename, rect) = match (self.filename_for(part), part.atlas_rect()) {
+                (Some(filename), Some(rect)) => (filename, rect),
+                _ => continue,
+            };
+
+            let slot = match slots.iter().position(|s| s.0 == filename) {
+                Some(slot) => slot,
+                None => {
+                    slots.push((filename, 0));
+                    slots.len() - 1
+                }
+            };
+
+            let (x, y, width, height) = rect;
+            slots[slot].1 = slots[slot].1.max(x + width).max(y + height);
+            parts.push((slot, rect));
+        }
+
+        let mut slot_offsets = Vec::with_capacity(slots.len());
+        let mut canvas_width = 0;
+        for slot in &slots {
+            slot_offsets.push(canvas_width);
+            canvas_width += slot.1;
+        }
+        let canvas_height = slots.iter().map(|s| s.1).max().unwrap_or(0);
+        let mut canvas = RgbaImage::new(canvas_width, canvas_height);
+
+        let mut textures: HashMap<usize, Option<RgbaImage>> = HashMap::new();
+        for (slot, (x, y, width, height)) in parts {
+            let texture = textures.entry(slot).or_insert_with(|| dds_loader(slots[slot].0));
+            let texture = match *texture {
+                Some(ref texture) => texture,
+                None => continue,
+            };
+
+            let right = (x + width).min(texture.width());
+            let bottom = (y + height).min(texture.height());
+            for ty in y..bottom {
+                for tx in x..right {
+                    canvas.put_pixel(slot_offsets[slot] + tx, ty, *texture.get_pixel(tx, ty));
+                }
+            }
+        }
+
+        canvas
+    }
 }
@@ -1,14 +1,32 @@
-// pub mod idx;
+pub mod aip;
+pub mod dds;
+pub mod filetype;
 pub mod him;
 pub mod idx;
+pub mod ifo;
 pub mod lit;
+pub mod stb;
+pub mod stl;
 pub mod til;
+pub mod tsi;
+pub mod zmd;
+pub mod zmo;
 pub mod zms;
 pub mod zon;
+pub mod zsc;
 
+pub use self::aip::AIP;
+pub use self::dds::DDS;
 pub use self::him::HIM;
 pub use self::idx::IDX;
+pub use self::ifo::IFO;
 pub use self::lit::LIT;
+pub use self::stb::STB;
+pub use self::stl::STL;
 pub use self::til::TIL;
+pub use self::tsi::TSI;
+pub use self::zmd::ZMD;
+pub use self::zmo::ZMO;
 pub use self::zms::ZMS;
 pub use self::zon::ZON;
+pub use self::zsc::ZSC;
@@ -0,0 +1,182 @@
+//! ROSE Online Data Tables
+//!
+//! Most of the game data (items, NPCs, skills, zones, ...) is stored in
+//! spreadsheet-like `.STB` tables of string cells. The first row holds the
+//! column names and the first column holds the row names, these are stored
+//! separately from the data cells.
+//!
+//! Text displayed to the player is usually not stored in the table itself
+//! but in a matching string table (`.STL`), see `files::stl`.
+use std::io::SeekFrom;
+
+use errors::RoseError;
+use failure::Error;
+use io::{RoseFile, ReadRoseExt, WriteRoseExt};
+
+/// Data Table File
+pub type STB = DataTable;
+
+/// Data Table
+///
+/// `rows` holds the data cells only, each row has `column_names.len() - 1`
+/// cells as the first column name is the heading of the row names.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct DataTable {
+    pub identifier: String,
+    pub row_height: i32,
+    pub root_column_width: i16,
+    pub column_widths: Vec<i16>,
+    pub root_column_name: String,
+    pub column_names: Vec<String>,
+    pub row_names: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl DataTable {
+    /// Number of data rows
+    pub fn row_count(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Number of data columns
+    pub fn column_count(&self) -> usize {
+        self.column_names.len().saturating_sub(1)
+    }
+
+    /// Get a data cell
+    pub fn cell(&self, row: usize, column: usize) -> Option<&str> {
+        self.rows.get(row)
+            .and_then(|r| r.get(column))
+            .map(|c| c.as_str())
+    }
+}
+
+impl RoseFile for DataTable {
+    fn new() -> DataTable {
+        DataTable {
+            identifier: String::from("STB1"),
+            row_height: 0,
+            root_column_width: 0,
+            column_widths: Vec::new(),
+            root_column_name: String::new(),
+            column_names: Vec::new(),
+            row_names: Vec::new(),
+            rows: Vec::new(),
+        }
+    }
+
+    fn read<R: ReadRoseExt>(&mut self, reader: &mut R) -> Result<(), Error> {
+        self.identifier = reader.read_string(4)?;
+        if self.identifier != "STB1" {
+            if self.identifier.starts_with("STB") {
+                return Err(RoseError::UnsupportedVersion {
+                    format: "STB",
+                    found: self.identifier.clone(),
+                }.into());
+            }
+            return Err(RoseError::InvalidMagic {
+                format: "STB",
+                found: self.identifier.clone(),
+            }.into());
+        }
+
+        let data_offset = reader.read_u32()?;
+        let row_count = reader.read_i32()?;
+        let row_count = reader.check_count("STB", row_count as i64, 0)?;
+        let column_count = reader.read_i32()?;
+        let column_count = reader.check_count("STB", column_count as i64, 4)?;
+        self.row_height = reader.read_i32()?;
+
+        self.root_column_width = reader.read_i16()?;
+        for _ in 0..column_count {
+            self.column_widths.push(reader.read_i16()?);
+        }
+
+        self.root_column_name = reader.read_string_u16()?;
+        for _ in 0..column_count {
+            self.column_names.push(reader.read_string_u16()?);
+        }
+
+        // Row and column counts include the names
+        let data_rows = row_count.saturating_sub(1);
+        let data_columns = column_count.saturating_sub(1);
+
+        for _ in 0..data_rows {
+            self.row_names.push(reader.read_string_u16()?);
+        }
+
+        reader.seek(SeekFrom::Start(data_offset as u64))?;
+        reader.check_count("STB", (data_rows * data_columns) as i64, 2)?;
+
+        for _ in 0..data_rows {
+            let mut row = Vec::with_capacity(data_columns);
+            for _ in 0..data_columns {
+                row.push(reader.read_string_u16()?);
+            }
+            self.rows.push(row);
+        }
+
+        Ok(())
+    }
+
+    fn write<W: WriteRoseExt>(&mut self, writer: &mut W) -> Result<(), Error> {
+        let data_columns = self.column_count();
+        if self.column_widths.len() != self.column_names.len() {
+            bail!("STB has {} column widths for {} columns",
+                  self.column_widths.len(),
+                  self.column_names.len());
+        }
+        if self.row_names.len() != self.rows.len() {
+            bail!("STB has {} row names for {} rows", self.row_names.len(), self.rows.len());
+        }
+        if let Some(row) = self.rows.iter().position(|r| r.len() != data_columns) {
+            bail!("STB row {} does not have {} cells", row, data_columns);
+        }
+
+        let start = writer.stream_position()?;
+        writer.write_all(b"STB1")?;
+        writer.write_u32(0)?; // Data offset is written later
+        writer.write_i32(self.rows.len() as i32 + 1)?;
+        writer.write_i32(self.column_names.len() as i32)?;
+        writer.write_i32(self.row_height)?;
+
+        writer.write_i16(self.root_column_width)?;
+        for width in &self.column_widths {
+            writer.write_i16(*width)?;
+        }
+
+        writer.write_string_u16(&self.root_column_name)?;
+        for name in &self.column_names {
+            writer.write_string_u16(name)?;
+        }
+
+        for name in &self.row_names {
+            writer.write_string_u16(name)?;
+        }
+
+        let data_offset = writer.stream_position()?;
+        for row in &self.rows {
+            for cell in row {
+                writer.write_string_u16(cell)?;
+            }
+        }
+
+        let end = writer.stream_position()?;
+        writer.seek(SeekFrom::Start(start + 4))?;
+        writer.write_u32((data_offset - start) as u32)?;
+        writer.seek(SeekFrom::Start(end))?;
+
+        Ok(())
+    }
+
+    fn size_hint(&self) -> usize {
+        let mut size = 20 + 2 + self.column_widths.len() * 2;
+        size += 2 + self.root_column_name.len();
+        size += self.column_names.iter().map(|n| 2 + n.len()).sum::<usize>();
+        size += self.row_names.iter().map(|n| 2 + n.len()).sum::<usize>();
+        for row in &self.rows {
+            size += row.iter().map(|c| 2 + c.len()).sum::<usize>();
+        }
+        size
+    }
+}
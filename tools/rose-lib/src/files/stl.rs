@@ -0,0 +1,253 @@
+//! ROSE Online String Tables
+//!
+//! Text displayed to the player (item names, descriptions, quest messages)
+//! is stored in `.STL` string tables, one set of entries per language. Each
+//! entry is identified by a string key, usually referenced from a column of
+//! the matching data table (`.STB`).
+//!
+//! Strings are prefixed by their length encoded as a 7-bit variable length
+//! integer, the low 7 bits first and the high bit set while more bytes
+//! follow.
+use std::io::SeekFrom;
+
+use errors::RoseError;
+use failure::Error;
+use io::{RoseFile, ReadRoseExt, WriteRoseExt};
+
+/// String Table File
+pub type STL = StringTable;
+
+/// String Table
+///
+/// `languages` holds one entry per key for each language, in the same order
+/// as `keys`.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct StringTable {
+    pub identifier: String,
+    pub keys: Vec<StringTableKey>,
+    pub languages: Vec<Vec<StringTableEntry>>,
+}
+
+/// String Table key
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct StringTableKey {
+    pub key: String,
+    pub id: i32,
+}
+
+/// String Table entry
+///
+/// Only item tables (`ITST01`) and quest tables (`QEST01`) have
+/// descriptions and only quest tables have start and end messages, the
+/// fields are left empty for other tables.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct StringTableEntry {
+    pub text: String,
+    pub description: String,
+    pub start_message: String,
+    pub end_message: String,
+}
+
+impl StringTable {
+    /// Find the index of a key
+    pub fn index(&self, key: &str) -> Option<usize> {
+        self.keys.iter().position(|k| k.key == key)
+    }
+
+    /// Get the entry for a key in a language
+    pub fn entry(&self, key: &str, language: usize) -> Option<&StringTableEntry> {
+        let index = self.index(key)?;
+        self.languages.get(language).and_then(|l| l.get(index))
+    }
+
+    /// Get the text for a key in a language
+    pub fn text(&self, key: &str, language: usize) -> Option<&str> {
+        self.entry(key, language).map(|e| e.text.as_str())
+    }
+
+    fn has_description(&self) -> bool {
+        self.identifier == "ITST01" || self.identifier == "QEST01"
+    }
+
+    fn has_messages(&self) -> bool {
+        self.identifier == "QEST01"
+    }
+}
+
+impl StringTableEntry {
+    /// Construct an entry with only a text
+    pub fn new(text: &str) -> StringTableEntry {
+        StringTableEntry {
+            text: String::from(text),
+            description: String::new(),
+            start_message: String::new(),
+            end_message: String::new(),
+        }
+    }
+}
+
+impl RoseFile for StringTable {
+    fn new() -> StringTable {
+        StringTable {
+            identifier: String::from("NRST01"),
+            keys: Vec::new(),
+            languages: Vec::new(),
+        }
+    }
+
+    fn read<R: ReadRoseExt>(&mut self, reader: &mut R) -> Result<(), Error> {
+        self.identifier = read_string(reader)?;
+        match self.identifier.as_str() {
+            "NRST01" | "ITST01" | "QEST01" => {}
+            id if ["NRST", "ITST", "QEST"].iter().any(|t| id.starts_with(t)) => {
+                return Err(RoseError::UnsupportedVersion {
+                    format: "STL",
+                    found: self.identifier.clone(),
+                }.into());
+            }
+            _ => {
+                return Err(RoseError::InvalidMagic {
+                    format: "STL",
+                    found: self.identifier.clone(),
+                }.into());
+            }
+        }
+
+        let key_count = reader.read_i32()?;
+        let key_count = reader.check_count("STL", key_count as i64, 5)?;
+        for _ in 0..key_count {
+            let key = read_string(reader)?;
+            let id = reader.read_i32()?;
+            self.keys.push(StringTableKey { key, id });
+        }
+
+        let language_count = reader.read_i32()?;
+        let language_count = reader.check_count("STL", language_count as i64, 4)?;
+        let mut language_offsets = Vec::with_capacity(language_count);
+        for _ in 0..language_count {
+            language_offsets.push(reader.read_i32()?);
+        }
+
+        for language_offset in language_offsets {
+            reader.seek(SeekFrom::Start(language_offset as u64))?;
+
+            let mut entry_offsets = Vec::with_capacity(key_count);
+            for _ in 0..key_count {
+                entry_offsets.push(reader.read_i32()?);
+            }
+
+            let mut entries = Vec::with_capacity(key_count);
+            for entry_offset in entry_offsets {
+                reader.seek(SeekFrom::Start(entry_offset as u64))?;
+
+                let mut entry = StringTableEntry::new(&read_string(reader)?);
+                if self.has_description() {
+                    entry.description = read_string(reader)?;
+                }
+                if self.has_messages() {
+                    entry.start_message = read_string(reader)?;
+                    entry.end_message = read_string(reader)?;
+                }
+                entries.push(entry);
+            }
+
+            self.languages.push(entries);
+        }
+
+        Ok(())
+    }
+
+    fn write<W: WriteRoseExt>(&mut self, writer: &mut W) -> Result<(), Error> {
+        if let Some(language) = self.languages.iter().position(|l| l.len() != self.keys.len()) {
+            bail!("STL language {} does not have {} entries", language, self.keys.len());
+        }
+
+        write_string(writer, &self.identifier)?;
+
+        writer.write_i32(self.keys.len() as i32)?;
+        for key in &self.keys {
+            write_string(writer, &key.key)?;
+            writer.write_i32(key.id)?;
+        }
+
+        // Offset tables are reserved and written once the entries are
+        writer.write_i32(self.languages.len() as i32)?;
+        let language_table = writer.stream_position()?;
+        for _ in &self.languages {
+            writer.write_i32(0)?;
+        }
+
+        let mut language_offsets = Vec::with_capacity(self.languages.len());
+        for language in &self.languages {
+            let entry_table = writer.stream_position()?;
+            language_offsets.push(entry_table);
+            for _ in language {
+                writer.write_i32(0)?;
+            }
+
+            let mut entry_offsets = Vec::with_capacity(language.len());
+            for entry in language {
+                entry_offsets.push(writer.stream_position()?);
+                write_string(writer, &entry.text)?;
+                if self.has_description() {
+                    write_string(writer, &entry.description)?;
+                }
+                if self.has_messages() {
+                    write_string(writer, &entry.start_message)?;
+                    write_string(writer, &entry.end_message)?;
+                }
+            }
+
+            let end = writer.stream_position()?;
+            writer.seek(SeekFrom::Start(entry_table))?;
+            for offset in entry_offsets {
+                writer.write_i32(offset as i32)?;
+            }
+            writer.seek(SeekFrom::Start(end))?;
+        }
+
+        let end = writer.stream_position()?;
+        writer.seek(SeekFrom::Start(language_table))?;
+        for offset in language_offsets {
+            writer.write_i32(offset as i32)?;
+        }
+        writer.seek(SeekFrom::Start(end))?;
+
+        Ok(())
+    }
+}
+
+fn read_string<R: ReadRoseExt>(reader: &mut R) -> Result<String, Error> {
+    let mut length: u64 = 0;
+    let mut shift = 0;
+    loop {
+        let b = reader.read_u8()?;
+        length |= ((b & 0x7F) as u64) << shift;
+        if b & 0x80 == 0 {
+            break;
+        }
+
+        shift += 7;
+        if shift > 28 {
+            bail!("Invalid STL string length");
+        }
+    }
+
+    reader.read_string(length)
+}
+
+fn write_string<W: WriteRoseExt>(writer: &mut W, string: &str) -> Result<(), Error> {
+    let mut length = string.len();
+    loop {
+        let b = (length & 0x7F) as u8;
+        length >>= 7;
+        if length == 0 {
+            writer.write_u8(b)?;
+            break;
+        }
+        writer.write_u8(b | 0x80)?;
+    }
+
+    writer.write_all(string.as_bytes())?;
+    Ok(())
+}
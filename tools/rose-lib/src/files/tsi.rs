@@ -0,0 +1,149 @@
+//! ROSE Online Sprite Sheets
+//!
+//! UI and other 2D elements are stored in texture atlases. A `.TSI` file
+//! lists the atlas textures and the named rectangles (sprites) within each
+//! of them.
+use failure::Error;
+use io::{RoseFile, ReadRoseExt, WriteRoseExt};
+
+/// Sprite Sheet File
+pub type TSI = SpriteSheet;
+
+const NAME_SIZE: u64 = 32;
+
+/// Sprite Sheet
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct SpriteSheet {
+    pub textures: Vec<SpriteTexture>,
+}
+
+/// Sprite Sheet texture
+///
+/// An atlas texture and the sprites it contains
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct SpriteTexture {
+    pub path: String,
+    pub color_key: u32,
+    pub sprites: Vec<Sprite>,
+}
+
+/// Sprite
+///
+/// A named rectangle within a texture
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Sprite {
+    pub texture_id: u16,
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+    pub color: u32,
+    pub name: String,
+}
+
+impl SpriteSheet {
+    /// Find a sprite by name in any of the textures
+    pub fn sprite(&self, name: &str) -> Option<&Sprite> {
+        self.textures.iter()
+            .flat_map(|t| t.sprites.iter())
+            .find(|s| s.name == name)
+    }
+}
+
+impl Sprite {
+    /// Width of the sprite in pixels
+    pub fn width(&self) -> i32 {
+        self.right - self.left
+    }
+
+    /// Height of the sprite in pixels
+    pub fn height(&self) -> i32 {
+        self.bottom - self.top
+    }
+}
+
+impl RoseFile for SpriteSheet {
+    fn new() -> SpriteSheet {
+        SpriteSheet {
+            textures: Vec::new(),
+        }
+    }
+
+    fn read<R: ReadRoseExt>(&mut self, reader: &mut R) -> Result<(), Error> {
+        let texture_count = reader.read_u16()?;
+        let texture_count = reader.check_count("TSI", texture_count as i64, 6)?;
+        for _ in 0..texture_count {
+            let path = reader.read_string_u16()?;
+            let color_key = reader.read_u32()?;
+
+            self.textures.push(SpriteTexture {
+                path,
+                color_key,
+                sprites: Vec::new(),
+            });
+        }
+
+        let _total_sprite_count = reader.read_u16()?;
+        for texture in &mut self.textures {
+            let sprite_count = reader.read_u16()?;
+            let sprite_count = reader.check_count("TSI", sprite_count as i64, 22 + NAME_SIZE)?;
+            for _ in 0..sprite_count {
+                let texture_id = reader.read_u16()?;
+                let left = reader.read_i32()?;
+                let top = reader.read_i32()?;
+                let right = reader.read_i32()?;
+                let bottom = reader.read_i32()?;
+                let color = reader.read_u32()?;
+
+                let name = reader.read_string(NAME_SIZE)?;
+
+                texture.sprites.push(Sprite {
+                    texture_id,
+                    left,
+                    top,
+                    right,
+                    bottom,
+                    color,
+                    name,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write<W: WriteRoseExt>(&mut self, writer: &mut W) -> Result<(), Error> {
+        writer.write_u16(self.textures.len() as u16)?;
+        for texture in &self.textures {
+            writer.write_string_u16(&texture.path)?;
+            writer.write_u32(texture.color_key)?;
+        }
+
+        let total_sprite_count: usize = self.textures.iter().map(|t| t.sprites.len()).sum();
+        writer.write_u16(total_sprite_count as u16)?;
+
+        for texture in &self.textures {
+            writer.write_u16(texture.sprites.len() as u16)?;
+            for sprite in &texture.sprites {
+                writer.write_u16(sprite.texture_id)?;
+                writer.write_i32(sprite.left)?;
+                writer.write_i32(sprite.top)?;
+                writer.write_i32(sprite.right)?;
+                writer.write_i32(sprite.bottom)?;
+                writer.write_u32(sprite.color)?;
+                writer.write_string_fixed(&sprite.name, NAME_SIZE as usize)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn size_hint(&self) -> usize {
+        let mut size = 4;
+        for texture in &self.textures {
+            size += 2 + texture.path.len() + 4;
+            size += 2 + texture.sprites.len() * (2 + 16 + 4 + NAME_SIZE as usize);
+        }
+        size
+    }
+}
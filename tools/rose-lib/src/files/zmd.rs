@@ -0,0 +1,189 @@
+//! ROSE Online Skeletons
+//!
+//! A `.ZMD` file lists the bones of a skeleton followed by its dummies,
+//! the attachment points of weapons and effects. Each bone is placed
+//! relative to its parent, the root bone is its own parent. Motions (ZMO)
+//! index the bones then the dummies, dummy `i` being index
+//! `bones.len() + i`.
+use errors::RoseError;
+use failure::Error;
+use io::{RoseFile, ReadRoseExt, WriteRoseExt};
+use utils::{Matrix4, Vector3, Vector4};
+use utils::matrix;
+
+/// Skeleton File
+pub type ZMD = Skeleton;
+
+/// Skeleton
+///
+/// `ZMD0002` dummies have no rotation, they are read with the identity
+/// rotation.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Skeleton {
+    pub identifier: String,
+    pub bones: Vec<Bone>,
+    pub dummies: Vec<Bone>,
+}
+
+/// Bone or dummy of a skeleton
+///
+/// `parent` is the index of the parent bone, `position` and `rotation`
+/// (a quaternion) are relative to it.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Bone {
+    pub name: String,
+    pub parent: u32,
+    pub position: Vector3<f32>,
+    pub rotation: Vector4<f32>,
+}
+
+impl Skeleton {
+    /// Number of bones and dummies, the indices motions can animate
+    pub fn node_count(&self) -> usize {
+        self.bones.len() + self.dummies.len()
+    }
+
+    /// World transform of each bone in the bind pose
+    ///
+    /// Returns the matrices of the bones then the dummies, in the order
+    /// motions index them. The transform of a node is the transform of its
+    /// parent composed with its own position and rotation, bones that are
+    /// their own parent are roots.
+    ///
+    /// Fails if a bone's parent is not an earlier bone or a dummy's parent
+    /// is not a bone.
+    pub fn bind_pose_matrices(&self) -> Result<Vec<Matrix4>, Error> {
+        let mut matrices: Vec<Matrix4> = Vec::with_capacity(self.node_count());
+        for (i, bone) in self.bones.iter().chain(&self.dummies).enumerate() {
+            let local = matrix::multiply(&matrix::translate(bone.position),
+                                         &matrix::rotate(bone.rotation));
+
+            let parent = bone.parent as usize;
+            let is_root = i < self.bones.len() && parent == i;
+            if !is_root && parent >= matrices.len().min(self.bones.len()) {
+                return Err(RoseError::InvalidData {
+                    format: "ZMD",
+                    reason: format!("{} has invalid parent {}", bone.name, bone.parent),
+                }.into());
+            }
+
+            matrices.push(if is_root {
+                local
+            } else {
+                matrix::multiply(&matrices[parent], &local)
+            });
+        }
+        Ok(matrices)
+    }
+
+    /// Inverse of `bind_pose_matrices`, transforming from the world to the
+    /// space of each bone (the inverse bind matrices of glTF skins)
+    pub fn inverse_bind_matrices(&self) -> Result<Vec<Matrix4>, Error> {
+        Ok(self.bind_pose_matrices()?.iter().map(matrix::inverse_rigid).collect())
+    }
+
+    fn version(&self) -> Result<u8, Error> {
+        match self.identifier.as_str() {
+            "ZMD0002" => Ok(2),
+            "ZMD0003" => Ok(3),
+            _ if self.identifier.starts_with("ZMD") => {
+                Err(RoseError::UnsupportedVersion {
+                    format: "ZMD",
+                    found: self.identifier.clone(),
+                }.into())
+            }
+            _ => {
+                Err(RoseError::InvalidMagic {
+                    format: "ZMD",
+                    found: self.identifier.clone(),
+                }.into())
+            }
+        }
+    }
+}
+
+impl Bone {
+    pub fn new() -> Bone {
+        Bone {
+            name: String::new(),
+            parent: 0,
+            position: Vector3::<f32>::new(),
+            rotation: Vector4 { w: 1.0, x: 0.0, y: 0.0, z: 0.0 },
+        }
+    }
+}
+
+impl Default for Bone {
+    fn default() -> Bone {
+        Bone::new()
+    }
+}
+
+impl RoseFile for Skeleton {
+    fn new() -> Skeleton {
+        Skeleton {
+            identifier: String::from("ZMD0003"),
+            bones: Vec::new(),
+            dummies: Vec::new(),
+        }
+    }
+
+    fn read<R: ReadRoseExt>(&mut self, reader: &mut R) -> Result<(), Error> {
+        // The identifier is not null terminated
+        self.identifier = reader.read_string(7)?;
+        let version = self.version()?;
+
+        self.bones = reader.read_vec_u32("ZMD", 33, |r| {
+            let mut bone = Bone::new();
+            bone.parent = r.read_u32()?;
+            bone.name = r.read_cstring()?;
+            bone.position = r.read_vector3_f32()?;
+            bone.rotation = r.read_vector4_f32()?;
+            Ok(bone)
+        })?;
+
+        self.dummies = reader.read_vec_u32("ZMD", 17, |r| {
+            let mut dummy = Bone::new();
+            dummy.name = r.read_cstring()?;
+            dummy.parent = r.read_u32()?;
+            dummy.position = r.read_vector3_f32()?;
+            if version >= 3 {
+                dummy.rotation = r.read_vector4_f32()?;
+            }
+            Ok(dummy)
+        })?;
+
+        Ok(())
+    }
+
+    fn write<W: WriteRoseExt>(&mut self, writer: &mut W) -> Result<(), Error> {
+        let version = self.version()?;
+
+        writer.write_all(self.identifier.as_bytes())?;
+
+        writer.write_u32(self.bones.len() as u32)?;
+        for bone in &self.bones {
+            writer.write_u32(bone.parent)?;
+            writer.write_cstring(&bone.name)?;
+            writer.write_vector3_f32(&bone.position)?;
+            writer.write_vector4_f32(&bone.rotation)?;
+        }
+
+        writer.write_u32(self.dummies.len() as u32)?;
+        for dummy in &self.dummies {
+            writer.write_cstring(&dummy.name)?;
+            writer.write_u32(dummy.parent)?;
+            writer.write_vector3_f32(&dummy.position)?;
+            if version >= 3 {
+                writer.write_vector4_f32(&dummy.rotation)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn size_hint(&self) -> usize {
+        let names: usize = self.bones.iter().chain(&self.dummies).map(|b| b.name.len() + 1).sum();
+        7 + 8 + self.bones.len() * 32 + self.dummies.len() * 32 + names
+    }
+}
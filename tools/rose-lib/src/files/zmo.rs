@@ -0,0 +1,442 @@
+//! ROSE Online Motions
+//!
+//! A `.ZMO` file animates the bones of a skeleton or the vertices of a mesh.
+//! A motion is made of channels, each channel animates one property (e.g.
+//! the rotation) of one bone and has a value for every frame of the motion.
+//!
+//! Some files are followed by an extended block of frame events, it is not
+//! read.
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use errors::RoseError;
+use failure::Error;
+use files::zmd::Skeleton;
+use io::{RoseFile, ReadRoseExt, WriteRoseExt};
+use num::FromPrimitive as num_from;
+use utils::{Vector2, Vector3, Vector4};
+
+/// Motion File
+pub type ZMO = Motion;
+
+/// Motion
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Motion {
+    pub identifier: String,
+    pub fps: u32,
+    pub frame_count: u32,
+    pub channels: Vec<MotionChannel>,
+}
+
+/// Property animated by a channel
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, FromPrimitive, PartialEq)]
+pub enum ChannelType {
+    None = 1 << 0,
+    Position = 1 << 1,
+    Rotation = 1 << 2,
+    Normal = 1 << 3,
+    Alpha = 1 << 4,
+    UV1 = 1 << 5,
+    UV2 = 1 << 6,
+    UV3 = 1 << 7,
+    UV4 = 1 << 8,
+    Texture = 1 << 9,
+    Scale = 1 << 10,
+}
+
+/// Value of a channel at one frame
+///
+/// Rotations are quaternions.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub enum ChannelValue {
+    None,
+    Position(Vector3<f32>),
+    Rotation(Vector4<f32>),
+    Normal(Vector3<f32>),
+    Alpha(f32),
+    UV1(Vector2<f32>),
+    UV2(Vector2<f32>),
+    UV3(Vector2<f32>),
+    UV4(Vector2<f32>),
+    Texture(f32),
+    Scale(f32),
+}
+
+/// Motion channel
+///
+/// `index` is the bone (or vertex) animated by the channel, `frames` has one
+/// value of the channel type per frame.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct MotionChannel {
+    pub channel_type: ChannelType,
+    pub index: u32,
+    pub frames: Vec<ChannelValue>,
+}
+
+impl ChannelType {
+    /// Size in bytes of one value of this type
+    pub fn value_size(&self) -> usize {
+        match *self {
+            ChannelType::None => 0,
+            ChannelType::Position | ChannelType::Normal => 12,
+            ChannelType::Rotation => 16,
+            ChannelType::UV1 | ChannelType::UV2 | ChannelType::UV3 | ChannelType::UV4 => 8,
+            ChannelType::Alpha | ChannelType::Texture | ChannelType::Scale => 4,
+        }
+    }
+}
+
+impl ChannelValue {
+    /// Interpolate from `self` (`t = 0`) to `other` (`t = 1`)
+    ///
+    /// Rotations are interpolated spherically, other values linearly. Values
+    /// that can't be blended (e.g. different types) step from `self` to
+    /// `other` at `t = 0.5`.
+    pub fn interpolate(&self, other: &ChannelValue, t: f32) -> ChannelValue {
+        match (*self, *other) {
+            (ChannelValue::Position(a), ChannelValue::Position(b)) => {
+                ChannelValue::Position(a.lerp(&b, t))
+            }
+            (ChannelValue::Rotation(a), ChannelValue::Rotation(b)) => {
+                ChannelValue::Rotation(a.slerp(&b, t))
+            }
+            (ChannelValue::Normal(a), ChannelValue::Normal(b)) => {
+                ChannelValue::Normal(a.lerp(&b, t))
+            }
+            (ChannelValue::Alpha(a), ChannelValue::Alpha(b)) => {
+                ChannelValue::Alpha(a + (b - a) * t)
+            }
+            (ChannelValue::UV1(a), ChannelValue::UV1(b)) => ChannelValue::UV1(a.lerp(&b, t)),
+            (ChannelValue::UV2(a), ChannelValue::UV2(b)) => ChannelValue::UV2(a.lerp(&b, t)),
+            (ChannelValue::UV3(a), ChannelValue::UV3(b)) => ChannelValue::UV3(a.lerp(&b, t)),
+            (ChannelValue::UV4(a), ChannelValue::UV4(b)) => ChannelValue::UV4(a.lerp(&b, t)),
+            (ChannelValue::Texture(a), ChannelValue::Texture(b)) => {
+                ChannelValue::Texture(a + (b - a) * t)
+            }
+            (ChannelValue::Scale(a), ChannelValue::Scale(b)) => {
+                ChannelValue::Scale(a + (b - a) * t)
+            }
+            (a, b) => if t < 0.5 { a } else { b },
+        }
+    }
+
+    fn read<R: ReadRoseExt>(reader: &mut R, channel_type: ChannelType) -> Result<ChannelValue, Error> {
+        Ok(match channel_type {
+            ChannelType::None => ChannelValue::None,
+            ChannelType::Position => ChannelValue::Position(reader.read_vector3_f32()?),
+            ChannelType::Rotation => ChannelValue::Rotation(reader.read_vector4_f32()?),
+            ChannelType::Normal => ChannelValue::Normal(reader.read_vector3_f32()?),
+            ChannelType::Alpha => ChannelValue::Alpha(reader.read_f32()?),
+            ChannelType::UV1 => ChannelValue::UV1(reader.read_vector2_f32()?),
+            ChannelType::UV2 => ChannelValue::UV2(reader.read_vector2_f32()?),
+            ChannelType::UV3 => ChannelValue::UV3(reader.read_vector2_f32()?),
+            ChannelType::UV4 => ChannelValue::UV4(reader.read_vector2_f32()?),
+            ChannelType::Texture => ChannelValue::Texture(reader.read_f32()?),
+            ChannelType::Scale => ChannelValue::Scale(reader.read_f32()?),
+        })
+    }
+
+    fn write<W: WriteRoseExt>(&self, writer: &mut W) -> Result<(), Error> {
+        match *self {
+            ChannelValue::None => {}
+            ChannelValue::Position(ref v) | ChannelValue::Normal(ref v) => {
+                writer.write_vector3_f32(v)?;
+            }
+            ChannelValue::Rotation(ref v) => writer.write_vector4_f32(v)?,
+            ChannelValue::UV1(ref v) |
+            ChannelValue::UV2(ref v) |
+            ChannelValue::UV3(ref v) |
+            ChannelValue::UV4(ref v) => writer.write_vector2_f32(v)?,
+            ChannelValue::Alpha(v) | ChannelValue::Texture(v) | ChannelValue::Scale(v) => {
+                writer.write_f32(v)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn channel_type(&self) -> ChannelType {
+        match *self {
+            ChannelValue::None => ChannelType::None,
+            ChannelValue::Position(_) => ChannelType::Position,
+            ChannelValue::Rotation(_) => ChannelType::Rotation,
+            ChannelValue::Normal(_) => ChannelType::Normal,
+            ChannelValue::Alpha(_) => ChannelType::Alpha,
+            ChannelValue::UV1(_) => ChannelType::UV1,
+            ChannelValue::UV2(_) => ChannelType::UV2,
+            ChannelValue::UV3(_) => ChannelType::UV3,
+            ChannelValue::UV4(_) => ChannelType::UV4,
+            ChannelValue::Texture(_) => ChannelType::Texture,
+            ChannelValue::Scale(_) => ChannelType::Scale,
+        }
+    }
+}
+
+impl Motion {
+    /// Duration of the motion in seconds
+    pub fn duration(&self) -> f32 {
+        if self.fps == 0 {
+            return 0.0;
+        }
+        self.frame_count as f32 / self.fps as f32
+    }
+
+    /// Sample the value of every channel at `time_seconds`
+    ///
+    /// The value is interpolated between the two frames around the time.
+    /// When `looping`, times past the last frame wrap around and the last
+    /// frame blends back into the first one, otherwise the time is clamped
+    /// to the first and last frames.
+    ///
+    /// Returns one value per channel, in the order of `channels`.
+    pub fn sample(&self, time_seconds: f32, looping: bool) -> Vec<ChannelValue> {
+        let frame_count = self.frame_count as usize;
+        if frame_count == 0 {
+            return self.channels.iter().map(|_| ChannelValue::None).collect();
+        }
+
+        let frame = time_seconds * self.fps as f32;
+        let (frame0, frame1, t) = if looping {
+            let frame = frame.rem_euclid(frame_count as f32);
+            let frame0 = (frame.floor() as usize).min(frame_count - 1);
+            (frame0, (frame0 + 1) % frame_count, frame - frame0 as f32)
+        } else {
+            let frame = frame.max(0.0).min((frame_count - 1) as f32);
+            let frame0 = frame.floor() as usize;
+            (frame0, (frame0 + 1).min(frame_count - 1), frame - frame0 as f32)
+        };
+
+        self.channels
+            .iter()
+            .map(|channel| {
+                match (channel.frames.get(frame0), channel.frames.get(frame1)) {
+                    (Some(a), Some(b)) => a.interpolate(b, t),
+                    (Some(a), None) => *a,
+                    _ => ChannelValue::None,
+                }
+            })
+            .collect()
+    }
+
+    /// Check that the bone channels animate bones or dummies of `skeleton`
+    ///
+    /// Position, rotation and scale channels index the bones then the
+    /// dummies of the skeleton, other channels animate mesh vertices and
+    /// are not checked. Fails with `RoseError::IndexOutOfRange` for the
+    /// first channel out of the skeleton.
+    pub fn check_skeleton(&self, skeleton: &Skeleton) -> Result<(), Error> {
+        let count = skeleton.node_count();
+        for channel in &self.channels {
+            let is_bone_channel = matches!(channel.channel_type,
+                ChannelType::Position | ChannelType::Rotation | ChannelType::Scale);
+            if is_bone_channel && channel.index as usize >= count {
+                return Err(RoseError::IndexOutOfRange {
+                    kind: "ZMO bone",
+                    index: channel.index as i64,
+                    count,
+                }.into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Motions of one skeleton by name
+///
+/// Motions are checked against the skeleton with `Motion::check_skeleton`
+/// when they are added, a set only holds motions the skeleton can play.
+#[derive(Debug)]
+pub struct AnimationSet<'a> {
+    skeleton: &'a Skeleton,
+    motions: BTreeMap<String, Motion>,
+}
+
+impl<'a> AnimationSet<'a> {
+    pub fn new(skeleton: &'a Skeleton) -> AnimationSet<'a> {
+        AnimationSet {
+            skeleton,
+            motions: BTreeMap::new(),
+        }
+    }
+
+    /// Load the `.ZMO` files of a directory, named after their file stem
+    ///
+    /// The extension is matched case insensitively, sub-directories are not
+    /// searched.
+    pub fn from_dir(skeleton: &'a Skeleton, dir: &Path) -> Result<AnimationSet<'a>, Error> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let is_motion = path.extension()
+                .and_then(|e| e.to_str())
+                .map(|e| e.eq_ignore_ascii_case("zmo"))
+                .unwrap_or(false);
+            if is_motion && path.is_file() {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        AnimationSet::from_paths(skeleton, &paths)
+    }
+
+    /// Load motion files, named after their file stem
+    ///
+    /// Fails on the first file that cannot be read or does not fit the
+    /// skeleton.
+    pub fn from_paths<P: AsRef<Path>>(skeleton: &'a Skeleton, paths: &[P]) -> Result<AnimationSet<'a>, Error> {
+        let mut set = AnimationSet::new(skeleton);
+        for path in paths {
+            let path = path.as_ref();
+            let name = match path.file_stem().and_then(|s| s.to_str()) {
+                Some(name) => name.to_string(),
+                None => bail!("Invalid motion path: {}", path.display()),
+            };
+            set.insert(name, Motion::from_path(path)?)?;
+        }
+        Ok(set)
+    }
+
+    /// Add a motion, replacing any motion of the same name
+    ///
+    /// Fails and leaves the set unchanged if the motion does not fit the
+    /// skeleton.
+    pub fn insert(&mut self, name: String, motion: Motion) -> Result<(), Error> {
+        motion.check_skeleton(self.skeleton)?;
+        self.motions.insert(name, motion);
+        Ok(())
+    }
+
+    /// Skeleton of the motions
+    pub fn skeleton(&self) -> &'a Skeleton {
+        self.skeleton
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Motion> {
+        self.motions.get(name)
+    }
+
+    /// Names of the motions in alphabetical order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.motions.keys().map(|name| name.as_str())
+    }
+
+    pub fn len(&self) -> usize {
+        self.motions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.motions.is_empty()
+    }
+}
+
+impl RoseFile for Motion {
+    fn new() -> Motion {
+        Motion {
+            identifier: String::from("ZMO0002"),
+            fps: 0,
+            frame_count: 0,
+            channels: Vec::new(),
+        }
+    }
+
+    fn read<R: ReadRoseExt>(&mut self, reader: &mut R) -> Result<(), Error> {
+        self.identifier = reader.read_cstring()?;
+        if self.identifier != "ZMO0002" {
+            if self.identifier.starts_with("ZMO") {
+                return Err(RoseError::UnsupportedVersion {
+                    format: "ZMO",
+                    found: self.identifier.clone(),
+                }.into());
+            }
+            return Err(RoseError::InvalidMagic {
+                format: "ZMO",
+                found: self.identifier.clone(),
+            }.into());
+        }
+
+        self.fps = reader.read_u32()?;
+        let frame_count = reader.read_i32()?;
+        let channel_count = reader.read_i32()?;
+        let channel_count = reader.check_count("ZMO", channel_count as i64, 8)?;
+
+        self.channels = Vec::with_capacity(channel_count);
+        for _ in 0..channel_count {
+            let channel_type = reader.read_i32()?;
+            let channel_type = match num_from::from_i32(channel_type) {
+                Some(channel_type) => channel_type,
+                None => {
+                    return Err(RoseError::InvalidData {
+                        format: "ZMO",
+                        reason: format!("Unknown channel type {}", channel_type),
+                    }.into());
+                }
+            };
+
+            self.channels.push(MotionChannel {
+                channel_type,
+                index: reader.read_u32()?,
+                frames: Vec::new(),
+            });
+        }
+
+        let frame_size: usize = self.channels.iter().map(|c| c.channel_type.value_size()).sum();
+        let frame_count = reader.check_count("ZMO", frame_count as i64, frame_size as u64)?;
+        self.frame_count = frame_count as u32;
+
+        for channel in &mut self.channels {
+            channel.frames = Vec::with_capacity(frame_count);
+        }
+
+        for _ in 0..frame_count {
+            for channel in &mut self.channels {
+                let value = ChannelValue::read(reader, channel.channel_type)?;
+                channel.frames.push(value);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write<W: WriteRoseExt>(&mut self, writer: &mut W) -> Result<(), Error> {
+        for channel in &self.channels {
+            if channel.frames.len() != self.frame_count as usize {
+                bail!("ZMO channel {} has {} frames, expected {}",
+                      channel.index,
+                      channel.frames.len(),
+                      self.frame_count);
+            }
+            if channel.frames.iter().any(|f| f.channel_type() != channel.channel_type) {
+                bail!("ZMO channel {} has values of a different type than {:?}",
+                      channel.index,
+                      channel.channel_type);
+            }
+        }
+
+        writer.write_cstring("ZMO0002")?;
+        writer.write_u32(self.fps)?;
+        writer.write_i32(self.frame_count as i32)?;
+        writer.write_i32(self.channels.len() as i32)?;
+
+        for channel in &self.channels {
+            writer.write_i32(channel.channel_type as i32)?;
+            writer.write_u32(channel.index)?;
+        }
+
+        for frame in 0..self.frame_count as usize {
+            for channel in &self.channels {
+                channel.frames[frame].write(writer)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn size_hint(&self) -> usize {
+        let frames: usize = self.channels
+            .iter()
+            .map(|c| c.channel_type.value_size() * c.frames.len())
+            .sum();
+
+        8 + 12 + self.channels.len() * 8 + frames
+    }
+}
@@ -1,7 +1,14 @@
 //! ROSE Online 3D Meshes
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::io::{BufRead, Cursor, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use errors::RoseError;
 use failure::Error;
 use io::{RoseFile, ReadRoseExt, WriteRoseExt};
-use utils::{BoundingBox, Color4, Vector2, Vector3, Vector4};
+use utils::{ApproxEq, BoundingBox, Color4, Matrix4, Transform, Vector2, Vector3, Vector4};
+use utils::matrix;
 
 
 /// Mesh File
@@ -14,18 +21,27 @@ pub struct Mesh {
     pub format: i32,
 
     pub bounding_box: BoundingBox<f32>,
+    /// Bone palette of the mesh: the skeleton (ZMD) bone of each palette
+    /// entry. Vertex `bone_indices` are indices into this palette, not into
+    /// the skeleton, see `Mesh::global_bone_index`.
     pub bones: Vec<i16>,
     pub vertices: Vec<Vertex>,
     pub indices: Vec<Vector3<i16>>,
     pub materials: Vec<i16>,
     pub strips: Vec<i16>,
 
-    // Pool properties for the vertex buffer [Static/Dynamic/System]
+    // Pool properties for the vertex buffer [Static/Dynamic/System], see
+    // `Mesh::pool_kind`
     pub pool: i16,
 }
 
 /// Mesh Vertex
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+///
+/// Each vertex is influenced by up to 4 bones. `bone_indices` are indices
+/// into the mesh `bones` palette (see `Mesh::global_bone_index`) and
+/// `bone_weights` the matching weights, in the order they are stored: `x` is
+/// the first influence and `w` the last.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Vertex {
     pub position: Vector3<f32>,
     pub normal: Vector3<f32>,
@@ -39,8 +55,58 @@ pub struct Vertex {
     pub uv4: Vector2<f32>,
 }
 
+/// Mesh statistics, see `Mesh::stats`
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct MeshStats {
+    pub vertex_count: usize,
+    pub triangle_count: usize,
+    /// Number of material groups covering at least one triangle
+    pub material_count: usize,
+    /// Vertices not referenced by any triangle
+    pub unreferenced_vertices: usize,
+    /// Lowest and highest sum of the bone weights of a vertex, `None` if
+    /// bones are not enabled or the mesh has no vertices
+    pub bone_weight_sum_range: Option<(f32, f32)>,
+    /// Vertices whose bone weights don't sum to 1.0 within
+    /// `BONE_WEIGHT_EPSILON`
+    pub bad_weight_vertices: usize,
+}
+
+/// Tolerance of the bone weight sums checked by `Mesh::stats`
+pub const BONE_WEIGHT_EPSILON: f32 = 1e-3;
+
+/// Memory pool of the vertex buffer of a mesh, see `Mesh::pool`
+///
+/// Unknown values convert to `Static`, the pool of most meshes.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum VertexPool {
+    Static = 0,
+    Dynamic = 1,
+    System = 2,
+}
+
+impl From<i16> for VertexPool {
+    fn from(pool: i16) -> VertexPool {
+        match pool {
+            0 => VertexPool::Static,
+            1 => VertexPool::Dynamic,
+            2 => VertexPool::System,
+            _ => {
+                warn!("ZMS: unknown vertex pool {}, using static", pool);
+                VertexPool::Static
+            }
+        }
+    }
+}
+
+impl From<VertexPool> for i16 {
+    fn from(pool: VertexPool) -> i16 {
+        pool as i16
+    }
+}
+
 /// Mesh Vertex Flags
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
 pub enum VertexFormat {
     Position = 1 << 1,
     Normal = 1 << 2,
@@ -55,6 +121,11 @@ pub enum VertexFormat {
 }
 
 impl Mesh {
+    /// Memory pool of the vertex buffer, see `VertexPool`
+    pub fn pool_kind(&self) -> VertexPool {
+        VertexPool::from(self.pool)
+    }
+
     pub fn positions_enabled(&self) -> bool {
         (VertexFormat::Position as i32 & self.format) != 0
     }
@@ -91,231 +162,2234 @@ impl Mesh {
     pub fn uv4_enabled(&self) -> bool {
         (VertexFormat::UV4 as i32 & self.format) != 0
     }
-}
 
+    /// Normal of vertex `i`, `None` if normals are not enabled or the vertex
+    /// doesn't exist
+    pub fn normal(&self, i: usize) -> Option<Vector3<f32>> {
+        self.attribute(self.normals_enabled(), i, |v| v.normal)
+    }
 
-impl RoseFile for Mesh {
-    fn new() -> Mesh {
-        Mesh {
-            identifier: String::from(""),
-            format: -1,
-            bounding_box: BoundingBox {
-                min: Vector3::<f32>::new(),
-                max: Vector3::<f32>::new(),
-            },
-            bones: Vec::new(),
-            vertices: Vec::new(),
-            indices: Vec::new(),
-            materials: Vec::new(),
-            strips: Vec::new(),
-            pool: 0,
+    /// Color of vertex `i`, `None` if colors are not enabled or the vertex
+    /// doesn't exist
+    pub fn color(&self, i: usize) -> Option<Color4> {
+        self.attribute(self.colors_enabled(), i, |v| v.color)
+    }
+
+    /// Bone weights of vertex `i`, `None` if bones are not enabled or the
+    /// vertex doesn't exist
+    pub fn bone_weights(&self, i: usize) -> Option<Vector4<f32>> {
+        self.attribute(self.bones_enabled(), i, |v| v.bone_weights)
+    }
+
+    /// Bone palette indices of vertex `i`, `None` if bones are not enabled
+    /// or the vertex doesn't exist, see `global_bone_index`
+    pub fn bone_indices(&self, i: usize) -> Option<Vector4<i16>> {
+        self.attribute(self.bones_enabled(), i, |v| v.bone_indices)
+    }
+
+    /// Map a vertex bone index through the bone palette to a skeleton bone
+    ///
+    /// Returns `None` if `vertex_bone` is not an index of `bones`. Exporters
+    /// must use the skeleton bone for skinning, not the vertex bone index.
+    pub fn global_bone_index(&self, vertex_bone: i16) -> Option<i16> {
+        if vertex_bone < 0 {
+            return None;
         }
+        self.bones.get(vertex_bone as usize).cloned()
     }
 
-    fn read<R: ReadRoseExt>(&mut self, reader: &mut R) -> Result<(), Error> {
-        self.identifier = reader.read_cstring()?;
+    /// Tangent of vertex `i`, `None` if tangents are not enabled or the
+    /// vertex doesn't exist
+    pub fn tangent(&self, i: usize) -> Option<Vector3<f32>> {
+        self.attribute(self.tangents_enabled(), i, |v| v.tangent)
+    }
 
-        let version = match self.identifier.as_str() {
-            "ZMS0007" => 7,
-            "ZMS0008" => 8,
-            _ => bail!("Unsupported Mesh version"),
-        };
+    /// First UV of vertex `i`, `None` if the channel is not enabled or the
+    /// vertex doesn't exist
+    pub fn uv1(&self, i: usize) -> Option<Vector2<f32>> {
+        self.attribute(self.uv1_enabled(), i, |v| v.uv1)
+    }
 
-        self.format = reader.read_i32()?;
-        self.bounding_box.min = reader.read_vector3_f32()?;
-        self.bounding_box.max = reader.read_vector3_f32()?;
+    /// Second UV of vertex `i`, `None` if the channel is not enabled or the
+    /// vertex doesn't exist
+    pub fn uv2(&self, i: usize) -> Option<Vector2<f32>> {
+        self.attribute(self.uv2_enabled(), i, |v| v.uv2)
+    }
 
-        let bone_count = reader.read_i16()?;
-        for _ in 0..bone_count {
-            self.bones.push(reader.read_i16()?);
+    /// Third UV of vertex `i`, `None` if the channel is not enabled or the
+    /// vertex doesn't exist
+    pub fn uv3(&self, i: usize) -> Option<Vector2<f32>> {
+        self.attribute(self.uv3_enabled(), i, |v| v.uv3)
+    }
+
+    /// Fourth UV of vertex `i`, `None` if the channel is not enabled or the
+    /// vertex doesn't exist
+    pub fn uv4(&self, i: usize) -> Option<Vector2<f32>> {
+        self.attribute(self.uv4_enabled(), i, |v| v.uv4)
+    }
+
+    fn attribute<T, F: Fn(&Vertex) -> T>(&self, enabled: bool, i: usize, f: F) -> Option<T> {
+        if !enabled {
+            return None;
         }
+        self.vertices.get(i).map(f)
+    }
 
-        let vert_count = reader.read_i16()?;
-        for _ in 0..vert_count {
-            self.vertices.push(Vertex::new());
+    /// Size in bytes of a single vertex with the enabled attributes
+    fn vertex_size(&self) -> usize {
+        let attribute_sizes = [
+            (self.positions_enabled(), 12),
+            (self.normals_enabled(), 12),
+            (self.colors_enabled(), 16),
+            (self.bones_enabled(), 24),
+            (self.tangents_enabled(), 12),
+            (self.uv1_enabled(), 8),
+            (self.uv2_enabled(), 8),
+            (self.uv3_enabled(), 8),
+            (self.uv4_enabled(), 8),
+        ];
+        attribute_sizes.iter()
+            .filter(|&&(enabled, _)| enabled)
+            .map(|&(_, size)| size)
+            .sum()
+    }
+
+    /// Pack the vertices into a single interleaved buffer
+    ///
+    /// Each vertex contains only the attributes of `layout`, in that order,
+    /// as little-endian values: `f32` components for positions, normals,
+    /// colors (RGBA), bone weights, tangents and UVs, `i16` components for
+    /// bone indices. The stride is the sum of the attribute sizes. Fails if
+    /// an attribute of `layout` is not enabled.
+    pub fn interleaved_buffer(&self, layout: &[VertexFormat]) -> Result<Vec<u8>, Error> {
+        for &attribute in layout {
+            if (attribute as i32 & self.format) == 0 {
+                bail!("Vertex attribute {:?} is not enabled", attribute);
+            }
+        }
+
+        let mut buffer = Cursor::new(Vec::new());
+        for vertex in &self.vertices {
+            for &attribute in layout {
+                match attribute {
+                    VertexFormat::Position => buffer.write_vector3_f32(&vertex.position)?,
+                    VertexFormat::Normal => buffer.write_vector3_f32(&vertex.normal)?,
+                    VertexFormat::Color => buffer.write_color4(&vertex.color)?,
+                    VertexFormat::BoneWeight => buffer.write_vector4_f32(&vertex.bone_weights)?,
+                    VertexFormat::BoneIndex => buffer.write_vector4_i16(&vertex.bone_indices)?,
+                    VertexFormat::Tangent => buffer.write_vector3_f32(&vertex.tangent)?,
+                    VertexFormat::UV1 => buffer.write_vector2_f32(&vertex.uv1)?,
+                    VertexFormat::UV2 => buffer.write_vector2_f32(&vertex.uv2)?,
+                    VertexFormat::UV3 => buffer.write_vector2_f32(&vertex.uv3)?,
+                    VertexFormat::UV4 => buffer.write_vector2_f32(&vertex.uv4)?,
+                }
+            }
+        }
+        Ok(buffer.into_inner())
+    }
+
+    /// Pack the triangle indices into a buffer of little-endian `u16`,
+    /// matching the vertices of `interleaved_buffer`
+    pub fn index_buffer(&self) -> Vec<u8> {
+        let mut buffer = Vec::with_capacity(self.indices.len() * 6);
+        for triangle in &self.indices {
+            for &index in &[triangle.x, triangle.y, triangle.z] {
+                buffer.extend_from_slice(&(index as u16).to_le_bytes());
+            }
         }
+        buffer
+    }
+
+    /// Read the rest of a `ZMS0005` or `ZMS0006` mesh after its bounding box
+    ///
+    /// Older meshes store counts and bone indices as 32-bit integers and
+    /// prefix each vertex attribute and triangle with its (redundant) index.
+    /// Materials are stored as a count followed by the triangle count of
+    /// each material, strips and the pool did not exist yet.
+    fn read_legacy<R: ReadRoseExt>(&mut self, reader: &mut R) -> Result<(), Error> {
+        self.bones = reader.read_vec_i32("ZMS", 4, |r| Ok(r.read_i32()? as i16))?;
+
+        // Each attribute has a 4 byte index, bone indices are 4 bytes each
+        let attributes = [
+            self.positions_enabled(),
+            self.normals_enabled(),
+            self.colors_enabled(),
+            self.bones_enabled(),
+            self.tangents_enabled(),
+            self.uv1_enabled(),
+            self.uv2_enabled(),
+            self.uv3_enabled(),
+            self.uv4_enabled(),
+        ];
+        let attribute_count = attributes.iter().filter(|&&enabled| enabled).count();
+        let bone_index_size = if self.bones_enabled() { 8 } else { 0 };
+        let vertex_size = self.vertex_size() + attribute_count * 4 + bone_index_size;
+
+        let vert_count = reader.read_i32()?;
+        let vert_count = reader.check_count("ZMS", vert_count as i64, vertex_size as u64)?;
+        let mut vertices = vec![Vertex::new(); vert_count];
 
         if self.positions_enabled() {
-            for i in 0..vert_count as usize {
-                self.vertices[i].position = reader.read_vector3_f32()?;
+            for vertex in &mut vertices {
+                let _index = reader.read_i32()?;
+                vertex.position = reader.read_vector3_f32()?;
             }
         }
 
         if self.normals_enabled() {
-            for i in 0..vert_count as usize {
-                self.vertices[i].normal = reader.read_vector3_f32()?;
+            for vertex in &mut vertices {
+                let _index = reader.read_i32()?;
+                vertex.normal = reader.read_vector3_f32()?;
             }
         }
 
         if self.colors_enabled() {
-            for i in 0..vert_count as usize {
-                self.vertices[i].color = reader.read_color4()?;
+            for vertex in &mut vertices {
+                let _index = reader.read_i32()?;
+                vertex.color = reader.read_color4()?;
             }
         }
 
         if self.bones_enabled() {
-            for i in 0..vert_count as usize {
-                self.vertices[i].bone_weights = reader.read_vector4_f32()?;
-                self.vertices[i].bone_indices = reader.read_vector4_i16()?;
-
+            for vertex in &mut vertices {
+                let _index = reader.read_i32()?;
+                vertex.bone_weights = read_bone_weights(reader)?;
+                vertex.bone_indices.x = reader.read_i32()? as i16;
+                vertex.bone_indices.y = reader.read_i32()? as i16;
+                vertex.bone_indices.z = reader.read_i32()? as i16;
+                vertex.bone_indices.w = reader.read_i32()? as i16;
             }
         }
 
         if self.tangents_enabled() {
-            for i in 0..vert_count as usize {
-                self.vertices[i].tangent = reader.read_vector3_f32()?;
+            for vertex in &mut vertices {
+                let _index = reader.read_i32()?;
+                vertex.tangent = reader.read_vector3_f32()?;
             }
         }
 
         if self.uv1_enabled() {
-            for i in 0..vert_count as usize {
-                self.vertices[i].uv1 = reader.read_vector2_f32()?;
+            for vertex in &mut vertices {
+                let _index = reader.read_i32()?;
+                vertex.uv1 = reader.read_vector2_f32()?;
             }
         }
 
         if self.uv2_enabled() {
-            for i in 0..vert_count as usize {
-                self.vertices[i].uv2 = reader.read_vector2_f32()?;
+            for vertex in &mut vertices {
+                let _index = reader.read_i32()?;
+                vertex.uv2 = reader.read_vector2_f32()?;
             }
         }
 
         if self.uv3_enabled() {
-            for i in 0..vert_count as usize {
-                self.vertices[i].uv3 = reader.read_vector2_f32()?;
+            for vertex in &mut vertices {
+                let _index = reader.read_i32()?;
+                vertex.uv3 = reader.read_vector2_f32()?;
             }
         }
+
         if self.uv4_enabled() {
-            for i in 0..vert_count as usize {
-                self.vertices[i].uv4 = reader.read_vector2_f32()?;
+            for vertex in &mut vertices {
+                let _index = reader.read_i32()?;
+                vertex.uv4 = reader.read_vector2_f32()?;
             }
         }
+        self.vertices = vertices;
 
-        let index_count = reader.read_i16()?;
+        let index_count = reader.read_i32()?;
+        let index_count = reader.check_count("ZMS", index_count as i64, 16)?;
         for _ in 0..index_count {
-            self.indices.push(reader.read_vector3_i16()?);
+            let _index = reader.read_i32()?;
+            self.indices.push(Vector3 {
+                x: reader.read_i32()? as i16,
+                y: reader.read_i32()? as i16,
+                z: reader.read_i32()? as i16,
+            });
         }
 
-        let material_count = reader.read_i16()?;
+        let material_count = reader.read_i32()?;
+        let material_count = reader.check_count("ZMS", material_count as i64, 4)?;
         for _ in 0..material_count {
-            self.materials.push(reader.read_i16()?);
+            self.materials.push(reader.read_i32()? as i16);
         }
 
-        let strip_count = reader.read_i16()?;
-        for _ in 0..strip_count {
-            self.strips.push(reader.read_i16()?);
+        Ok(())
+    }
+
+    /// Recalculate the bounding box from the vertex positions
+    ///
+    /// A mesh without vertices gets an empty box at the origin.
+    pub fn recalculate_bounding_box(&mut self) {
+        let positions = self.vertices.iter().map(|v| v.position);
+        self.bounding_box = BoundingBox::from_points(positions).unwrap_or(BoundingBox {
+            min: Vector3::<f32>::new(),
+            max: Vector3::<f32>::new(),
+        });
+    }
+
+    /// Bounding sphere of the vertex positions as a center and radius
+    ///
+    /// Computed from the vertices with Ritter's algorithm, the sphere
+    /// contains all vertices but may be slightly larger than the smallest
+    /// one. The stored bounding box is not used. A mesh without vertices
+    /// gets a zero radius sphere at the origin.
+    pub fn bounding_sphere(&self) -> (Vector3<f32>, f32) {
+        let first = match self.vertices.first() {
+            Some(v) => v.position,
+            None => return (Vector3::<f32>::new(), 0.0),
+        };
+
+        let farthest = |from: Vector3<f32>| {
+            self.vertices.iter()
+                .map(|v| v.position)
+                .fold(from, |a, b| if distance(&from, &b) > distance(&from, &a) { b } else { a })
+        };
+        let x = farthest(first);
+        let y = farthest(x);
+
+        let mut center = x.lerp(&y, 0.5);
+        let mut radius = distance(&x, &y) / 2.0;
+
+        // Grow the sphere towards the vertices outside of it
+        for vertex in &self.vertices {
+            let d = distance(&center, &vertex.position);
+            if d > radius {
+                center = center.lerp(&vertex.position, (d - radius) / (2.0 * d));
+                radius = (radius + d) / 2.0;
+            }
         }
 
-        if version >= 8 {
-            self.pool = reader.read_i16()?;
+        (center, radius)
+    }
+
+    /// Compare two meshes ignoring their identifier
+    ///
+    /// Meshes are always written as `ZMS0008`, this compares a mesh read
+    /// from an older version with the mesh written from it.
+    pub fn eq_ignoring_version(&self, other: &Mesh) -> bool {
+        self.format == other.format &&
+        self.bounding_box == other.bounding_box &&
+        self.bones == other.bones &&
+        self.vertices == other.vertices &&
+        self.indices == other.indices &&
+        self.materials == other.materials &&
+        self.strips == other.strips &&
+        self.pool == other.pool
+    }
+
+    /// Apply a transform to the mesh
+    ///
+    /// Positions are transformed as points and tangents as directions,
+    /// normals by the inverse-transpose of `matrix` so they stay
+    /// perpendicular to the surface under non-uniform scaling. Normals and
+    /// tangents are normalized, UVs are left untouched and the bounding box
+    /// is recalculated. The winding of the triangles is unchanged, so
+    /// mirroring transforms turn the mesh inside out.
+    pub fn transform(&mut self, matrix: Matrix4) {
+        let normal_matrix = matrix::normal_matrix(&matrix);
+
+        for vertex in &mut self.vertices {
+            vertex.position = matrix::transform_point(&matrix, vertex.position);
+            vertex.normal = matrix::transform_direction(&normal_matrix, vertex.normal).normalize();
+            vertex.tangent = matrix::transform_direction(&matrix, vertex.tangent).normalize();
+        }
+
+        self.recalculate_bounding_box();
+    }
+
+    /// Keep only the listed UV channels
+    ///
+    /// Channels are numbered 1 to 4. The channels not listed are disabled and
+    /// their data zeroed, listed channels that are not enabled stay disabled.
+    /// Fails if a channel is out of range, the mesh is left unchanged.
+    pub fn keep_uv_channels(&mut self, channels: &[u8]) -> Result<(), Error> {
+        let mut keep = 0;
+        for &channel in channels {
+            keep |= uv_flag(channel)?;
+        }
+
+        for channel in 1..5 {
+            let flag = uv_flag(channel)?;
+            if keep & flag != 0 {
+                continue;
+            }
+            self.format &= !flag;
+            for vertex in &mut self.vertices {
+                *vertex.uv_mut(channel) = Vector2::<f32>::new();
+            }
         }
 
         Ok(())
     }
 
-    fn write<W: WriteRoseExt>(&mut self, writer: &mut W) -> Result<(), Error> {
-        writer.write_cstring("ZMS0008")?;
-        writer.write_i32(self.format)?;
+    /// Move the data of UV channel `from` to UV channel `to`
+    ///
+    /// Channels are numbered 1 to 4. `to` is enabled and overwritten, `from`
+    /// is disabled and zeroed. Fails if a channel is out of range or `from`
+    /// is not enabled, the mesh is left unchanged.
+    pub fn move_uv(&mut self, from: u8, to: u8) -> Result<(), Error> {
+        let from_flag = uv_flag(from)?;
+        let to_flag = uv_flag(to)?;
+        if self.format & from_flag == 0 {
+            bail!("UV channel {} is not enabled", from);
+        }
+        if from == to {
+            return Ok(());
+        }
 
-        writer.write_vector3_f32(&self.bounding_box.min)?;
-        writer.write_vector3_f32(&self.bounding_box.max)?;
+        for vertex in &mut self.vertices {
+            let uv = *vertex.uv_mut(from);
+            *vertex.uv_mut(to) = uv;
+            *vertex.uv_mut(from) = Vector2::<f32>::new();
+        }
 
-        writer.write_i16(self.bones.len() as i16)?;
-        for bone in &self.bones {
-            writer.write_i16(*bone)?;
+        self.format = (self.format & !from_flag) | to_flag;
+        Ok(())
+    }
+
+    /// Reverse the winding of the triangles
+    ///
+    /// Swaps the last two indices of each triangle, turning front faces into
+    /// back faces. If `flip_normals` is set the normals are negated so they
+    /// keep pointing out of the front faces. Strips are regenerated from the
+    /// triangles, see `repair`.
+    pub fn flip_winding(&mut self, flip_normals: bool) {
+        for triangle in &mut self.indices {
+            ::std::mem::swap(&mut triangle.y, &mut triangle.z);
         }
 
-        writer.write_i16(self.vertices.len() as i16)?;
+        if flip_normals {
+            for vertex in &mut self.vertices {
+                vertex.normal = Vector3 {
+                    x: -vertex.normal.x,
+                    y: -vertex.normal.y,
+                    z: -vertex.normal.z,
+                };
+            }
+        }
 
-        if self.positions_enabled() {
-            for ref vertex in &self.vertices {
-                writer.write_vector3_f32(&vertex.position)?;
+        if !self.strips.is_empty() {
+            self.strips = stitch_strips(&self.to_triangle_strips());
+        }
+    }
+
+    /// Flip the V coordinate of a UV channel, replacing `v` with `1 - v`
+    ///
+    /// Converts between the top-left UV origin of ROSE and the bottom-left
+    /// origin of OBJ and OpenGL. Channels are numbered 1 to 4. Fails if the
+    /// channel is out of range or not enabled, the mesh is left unchanged.
+    pub fn flip_uv_v(&mut self, channel: u8) -> Result<(), Error> {
+        if self.format & uv_flag(channel)? == 0 {
+            bail!("UV channel {} is not enabled", channel);
+        }
+
+        for vertex in &mut self.vertices {
+            let uv = vertex.uv_mut(channel);
+            uv.y = 1.0 - uv.y;
+        }
+        Ok(())
+    }
+
+    /// Append another mesh to this mesh
+    ///
+    /// Vertex attributes enabled in either mesh are enabled in the result,
+    /// attributes missing from one of the meshes are zeroed. Skinned meshes
+    /// can only be merged with other skinned meshes, the bone palette of
+    /// `other` is appended and its vertex bone indices remapped.
+    ///
+    /// Materials are stored as triangle counts per material so they are
+    /// appended as-is, a mesh without materials is treated as a single
+    /// material covering all of its triangles. The strip of `other` is
+    /// joined to the strip of this mesh with `stitch_strips`.
+    ///
+    /// Fails without modifying this mesh when the merged vertices, bones or
+    /// triangles can't be indexed by an `i16`.
+    pub fn merge(&mut self, other: &Mesh) -> Result<(), Error> {
+        if !self.vertices.is_empty() && self.bones_enabled() != other.bones_enabled() {
+            bail!("Unable to merge a skinned mesh with an unskinned mesh");
+        }
+
+        let vertex_count = self.vertices.len() + other.vertices.len();
+        if vertex_count > i16::max_value() as usize {
+            bail!("Merged mesh has too many vertices: {}", vertex_count);
+        }
+
+        let bone_count = self.bones.len() + other.bones.len();
+        if bone_count > i16::max_value() as usize {
+            bail!("Merged mesh has too many bones: {}", bone_count);
+        }
+
+        let triangle_count = self.indices.len() + other.indices.len();
+        if triangle_count > i16::max_value() as usize {
+            bail!("Merged mesh has too many triangles: {}", triangle_count);
+        }
+
+        let vertex_offset = self.vertices.len() as i16;
+        let bone_offset = self.bones.len() as i16;
+
+        // Remap everything before modifying this mesh so it is left as-is
+        // if an index of `other` is out of range
+        let mut vertices = Vec::with_capacity(other.vertices.len());
+        for vertex in &other.vertices {
+            let mut v = vertex.clone();
+            if other.bones_enabled() {
+                let i = &vertex.bone_indices;
+                v.bone_indices = Vector4 {
+                    w: offset_index(i.w, bone_offset)?,
+                    x: offset_index(i.x, bone_offset)?,
+                    y: offset_index(i.y, bone_offset)?,
+                    z: offset_index(i.z, bone_offset)?,
+                };
             }
+            vertices.push(v);
         }
 
-        if self.normals_enabled() {
-            for ref vertex in &self.vertices {
-                writer.write_vector3_f32(&vertex.normal)?;
+        let mut indices = Vec::with_capacity(other.indices.len());
+        for index in &other.indices {
+            indices.push(Vector3 {
+                x: offset_index(index.x, vertex_offset)?,
+                y: offset_index(index.y, vertex_offset)?,
+                z: offset_index(index.z, vertex_offset)?,
+            });
+        }
+
+        let other_strip = other.strips.iter()
+            .map(|&i| offset_index(i, vertex_offset))
+            .collect::<Result<Vec<i16>, Error>>()?;
+
+        if self.vertices.is_empty() {
+            self.format = other.format;
+        }
+        self.vertices.extend(vertices);
+
+        if !self.materials.is_empty() || !other.materials.is_empty() {
+            if self.materials.is_empty() && !self.indices.is_empty() {
+                self.materials.push(self.indices.len() as i16);
+            }
+
+            if other.materials.is_empty() {
+                if !other.indices.is_empty() {
+                    self.materials.push(other.indices.len() as i16);
+                }
+            } else {
+                self.materials.extend_from_slice(&other.materials);
             }
         }
 
-        if self.colors_enabled() {
-            for ref vertex in &self.vertices {
-                writer.write_color4(&vertex.color)?;
+        self.indices.extend(indices);
+        self.strips = stitch_strips(&[::std::mem::take(&mut self.strips), other_strip]);
+
+        self.bones.extend_from_slice(&other.bones);
+        self.format |= other.format;
+        self.recalculate_bounding_box();
+
+        Ok(())
+    }
+
+    /// Merge vertices that are within `epsilon` of each other
+    ///
+    /// Vertices are only merged if their positions and every other enabled
+    /// attribute (normals, colors, UVs, ...) are within `epsilon`, so UV and
+    /// normal seams are preserved. Bone indices must match exactly and
+    /// vertices of different material groups are never merged. Indices
+    /// and strips are remapped to the merged vertices and triangles that
+    /// become degenerate are removed along with their material counts.
+    pub fn weld_vertices(&mut self, epsilon: f32) {
+        let cell_size = if epsilon > 0.0 { epsilon } else { 1.0 };
+        let cell = |p: &Vector3<f32>| {
+            ((p.x / cell_size).floor() as i64,
+             (p.y / cell_size).floor() as i64,
+             (p.z / cell_size).floor() as i64)
+        };
+
+        let mut material_counts = if self.materials.is_empty() {
+            vec![self.indices.len() as i16]
+        } else {
+            self.materials.clone()
+        };
+
+        // Material group of each triangle, a triangle past the material
+        // counts belongs to the last group
+        let mut triangle_groups = Vec::with_capacity(self.indices.len());
+        let mut current = 0;
+        let mut remaining = material_counts.first().cloned().unwrap_or(0);
+        for _ in &self.indices {
+            while remaining <= 0 && current + 1 < material_counts.len() {
+                current += 1;
+                remaining = material_counts[current];
             }
+            remaining -= 1;
+            triangle_groups.push(current);
         }
 
-        if self.bones_enabled() {
-            for ref vertex in &self.vertices {
-                writer.write_vector4_f32(&vertex.bone_weights)?;
-                writer.write_vector4_i16(&vertex.bone_indices)?;
+        // Vertices take the group of the first triangle using them and are
+        // only welded with vertices of the same group
+        let mut vertex_groups: Vec<Option<usize>> = vec![None; self.vertices.len()];
+        for (t, &group) in self.indices.iter().zip(&triangle_groups) {
+            for &i in [t.x, t.y, t.z].iter() {
+                if let Some(vertex_group) = vertex_groups.get_mut(i as usize) {
+                    vertex_group.get_or_insert(group);
+                }
             }
         }
 
-        if self.tangents_enabled() {
-            for ref vertex in &self.vertices {
-                writer.write_vector3_f32(&vertex.tangent)?;
+        // Spatial hash of welded vertex indices, a vertex only needs to be
+        // compared against the vertices in its own and neighbouring cells
+        let mut grid: HashMap<(Option<usize>, i64, i64, i64), Vec<usize>> = HashMap::new();
+        let mut welded: Vec<Vertex> = Vec::new();
+        let mut remap: Vec<i16> = Vec::with_capacity(self.vertices.len());
+
+        for (vertex, &group) in self.vertices.iter().zip(&vertex_groups) {
+            let (cx, cy, cz) = cell(&vertex.position);
+
+            let mut found = None;
+            'search: for dx in -1..2 {
+                for dy in -1..2 {
+                    for dz in -1..2 {
+                        if let Some(candidates) = grid.get(&(group, cx + dx, cy + dy, cz + dz)) {
+                            for &i in candidates {
+                                if self.vertices_match(&welded[i], vertex, epsilon) {
+                                    found = Some(i);
+                                    break 'search;
+                                }
+                            }
+                        }
+                    }
+                }
             }
+
+            let index = match found {
+                Some(i) => i,
+                None => {
+                    welded.push(vertex.clone());
+                    grid.entry((group, cx, cy, cz)).or_insert_with(Vec::new).push(welded.len() - 1);
+                    welded.len() - 1
+                }
+            };
+            remap.push(index as i16);
         }
 
-        if self.uv1_enabled() {
-            for ref vertex in &self.vertices {
-                writer.write_vector2_f32(&vertex.uv1)?;
+        let remap_index = |i: i16| remap.get(i as usize).cloned().unwrap_or(i);
+        let mut indices = Vec::with_capacity(self.indices.len());
+        for (index, &group) in self.indices.iter().zip(&triangle_groups) {
+            let t = Vector3 {
+                x: remap_index(index.x),
+                y: remap_index(index.y),
+                z: remap_index(index.z),
+            };
+
+            if t.x == t.y || t.y == t.z || t.z == t.x {
+                material_counts[group] -= 1;
+            } else {
+                indices.push(t);
             }
         }
 
-        if self.uv2_enabled() {
-            for ref vertex in &self.vertices {
-                writer.write_vector2_f32(&vertex.uv2)?;
+        if !self.materials.is_empty() {
+            self.materials = material_counts;
+        }
+
+        self.strips = self.strips.iter().map(|&i| remap_index(i)).collect();
+        self.indices = indices;
+        self.vertices = welded;
+        self.recalculate_bounding_box();
+    }
+
+    fn vertices_match(&self, a: &Vertex, b: &Vertex, epsilon: f32) -> bool {
+        let close = |x: f32, y: f32| (x - y).abs() <= epsilon;
+        let close2 = |u: &Vector2<f32>, v: &Vector2<f32>| close(u.x, v.x) && close(u.y, v.y);
+        let close3 = |u: &Vector3<f32>, v: &Vector3<f32>| {
+            close(u.x, v.x) && close(u.y, v.y) && close(u.z, v.z)
+        };
+
+        close3(&a.position, &b.position)
+            && (!self.normals_enabled() || close3(&a.normal, &b.normal))
+            && (!self.colors_enabled() || (close(a.color.r, b.color.r)
+                                           && close(a.color.g, b.color.g)
+                                           && close(a.color.b, b.color.b)
+                                           && close(a.color.a, b.color.a)))
+            && (!self.bones_enabled() || (a.bone_indices == b.bone_indices
+                                          && close(a.bone_weights.w, b.bone_weights.w)
+                                          && close(a.bone_weights.x, b.bone_weights.x)
+                                          && close(a.bone_weights.y, b.bone_weights.y)
+                                          && close(a.bone_weights.z, b.bone_weights.z)))
+            && (!self.tangents_enabled() || close3(&a.tangent, &b.tangent))
+            && (!self.uv1_enabled() || close2(&a.uv1, &b.uv1))
+            && (!self.uv2_enabled() || close2(&a.uv2, &b.uv2))
+            && (!self.uv3_enabled() || close2(&a.uv3, &b.uv3))
+            && (!self.uv4_enabled() || close2(&a.uv4, &b.uv4))
+    }
+
+    /// Build a mesh from a Wavefront OBJ
+    ///
+    /// Reads the `v`, `vt`, `vn` and `f` statements, all other statements are
+    /// ignored. Each unique position/uv/normal combination referenced by a
+    /// face becomes a vertex and faces with more than 3 vertices are
+    /// triangulated as a fan. Positions and normals are converted back to
+    /// ROSE space with the inverse of `transform` and the V coordinate is
+    /// flipped back to ROSE's top-left UV origin.
+    pub fn from_obj<R: BufRead>(reader: &mut R, transform: &Transform) -> Result<Mesh, Error> {
+        let mut positions: Vec<Vector3<f32>> = Vec::new();
+        let mut uvs: Vec<Vector2<f32>> = Vec::new();
+        let mut normals: Vec<Vector3<f32>> = Vec::new();
+
+        let mut mesh = Mesh::new();
+        mesh.identifier = String::from("ZMS0008");
+        mesh.format = VertexFormat::Position as i32;
+
+        let mut vertex_lookup: HashMap<(usize, Option<usize>, Option<usize>), i16> = HashMap::new();
+
+        for (line_number, line) in reader.lines().enumerate() {
+            let line = line?;
+            let mut tokens = line.split_whitespace();
+
+            let parse_floats = |tokens: &mut ::std::str::SplitWhitespace, n: usize| -> Result<Vec<f32>, Error> {
+                let values: Vec<f32> = tokens.take(n).map(|t| t.parse::<f32>()).collect::<Result<_, _>>()?;
+                if values.len() < n {
+                    bail!("Expected {} values on OBJ line {}", n, line_number + 1);
+                }
+                Ok(values)
+            };
+
+            match tokens.next() {
+                Some("v") => {
+                    let v = parse_floats(&mut tokens, 3)?;
+                    positions.push(Vector3 { x: v[0], y: v[1], z: v[2] });
+                }
+                Some("vt") => {
+                    let v = parse_floats(&mut tokens, 2)?;
+                    uvs.push(Vector2 { x: v[0], y: 1.0 - v[1] });
+                }
+                Some("vn") => {
+                    let v = parse_floats(&mut tokens, 3)?;
+                    normals.push(Vector3 { x: v[0], y: v[1], z: v[2] });
+                }
+                Some("f") => {
+                    let mut face: Vec<i16> = Vec::new();
+                    for token in tokens {
+                        let mut parts = token.split('/');
+                        let p = obj_index(parts.next(), positions.len(), line_number)?;
+                        let t = obj_index(parts.next(), uvs.len(), line_number)?;
+                        let n = obj_index(parts.next(), normals.len(), line_number)?;
+
+                        let p = match p {
+                            Some(p) => p,
+                            None => bail!("Missing position index on OBJ line {}", line_number + 1),
+                        };
+
+                        let key = (p, t, n);
+                        if let Some(&index) = vertex_lookup.get(&key) {
+                            face.push(index);
+                            continue;
+                        }
+
+                        if mesh.vertices.len() >= i16::max_value() as usize {
+                            bail!("OBJ has too many vertices for a ZMS");
+                        }
+
+                        let mut vertex = Vertex::new();
+                        vertex.position = transform.inverse_position(positions[p]);
+                        if let Some(t) = t {
+                            vertex.uv1 = uvs[t];
+                            mesh.format |= VertexFormat::UV1 as i32;
+                        }
+                        if let Some(n) = n {
+                            vertex.normal = transform.inverse_normal(normals[n]);
+                            mesh.format |= VertexFormat::Normal as i32;
+                        }
+
+                        let index = mesh.vertices.len() as i16;
+                        mesh.vertices.push(vertex);
+                        vertex_lookup.insert(key, index);
+                        face.push(index);
+                    }
+
+                    if face.len() < 3 {
+                        bail!("Face with less than 3 vertices on OBJ line {}", line_number + 1);
+                    }
+
+                    for i in 1..face.len() - 1 {
+                        mesh.indices.push(Vector3 { x: face[0], y: face[i], z: face[i + 1] });
+                    }
+                }
+                _ => {}
             }
         }
 
-        if self.uv3_enabled() {
-            for ref vertex in &self.vertices {
-                writer.write_vector2_f32(&vertex.uv3)?;
+        mesh.recalculate_bounding_box();
+        Ok(mesh)
+    }
+
+    /// Whether the mesh has a triangle strip
+    ///
+    /// The client draws the strip of meshes that have one instead of their
+    /// triangle list, both are expected to hold the same triangles.
+    pub fn uses_strips(&self) -> bool {
+        !self.strips.is_empty()
+    }
+
+    /// Triangles in the order the client draws them
+    ///
+    /// The triangles of the strip if `uses_strips`, see
+    /// `strip_to_triangles`, otherwise the triangle list.
+    pub fn draw_triangles(&self) -> Vec<Vector3<i16>> {
+        if self.uses_strips() {
+            strip_to_triangles(&self.strips)
+        } else {
+            self.indices.clone()
+        }
+    }
+
+    /// Generate triangle strips from the triangle list
+    ///
+    /// Triangles are greedily joined with adjacent triangles that share an
+    /// edge with a matching winding. Degenerate triangles are dropped. Use
+    /// `stitch_strips` to join the strips into a single strip.
+    pub fn to_triangle_strips(&self) -> Vec<Vec<i16>> {
+        let triangles: Vec<[i16; 3]> = self.indices.iter()
+            .map(|i| [i.x, i.y, i.z])
+            .filter(|t| t[0] != t[1] && t[1] != t[2] && t[0] != t[2])
+            .collect();
+
+        // Directed edge -> triangles containing that edge (in winding order)
+        let mut edges: HashMap<(i16, i16), Vec<usize>> = HashMap::new();
+        for (i, t) in triangles.iter().enumerate() {
+            for e in 0..3 {
+                edges.entry((t[e], t[(e + 1) % 3])).or_insert_with(Vec::new).push(i);
             }
         }
 
-        if self.uv4_enabled() {
-            for ref vertex in &self.vertices {
-                writer.write_vector2_f32(&vertex.uv4)?;
+        let mut used = vec![false; triangles.len()];
+        let mut strips = Vec::new();
+
+        for start in 0..triangles.len() {
+            if used[start] {
+                continue;
+            }
+            used[start] = true;
+
+            let mut strip = triangles[start].to_vec();
+            loop {
+                // The next triangle is (a, b, n) on even positions and
+                // (b, a, n) on odd positions to preserve the winding
+                let k = strip.len() - 2;
+                let edge = if k % 2 == 0 {
+                    (strip[k], strip[k + 1])
+                } else {
+                    (strip[k + 1], strip[k])
+                };
+
+                let next = edges.get(&edge).and_then(|candidates| {
+                    candidates.iter().cloned().find(|&c| !used[c])
+                });
+
+                match next {
+                    Some(c) => {
+                        used[c] = true;
+                        let t = triangles[c];
+                        let third = t.iter()
+                            .cloned()
+                            .find(|&v| v != edge.0 && v != edge.1)
+                            .unwrap();
+                        strip.push(third);
+                    }
+                    None => break,
+                }
             }
+
+            strips.push(strip);
         }
 
-        writer.write_i16(self.indices.len() as i16)?;
-        for index in &self.indices {
-            writer.write_vector3_i16(index)?;
+        strips
+    }
+
+    /// Replace the triangle list with the triangles of the given strips
+    pub fn set_triangle_strips(&mut self, strips: &[Vec<i16>]) {
+        self.indices.clear();
+        for strip in strips {
+            self.indices.extend(strip_to_triangles(strip));
         }
+    }
 
-        writer.write_i16(self.materials.len() as i16)?;
-        for mat in &self.materials {
-            writer.write_i16(*mat)?;
+    /// Split the mesh into one mesh per material
+    ///
+    /// Each mesh has the triangles of its material and only the vertices they
+    /// reference, in the order they are first referenced. The vertex format,
+    /// bones and pool are kept, strips are dropped and the bounding box is
+    /// recalculated. Meshes are returned in the order of `materials`, a
+    /// material without triangles gives an empty mesh. Triangles past the
+    /// material counts are ignored, see `repair`.
+    ///
+    /// A mesh without materials is returned whole as a single mesh.
+    pub fn split_by_material(&self) -> Vec<Mesh> {
+        if self.materials.is_empty() {
+            return vec![Mesh {
+                identifier: self.identifier.clone(),
+                format: self.format,
+                bounding_box: self.bounding_box,
+                bones: self.bones.clone(),
+                vertices: self.vertices.clone(),
+                indices: self.indices.clone(),
+                materials: Vec::new(),
+                strips: self.strips.clone(),
+                pool: self.pool,
+            }];
         }
 
-        writer.write_i16(self.strips.len() as i16)?;
-        for strip in &self.strips {
-            writer.write_i16(*strip)?;
+        let mut meshes = Vec::with_capacity(self.materials.len());
+        let mut start = 0;
+        for &count in &self.materials {
+            let end = (start + count.max(0) as usize).min(self.indices.len());
+
+            let mut mesh = Mesh::new();
+            mesh.identifier = self.identifier.clone();
+            mesh.format = self.format;
+            mesh.bones = self.bones.clone();
+            mesh.pool = self.pool;
+
+            let mut remap: HashMap<i16, i16> = HashMap::new();
+            for t in &self.indices[start..end] {
+                let mut triangle = [t.x, t.y, t.z];
+                for i in triangle.iter_mut() {
+                    let vertices = &self.vertices;
+                    let new_vertices = &mut mesh.vertices;
+                    *i = *remap.entry(*i).or_insert_with(|| {
+                        new_vertices.push(vertices.get(*i as usize).cloned().unwrap_or_else(Vertex::new));
+                        (new_vertices.len() - 1) as i16
+                    });
+                }
+                mesh.indices.push(Vector3 { x: triangle[0], y: triangle[1], z: triangle[2] });
+            }
+
+            mesh.materials.push(mesh.indices.len() as i16);
+            mesh.recalculate_bounding_box();
+            meshes.push(mesh);
+            start = end;
         }
 
-        writer.write_i16(self.pool)?;
+        meshes
+    }
 
-        Ok(())
+    /// Repair materials and strips that don't match the triangles
+    ///
+    /// Materials are triangle counts, they are inconsistent when a count is
+    /// negative or the counts don't add up to the number of triangles (e.g.
+    /// a mesh with triangles but no materials). Inconsistent materials are
+    /// replaced by a single material covering all the triangles, split in
+    /// several materials if there are more than `i16::max_value()`
+    /// triangles. A mesh without triangles gets no materials.
+    ///
+    /// Strips are inconsistent when they reference missing vertices, they are
+    /// then regenerated from the triangles with `to_triangle_strips` and
+    /// `stitch_strips`. Empty strips are valid and kept as-is.
+    ///
+    /// Does nothing if the mesh is consistent, returns whether the mesh was
+    /// changed.
+    pub fn repair(&mut self) -> bool {
+        let mut repaired = false;
+
+        let triangle_count = self.indices.len();
+        let material_total: i64 = self.materials.iter().map(|&m| m as i64).sum();
+        let materials_valid = self.materials.iter().all(|&m| m >= 0)
+            && material_total == triangle_count as i64;
+        if !materials_valid {
+            let max = i16::max_value() as usize;
+            self.materials = (0..triangle_count)
+                .step_by(max)
+                .map(|start| (triangle_count - start).min(max) as i16)
+                .collect();
+            repaired = true;
+        }
+
+        let vertex_count = self.vertices.len();
+        if self.strips.iter().any(|&i| i < 0 || i as usize >= vertex_count) {
+            self.strips = stitch_strips(&self.to_triangle_strips());
+            repaired = true;
+        }
+
+        repaired
     }
-}
 
-impl Vertex {
-    pub fn new() -> Vertex {
-        Vertex {
-            position: Vector3::<f32>::new(),
-            normal: Vector3::<f32>::new(),
-            color: Color4::new(),
-            bone_weights: Vector4::<f32>::new(),
-            bone_indices: Vector4::<i16>::new(),
-            tangent: Vector3::<f32>::new(),
-            uv1: Vector2::<f32>::new(),
-            uv2: Vector2::<f32>::new(),
-            uv3: Vector2::<f32>::new(),
+    /// Scale the bone weights of each vertex so they sum to 1.0
+    ///
+    /// Vertices whose weights sum to zero have no bone to assign them to and
+    /// are left untouched, `stats` reports them. Does nothing if bones are
+    /// not enabled.
+    pub fn normalize_bone_weights(&mut self) {
+        if !self.bones_enabled() {
+            return;
+        }
+
+        for v in &mut self.vertices {
+            let w = &mut v.bone_weights;
+            let sum = w.x + w.y + w.z + w.w;
+            if sum != 0.0 {
+                w.x /= sum;
+                w.y /= sum;
+                w.z /= sum;
+                w.w /= sum;
+            }
+        }
+    }
+
+    /// Read only the vertex positions of a mesh
+    ///
+    /// Positions are stored first, the reader stops after them so the other
+    /// vertex attributes, triangles and materials are neither read nor
+    /// stored. Returns the same positions as a full read, empty if positions
+    /// are not enabled.
+    pub fn read_positions_only<R: ReadRoseExt>(reader: &mut R) -> Result<Vec<Vector3<f32>>, Error> {
+        let mut mesh = Mesh::new();
+        mesh.identifier = reader.read_cstring()?;
+        let version = read_version(&mesh.identifier)?;
+
+        mesh.format = reader.read_i32()?;
+        let _bounding_box = (reader.read_vector3_f32()?, reader.read_vector3_f32()?);
+
+        // Legacy versions store 4 byte counts and a 4 byte index before each
+        // attribute
+        let (vert_count, index_size) = if version < 7 {
+            let bone_count = reader.read_i32()?;
+            let bone_count = reader.check_count("ZMS", bone_count as i64, 4)?;
+            reader.seek(SeekFrom::Current(bone_count as i64 * 4))?;
+            (reader.read_i32()? as i64, 4)
+        } else {
+            let bone_count = reader.read_i16()?;
+            let bone_count = reader.check_count("ZMS", bone_count as i64, 2)?;
+            reader.seek(SeekFrom::Current(bone_count as i64 * 2))?;
+            (reader.read_i16()? as i64, 0)
+        };
+
+        if !mesh.positions_enabled() {
+            return Ok(Vec::new());
+        }
+
+        let vert_count = reader.check_count("ZMS", vert_count, 12 + index_size)?;
+        let mut positions = Vec::with_capacity(vert_count);
+        for _ in 0..vert_count {
+            if index_size > 0 {
+                let _index = reader.read_i32()?;
+            }
+            positions.push(reader.read_vector3_f32()?);
+        }
+
+        Ok(positions)
+    }
+
+    /// Reorder triangles and vertices for the GPU vertex cache
+    ///
+    /// The triangles of each material are reordered with Tom Forsyth's
+    /// linear-speed vertex cache optimisation, then the vertices are
+    /// renumbered in the order the triangles first reference them so they
+    /// are fetched sequentially. Unreferenced vertices are moved to the end
+    /// and the strips are remapped to the new vertex order. The triangles,
+    /// their winding and the material groups are kept.
+    ///
+    /// If the materials don't match the triangles all the triangles are
+    /// reordered together, see `repair`. A mesh referencing missing vertices
+    /// is left unchanged.
+    pub fn optimize_vertex_cache(&mut self) {
+        let vertex_count = self.vertices.len();
+        let in_range = |i: i16| i >= 0 && (i as usize) < vertex_count;
+        if !self.indices.iter().all(|t| in_range(t.x) && in_range(t.y) && in_range(t.z)) {
+            return;
+        }
+
+        let material_total: i64 = self.materials.iter().map(|&m| m as i64).sum();
+        let groups = if self.materials.iter().all(|&m| m >= 0)
+            && material_total == self.indices.len() as i64 {
+            self.materials.iter().map(|&m| m as usize).collect()
+        } else {
+            vec![self.indices.len()]
+        };
+
+        let mut indices = Vec::with_capacity(self.indices.len());
+        let mut start = 0;
+        for count in groups {
+            let triangles: Vec<[usize; 3]> = self.indices[start..start + count]
+                .iter()
+                .map(|t| [t.x as usize, t.y as usize, t.z as usize])
+                .collect();
+            for t in forsyth_order(&triangles, vertex_count) {
+                indices.push(self.indices[start + t]);
+            }
+            start += count;
+        }
+
+        // Renumber the vertices in the order they are first referenced
+        let mut remap: Vec<Option<i16>> = vec![None; vertex_count];
+        let mut order = Vec::with_capacity(vertex_count);
+        for t in indices.iter_mut() {
+            for i in [&mut t.x, &mut t.y, &mut t.z].iter_mut() {
+                let old = **i as usize;
+                **i = *remap[old].get_or_insert_with(|| {
+                    order.push(old);
+                    (order.len() - 1) as i16
+                });
+            }
+        }
+        for (old, new) in remap.iter_mut().enumerate() {
+            if new.is_none() {
+                order.push(old);
+                *new = Some((order.len() - 1) as i16);
+            }
+        }
+
+        self.vertices = order.iter().map(|&old| self.vertices[old].clone()).collect();
+        self.indices = indices;
+        for i in self.strips.iter_mut() {
+            if in_range(*i) {
+                *i = remap[*i as usize].unwrap();
+            }
+        }
+    }
+
+    /// Reduce the number of triangles by collapsing edges, e.g. to generate
+    /// LODs
+    ///
+    /// Edges are collapsed by increasing quadric error (Garland and Heckbert)
+    /// until at most `target_ratio` of the triangles are left or no edge can
+    /// be collapsed. An edge collapses into one of its vertices or into its
+    /// midpoint, whichever changes the surface the least, the attributes of
+    /// a midpoint are interpolated and its bone weights merged. Collapses
+    /// that would flip a triangle or join unconnected parts of the mesh are
+    /// skipped.
+    ///
+    /// Vertices of open edges and of material borders don't move, keeping
+    /// the outline of the mesh. UV seams are kept too as the vertices of a
+    /// seam are split, which makes its edges open.
+    ///
+    /// Degenerate triangles are dropped, unused vertices are removed, the
+    /// material counts are updated and strips regenerated. If the materials
+    /// don't match the triangles all the triangles are simplified as one
+    /// group, see `repair`. A mesh referencing missing vertices is left
+    /// unchanged.
+    pub fn simplify(&mut self, target_ratio: f32) {
+        let vertex_count = self.vertices.len();
+        let in_range = |i: i16| i >= 0 && (i as usize) < vertex_count;
+        if !self.indices.iter().all(|t| in_range(t.x) && in_range(t.y) && in_range(t.z)) {
+            return;
+        }
+
+        let material_total: i64 = self.materials.iter().map(|&m| m as i64).sum();
+        let groups_match = self.materials.iter().all(|&m| m >= 0)
+            && material_total == self.indices.len() as i64;
+        let mut groups = Vec::with_capacity(self.indices.len());
+        if groups_match {
+            for (group, &count) in self.materials.iter().enumerate() {
+                groups.extend((0..count).map(|_| group));
+            }
+        } else {
+            groups.resize(self.indices.len(), 0);
+        }
+
+        let triangles: Vec<([usize; 3], usize)> = self.indices.iter()
+            .zip(groups)
+            .map(|(t, group)| ([t.x as usize, t.y as usize, t.z as usize], group))
+            .filter(|&(t, _)| t[0] != t[1] && t[1] != t[2] && t[0] != t[2])
+            .collect();
+        let target = (triangles.len() as f32 * target_ratio.max(0.0).min(1.0)).round() as usize;
+
+        let mut simplifier = Simplifier::new(&self.vertices, &triangles, self.bones_enabled());
+        simplifier.run(target);
+
+        // Keep the remaining triangles in order and the vertices they use
+        let mut remap: Vec<Option<i16>> = vec![None; vertex_count];
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let mut material_counts = vec![0i16; self.materials.len()];
+        for (i, &(_, group)) in triangles.iter().enumerate() {
+            if !simplifier.alive[i] {
+                continue;
+            }
+
+            let mut t = [0i16; 3];
+            for (new, &old) in t.iter_mut().zip(&simplifier.triangles[i]) {
+                *new = *remap[old].get_or_insert_with(|| {
+                    vertices.push(simplifier.vertices[old].clone());
+                    (vertices.len() - 1) as i16
+                });
+            }
+            indices.push(Vector3 { x: t[0], y: t[1], z: t[2] });
+            if groups_match {
+                material_counts[group] += 1;
+            }
+        }
+
+        if groups_match {
+            self.materials = material_counts;
+        } else if !self.materials.is_empty() {
+            self.materials = vec![indices.len() as i16];
+        }
+        self.vertices = vertices;
+        self.indices = indices;
+        if !self.strips.is_empty() {
+            self.strips = stitch_strips(&self.to_triangle_strips());
+        }
+        self.recalculate_bounding_box();
+    }
+
+    /// Check if this is a collision mesh
+    ///
+    /// Collision meshes are never rendered and only store vertex positions.
+    pub fn is_collision(&self) -> bool {
+        let attributes = VertexFormat::Position as i32
+            | VertexFormat::Normal as i32
+            | VertexFormat::Color as i32
+            | VertexFormat::BoneWeight as i32
+            | VertexFormat::BoneIndex as i32
+            | VertexFormat::Tangent as i32
+            | VertexFormat::UV1 as i32
+            | VertexFormat::UV2 as i32
+            | VertexFormat::UV3 as i32
+            | VertexFormat::UV4 as i32;
+
+        self.format & attributes == VertexFormat::Position as i32
+    }
+
+    /// Copy of this mesh with only the vertex positions and triangles
+    ///
+    /// Bones, materials and strips are dropped, the result is suitable for
+    /// importing into a physics engine.
+    pub fn to_collision(&self) -> Mesh {
+        let mut mesh = Mesh::new();
+        mesh.identifier = self.identifier.clone();
+        mesh.format = VertexFormat::Position as i32;
+        mesh.bounding_box = self.bounding_box;
+        mesh.indices = self.indices.clone();
+        mesh.pool = self.pool;
+
+        mesh.vertices = self.vertices.iter()
+            .map(|v| {
+                let mut vertex = Vertex::new();
+                vertex.position = v.position;
+                vertex
+            })
+            .collect();
+
+        mesh
+    }
+
+    /// Vertex positions of each triangle
+    ///
+    /// Triangles referencing missing vertices are skipped.
+    pub fn to_triangles(&self) -> Vec<[Vector3<f32>; 3]> {
+        self.triangles()
+            .map(|[a, b, c]| [a.position, b.position, c.position])
+            .collect()
+    }
+
+    /// Iterate over the vertices of each triangle
+    ///
+    /// Triangles referencing missing vertices are skipped.
+    pub fn triangles(&self) -> impl Iterator<Item = [&Vertex; 3]> {
+        let vertices = &self.vertices;
+        let vertex = move |i: i16| vertices.get(i as usize);
+
+        self.indices.iter()
+            .filter_map(move |t| Some([vertex(t.x)?, vertex(t.y)?, vertex(t.z)?]))
+    }
+
+    /// Iterate over the edges of each triangle, in winding order
+    ///
+    /// Edges shared by multiple triangles are yielded once per triangle.
+    pub fn edges(&self) -> impl Iterator<Item = (&Vertex, &Vertex)> {
+        self.triangles()
+            .flat_map(|[a, b, c]| vec![(a, b), (b, c), (c, a)])
+    }
+
+    /// Compute statistics to audit the mesh
+    pub fn stats(&self) -> MeshStats {
+        let mut referenced = vec![false; self.vertices.len()];
+        for t in &self.indices {
+            for &i in &[t.x, t.y, t.z] {
+                if let Some(r) = referenced.get_mut(i as usize) {
+                    *r = true;
+                }
+            }
+        }
+
+        let mut bone_weight_sum_range: Option<(f32, f32)> = None;
+        let mut bad_weight_vertices = 0;
+        if self.bones_enabled() {
+            for v in &self.vertices {
+                let w = v.bone_weights;
+                let sum = w.x + w.y + w.z + w.w;
+                if (sum - 1.0).abs() > BONE_WEIGHT_EPSILON {
+                    bad_weight_vertices += 1;
+                }
+
+                bone_weight_sum_range = Some(match bone_weight_sum_range {
+                    Some((min, max)) => (min.min(sum), max.max(sum)),
+                    None => (sum, sum),
+                });
+            }
+        }
+
+        MeshStats {
+            vertex_count: self.vertices.len(),
+            triangle_count: self.indices.len(),
+            material_count: if self.materials.is_empty() && !self.indices.is_empty() {
+                1
+            } else {
+                self.materials.iter().filter(|&&count| count > 0).count()
+            },
+            unreferenced_vertices: referenced.iter().filter(|&&r| !r).count(),
+            bone_weight_sum_range,
+            bad_weight_vertices,
+        }
+    }
+
+    /// Problems of the mesh, empty if the mesh is valid
+    ///
+    /// Reports triangles and strips referencing missing vertices, positions
+    /// that are not finite, bone weights that don't sum to 1.0 within
+    /// `BONE_WEIGHT_EPSILON` and materials that don't match the triangles
+    /// (see `repair`). Meshes without materials are valid, many client meshes
+    /// have none. Each kind of problem is reported once with the number of
+    /// elements affected.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let vertex_count = self.vertices.len();
+        let out_of_range = |i: i16| i < 0 || i as usize >= vertex_count;
+
+        let bad_indices = self.indices
+            .iter()
+            .flat_map(|t| vec![t.x, t.y, t.z])
+            .filter(|&i| out_of_range(i))
+            .count();
+        if bad_indices > 0 {
+            problems.push(format!("{} triangle indices out of range ({} vertices)",
+                                  bad_indices,
+                                  vertex_count));
+        }
+
+        let bad_strips = self.strips.iter().filter(|&&i| out_of_range(i)).count();
+        if bad_strips > 0 {
+            problems.push(format!("{} strip indices out of range ({} vertices)",
+                                  bad_strips,
+                                  vertex_count));
+        }
+
+        let bad_positions = self.vertices
+            .iter()
+            .filter(|v| {
+                let p = v.position;
+                !(p.x.is_finite() && p.y.is_finite() && p.z.is_finite())
+            })
+            .count();
+        if bad_positions > 0 {
+            problems.push(format!("{} vertex positions are not finite", bad_positions));
+        }
+
+        let stats = self.stats();
+        if stats.bad_weight_vertices > 0 {
+            problems.push(format!("{} vertices have bone weights not summing to 1.0",
+                                  stats.bad_weight_vertices));
+        }
+
+        let material_total: i64 = self.materials.iter().map(|&m| m as i64).sum();
+        let materials_valid = self.materials.is_empty()
+            || (self.materials.iter().all(|&m| m >= 0) && material_total == self.indices.len() as i64);
+        if !materials_valid {
+            problems.push(format!("Materials cover {} triangles of {}",
+                                  material_total,
+                                  self.indices.len()));
+        }
+
+        problems
+    }
+}
+
+/// Resolve a 1-based (or negative relative) OBJ index to a 0-based index
+fn obj_index(token: Option<&str>, count: usize, line_number: usize) -> Result<Option<usize>, Error> {
+    let token = match token {
+        Some(t) if !t.is_empty() => t,
+        _ => return Ok(None),
+    };
+
+    let i: i64 = token.parse()?;
+    let index = if i < 0 { count as i64 + i } else { i - 1 };
+    if index < 0 || index >= count as i64 {
+        bail!("OBJ index {} out of range on line {}", i, line_number + 1);
+    }
+
+    Ok(Some(index as usize))
+}
+
+/// Format flag of a 1-based UV channel
+fn uv_flag(channel: u8) -> Result<i32, Error> {
+    Ok(match channel {
+        1 => VertexFormat::UV1 as i32,
+        2 => VertexFormat::UV2 as i32,
+        3 => VertexFormat::UV3 as i32,
+        4 => VertexFormat::UV4 as i32,
+        _ => bail!("Invalid UV channel {}, expected 1 to 4", channel),
+    })
+}
+
+/// Index of a merged mesh, `index` of the appended mesh shifted by `offset`
+fn offset_index(index: i16, offset: i16) -> Result<i16, Error> {
+    match index.checked_add(offset) {
+        Some(index) => Ok(index),
+        None => bail!("Index {} out of range after offsetting it by {}", index, offset),
+    }
+}
+
+/// Euclidean distance between two points
+fn distance(a: &Vector3<f32>, b: &Vector3<f32>) -> f32 {
+    ((a.x - b.x).powi(2) + (a.y - b.y).powi(2) + (a.z - b.z).powi(2)).sqrt()
+}
+
+/// Error quadric of a vertex, the symmetric 4x4 matrix of the squared
+/// distances to a set of planes stored as its upper triangle
+#[derive(Clone, Copy)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    /// Quadric of the plane `ax + by + cz + d = 0` with a unit normal
+    fn plane(a: f64, b: f64, c: f64, d: f64, weight: f64) -> Quadric {
+        let q = [a * a, a * b, a * c, a * d, b * b, b * c, b * d, c * c, c * d, d * d];
+        Quadric([q[0] * weight, q[1] * weight, q[2] * weight, q[3] * weight, q[4] * weight,
+                 q[5] * weight, q[6] * weight, q[7] * weight, q[8] * weight, q[9] * weight])
+    }
+
+    fn add(&self, other: &Quadric) -> Quadric {
+        let mut q = self.0;
+        for (a, b) in q.iter_mut().zip(other.0.iter()) {
+            *a += b;
+        }
+        Quadric(q)
+    }
+
+    /// Sum of the squared distances of a point to the planes
+    fn error(&self, p: &Vector3<f32>) -> f64 {
+        let q = &self.0;
+        let (x, y, z) = (p.x as f64, p.y as f64, p.z as f64);
+        q[0] * x * x + 2.0 * q[1] * x * y + 2.0 * q[2] * x * z + 2.0 * q[3] * x
+            + q[4] * y * y + 2.0 * q[5] * y * z + 2.0 * q[6] * y
+            + q[7] * z * z + 2.0 * q[8] * z + q[9]
+    }
+}
+
+/// Queued edge collapse as `(cost, a, b, version of a, version of b)`
+type Collapse = (u64, usize, usize, u32, u32);
+
+/// Edge collapse state of `Mesh::simplify`
+///
+/// Vertices are never renumbered: an edge `(a, b)` collapses into `a` and
+/// `b` is removed. `versions` are bumped when a vertex changes so queued
+/// collapses computed from an older state are skipped.
+struct Simplifier {
+    vertices: Vec<Vertex>,
+    triangles: Vec<[usize; 3]>,
+    alive: Vec<bool>,
+    vertex_triangles: Vec<Vec<usize>>,
+    quadrics: Vec<Quadric>,
+    locked: Vec<bool>,
+    removed: Vec<bool>,
+    versions: Vec<u32>,
+    bones: bool,
+}
+
+impl Simplifier {
+    fn new(vertices: &[Vertex], triangles: &[([usize; 3], usize)], bones: bool) -> Simplifier {
+        let count = vertices.len();
+        let mut vertex_triangles = vec![Vec::new(); count];
+        let mut quadrics = vec![Quadric([0.0; 10]); count];
+        let mut edges: HashMap<(usize, usize), (usize, usize, bool)> = HashMap::new();
+
+        for (i, &(t, group)) in triangles.iter().enumerate() {
+            let normal = triangle_normal(&vertices[t[0]].position,
+                                         &vertices[t[1]].position,
+                                         &vertices[t[2]].position);
+            let length = normal.iter().map(|n| n * n).sum::<f64>().sqrt();
+
+            for e in 0..3 {
+                vertex_triangles[t[e]].push(i);
+                if length > 0.0 {
+                    let p = &vertices[t[e]].position;
+                    let (a, b, c) = (normal[0] / length, normal[1] / length, normal[2] / length);
+                    let d = -(a * p.x as f64 + b * p.y as f64 + c * p.z as f64);
+                    // Weighted by the area of the triangle
+                    quadrics[t[e]] = quadrics[t[e]].add(&Quadric::plane(a, b, c, d, length / 2.0));
+                }
+
+                let (a, b) = (t[e], t[(e + 1) % 3]);
+                let edge = edges.entry((a.min(b), a.max(b))).or_insert((0, group, false));
+                edge.0 += 1;
+                edge.2 |= edge.1 != group;
+            }
+        }
+
+        // Open, non-manifold and material border edges don't move
+        let mut locked = vec![false; count];
+        for (&(a, b), &(uses, _, border)) in &edges {
+            if uses != 2 || border {
+                locked[a] = true;
+                locked[b] = true;
+            }
+        }
+
+        Simplifier {
+            vertices: vertices.to_vec(),
+            triangles: triangles.iter().map(|&(t, _)| t).collect(),
+            alive: vec![true; triangles.len()],
+            vertex_triangles,
+            quadrics,
+            locked,
+            removed: vec![false; count],
+            versions: vec![0; count],
+            bones,
+        }
+    }
+
+    /// Collapse edges until at most `target` triangles are left
+    fn run(&mut self, target: usize) {
+        let mut live = self.triangles.len();
+        let mut queue = BinaryHeap::new();
+        for v in 0..self.vertices.len() {
+            self.queue_edges(&mut queue, v);
+        }
+
+        while live > target {
+            let Reverse((_, a, b, version_a, version_b)) = match queue.pop() {
+                Some(entry) => entry,
+                None => break,
+            };
+            if self.removed[a] || self.removed[b]
+                || self.versions[a] != version_a || self.versions[b] != version_b {
+                continue;
+            }
+
+            let (a, b) = if self.locked[b] { (b, a) } else { (a, b) };
+            let merged = match self.collapse_target(a, b) {
+                Some((_, merged)) => merged,
+                None => continue,
+            };
+            if !self.can_collapse(a, b, &merged.position) {
+                continue;
+            }
+
+            live -= self.collapse(a, b, merged);
+            self.queue_edges(&mut queue, a);
+        }
+    }
+
+    /// Queue the collapses of the edges of `v` by cost, the cost is stored
+    /// as the bits of a positive `f64`, which sort like the value
+    fn queue_edges(&self, queue: &mut BinaryHeap<Reverse<Collapse>>, v: usize) {
+        for n in self.neighbours(v) {
+            if let Some((cost, _)) = self.collapse_target(v, n) {
+                let (a, b) = (v.min(n), v.max(n));
+                let bits = cost.max(0.0).to_bits();
+                queue.push(Reverse((bits, a, b, self.versions[a], self.versions[b])));
+            }
+        }
+    }
+
+    fn neighbours(&self, v: usize) -> Vec<usize> {
+        let mut neighbours: Vec<usize> = self.vertex_triangles[v]
+            .iter()
+            .filter(|&&t| self.alive[t])
+            .flat_map(|&t| self.triangles[t].to_vec())
+            .filter(|&n| n != v)
+            .collect();
+        neighbours.sort();
+        neighbours.dedup();
+        neighbours
+    }
+
+    /// Error and merged vertex of collapsing `b` into `a`, `None` if both
+    /// are locked
+    fn collapse_target(&self, a: usize, b: usize) -> Option<(f64, Vertex)> {
+        let (locked_a, locked_b) = (self.locked[a], self.locked[b]);
+        if locked_a && locked_b {
+            return None;
+        }
+
+        let quadric = self.quadrics[a].add(&self.quadrics[b]);
+        let (va, vb) = (&self.vertices[a], &self.vertices[b]);
+        let mut candidates = Vec::with_capacity(3);
+        if !locked_b {
+            candidates.push(va.clone());
+        }
+        if !locked_a {
+            candidates.push(vb.clone());
+        }
+        if !locked_a && !locked_b {
+            candidates.push(self.midpoint(va, vb));
+        }
+
+        candidates.into_iter()
+            .map(|v| (quadric.error(&v.position), v))
+            .fold(None, |best: Option<(f64, Vertex)>, (error, v)| match best {
+                Some(ref best) if best.0 <= error => Some(best.clone()),
+                _ => Some((error, v)),
+            })
+    }
+
+    fn midpoint(&self, a: &Vertex, b: &Vertex) -> Vertex {
+        let mut v = a.clone();
+        v.position = a.position.lerp(&b.position, 0.5);
+        v.normal = a.normal.lerp(&b.normal, 0.5).normalize();
+        v.tangent = a.tangent.lerp(&b.tangent, 0.5).normalize();
+        v.color = Color4 {
+            r: (a.color.r + b.color.r) / 2.0,
+            g: (a.color.g + b.color.g) / 2.0,
+            b: (a.color.b + b.color.b) / 2.0,
+            a: (a.color.a + b.color.a) / 2.0,
+        };
+        v.uv1 = a.uv1.lerp(&b.uv1, 0.5);
+        v.uv2 = a.uv2.lerp(&b.uv2, 0.5);
+        v.uv3 = a.uv3.lerp(&b.uv3, 0.5);
+        v.uv4 = a.uv4.lerp(&b.uv4, 0.5);
+
+        if self.bones {
+            // Half of the weight of each bone of both vertices, keeping the
+            // 4 largest influences
+            let mut influences: Vec<(i16, f32)> = Vec::with_capacity(8);
+            for vertex in &[a, b] {
+                let w = &vertex.bone_weights;
+                let i = &vertex.bone_indices;
+                for &(index, weight) in &[(i.x, w.x), (i.y, w.y), (i.z, w.z), (i.w, w.w)] {
+                    match influences.iter_mut().find(|&&mut (existing, _)| existing == index) {
+                        Some(influence) => influence.1 += weight / 2.0,
+                        None => influences.push((index, weight / 2.0)),
+                    }
+                }
+            }
+            influences.sort_by(|x, y| y.1.partial_cmp(&x.1).unwrap_or(::std::cmp::Ordering::Equal));
+            influences.resize(4, (0, 0.0));
+
+            let total: f32 = influences.iter().map(|&(_, w)| w).sum();
+            let scale = if total > 0.0 { 1.0 / total } else { 0.0 };
+            v.bone_indices = Vector4 {
+                x: influences[0].0,
+                y: influences[1].0,
+                z: influences[2].0,
+                w: influences[3].0,
+            };
+            v.bone_weights = Vector4 {
+                x: influences[0].1 * scale,
+                y: influences[1].1 * scale,
+                z: influences[2].1 * scale,
+                w: influences[3].1 * scale,
+            };
+        }
+        v
+    }
+
+    /// Whether collapsing `b` into `a` at `position` keeps the mesh
+    /// manifold without flipping triangles
+    fn can_collapse(&self, a: usize, b: usize, position: &Vector3<f32>) -> bool {
+        // The vertices connected to both must be the opposite vertices of
+        // the shared triangles, otherwise the collapse pinches the mesh
+        let shared: Vec<usize> = self.vertex_triangles[a]
+            .iter()
+            .cloned()
+            .filter(|&t| self.alive[t] && self.triangles[t].contains(&b))
+            .collect();
+        let neighbours_b = self.neighbours(b);
+        let common = self.neighbours(a).iter().filter(|n| neighbours_b.contains(n)).count();
+        if common != shared.len() {
+            return false;
+        }
+
+        for &v in &[a, b] {
+            for &t in self.vertex_triangles[v].iter().filter(|&&t| self.alive[t]) {
+                if shared.contains(&t) {
+                    continue;
+                }
+
+                let corners = &self.triangles[t];
+                let p = |i: usize| &self.vertices[i].position;
+                let moved = |i: usize| if i == a || i == b { position } else { p(i) };
+                let before = triangle_normal(p(corners[0]), p(corners[1]), p(corners[2]));
+                let after = triangle_normal(moved(corners[0]), moved(corners[1]), moved(corners[2]));
+                let dot: f64 = before.iter().zip(after.iter()).map(|(x, y)| x * y).sum();
+                if dot <= 0.0 {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Collapse `b` into `a`, returning the number of triangles removed
+    fn collapse(&mut self, a: usize, b: usize, merged: Vertex) -> usize {
+        self.vertices[a] = merged;
+        self.quadrics[a] = self.quadrics[a].add(&self.quadrics[b]);
+        self.locked[a] |= self.locked[b];
+        self.removed[b] = true;
+        self.versions[a] += 1;
+        self.versions[b] += 1;
+
+        let mut removed = 0;
+        for t in ::std::mem::take(&mut self.vertex_triangles[b]) {
+            if !self.alive[t] {
+                continue;
+            }
+            if self.triangles[t].contains(&a) {
+                self.alive[t] = false;
+                removed += 1;
+            } else {
+                for i in self.triangles[t].iter_mut().filter(|i| **i == b) {
+                    *i = a;
+                }
+                self.vertex_triangles[a].push(t);
+            }
+        }
+
+        let alive = &self.alive;
+        self.vertex_triangles[a].retain(|&t| alive[t]);
+        removed
+    }
+}
+
+/// Unnormalized normal of a triangle, twice its area long
+fn triangle_normal(a: &Vector3<f32>, b: &Vector3<f32>, c: &Vector3<f32>) -> [f64; 3] {
+    let u = [(b.x - a.x) as f64, (b.y - a.y) as f64, (b.z - a.z) as f64];
+    let v = [(c.x - a.x) as f64, (c.y - a.y) as f64, (c.z - a.z) as f64];
+    [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]]
+}
+
+/// Order of the triangles for the vertex cache, see
+/// `Mesh::optimize_vertex_cache`
+///
+/// Tom Forsyth's algorithm: a vertex is scored by its position in a
+/// simulated LRU cache and by the number of triangles still using it, the
+/// triangle with the highest score is added next. Returns the indices of
+/// `triangles` in their new order.
+fn forsyth_order(triangles: &[[usize; 3]], vertex_count: usize) -> Vec<usize> {
+    const CACHE_SIZE: usize = 32;
+    const CACHE_DECAY_POWER: f32 = 1.5;
+    const LAST_TRIANGLE_SCORE: f32 = 0.75;
+    const VALENCE_BOOST_SCALE: f32 = 2.0;
+    const VALENCE_BOOST_POWER: f32 = 0.5;
+
+    let vertex_score = |cache_position: Option<usize>, remaining: usize| -> f32 {
+        if remaining == 0 {
+            return -1.0;
+        }
+
+        let cache_score = match cache_position {
+            None => 0.0,
+            Some(p) if p < 3 => LAST_TRIANGLE_SCORE,
+            Some(p) => {
+                let scale = 1.0 / (CACHE_SIZE - 3) as f32;
+                (1.0 - (p - 3) as f32 * scale).powf(CACHE_DECAY_POWER)
+            }
+        };
+        cache_score + VALENCE_BOOST_SCALE * (remaining as f32).powf(-VALENCE_BOOST_POWER)
+    };
+
+    // Triangles using each vertex
+    let mut vertex_triangles: Vec<Vec<usize>> = vec![Vec::new(); vertex_count];
+    for (t, triangle) in triangles.iter().enumerate() {
+        for &v in triangle {
+            vertex_triangles[v].push(t);
+        }
+    }
+
+    let mut remaining: Vec<usize> = vertex_triangles.iter().map(|t| t.len()).collect();
+    let mut scores: Vec<f32> = remaining.iter().map(|&r| vertex_score(None, r)).collect();
+    let triangle_score = |scores: &[f32], t: usize| -> f32 {
+        triangles[t].iter().map(|&v| scores[v]).sum()
+    };
+
+    let mut added = vec![false; triangles.len()];
+    let mut order = Vec::with_capacity(triangles.len());
+    let mut cache: Vec<usize> = Vec::with_capacity(CACHE_SIZE + 3);
+    let mut next_unadded = 0;
+
+    while order.len() < triangles.len() {
+        // Best triangle using a cached vertex, or the first one left
+        let mut best: Option<(usize, f32)> = None;
+        for &v in &cache {
+            for &t in &vertex_triangles[v] {
+                let score = triangle_score(&scores, t);
+                if !added[t] && best.map_or(true, |(_, s)| score > s) {
+                    best = Some((t, score));
+                }
+            }
+        }
+
+        let best = match best {
+            Some((t, _)) => t,
+            None => {
+                while added[next_unadded] {
+                    next_unadded += 1;
+                }
+                next_unadded
+            }
+        };
+
+        added[best] = true;
+        order.push(best);
+
+        // Move the vertices of the triangle to the front of the cache
+        for &v in triangles[best].iter().rev() {
+            remaining[v] -= 1;
+            cache.retain(|&c| c != v);
+            cache.insert(0, v);
+        }
+
+        let evicted = if cache.len() > CACHE_SIZE {
+            cache.split_off(CACHE_SIZE)
+        } else {
+            Vec::new()
+        };
+
+        for (p, &v) in cache.iter().enumerate() {
+            scores[v] = vertex_score(Some(p), remaining[v]);
+        }
+        for v in evicted {
+            scores[v] = vertex_score(None, remaining[v]);
+        }
+    }
+
+    order
+}
+
+/// Version of a mesh from its identifier
+fn read_version(identifier: &str) -> Result<u8, Error> {
+    Ok(match identifier {
+        "ZMS0005" => 5,
+        "ZMS0006" => 6,
+        "ZMS0007" => 7,
+        "ZMS0008" => 8,
+        id if id.starts_with("ZMS") => {
+            return Err(RoseError::UnsupportedVersion {
+                format: "ZMS",
+                found: id.to_string(),
+            }.into());
+        }
+        id => {
+            return Err(RoseError::InvalidMagic {
+                format: "ZMS",
+                found: id.to_string(),
+            }.into());
+        }
+    })
+}
+
+/// Path of a level of detail variant of a mesh
+///
+/// Level `0` is the base mesh, other levels insert `_LOD<n>` before the
+/// extension, e.g. `STONE014_LOD1.ZMS`. The suffix is lowercase when the
+/// extension is.
+pub fn lod_path(base: &Path, lod: u8) -> PathBuf {
+    if lod == 0 {
+        return base.to_path_buf();
+    }
+
+    let stem = base.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    let extension = base.extension().and_then(|e| e.to_str()).unwrap_or("");
+    let suffix = if extension.chars().any(|c| c.is_lowercase()) { "_lod" } else { "_LOD" };
+
+    let mut filename = format!("{}{}{}", stem, suffix, lod);
+    if !extension.is_empty() {
+        filename.push('.');
+        filename.push_str(extension);
+    }
+    base.with_file_name(filename)
+}
+
+/// Load a level of detail variant of a mesh
+///
+/// Falls back to the base mesh when the variant does not exist, see
+/// `lod_path`.
+pub fn load_model_lod(base: &Path, lod: u8) -> Result<Mesh, Error> {
+    let path = lod_path(base, lod);
+    if path.is_file() {
+        Mesh::from_path(&path)
+    } else {
+        Mesh::from_path(base)
+    }
+}
+
+/// Join multiple triangle strips into a single strip
+///
+/// Strips are joined with degenerate triangles, an extra vertex is inserted
+/// when required to preserve the winding of the following strip.
+pub fn stitch_strips(strips: &[Vec<i16>]) -> Vec<i16> {
+    let mut stitched: Vec<i16> = Vec::new();
+
+    for strip in strips.iter().filter(|s| !s.is_empty()) {
+        if let Some(&last) = stitched.last() {
+            stitched.push(last);
+            stitched.push(strip[0]);
+            if stitched.len() % 2 == 1 {
+                stitched.push(strip[0]);
+            }
+        }
+        stitched.extend_from_slice(strip);
+    }
+
+    stitched
+}
+
+/// Expand a triangle strip into a triangle list
+///
+/// Degenerate triangles (e.g. from stitching) are skipped.
+pub fn strip_to_triangles(strip: &[i16]) -> Vec<Vector3<i16>> {
+    let mut triangles = Vec::new();
+
+    for i in 0..strip.len().saturating_sub(2) {
+        let (a, b, c) = if i % 2 == 0 {
+            (strip[i], strip[i + 1], strip[i + 2])
+        } else {
+            (strip[i + 1], strip[i], strip[i + 2])
+        };
+
+        if a == b || b == c || a == c {
+            continue;
+        }
+
+        triangles.push(Vector3 { x: a, y: b, z: c });
+    }
+
+    triangles
+}
+
+
+impl Mesh {
+    /// Write the mesh as `ZMS0007` or `ZMS0008`
+    ///
+    /// `ZMS0007` meshes do not have the pool field. `write` always writes
+    /// `ZMS0008`.
+    ///
+    /// Counts are stored as `i16`, fails before writing anything if a list
+    /// has more than `i16::MAX` elements.
+    pub fn write_version<W: WriteRoseExt>(&self, writer: &mut W, version: u8) -> Result<(), Error> {
+        let identifier = match version {
+            7 => "ZMS0007",
+            8 => "ZMS0008",
+            v => {
+                return Err(RoseError::UnsupportedVersion {
+                    format: "ZMS",
+                    found: format!("ZMS{:04}", v),
+                }.into());
+            }
+        };
+
+        let counts = [
+            ("bones", self.bones.len()),
+            ("vertices", self.vertices.len()),
+            ("triangles", self.indices.len()),
+            ("materials", self.materials.len()),
+            ("strip indices", self.strips.len()),
+        ];
+        for &(kind, count) in &counts {
+            if count > i16::MAX as usize {
+                bail!("ZMS has too many {} to be written: {} (at most {})", kind, count, i16::MAX);
+            }
+        }
+
+        writer.write_cstring(identifier)?;
+        writer.write_i32(self.format)?;
+
+        writer.write_vector3_f32(&self.bounding_box.min)?;
+        writer.write_vector3_f32(&self.bounding_box.max)?;
+
+        writer.write_i16(self.bones.len() as i16)?;
+        for bone in &self.bones {
+            writer.write_i16(*bone)?;
+        }
+
+        writer.write_i16(self.vertices.len() as i16)?;
+
+        if self.positions_enabled() {
+            for ref vertex in &self.vertices {
+                writer.write_vector3_f32(&vertex.position)?;
+            }
+        }
+
+        if self.normals_enabled() {
+            for ref vertex in &self.vertices {
+                writer.write_vector3_f32(&vertex.normal)?;
+            }
+        }
+
+        if self.colors_enabled() {
+            for ref vertex in &self.vertices {
+                writer.write_color4(&vertex.color)?;
+            }
+        }
+
+        if self.bones_enabled() {
+            for ref vertex in &self.vertices {
+                let (weights, indices) = (&vertex.bone_weights, &vertex.bone_indices);
+                for &weight in &[weights.x, weights.y, weights.z, weights.w] {
+                    writer.write_f32(weight)?;
+                }
+                for &index in &[indices.x, indices.y, indices.z, indices.w] {
+                    writer.write_i16(index)?;
+                }
+            }
+        }
+
+        if self.tangents_enabled() {
+            for ref vertex in &self.vertices {
+                writer.write_vector3_f32(&vertex.tangent)?;
+            }
+        }
+
+        if self.uv1_enabled() {
+            for ref vertex in &self.vertices {
+                writer.write_vector2_f32(&vertex.uv1)?;
+            }
+        }
+
+        if self.uv2_enabled() {
+            for ref vertex in &self.vertices {
+                writer.write_vector2_f32(&vertex.uv2)?;
+            }
+        }
+
+        if self.uv3_enabled() {
+            for ref vertex in &self.vertices {
+                writer.write_vector2_f32(&vertex.uv3)?;
+            }
+        }
+
+        if self.uv4_enabled() {
+            for ref vertex in &self.vertices {
+                writer.write_vector2_f32(&vertex.uv4)?;
+            }
+        }
+
+        writer.write_i16(self.indices.len() as i16)?;
+        for index in &self.indices {
+            writer.write_vector3_i16(index)?;
+        }
+
+        writer.write_i16(self.materials.len() as i16)?;
+        for mat in &self.materials {
+            writer.write_i16(*mat)?;
+        }
+
+        writer.write_i16(self.strips.len() as i16)?;
+        for strip in &self.strips {
+            writer.write_i16(*strip)?;
+        }
+
+        if version >= 8 {
+            writer.write_i16(self.pool)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl RoseFile for Mesh {
+    fn new() -> Mesh {
+        Mesh {
+            identifier: String::from(""),
+            format: -1,
+            bounding_box: BoundingBox {
+                min: Vector3::<f32>::new(),
+                max: Vector3::<f32>::new(),
+            },
+            bones: Vec::new(),
+            vertices: Vec::new(),
+            indices: Vec::new(),
+            materials: Vec::new(),
+            strips: Vec::new(),
+            pool: 0,
+        }
+    }
+
+    fn read<R: ReadRoseExt>(&mut self, reader: &mut R) -> Result<(), Error> {
+        self.identifier = reader.read_cstring()?;
+
+        let version = read_version(&self.identifier)?;
+
+        self.format = reader.read_i32()?;
+        self.bounding_box.min = reader.read_vector3_f32()?;
+        self.bounding_box.max = reader.read_vector3_f32()?;
+
+        trace!("ZMS: reading version {}", version);
+        if version < 7 {
+            return self.read_legacy(reader);
+        }
+
+        self.bones = reader.read_vec_i16("ZMS", 2, |r| r.read_i16())?;
+
+        let vert_count = reader.read_i16()?;
+        let vert_count = reader.check_count("ZMS", vert_count as i64, self.vertex_size() as u64)?;
+        for _ in 0..vert_count {
+            self.vertices.push(Vertex::new());
+        }
+
+        if self.positions_enabled() {
+            for i in 0..vert_count {
+                self.vertices[i].position = reader.read_vector3_f32()?;
+            }
+        }
+
+        if self.normals_enabled() {
+            for i in 0..vert_count {
+                self.vertices[i].normal = reader.read_vector3_f32()?;
+            }
+        }
+
+        if self.colors_enabled() {
+            for i in 0..vert_count {
+                self.vertices[i].color = reader.read_color4()?;
+            }
+        }
+
+        if self.bones_enabled() {
+            for i in 0..vert_count {
+                self.vertices[i].bone_weights = read_bone_weights(reader)?;
+                self.vertices[i].bone_indices = Vector4 {
+                    x: reader.read_i16()?,
+                    y: reader.read_i16()?,
+                    z: reader.read_i16()?,
+                    w: reader.read_i16()?,
+                };
+
+            }
+        }
+
+        if self.tangents_enabled() {
+            for i in 0..vert_count {
+                self.vertices[i].tangent = reader.read_vector3_f32()?;
+            }
+        }
+
+        if self.uv1_enabled() {
+            for i in 0..vert_count {
+                self.vertices[i].uv1 = reader.read_vector2_f32()?;
+            }
+        }
+
+        if self.uv2_enabled() {
+            for i in 0..vert_count {
+                self.vertices[i].uv2 = reader.read_vector2_f32()?;
+            }
+        }
+
+        if self.uv3_enabled() {
+            for i in 0..vert_count {
+                self.vertices[i].uv3 = reader.read_vector2_f32()?;
+            }
+        }
+        if self.uv4_enabled() {
+            for i in 0..vert_count {
+                self.vertices[i].uv4 = reader.read_vector2_f32()?;
+            }
+        }
+
+        self.indices = reader.read_vec_i16("ZMS", 6, |r| r.read_vector3_i16())?;
+        self.materials = reader.read_vec_i16("ZMS", 2, |r| r.read_i16())?;
+        self.strips = reader.read_vec_i16("ZMS", 2, |r| r.read_i16())?;
+
+        if version >= 8 {
+            self.pool = reader.read_i16()?;
+        }
+
+        Ok(())
+    }
+
+    fn write<W: WriteRoseExt>(&mut self, writer: &mut W) -> Result<(), Error> {
+        self.write_version(writer, 8)
+    }
+
+    fn size_hint(&self) -> usize {
+        // Identifier, format and bounding box
+        let header_size = 8 + 4 + 24;
+
+        // Each list is prefixed by an i16 count, followed by the pool
+        header_size
+            + 2 + self.bones.len() * 2
+            + 2 + self.vertices.len() * self.vertex_size()
+            + 2 + self.indices.len() * 6
+            + 2 + self.materials.len() * 2
+            + 2 + self.strips.len() * 2
+            + 2
+    }
+}
+
+impl ApproxEq for Mesh {
+    fn approx_eq(&self, other: &Mesh, epsilon: f32) -> bool {
+        self.identifier == other.identifier &&
+        self.format == other.format &&
+        self.bounding_box.approx_eq(&other.bounding_box, epsilon) &&
+        self.bones == other.bones &&
+        self.vertices.approx_eq(&other.vertices, epsilon) &&
+        self.indices == other.indices &&
+        self.materials == other.materials &&
+        self.strips == other.strips &&
+        self.pool == other.pool
+    }
+}
+
+impl ApproxEq for Vertex {
+    fn approx_eq(&self, other: &Vertex, epsilon: f32) -> bool {
+        self.position.approx_eq(&other.position, epsilon) &&
+        self.normal.approx_eq(&other.normal, epsilon) &&
+        self.color.approx_eq(&other.color, epsilon) &&
+        self.bone_weights.approx_eq(&other.bone_weights, epsilon) &&
+        self.bone_indices == other.bone_indices &&
+        self.tangent.approx_eq(&other.tangent, epsilon) &&
+        self.uv1.approx_eq(&other.uv1, epsilon) &&
+        self.uv2.approx_eq(&other.uv2, epsilon) &&
+        self.uv3.approx_eq(&other.uv3, epsilon) &&
+        self.uv4.approx_eq(&other.uv4, epsilon)
+    }
+}
+
+/// Read bone weights in file order
+///
+/// Unlike quaternions, which `read_vector4_f32` reads `w` first, bone
+/// weights are a plain list of 4 weights read as `x, y, z, w`.
+fn read_bone_weights<R: ReadRoseExt>(reader: &mut R) -> Result<Vector4<f32>, Error> {
+    Ok(Vector4 {
+        x: reader.read_f32()?,
+        y: reader.read_f32()?,
+        z: reader.read_f32()?,
+        w: reader.read_f32()?,
+    })
+}
+
+impl Vertex {
+    pub fn new() -> Vertex {
+        Vertex {
+            position: Vector3::<f32>::new(),
+            normal: Vector3::<f32>::new(),
+            color: Color4::new(),
+            bone_weights: Vector4::<f32>::new(),
+            bone_indices: Vector4::<i16>::new(),
+            tangent: Vector3::<f32>::new(),
+            uv1: Vector2::<f32>::new(),
+            uv2: Vector2::<f32>::new(),
+            uv3: Vector2::<f32>::new(),
             uv4: Vector2::<f32>::new(),
         }
     }
+
+    /// Vertex color as 8-bit RGBA, see `Color4::to_rgba8`
+    pub fn color_rgba8(&self) -> [u8; 4] {
+        self.color.to_rgba8()
+    }
+
+    /// Set the vertex color from 8-bit RGBA
+    pub fn set_color_rgba8(&mut self, rgba: [u8; 4]) {
+        self.color = Color4::from_rgba8(rgba);
+    }
+
+    /// UV of a channel checked by `uv_flag`
+    fn uv_mut(&mut self, channel: u8) -> &mut Vector2<f32> {
+        match channel {
+            1 => &mut self.uv1,
+            2 => &mut self.uv2,
+            3 => &mut self.uv3,
+            _ => &mut self.uv4,
+        }
+    }
 }
 
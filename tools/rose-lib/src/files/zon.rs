@@ -2,6 +2,7 @@
 use std::io::SeekFrom;
 use std::iter;
 
+use errors::RoseError;
 use failure::Error;
 use num;
 use num::FromPrimitive as num_from;
@@ -13,7 +14,7 @@ use utils::{Vector2, Vector3};
 pub type ZON = Zone;
 
 /// Zone Type
-#[derive(Debug, Serialize, Deserialize, FromPrimitive, PartialEq)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, FromPrimitive, PartialEq)]
 pub enum ZoneType {
     Grass = 0,
     Mountain = 1,
@@ -32,7 +33,7 @@ pub enum ZoneType {
     JunonPyramids = 14,
 }
 
-#[derive(Debug, Serialize, Deserialize, FromPrimitive, PartialEq)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, FromPrimitive, PartialEq)]
 pub enum ZoneBlockType {
     BasicInfo = 0,
     EventPoints = 1,
@@ -41,7 +42,7 @@ pub enum ZoneBlockType {
     Economy = 4,
 }
 
-#[derive(Debug, Serialize, Deserialize, FromPrimitive, PartialEq)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, FromPrimitive, PartialEq)]
 pub enum ZoneTileRotation {
     Unkown = 0,
     None = 1,
@@ -102,10 +103,20 @@ pub struct Zone {
     pub cloth_consumption: i32,
     pub alchemy_consumption: i32,
     pub chemical_consumption: i32,
+    pub industrial_consumption: i32,
     pub medicine_consumption: i32,
     pub food_consumption: i32,
 }
 
+/// Block types in the order they are written
+const BLOCK_TYPES: [ZoneBlockType; 5] = [
+    ZoneBlockType::BasicInfo,
+    ZoneBlockType::EventPoints,
+    ZoneBlockType::Textures,
+    ZoneBlockType::Tiles,
+    ZoneBlockType::Economy,
+];
+
 impl ZonePosition {
     fn new() -> ZonePosition {
         ZonePosition {
@@ -164,6 +175,7 @@ impl RoseFile for Zone {
             cloth_consumption: 0,
             alchemy_consumption: 0,
             chemical_consumption: 0,
+            industrial_consumption: 0,
             medicine_consumption: 0,
             food_consumption: 0,
         }
@@ -185,14 +197,17 @@ impl RoseFile for Zone {
             let block_offset = block.1;
 
             if block_type.is_none() {
-                bail!("Invalid block type: {}", block.0);
+                return Err(RoseError::InvalidData {
+                    format: "ZON",
+                    reason: format!("Invalid block type: {}", block.0),
+                }.into());
             }
 
             reader.seek(SeekFrom::Start(block_offset as u64))?;
 
             match block_type.unwrap() {
                 ZoneBlockType::BasicInfo => {
-                    self.zone_type = num_from::from_i32(reader.read_i32()?).unwrap();
+                    self.zone_type = read_enum(reader, "zone type")?;
                     self.width = reader.read_i32()?;
                     self.height = reader.read_i32()?;
                     self.grid_count = reader.read_i32()?;
@@ -239,7 +254,7 @@ impl RoseFile for Zone {
                         t.offset1 = reader.read_i32()?;
                         t.offset2 = reader.read_i32()?;
                         t.blend = reader.read_i32()? != 0;
-                        t.rotation = num_from::from_i32(reader.read_i32()?).unwrap();
+                        t.rotation = read_enum(reader, "tile rotation")?;
                         t.tile_type = reader.read_i32()?;
                         self.tiles.push(t);
                     }
@@ -259,6 +274,7 @@ impl RoseFile for Zone {
                     self.cloth_consumption = reader.read_i32()?;
                     self.alchemy_consumption = reader.read_i32()?;
                     self.chemical_consumption = reader.read_i32()?;
+                    self.industrial_consumption = reader.read_i32()?;
                     self.medicine_consumption = reader.read_i32()?;
                     self.food_consumption = reader.read_i32()?;
                 },
@@ -269,6 +285,117 @@ impl RoseFile for Zone {
     }
 
     fn write<W: WriteRoseExt>(&mut self, writer: &mut W) -> Result<(), Error> {
-        unimplemented!();
+        if self.positions.len() != self.height as usize
+            || self.positions.iter().any(|row| row.len() != self.width as usize)
+        {
+            bail!("ZON positions do not match the zone size {}x{}", self.width, self.height);
+        }
+
+        let start = writer.stream_position()?;
+
+        // Offsets are filled in once the blocks are written
+        writer.write_i32(BLOCK_TYPES.len() as i32)?;
+        for &block_type in BLOCK_TYPES.iter() {
+            writer.write_i32(block_type as i32)?;
+            writer.write_i32(0)?;
+        }
+
+        let mut offsets = Vec::with_capacity(BLOCK_TYPES.len());
+        for &block_type in BLOCK_TYPES.iter() {
+            offsets.push(writer.stream_position()? - start);
+            self.write_block(writer, block_type)?;
+        }
+        let end = writer.stream_position()?;
+
+        for (i, &offset) in offsets.iter().enumerate() {
+            writer.seek(SeekFrom::Start(start + 4 + i as u64 * 8 + 4))?;
+            writer.write_i32(offset as i32)?;
+        }
+        writer.seek(SeekFrom::Start(end))?;
+
+        Ok(())
+    }
+}
+
+impl Zone {
+    fn write_block<W: WriteRoseExt>(&self, writer: &mut W, block_type: ZoneBlockType) -> Result<(), Error> {
+        match block_type {
+            ZoneBlockType::BasicInfo => {
+                writer.write_i32(self.zone_type as i32)?;
+                writer.write_i32(self.width)?;
+                writer.write_i32(self.height)?;
+                writer.write_i32(self.grid_count)?;
+                writer.write_f32(self.grid_size)?;
+                writer.write_vector2_i32(&self.start_position)?;
+
+                for w in 0..self.width as usize {
+                    for h in 0..self.height as usize {
+                        let pos = &self.positions[h][w];
+                        writer.write_bool(pos.is_used)?;
+                        writer.write_vector2_f32(&pos.position)?;
+                    }
+                }
+            },
+            ZoneBlockType::EventPoints => {
+                writer.write_i32(self.event_points.len() as i32)?;
+                for p in &self.event_points {
+                    writer.write_vector3_f32(&p.position)?;
+                    writer.write_string_u8(&p.name)?;
+                }
+            },
+            ZoneBlockType::Textures => {
+                writer.write_i32(self.textures.len() as i32)?;
+                for texture in &self.textures {
+                    writer.write_string_u8(texture)?;
+                }
+            },
+            ZoneBlockType::Tiles => {
+                writer.write_i32(self.tiles.len() as i32)?;
+                for t in &self.tiles {
+                    writer.write_i32(t.layer1)?;
+                    writer.write_i32(t.layer2)?;
+                    writer.write_i32(t.offset1)?;
+                    writer.write_i32(t.offset2)?;
+                    writer.write_i32(t.blend as i32)?;
+                    writer.write_i32(t.rotation as i32)?;
+                    writer.write_i32(t.tile_type)?;
+                }
+            },
+            ZoneBlockType::Economy => {
+                writer.write_string_u8(&self.name)?;
+                writer.write_i32(self.is_underground as i32)?;
+                writer.write_string_u8(&self.background_music)?;
+                writer.write_string_u8(&self.sky)?;
+                writer.write_i32(self.economy_tick_rate)?;
+                writer.write_i32(self.population_base)?;
+                writer.write_i32(self.population_growth_rate)?;
+                writer.write_i32(self.metal_consumption)?;
+                writer.write_i32(self.stone_consumption)?;
+                writer.write_i32(self.wood_consumption)?;
+                writer.write_i32(self.leather_consumption)?;
+                writer.write_i32(self.cloth_consumption)?;
+                writer.write_i32(self.alchemy_consumption)?;
+                writer.write_i32(self.chemical_consumption)?;
+                writer.write_i32(self.industrial_consumption)?;
+                writer.write_i32(self.medicine_consumption)?;
+                writer.write_i32(self.food_consumption)?;
+            },
+        }
+
+        Ok(())
+    }
+}
+
+/// Read an `i32` enum value, failing on values without a variant
+fn read_enum<R: ReadRoseExt, T: num_from>(reader: &mut R, name: &str) -> Result<T, Error> {
+    let value = reader.read_i32()?;
+    match num_from::from_i32(value) {
+        Some(value) => Ok(value),
+        None => {
+            Err(RoseError::InvalidData {
+                format: "ZON",
+                reason: format!("Invalid {}: {}", name, value),
+            }.into())
+        }
     }
 }
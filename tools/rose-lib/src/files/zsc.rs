@@ -0,0 +1,430 @@
+//! ROSE Online Model Lists
+//!
+//! A `.ZSC` file ties meshes, materials and effects together into objects.
+//! Each object is made of one or more parts, a part pairs a mesh from the
+//! mesh list with a material from the material list and places it relative
+//! to the other parts of the object.
+//!
+//! The ZMS `materials` list only stores the triangle count of each material
+//! group in the mesh, the material actually used to render a mesh comes from
+//! the parts referencing it in the model list.
+use std::io::Cursor;
+
+use errors::RoseError;
+use failure::Error;
+use io::{RoseFile, ReadRoseExt, WriteRoseExt};
+use utils::{BoundingBox, Vector2, Vector3, Vector4};
+
+/// Model List File
+pub type ZSC = Scene;
+
+/// Model List
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Scene {
+    pub meshes: Vec<String>,
+    pub materials: Vec<SceneMaterial>,
+    pub effects: Vec<String>,
+    pub objects: Vec<SceneObject>,
+}
+
+/// Model List material
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct SceneMaterial {
+    pub path: String,
+    pub is_skin: bool,
+    pub alpha_enabled: bool,
+    pub two_sided: bool,
+    pub alpha_test_enabled: bool,
+    pub alpha_ref: u16,
+    pub z_test_enabled: bool,
+    pub z_write_enabled: bool,
+    pub blend_type: u16,
+    pub specular_enabled: bool,
+    pub alpha: f32,
+    pub glow_type: u16,
+    pub glow_color: Vector3<f32>,
+}
+
+/// Model List object
+///
+/// Objects without any parts have no effects or bounding box.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct SceneObject {
+    pub radius: i32,
+    pub center: Vector2<i32>,
+    pub parts: Vec<ScenePart>,
+    pub effects: Vec<SceneEffect>,
+    pub bounding_box: BoundingBox<f32>,
+}
+
+/// Model List object part
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct ScenePart {
+    pub mesh_id: u16,
+    pub material_id: u16,
+    pub properties: SceneProperties,
+}
+
+/// Model List object effect
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct SceneEffect {
+    pub effect_id: u16,
+    pub effect_type: u16,
+    pub properties: SceneProperties,
+}
+
+/// Optional properties of a part or effect
+///
+/// Properties are stored as `(id, size, data)` records terminated by a zero
+/// id. Unknown records are kept as raw bytes and written after the known
+/// properties.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct SceneProperties {
+    pub position: Option<Vector3<f32>>,
+    pub rotation: Option<Vector4<f32>>,
+    pub scale: Option<Vector3<f32>>,
+    pub axis_rotation: Option<Vector4<f32>>,
+    pub bone_index: Option<u16>,
+    pub dummy_index: Option<u16>,
+    pub parent: Option<u16>,
+    pub collision: Option<u16>,
+    pub animation: Option<String>,
+    pub range: Option<u16>,
+    pub use_lightmap: Option<u16>,
+    pub unknown: Vec<(u8, Vec<u8>)>,
+}
+
+impl Scene {
+    /// Find the index of a mesh by path
+    ///
+    /// Paths are compared case-insensitively and ignoring the type of slash.
+    pub fn mesh_id(&self, path: &str) -> Option<usize> {
+        let normalize = |p: &str| p.replace('\\', "/").to_lowercase();
+        let path = normalize(path);
+        self.meshes.iter().position(|m| normalize(m) == path)
+    }
+
+    /// Resolve the materials used to render a mesh
+    ///
+    /// Returns the material of every part referencing `mesh_id`, in the
+    /// order they first appear and without duplicates.
+    pub fn resolve_materials(&self, mesh_id: usize) -> Result<Vec<&SceneMaterial>, Error> {
+        let mut material_ids: Vec<u16> = Vec::new();
+        for part in self.objects.iter().flat_map(|o| o.parts.iter()) {
+            if part.mesh_id as usize == mesh_id && !material_ids.contains(&part.material_id) {
+                material_ids.push(part.material_id);
+            }
+        }
+
+        let mut materials = Vec::new();
+        for id in material_ids {
+            match self.materials.get(id as usize) {
+                Some(material) => materials.push(material),
+                None => {
+                    return Err(RoseError::IndexOutOfRange {
+                        kind: "ZSC material",
+                        index: id as i64,
+                        count: self.materials.len(),
+                    }.into());
+                }
+            }
+        }
+
+        Ok(materials)
+    }
+}
+
+impl SceneMaterial {
+    /// Construct an opaque material for a texture
+    pub fn new(path: &str) -> SceneMaterial {
+        SceneMaterial {
+            path: String::from(path),
+            is_skin: false,
+            alpha_enabled: false,
+            two_sided: false,
+            alpha_test_enabled: false,
+            alpha_ref: 0,
+            z_test_enabled: true,
+            z_write_enabled: true,
+            blend_type: 0,
+            specular_enabled: false,
+            alpha: 1.0,
+            glow_type: 0,
+            glow_color: Vector3::<f32>::new(),
+        }
+    }
+}
+
+impl SceneObject {
+    fn new() -> SceneObject {
+        SceneObject {
+            radius: 0,
+            center: Vector2::<i32>::new(),
+            parts: Vec::new(),
+            effects: Vec::new(),
+            bounding_box: BoundingBox {
+                min: Vector3::<f32>::new(),
+                max: Vector3::<f32>::new(),
+            },
+        }
+    }
+}
+
+impl SceneProperties {
+    fn new() -> SceneProperties {
+        SceneProperties {
+            position: None,
+            rotation: None,
+            scale: None,
+            axis_rotation: None,
+            bone_index: None,
+            dummy_index: None,
+            parent: None,
+            collision: None,
+            animation: None,
+            range: None,
+            use_lightmap: None,
+            unknown: Vec::new(),
+        }
+    }
+
+    fn read<R: ReadRoseExt>(&mut self, reader: &mut R) -> Result<(), Error> {
+        loop {
+            let id = reader.read_u8()?;
+            if id == 0 {
+                break;
+            }
+
+            let size = reader.read_u8()?;
+            let mut data = vec![0u8; size as usize];
+            reader.read_exact(&mut data)?;
+
+            let mut data_reader = Cursor::new(&data[..]);
+            match id {
+                1 => self.position = Some(data_reader.read_vector3_f32()?),
+                2 => self.rotation = Some(data_reader.read_vector4_f32()?),
+                3 => self.scale = Some(data_reader.read_vector3_f32()?),
+                4 => self.axis_rotation = Some(data_reader.read_vector4_f32()?),
+                5 => self.bone_index = Some(data_reader.read_u16()?),
+                6 => self.dummy_index = Some(data_reader.read_u16()?),
+                7 => self.parent = Some(data_reader.read_u16()?),
+                29 => self.collision = Some(data_reader.read_u16()?),
+                30 => self.animation = Some(data_reader.read_string(size as u64)?),
+                31 => self.range = Some(data_reader.read_u16()?),
+                32 => self.use_lightmap = Some(data_reader.read_u16()?),
+                _ => self.unknown.push((id, data)),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write<W: WriteRoseExt>(&self, writer: &mut W) -> Result<(), Error> {
+        if let Some(ref v) = self.position {
+            writer.write_u8(1)?;
+            writer.write_u8(12)?;
+            writer.write_vector3_f32(v)?;
+        }
+        if let Some(ref v) = self.rotation {
+            writer.write_u8(2)?;
+            writer.write_u8(16)?;
+            writer.write_vector4_f32(v)?;
+        }
+        if let Some(ref v) = self.scale {
+            writer.write_u8(3)?;
+            writer.write_u8(12)?;
+            writer.write_vector3_f32(v)?;
+        }
+        if let Some(ref v) = self.axis_rotation {
+            writer.write_u8(4)?;
+            writer.write_u8(16)?;
+            writer.write_vector4_f32(v)?;
+        }
+
+        let shorts = [(5, self.bone_index), (6, self.dummy_index), (7, self.parent), (29, self.collision)];
+        for &(id, value) in shorts.iter() {
+            if let Some(n) = value {
+                writer.write_u8(id)?;
+                writer.write_u8(2)?;
+                writer.write_u16(n)?;
+            }
+        }
+
+        if let Some(ref path) = self.animation {
+            if path.len() > u8::max_value() as usize {
+                bail!("ZSC animation path is longer than 255 bytes: {}", path);
+            }
+            writer.write_u8(30)?;
+            writer.write_string_u8(path)?;
+        }
+
+        let shorts = [(31, self.range), (32, self.use_lightmap)];
+        for &(id, value) in shorts.iter() {
+            if let Some(n) = value {
+                writer.write_u8(id)?;
+                writer.write_u8(2)?;
+                writer.write_u16(n)?;
+            }
+        }
+
+        for &(id, ref data) in &self.unknown {
+            writer.write_u8(id)?;
+            writer.write_u8(data.len() as u8)?;
+            writer.write_all(data)?;
+        }
+
+        writer.write_u8(0)?;
+        Ok(())
+    }
+}
+
+impl RoseFile for Scene {
+    fn new() -> Scene {
+        Scene {
+            meshes: Vec::new(),
+            materials: Vec::new(),
+            effects: Vec::new(),
+            objects: Vec::new(),
+        }
+    }
+
+    fn read<R: ReadRoseExt>(&mut self, reader: &mut R) -> Result<(), Error> {
+        let mesh_count = reader.read_u16()?;
+        let mesh_count = reader.check_count("ZSC", mesh_count as i64, 1)?;
+        for _ in 0..mesh_count {
+            self.meshes.push(reader.read_cstring()?);
+        }
+
+        let material_count = reader.read_u16()?;
+        let material_count = reader.check_count("ZSC", material_count as i64, 37)?;
+        for _ in 0..material_count {
+            let mut material = SceneMaterial::new(&reader.read_cstring()?);
+            material.is_skin = reader.read_u16()? != 0;
+            material.alpha_enabled = reader.read_u16()? != 0;
+            material.two_sided = reader.read_u16()? != 0;
+            material.alpha_test_enabled = reader.read_u16()? != 0;
+            material.alpha_ref = reader.read_u16()?;
+            material.z_test_enabled = reader.read_u16()? != 0;
+            material.z_write_enabled = reader.read_u16()? != 0;
+            material.blend_type = reader.read_u16()?;
+            material.specular_enabled = reader.read_u16()? != 0;
+            material.alpha = reader.read_f32()?;
+            material.glow_type = reader.read_u16()?;
+            material.glow_color = reader.read_vector3_f32()?;
+            self.materials.push(material);
+        }
+
+        let effect_count = reader.read_u16()?;
+        let effect_count = reader.check_count("ZSC", effect_count as i64, 1)?;
+        for _ in 0..effect_count {
+            self.effects.push(reader.read_cstring()?);
+        }
+
+        let object_count = reader.read_u16()?;
+        let object_count = reader.check_count("ZSC", object_count as i64, 14)?;
+        for _ in 0..object_count {
+            let mut object = SceneObject::new();
+            object.radius = reader.read_i32()?;
+            object.center = reader.read_vector2_i32()?;
+
+            let part_count = reader.read_u16()?;
+            if part_count == 0 {
+                self.objects.push(object);
+                continue;
+            }
+
+            let part_count = reader.check_count("ZSC", part_count as i64, 5)?;
+            for _ in 0..part_count {
+                let mesh_id = reader.read_u16()?;
+                let material_id = reader.read_u16()?;
+                let mut properties = SceneProperties::new();
+                properties.read(reader)?;
+
+                object.parts.push(ScenePart {
+                    mesh_id,
+                    material_id,
+                    properties,
+                });
+            }
+
+            let effect_count = reader.read_u16()?;
+            let effect_count = reader.check_count("ZSC", effect_count as i64, 5)?;
+            for _ in 0..effect_count {
+                let effect_id = reader.read_u16()?;
+                let effect_type = reader.read_u16()?;
+                let mut properties = SceneProperties::new();
+                properties.read(reader)?;
+
+                object.effects.push(SceneEffect {
+                    effect_id,
+                    effect_type,
+                    properties,
+                });
+            }
+
+            object.bounding_box.min = reader.read_vector3_f32()?;
+            object.bounding_box.max = reader.read_vector3_f32()?;
+            self.objects.push(object);
+        }
+
+        Ok(())
+    }
+
+    fn write<W: WriteRoseExt>(&mut self, writer: &mut W) -> Result<(), Error> {
+        writer.write_u16(self.meshes.len() as u16)?;
+        for mesh in &self.meshes {
+            writer.write_cstring(mesh)?;
+        }
+
+        writer.write_u16(self.materials.len() as u16)?;
+        for material in &self.materials {
+            writer.write_cstring(&material.path)?;
+            writer.write_u16(material.is_skin as u16)?;
+            writer.write_u16(material.alpha_enabled as u16)?;
+            writer.write_u16(material.two_sided as u16)?;
+            writer.write_u16(material.alpha_test_enabled as u16)?;
+            writer.write_u16(material.alpha_ref)?;
+            writer.write_u16(material.z_test_enabled as u16)?;
+            writer.write_u16(material.z_write_enabled as u16)?;
+            writer.write_u16(material.blend_type)?;
+            writer.write_u16(material.specular_enabled as u16)?;
+            writer.write_f32(material.alpha)?;
+            writer.write_u16(material.glow_type)?;
+            writer.write_vector3_f32(&material.glow_color)?;
+        }
+
+        writer.write_u16(self.effects.len() as u16)?;
+        for effect in &self.effects {
+            writer.write_cstring(effect)?;
+        }
+
+        writer.write_u16(self.objects.len() as u16)?;
+        for object in &self.objects {
+            writer.write_i32(object.radius)?;
+            writer.write_vector2_i32(&object.center)?;
+
+            writer.write_u16(object.parts.len() as u16)?;
+            if object.parts.is_empty() {
+                continue;
+            }
+
+            for part in &object.parts {
+                writer.write_u16(part.mesh_id)?;
+                writer.write_u16(part.material_id)?;
+                part.properties.write(writer)?;
+            }
+
+            writer.write_u16(object.effects.len() as u16)?;
+            for effect in &object.effects {
+                writer.write_u16(effect.effect_id)?;
+                writer.write_u16(effect.effect_type)?;
+                effect.properties.write(writer)?;
+            }
+
+            writer.write_vector3_f32(&object.bounding_box.min)?;
+            writer.write_vector3_f32(&object.bounding_box.max)?;
+        }
+
+        Ok(())
+    }
+}
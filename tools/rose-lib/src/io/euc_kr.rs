@@ -0,0 +1,29 @@
+//! Conversion of EUC-KR strings
+//!
+//! The ROSE client is Korean and stores some strings, such as the paths of
+//! VFS entries, as EUC-KR (code page 949).
+use encoding_rs::EUC_KR;
+use failure::Error;
+
+/// Decode EUC-KR bytes to a `String`
+///
+/// Bytes that are not valid EUC-KR are decoded as UTF-8 instead, invalid
+/// sequences are replaced with `U+FFFD`. ASCII strings are the same in both,
+/// but much non-ASCII UTF-8 text is also valid EUC-KR and is decoded as such.
+pub fn decode_euc_kr(bytes: &[u8]) -> String {
+    match EUC_KR.decode_without_bom_handling_and_without_replacement(bytes) {
+        Some(s) => s.into_owned(),
+        None => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+/// Encode a string as EUC-KR
+///
+/// Fails if the string has characters that EUC-KR can not represent.
+pub fn encode_euc_kr(string: &str) -> Result<Vec<u8>, Error> {
+    let (bytes, _, unmappable) = EUC_KR.encode(string);
+    if unmappable {
+        bail!("String can not be encoded as EUC-KR: {}", string);
+    }
+    Ok(bytes.into_owned())
+}
@@ -1,9 +1,14 @@
 use std::fs::File;
-use std::io::{BufReader, BufWriter};
+use std::io::{BufReader, BufWriter, Cursor, Read, Write};
 use std::path::Path;
 
+use errors::RoseError;
 use failure::Error;
-use io::{ReadRoseExt, WriteRoseExt};
+use files::idx::VfsIndex;
+use io::{ReadRoseExt, WriteRoseExt, resolve_path};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json;
 
 pub trait RoseFile {
     /// Construct a new file
@@ -23,6 +28,82 @@ pub trait RoseFile {
     /// Write data to a writer
     fn write<W: WriteRoseExt>(&mut self, writer: &mut W) -> Result<(), Error> ;
 
+    /// Estimate the number of bytes `write` will produce
+    ///
+    /// The estimate does not need to be exact but should be an upper bound so
+    /// buffers can be allocated up front. Defaults to `0` (no estimate).
+    fn size_hint(&self) -> usize {
+        0
+    }
+
+    /// Write data to a new byte buffer pre-sized with `size_hint`
+    ///
+    /// # Example
+    /// ```rust
+    /// use roselib::files::ZMS;
+    /// use roselib::io::RoseFile;
+    ///
+    /// let mut zms = ZMS::new();
+    /// let bytes = zms.to_bytes().unwrap();
+    /// assert!(bytes.len() <= zms.size_hint());
+    /// ```
+    fn to_bytes(&mut self) -> Result<Vec<u8>, Error> {
+        let mut cursor = Cursor::new(Vec::with_capacity(self.size_hint()));
+        self.write(&mut cursor)?;
+        Ok(cursor.into_inner())
+    }
+
+    /// Read data from a reader and check that it was fully consumed
+    ///
+    /// Fails with `RoseError::InvalidData` if bytes are left after `read`,
+    /// which usually means the reader is missing fields of the format. Use
+    /// `ReadRoseExt::bytes_remaining` after `read` to only report them.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use std::fs::File;
+    /// use std::io::BufReader;
+    /// use roselib::files::ZMS;
+    /// use roselib::io::RoseFile;
+    ///
+    /// let mut reader = BufReader::new(File::open("foo.zms").unwrap());
+    /// let mut zms = ZMS::new();
+    /// zms.read_until_eof(&mut reader).unwrap();
+    /// ```
+    fn read_until_eof<R: ReadRoseExt>(&mut self, reader: &mut R) -> Result<(), Error> {
+        self.read(reader)?;
+
+        let remaining = reader.bytes_remaining()?;
+        if remaining > 0 {
+            return Err(RoseError::InvalidData {
+                format: "file",
+                reason: format!("{} bytes left unread", remaining),
+            }.into());
+        }
+
+        Ok(())
+    }
+
+    /// Read data from a reader into a new file
+    ///
+    /// # Example
+    /// ```rust
+    /// use std::io::Cursor;
+    /// use roselib::files::LIT;
+    /// use roselib::io::RoseFile;
+    ///
+    /// let bytes = LIT::new().to_bytes().unwrap();
+    /// let lit = LIT::from_reader(&mut Cursor::new(bytes)).unwrap();
+    /// assert!(lit.objects.is_empty());
+    /// ```
+    fn from_reader<R: ReadRoseExt>(reader: &mut R) -> Result<Self, Error>
+        where Self: Sized
+    {
+        let mut rf = Self::new();
+        rf.read(reader)?;
+        Ok(rf)
+    }
+
     /// Read data from a `File`
     ///
     /// # Example
@@ -43,6 +124,29 @@ pub trait RoseFile {
         Ok(rf)
     }
 
+    /// Read data from a `File` through a buffer of `capacity` bytes
+    ///
+    /// Like `from_file` with a chosen buffer size, e.g. a large buffer for
+    /// big indices.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use std::fs::File;
+    /// use roselib::files::IDX;
+    /// use roselib::io::RoseFile;
+    ///
+    /// let f = File::open("data.idx").unwrap();
+    /// let _ = IDX::from_file_with_capacity(&f, 1 << 20);
+    /// ```
+    fn from_file_with_capacity(file: &File, capacity: usize) -> Result<Self, Error>
+        where Self: Sized
+    {
+        let mut rf = Self::new();
+        let mut reader = BufReader::with_capacity(capacity, file);
+        rf.read(&mut reader)?;
+        Ok(rf)
+    }
+
     /// Write data to a `File`
     ///
     /// # Example
@@ -79,6 +183,35 @@ pub trait RoseFile {
         Self::from_file(&f)
     }
 
+    /// Read data from file at `Path` through a buffer of `capacity` bytes,
+    /// see `from_file_with_capacity`
+    fn from_path_with_capacity(path: &Path, capacity: usize) -> Result<Self, Error>
+        where Self: Sized
+    {
+        let f = File::open(path)?;
+        Self::from_file_with_capacity(&f, capacity)
+    }
+
+    /// Read data from file at `Path`, ignoring the case of its name
+    ///
+    /// The file is looked up with `resolve_path`, fails with
+    /// `RoseError::PathNotFound` if no case variant of the path exists.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use std::path::Path;
+    /// use roselib::files::ZMS;
+    /// use roselib::io::RoseFile;
+    ///
+    /// // Also finds `stone014.zms` or `STONE014.zms`
+    /// let _ = ZMS::from_path_any_case(Path::new("/path/to/STONE014.ZMS"));
+    /// ```
+    fn from_path_any_case(path: &Path) -> Result<Self, Error>
+        where Self: Sized
+    {
+        Self::from_path(&resolve_path(path)?)
+    }
+
     /// Write data to file at `Path`
     ///
     /// # Example
@@ -95,4 +228,66 @@ pub trait RoseFile {
         self.to_file(&f)?;
         Ok(())
     }
+
+    /// Read a file from the `.vfs` blobs of an index
+    ///
+    /// `vfs_dir` is the directory of the blobs and `filepath` the ROSE path
+    /// of the file in the index, see `VfsIndex::read_file`. Fails with
+    /// `RoseError::FileNotFound` if the file is not in the index, otherwise
+    /// with the error of the reader if the file can't be parsed.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// use std::path::Path;
+    /// use roselib::files::{IDX, ZMS};
+    /// use roselib::io::RoseFile;
+    ///
+    /// let dir = Path::new("/path/to/client");
+    /// let idx = IDX::from_path(&dir.join("data.idx")).unwrap();
+    /// let zms = ZMS::from_vfs(&idx, dir, "3DDATA/JUNON/SIMPLE/STONE014.ZMS").unwrap();
+    /// ```
+    fn from_vfs(idx: &VfsIndex, vfs_dir: &Path, filepath: &str) -> Result<Self, Error>
+        where Self: Sized
+    {
+        let data = idx.read_file(vfs_dir, filepath)?;
+        Self::from_reader(&mut Cursor::new(data))
+    }
+
+    /// Read a file from its JSON representation
+    ///
+    /// Reads the JSON produced by `to_json` (or any `serde_json` output of
+    /// the file), e.g. to convert an edited export back to the binary format.
+    ///
+    /// # Example
+    /// ```rust
+    /// use std::io::Cursor;
+    /// use roselib::files::ZMS;
+    /// use roselib::io::RoseFile;
+    ///
+    /// let json = ZMS::new().to_json_bytes().unwrap();
+    /// let zms = ZMS::from_json(&mut Cursor::new(json)).unwrap();
+    /// assert!(zms.vertices.is_empty());
+    /// ```
+    fn from_json<R: Read>(reader: &mut R) -> Result<Self, Error>
+        where Self: Sized + DeserializeOwned
+    {
+        Ok(serde_json::from_reader(reader)?)
+    }
+
+    /// Write the JSON representation of a file
+    fn to_json<W: Write>(&self, writer: &mut W) -> Result<(), Error>
+        where Self: Serialize
+    {
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+
+    /// Write the JSON representation of a file to a new byte buffer
+    fn to_json_bytes(&self) -> Result<Vec<u8>, Error>
+        where Self: Serialize
+    {
+        let mut bytes = Vec::new();
+        self.to_json(&mut bytes)?;
+        Ok(bytes)
+    }
 }
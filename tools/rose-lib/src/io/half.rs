@@ -0,0 +1,72 @@
+//! Conversion of IEEE 754 half precision floats
+
+/// Convert the bits of a half precision float to a `f32`
+///
+/// The conversion is exact, subnormals, infinities and NaN payloads are
+/// kept.
+pub fn f16_to_f32(half: u16) -> f32 {
+    let sign = ((half as u32) & 0x8000) << 16;
+    let exponent = ((half >> 10) & 0x1f) as u32;
+    let mantissa = (half & 0x3ff) as u32;
+
+    match exponent {
+        0 => {
+            // Zero or subnormal, `mantissa` units of 2^-24
+            let value = mantissa as f32 / (1 << 24) as f32;
+            if sign != 0 { -value } else { value }
+        }
+        0x1f => f32::from_bits(sign | 0x7f80_0000 | (mantissa << 13)),
+        _ => f32::from_bits(sign | ((exponent + 112) << 23) | (mantissa << 13)),
+    }
+}
+
+/// Convert a `f32` to the bits of a half precision float
+///
+/// Values are rounded to the nearest half float (ties to even), values too
+/// large for a half float become infinities and values too small zeros.
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    if exponent == 0xff {
+        return if mantissa == 0 {
+            sign | 0x7c00
+        } else {
+            sign | 0x7e00 | (mantissa >> 13) as u16
+        };
+    }
+
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    if exponent <= 0 {
+        if exponent < -10 {
+            return sign;
+        }
+
+        // Subnormal, shift the mantissa with its implicit bit into units of
+        // 2^-24
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        return sign | round_shift(mantissa, shift) as u16;
+    }
+
+    // A carry out of the mantissa correctly rounds up into the exponent
+    sign | round_shift(((exponent as u32) << 23) | mantissa, 13) as u16
+}
+
+/// Shift `value` right by `shift` bits, rounding to nearest even
+fn round_shift(value: u32, shift: u32) -> u32 {
+    let half = 1 << (shift - 1);
+    let remainder = value & ((1 << shift) - 1);
+    let shifted = value >> shift;
+    if remainder > half || (remainder == half && shifted & 1 == 1) {
+        shifted + 1
+    } else {
+        shifted
+    }
+}
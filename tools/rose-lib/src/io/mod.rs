@@ -1,11 +1,16 @@
 //! A module for Reading/Writing ROSE data types to/from disk
 
+mod euc_kr;
 mod file;
+mod half;
 mod path;
 mod reader;
 mod writer;
 
+pub use self::euc_kr::{decode_euc_kr, encode_euc_kr};
 pub use self::file::RoseFile;
-pub use self::path::PathRoseExt;
-pub use self::reader::ReadRoseExt;
+pub use self::half::{f16_to_f32, f32_to_f16};
+pub use self::path::{PathRoseExt, resolve_path};
+pub use self::reader::{ReadRoseExt, DEFAULT_MAX_ELEMENT_COUNT, max_element_count,
+                       set_max_element_count};
 pub use self::writer::WriteRoseExt;
@@ -1,4 +1,8 @@
-use std::path::PathBuf;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use errors::RoseError;
+use failure::Error;
 
 /// Extends `PathBuf` to read/write ROSE-style path strings
 ///
@@ -62,3 +66,52 @@ impl PathRoseExt for PathBuf {
         s
     }
 }
+
+/// Find a file on a case sensitive file system
+///
+/// ROSE paths are case insensitive but their case often doesn't match the
+/// files on disk (e.g. `STONE014.ZMS` extracted as `stone014.zms`). Tries
+/// `path` as is, then with an uppercase and a lowercase extension, then any
+/// file of its directory with the same name ignoring case. Fails with
+/// `RoseError::PathNotFound` listing the paths tried.
+///
+/// # Examples
+/// ```rust,no_run
+/// use std::path::Path;
+/// use roselib::io::resolve_path;
+///
+/// let path = resolve_path(Path::new("3DDATA/JUNON/SIMPLE/STONE014.ZMS")).unwrap();
+/// ```
+pub fn resolve_path(path: &Path) -> Result<PathBuf, Error> {
+    let mut tried = vec![path.to_path_buf()];
+    if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
+        for variant in &[extension.to_uppercase(), extension.to_lowercase()] {
+            let candidate = path.with_extension(variant);
+            if !tried.contains(&candidate) {
+                tried.push(candidate);
+            }
+        }
+    }
+
+    if let Some(found) = tried.iter().find(|p| p.is_file()) {
+        return Ok(found.clone());
+    }
+
+    if let (Some(name), Some(parent)) = (path.file_name().and_then(|n| n.to_str()), path.parent()) {
+        let dir = if parent.as_os_str().is_empty() { Path::new(".") } else { parent };
+        if let Ok(entries) = fs::read_dir(dir) {
+            for entry in entries.filter_map(|e| e.ok()) {
+                let matches = entry.file_name()
+                    .to_str()
+                    .map_or(false, |n| n.eq_ignore_ascii_case(name));
+                if matches && entry.path().is_file() {
+                    return Ok(parent.join(entry.file_name()));
+                }
+            }
+        }
+    }
+
+    Err(RoseError::PathNotFound {
+        tried: tried.iter().map(|p| p.display().to_string()).collect(),
+    }.into())
+}
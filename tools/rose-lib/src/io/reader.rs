@@ -1,10 +1,31 @@
-use std::io::{BufRead, Read, Seek};
+use std::io::{BufRead, ErrorKind, Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use byteorder::{ReadBytesExt, LittleEndian};
+use byteorder::ReadBytesExt;
+use errors::RoseError;
 use failure::Error;
+use io::{decode_euc_kr, f16_to_f32};
 
 use utils::{Color4, Vector2, Vector3, Vector4};
 
+/// Default maximum number of elements a file may declare for a single list
+pub const DEFAULT_MAX_ELEMENT_COUNT: usize = 1 << 24;
+
+static MAX_ELEMENT_COUNT: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_ELEMENT_COUNT);
+
+/// Set the maximum number of elements a file may declare for a single list
+///
+/// Counts above this are rejected by `ReadRoseExt::check_count` before any
+/// memory is allocated for them.
+pub fn set_max_element_count(n: usize) {
+    MAX_ELEMENT_COUNT.store(n, Ordering::Relaxed);
+}
+
+/// Get the maximum number of elements a file may declare for a single list
+pub fn max_element_count() -> usize {
+    MAX_ELEMENT_COUNT.load(Ordering::Relaxed)
+}
+
 /// Extends `BufReader` with methods for reading ROSE data types
 ///
 ///# Example
@@ -38,10 +59,16 @@ pub trait ReadRoseExt: Read + Seek + BufRead {
     fn read_f32(&mut self) -> Result<f32, Error>;
     fn read_f64(&mut self) -> Result<f64, Error>;
 
+    /// Read a half precision float, see `f16_to_f32`
+    fn read_f16(&mut self) -> Result<f32, Error>;
+
     /// Read a null-terminated (c-style string) from the reader
     fn read_cstring(&mut self) -> Result<String, Error>;
 
     /// Read a string of n-bytes length from the reader
+    ///
+    /// The string ends at the first null byte, any padding after it is
+    /// discarded.
     fn read_string(&mut self, n: u64) -> Result<String, Error>;
 
     /// Read a string with a u8 prefixed length from the reader
@@ -50,17 +77,131 @@ pub trait ReadRoseExt: Read + Seek + BufRead {
     /// Read a string with a u16 prefixed length from the reader
     fn read_string_u16(&mut self) -> Result<String, Error>;
 
+    /// Read an EUC-KR string with a u16 prefixed length, see `decode_euc_kr`
+    fn read_string_u16_euc_kr(&mut self) -> Result<String, Error>;
+
     /// Read a string with a u32 prefixed length from the reader
     fn read_string_u32(&mut self) -> Result<String, Error>;
 
     fn read_color4(&mut self) -> Result<Color4, Error>;
 
+    // Vectors are read in `x, y, z` order, except 4 component vectors which
+    // are read `w` first as `w, x, y, z` (the order of ROSE quaternions)
     fn read_vector2_f32(&mut self) -> Result<Vector2<f32>, Error>;
+    fn read_vector2_i16(&mut self) -> Result<Vector2<i16>, Error>;
     fn read_vector2_i32(&mut self) -> Result<Vector2<i32>, Error>;
+    fn read_vector2_u32(&mut self) -> Result<Vector2<u32>, Error>;
     fn read_vector3_f32(&mut self) -> Result<Vector3<f32>, Error>;
     fn read_vector3_i16(&mut self) -> Result<Vector3<i16>, Error>;
     fn read_vector4_f32(&mut self) -> Result<Vector4<f32>, Error>;
     fn read_vector4_i16(&mut self) -> Result<Vector4<i16>, Error>;
+
+    /// Check an element count read from a file before looping over it
+    ///
+    /// Fails if the count is negative, above `max_element_count()` or if
+    /// `count` elements of at least `element_size` bytes do not fit in the
+    /// rest of the stream.
+    fn check_count(&mut self, format: &'static str, count: i64, element_size: u64) -> Result<usize, Error>;
+
+    /// Number of bytes between the current position and the end of the stream
+    fn bytes_remaining(&mut self) -> Result<u64, Error>;
+
+    /// Read a list prefixed with a `u8` count, reading each element with `f`
+    ///
+    /// The count is checked with `check_count`, `element_size` being the
+    /// smallest size of an element in bytes.
+    ///
+    /// # Example
+    /// ```rust
+    /// use std::io::Cursor;
+    /// use roselib::io::ReadRoseExt;
+    ///
+    /// let mut reader = Cursor::new(vec![2, 10, 20]);
+    /// let values = reader.read_vec_u8("file", 1, |r| r.read_u8()).unwrap();
+    /// assert_eq!(values, vec![10, 20]);
+    /// ```
+    fn read_vec_u8<T, F>(&mut self, format: &'static str, element_size: u64, f: F) -> Result<Vec<T>, Error>
+        where Self: Sized,
+              F: FnMut(&mut Self) -> Result<T, Error>
+    {
+        let count = self.read_u8()?;
+        read_elements(self, format, count as i64, element_size, f)
+    }
+
+    /// Read a list prefixed with a `u16` count, see `read_vec_u8`
+    fn read_vec_u16<T, F>(&mut self, format: &'static str, element_size: u64, f: F) -> Result<Vec<T>, Error>
+        where Self: Sized,
+              F: FnMut(&mut Self) -> Result<T, Error>
+    {
+        let count = self.read_u16()?;
+        read_elements(self, format, count as i64, element_size, f)
+    }
+
+    /// Read a list prefixed with a `u32` count, see `read_vec_u8`
+    fn read_vec_u32<T, F>(&mut self, format: &'static str, element_size: u64, f: F) -> Result<Vec<T>, Error>
+        where Self: Sized,
+              F: FnMut(&mut Self) -> Result<T, Error>
+    {
+        let count = self.read_u32()?;
+        read_elements(self, format, count as i64, element_size, f)
+    }
+
+    /// Read a list prefixed with an `i16` count, see `read_vec_u8`
+    fn read_vec_i16<T, F>(&mut self, format: &'static str, element_size: u64, f: F) -> Result<Vec<T>, Error>
+        where Self: Sized,
+              F: FnMut(&mut Self) -> Result<T, Error>
+    {
+        let count = self.read_i16()?;
+        read_elements(self, format, count as i64, element_size, f)
+    }
+
+    /// Read a list prefixed with an `i32` count, see `read_vec_u8`
+    fn read_vec_i32<T, F>(&mut self, format: &'static str, element_size: u64, f: F) -> Result<Vec<T>, Error>
+        where Self: Sized,
+              F: FnMut(&mut Self) -> Result<T, Error>
+    {
+        let count = self.read_i32()?;
+        read_elements(self, format, count as i64, element_size, f)
+    }
+}
+
+/// Read `count` elements with `f` after checking the count
+fn read_elements<R, T, F>(reader: &mut R,
+                          format: &'static str,
+                          count: i64,
+                          element_size: u64,
+                          mut f: F)
+                          -> Result<Vec<T>, Error>
+    where R: ReadRoseExt,
+          F: FnMut(&mut R) -> Result<T, Error>
+{
+    let count = reader.check_count(format, count, element_size)?;
+    let mut elements = Vec::with_capacity(count);
+    for _ in 0..count {
+        elements.push(f(reader)?);
+    }
+    Ok(elements)
+}
+
+/// Read exactly `N` bytes
+///
+/// Fails with `RoseError::UnexpectedEof` holding the offset of the first
+/// byte if the stream ends before all of them are read.
+fn read_bytes<R: Read + Seek, const N: usize>(reader: &mut R) -> Result<[u8; N], Error> {
+    let mut buffer = [0u8; N];
+    let mut read = 0;
+    while read < N {
+        match reader.read(&mut buffer[read..]) {
+            Ok(0) => {
+                let offset = reader.stream_position()? - read as u64;
+                return Err(RoseError::UnexpectedEof { offset }.into());
+            }
+            Ok(n) => read += n,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(buffer)
 }
 
 impl<R> ReadRoseExt for R
@@ -70,27 +211,27 @@ impl<R> ReadRoseExt for R
           R: ReadBytesExt
 {
     fn read_u8(&mut self) -> Result<u8, Error> {
-        Ok(ReadBytesExt::read_u8(self)?)
+        Ok(u8::from_le_bytes(read_bytes(self)?))
     }
 
     fn read_u16(&mut self) -> Result<u16, Error> {
-        Ok(ReadBytesExt::read_u16::<LittleEndian>(self)?)
+        Ok(u16::from_le_bytes(read_bytes(self)?))
     }
 
     fn read_u32(&mut self) -> Result<u32, Error> {
-        Ok(ReadBytesExt::read_u32::<LittleEndian>(self)?)
+        Ok(u32::from_le_bytes(read_bytes(self)?))
     }
 
     fn read_i8(&mut self) -> Result<i8, Error> {
-        Ok(ReadBytesExt::read_i8(self)?)
+        Ok(i8::from_le_bytes(read_bytes(self)?))
     }
 
     fn read_i16(&mut self) -> Result<i16, Error> {
-        Ok(ReadBytesExt::read_i16::<LittleEndian>(self)?)
+        Ok(i16::from_le_bytes(read_bytes(self)?))
     }
 
     fn read_i32(&mut self) -> Result<i32, Error> {
-        Ok(ReadBytesExt::read_i32::<LittleEndian>(self)?)
+        Ok(i32::from_le_bytes(read_bytes(self)?))
     }
 
     fn read_bool(&mut self) -> Result<bool, Error> {
@@ -102,11 +243,15 @@ impl<R> ReadRoseExt for R
     }
 
     fn read_f32(&mut self) -> Result<f32, Error> {
-        Ok(ReadBytesExt::read_f32::<LittleEndian>(self)?)
+        Ok(f32::from_le_bytes(read_bytes(self)?))
     }
 
     fn read_f64(&mut self) -> Result<f64, Error> {
-        Ok(ReadBytesExt::read_f64::<LittleEndian>(self)?)
+        Ok(f64::from_le_bytes(read_bytes(self)?))
+    }
+
+    fn read_f16(&mut self) -> Result<f32, Error> {
+        Ok(f16_to_f32(ReadRoseExt::read_u16(self)?))
     }
 
     fn read_cstring(&mut self) -> Result<String, Error> {
@@ -121,9 +266,9 @@ impl<R> ReadRoseExt for R
         let mut bytes = self.take(n as u64);
         bytes.read_to_end(&mut buffer)?;
 
-        // Remove terminating null bytes
-        if let Some(&0x00) = buffer.last() {
-            let _ = buffer.pop();
+        // Remove the null terminator and any padding after it
+        if let Some(end) = buffer.iter().position(|&b| b == 0x00) {
+            buffer.truncate(end);
         }
 
         Ok(String::from_utf8_lossy(&buffer).into_owned())
@@ -139,6 +284,18 @@ impl<R> ReadRoseExt for R
         self.read_string(length as u64)
     }
 
+    fn read_string_u16_euc_kr(&mut self) -> Result<String, Error> {
+        let length = ReadRoseExt::read_u16(self)?;
+        let mut buffer = Vec::new();
+        self.take(length as u64).read_to_end(&mut buffer)?;
+
+        // Like `read_string`, the string ends at the first null byte
+        if let Some(end) = buffer.iter().position(|&b| b == 0x00) {
+            buffer.truncate(end);
+        }
+        Ok(decode_euc_kr(&buffer))
+    }
+
     fn read_string_u32(&mut self) -> Result<String, Error> {
         let length = ReadRoseExt::read_u32(self)?;
         self.read_string(length as u64)
@@ -160,11 +317,22 @@ impl<R> ReadRoseExt for R
         Ok(v)
     }
 
+    fn read_vector2_i16(&mut self) -> Result<Vector2<i16>, Error> {
+        let x = ReadRoseExt::read_i16(self)?;
+        let y = ReadRoseExt::read_i16(self)?;
+        Ok(Vector2 { x, y })
+    }
+
     fn read_vector2_i32(&mut self) -> Result<Vector2<i32>, Error> {
-        let mut v = Vector2::<i32>::new();
-        v.x = ReadRoseExt::read_i32(self)?;
-        v.y = ReadRoseExt::read_i32(self)?;
-        Ok(v)
+        let x = ReadRoseExt::read_i32(self)?;
+        let y = ReadRoseExt::read_i32(self)?;
+        Ok(Vector2 { x, y })
+    }
+
+    fn read_vector2_u32(&mut self) -> Result<Vector2<u32>, Error> {
+        let x = ReadRoseExt::read_u32(self)?;
+        let y = ReadRoseExt::read_u32(self)?;
+        Ok(Vector2 { x, y })
     }
 
     fn read_vector3_f32(&mut self) -> Result<Vector3<f32>, Error> {
@@ -199,4 +367,26 @@ impl<R> ReadRoseExt for R
         v.z = ReadRoseExt::read_i16(self)?;
         Ok(v)
     }
+
+    fn check_count(&mut self, format: &'static str, count: i64, element_size: u64) -> Result<usize, Error> {
+        if count < 0 || count as u64 > max_element_count() as u64 {
+            return Err(RoseError::InvalidData {
+                format,
+                reason: format!("Invalid element count: {}", count),
+            }.into());
+        }
+
+        if (count as u64).saturating_mul(element_size) > self.bytes_remaining()? {
+            return Err(RoseError::TruncatedFile { format }.into());
+        }
+
+        Ok(count as usize)
+    }
+
+    fn bytes_remaining(&mut self) -> Result<u64, Error> {
+        let position = self.stream_position()?;
+        let end = self.seek(SeekFrom::End(0))?;
+        self.seek(SeekFrom::Start(position))?;
+        Ok(end.saturating_sub(position))
+    }
 }
@@ -1,6 +1,7 @@
 use std::io::{Write, Seek};
 use byteorder::{WriteBytesExt, LittleEndian};
 use failure::Error;
+use io::{encode_euc_kr, f32_to_f16};
 use utils::{Color4, Vector2, Vector3, Vector4};
 
 /// Extends `BufWriter` with methods for writing ROSE data types
@@ -35,6 +36,9 @@ pub trait WriteRoseExt: Write + Seek {
     fn write_f32(&mut self, n: f32) -> Result<(), Error>;
     fn write_f64(&mut self, n: f64) -> Result<(), Error>;
 
+    /// Write a half precision float rounded to nearest, see `f32_to_f16`
+    fn write_f16(&mut self, n: f32) -> Result<(), Error>;
+
     // Write string as null terminated string
     fn write_cstring(&mut self, string: &str) -> Result<(), Error>;
 
@@ -44,12 +48,25 @@ pub trait WriteRoseExt: Write + Seek {
     // Write a string with length prefix as u16
     fn write_string_u16(&mut self, string: &str) -> Result<(), Error>;
 
+    // Write a string encoded as EUC-KR with length prefix as u16, see
+    // `encode_euc_kr`
+    fn write_string_u16_euc_kr(&mut self, string: &str) -> Result<(), Error>;
+
     // Write a string with length prefix as u32
     fn write_string_u32(&mut self, string: &str) -> Result<(), Error>;
 
+    // Write a string zero-padded to exactly n bytes, a string of n bytes is
+    // written without its null terminator like `read_string` reads it
+    fn write_string_fixed(&mut self, string: &str, n: usize) -> Result<(), Error>;
+
     fn write_color4(&mut self, color: &Color4) -> Result<(), Error>;
 
+    // Vectors are written in `x, y, z` order, except 4 component vectors
+    // which are written `w` first as `w, x, y, z`
     fn write_vector2_f32(&mut self, v: &Vector2<f32>) -> Result<(), Error>;
+    fn write_vector2_i16(&mut self, v: &Vector2<i16>) -> Result<(), Error>;
+    fn write_vector2_i32(&mut self, v: &Vector2<i32>) -> Result<(), Error>;
+    fn write_vector2_u32(&mut self, v: &Vector2<u32>) -> Result<(), Error>;
     fn write_vector3_f32(&mut self, v: &Vector3<f32>) -> Result<(), Error>;
     fn write_vector3_i16(&mut self, v: &Vector3<i16>) -> Result<(), Error>;
     fn write_vector4_f32(&mut self, v: &Vector4<f32>) -> Result<(), Error>;
@@ -107,6 +124,11 @@ impl<W> WriteRoseExt for W
         Ok(())
     }
 
+    fn write_f16(&mut self, n: f32) -> Result<(), Error> {
+        WriteBytesExt::write_u16::<LittleEndian>(self, f32_to_f16(n))?;
+        Ok(())
+    }
+
     fn write_cstring(&mut self, string: &str) -> Result<(), Error> {
         self.write_all(string.as_bytes())?;
         WriteRoseExt::write_u8(self, 0x00)?;
@@ -125,12 +147,32 @@ impl<W> WriteRoseExt for W
         Ok(())
     }
 
+    fn write_string_u16_euc_kr(&mut self, string: &str) -> Result<(), Error> {
+        let bytes = encode_euc_kr(string)?;
+        WriteRoseExt::write_u16(self, bytes.len() as u16)?;
+        self.write_all(&bytes)?;
+        Ok(())
+    }
+
     fn write_string_u32(&mut self, string: &str) -> Result<(), Error> {
         WriteRoseExt::write_u32(self, string.len() as u32)?;
         self.write_all(&string.as_bytes())?;
         Ok(())
     }
 
+    fn write_string_fixed(&mut self, string: &str, n: usize) -> Result<(), Error> {
+        let bytes = string.as_bytes();
+        if bytes.len() > n {
+            bail!("String does not fit in {} bytes: {}", n, string);
+        }
+
+        self.write_all(bytes)?;
+        for _ in bytes.len()..n {
+            WriteRoseExt::write_u8(self, 0x00)?;
+        }
+        Ok(())
+    }
+
     fn write_color4(&mut self, color: &Color4) -> Result<(), Error> {
         WriteRoseExt::write_f32(self, color.r)?;
         WriteRoseExt::write_f32(self, color.g)?;
@@ -145,6 +187,24 @@ impl<W> WriteRoseExt for W
         Ok(())
     }
 
+    fn write_vector2_i16(&mut self, v: &Vector2<i16>) -> Result<(), Error> {
+        WriteRoseExt::write_i16(self, v.x)?;
+        WriteRoseExt::write_i16(self, v.y)?;
+        Ok(())
+    }
+
+    fn write_vector2_i32(&mut self, v: &Vector2<i32>) -> Result<(), Error> {
+        WriteRoseExt::write_i32(self, v.x)?;
+        WriteRoseExt::write_i32(self, v.y)?;
+        Ok(())
+    }
+
+    fn write_vector2_u32(&mut self, v: &Vector2<u32>) -> Result<(), Error> {
+        WriteRoseExt::write_u32(self, v.x)?;
+        WriteRoseExt::write_u32(self, v.y)?;
+        Ok(())
+    }
+
     fn write_vector3_f32(&mut self, v: &Vector3<f32>) -> Result<(), Error> {
         WriteRoseExt::write_f32(self, v.x)?;
         WriteRoseExt::write_f32(self, v.y)?;
@@ -3,11 +3,29 @@
 #[macro_use] extern crate num_derive;
 #[macro_use] extern crate serde_derive;
 extern crate byteorder;
+extern crate encoding_rs;
+#[cfg(feature = "image")]
+pub extern crate image;
+#[cfg(feature = "logging")]
+#[macro_use] extern crate log;
+#[cfg(feature = "mmap")]
+extern crate memmap2;
+extern crate miniz_oxide;
 extern crate num;
 extern crate num_traits;
+extern crate serde;
+extern crate serde_json;
 
+#[cfg(not(feature = "logging"))]
+#[macro_use]
+mod logging;
+
+pub mod errors;
 pub mod io;
 pub mod utils;
 
+pub mod export;
 pub mod files;
+pub mod map;
+pub mod tables;
 
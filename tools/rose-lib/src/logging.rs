@@ -0,0 +1,20 @@
+//! Stand-ins for the `log` macros without the `logging` feature
+//!
+//! The messages are type checked but never formatted, `log_enabled!` is
+//! always false.
+
+macro_rules! warn {
+    ($($arg:tt)+) => { if false { let _ = format!($($arg)+); } };
+}
+
+macro_rules! debug {
+    ($($arg:tt)+) => { if false { let _ = format!($($arg)+); } };
+}
+
+macro_rules! trace {
+    ($($arg:tt)+) => { if false { let _ = format!($($arg)+); } };
+}
+
+macro_rules! log_enabled {
+    ($($arg:tt)+) => { false };
+}
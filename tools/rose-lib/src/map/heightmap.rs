@@ -0,0 +1,171 @@
+use std::f32;
+use std::iter;
+use std::path::Path;
+
+use failure::Error;
+use files::HIM;
+use io::RoseFile;
+use map::{HIM_GRID_SIZE, TileCoordinates, tile_files};
+#[cfg(feature = "image")]
+use image::GrayImage;
+use utils;
+
+/// Heightmap of a whole map
+///
+/// The heightmaps of the tiles are placed next to each other relative to the
+/// lowest tile coordinates. Each column of tiles is as wide as its widest
+/// tile and each row as high as its highest tile, so tiles of different
+/// sizes never overlap. Samples not covered by a tile are filled with zeros.
+/// `min_height` and `max_height` are computed over all the tiles.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MapHeightmap {
+    pub x_min: u32,
+    pub x_max: u32,
+    pub y_min: u32,
+    pub y_max: u32,
+    pub width: usize,
+    pub height: usize,
+    pub heights: Vec<Vec<f32>>,
+    #[serde(with = "::utils::nan_as_null")]
+    pub min_height: f32,
+    #[serde(with = "::utils::nan_as_null")]
+    pub max_height: f32,
+
+    /// Problems found while stitching that did not prevent it, e.g. tiles
+    /// smaller than the other tiles of their row or column
+    #[serde(skip)]
+    pub warnings: Vec<String>,
+}
+
+impl MapHeightmap {
+    /// Stitch the `<x>_<y>.HIM` files in a map directory
+    pub fn from_tiles(map_dir: &Path) -> Result<MapHeightmap, Error> {
+        let mut tiles = Vec::new();
+        for (coords, path) in tile_files(map_dir, "him")? {
+            tiles.push((coords, HIM::from_path(&path)?));
+        }
+
+        if tiles.is_empty() {
+            bail!("No HIM files found in {}", map_dir.display());
+        }
+
+        MapHeightmap::from_heightmaps(&tiles)
+    }
+
+    /// Stitch loaded tile heightmaps
+    pub fn from_heightmaps(tiles: &[(TileCoordinates, HIM)]) -> Result<MapHeightmap, Error> {
+        if tiles.is_empty() {
+            bail!("No tiles to stitch");
+        }
+
+        let xs = || tiles.iter().map(|&((x, _), _)| x);
+        let ys = || tiles.iter().map(|&((_, y), _)| y);
+        let (x_min, x_max) = (xs().min().unwrap(), xs().max().unwrap());
+        let (y_min, y_max) = (ys().min().unwrap(), ys().max().unwrap());
+
+        let mut warnings = Vec::new();
+
+        // Only tiles with samples take part in the layout
+        let mut placed = Vec::new();
+        for &(coords, ref him) in tiles {
+            let (width, height) = tile_size(him);
+            if width == 0 || height == 0 {
+                warnings.push(format!("Tile {:?} has no height samples, skipping it", coords));
+                continue;
+            }
+            placed.push((coords, him, width, height));
+        }
+
+        let mut column_widths = vec![0; (x_max - x_min + 1) as usize];
+        let mut row_heights = vec![0; (y_max - y_min + 1) as usize];
+        for &((x, y), _, width, height) in &placed {
+            let column = &mut column_widths[(x - x_min) as usize];
+            *column = (*column).max(width);
+            let row = &mut row_heights[(y - y_min) as usize];
+            *row = (*row).max(height);
+        }
+        // Columns and rows of the map without any tile are as large as a
+        // regular tile
+        for size in column_widths.iter_mut().chain(row_heights.iter_mut()) {
+            if *size == 0 {
+                *size = HIM_GRID_SIZE;
+            }
+        }
+
+        let column_offsets = offsets(&column_widths);
+        let row_offsets = offsets(&row_heights);
+        let width = column_widths.iter().sum();
+        let height = row_heights.iter().sum();
+        let mut heights = vec![vec![0.0; width]; height];
+
+        for &(coords, him, tile_width, tile_height) in &placed {
+            let (x, y) = coords;
+            let column = (x - x_min) as usize;
+            let row = (y - y_min) as usize;
+
+            if tile_width != column_widths[column] || tile_height != row_heights[row] {
+                warnings.push(format!("Tile {:?} is {}x{} but its slot is {}x{}, leaving a gap",
+                                      coords,
+                                      tile_width,
+                                      tile_height,
+                                      column_widths[column],
+                                      row_heights[row]));
+            }
+
+            let offset_x = column_offsets[column];
+            let offset_y = row_offsets[row];
+            for (h, tile_row) in him.heights.iter().take(tile_height).enumerate() {
+                let tile_row = &tile_row[..tile_width];
+                heights[offset_y + h][offset_x..offset_x + tile_width].copy_from_slice(tile_row);
+            }
+        }
+
+        // The range of the tiles, gaps between them are not heights
+        let ranges = placed.iter().flat_map(|&(_, him, _, _)| {
+            iter::once(him.min_height).chain(iter::once(him.max_height))
+        });
+        let (min_height, max_height) = utils::min_max(ranges).unwrap_or((f32::NAN, f32::NAN));
+
+        Ok(MapHeightmap {
+            x_min,
+            x_max,
+            y_min,
+            y_max,
+            width,
+            height,
+            heights,
+            min_height,
+            max_height,
+            warnings,
+        })
+    }
+}
+
+#[cfg(feature = "image")]
+impl MapHeightmap {
+    /// Render the heights as a greyscale image, from black at `min_height`
+    /// to white at `max_height`
+    pub fn to_gray_image(&self) -> GrayImage {
+        utils::heights_to_gray_image(&self.heights, self.min_height, self.max_height)
+    }
+}
+
+/// Size of the heights actually present in a tile as `(width, height)`
+///
+/// The declared dimensions are not trusted over the data read.
+fn tile_size(him: &HIM) -> (usize, usize) {
+    let height = him.heights.len();
+    let width = him.heights.iter().map(|row| row.len()).min().unwrap_or(0);
+    (width, height)
+}
+
+/// Starting offset of each slot from the size of the slots
+fn offsets(sizes: &[usize]) -> Vec<usize> {
+    sizes.iter()
+        .scan(0, |offset, &size| {
+            let start = *offset;
+            *offset += size;
+            Some(start)
+        })
+        .collect()
+}
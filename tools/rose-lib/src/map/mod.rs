@@ -0,0 +1,107 @@
+//! A module for working with whole maps
+//!
+//! A map (zone) directory contains a `.ZON` file and a set of tiles named
+//! `<x>_<y>`, each with its own `.HIM`, `.TIL` and `.IFO` files. The types in
+//! this module combine the files of all the tiles of a map.
+
+mod heightmap;
+mod objects;
+mod tiles;
+
+pub use self::heightmap::MapHeightmap;
+pub use self::objects::{ObjectInstance, PartInstance, object_instances};
+pub use self::tiles::{ResolvedTile, resolve_tile};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use failure::Error;
+use utils::Vector2;
+
+/// Tile coordinates as `(x, y)`
+pub type TileCoordinates = (u32, u32);
+
+/// Number of height samples on each side of a HIM tile
+pub const HIM_GRID_SIZE: usize = 65;
+
+/// Number of patches on each side of a HIM tile, see `Heightmap::patches`
+pub const HIM_PATCH_GRID_SIZE: usize = 16;
+
+/// Number of cells on each side of a TIL tile
+pub const TIL_GRID_SIZE: usize = 16;
+
+/// Number of height cells on each side of a TIL cell
+pub const TIL_CELL_GRID_SIZE: usize = (HIM_GRID_SIZE - 1) / TIL_GRID_SIZE;
+
+/// Size of a tile in world units (centimeters)
+pub const TILE_WORLD_SIZE: f32 = 16000.0;
+
+/// Distance between two height samples in world units
+pub const GRID_CELL_WORLD_SIZE: f32 = TILE_WORLD_SIZE / (HIM_GRID_SIZE - 1) as f32;
+
+/// Number of tile rows of the world
+///
+/// Tile rows grow southwards while world Y grows northwards, tile row `y`
+/// spans the world rows `WORLD_TILE_ROWS - y - 1` to `WORLD_TILE_ROWS - y`.
+pub const WORLD_TILE_ROWS: u32 = 65;
+
+/// Offset of IFO object positions, an object at `position` in an IFO is at
+/// `position + IFO_POSITION_OFFSET` in the world
+pub const IFO_POSITION_OFFSET: f32 = 520000.0;
+
+/// World position of the height sample at `(column, row)` of a tile
+///
+/// Rows are counted from the top (north) of the tile like the rows of a
+/// heightmap. The same position is the top-left corner of the height cell
+/// `(column, row)`.
+pub fn grid_to_world(tile: TileCoordinates, column: usize, row: usize) -> Vector2<f32> {
+    let (x, y) = tile;
+    Vector2 {
+        x: x as f32 * TILE_WORLD_SIZE + column as f32 * GRID_CELL_WORLD_SIZE,
+        y: (WORLD_TILE_ROWS - y) as f32 * TILE_WORLD_SIZE - row as f32 * GRID_CELL_WORLD_SIZE,
+    }
+}
+
+/// Find the tiles of a map from the files with the given extension
+///
+/// Returns the coordinates sorted by row then column along with the path of
+/// each file. The extension is matched case insensitively, other files and
+/// files not named `<x>_<y>` are skipped.
+pub fn tile_files(map_dir: &Path, extension: &str) -> Result<Vec<(TileCoordinates, PathBuf)>, Error> {
+    let extension = extension.to_lowercase();
+    let mut tiles = Vec::new();
+
+    for entry in fs::read_dir(map_dir)? {
+        let path = entry?.path();
+        if !path.is_file() {
+            continue;
+        }
+
+        let matches = path.extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_lowercase() == extension)
+            .unwrap_or(false);
+        if !matches {
+            continue;
+        }
+
+        let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+        if let Some(coords) = parse_tile_name(stem) {
+            tiles.push((coords, path));
+        }
+    }
+
+    tiles.sort_by_key(|&((x, y), _)| (y, x));
+    Ok(tiles)
+}
+
+/// Parse the coordinates of a tile named `<x>_<y>`
+fn parse_tile_name(name: &str) -> Option<TileCoordinates> {
+    let mut parts = name.split('_');
+    let x = parts.next()?.parse().ok()?;
+    let y = parts.next()?.parse().ok()?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((x, y))
+}
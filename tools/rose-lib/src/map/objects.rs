@@ -0,0 +1,84 @@
+//! Objects placed on a map
+use files::ZSC;
+use files::ifo::MapObject;
+use utils::{Matrix4, Vector3, Vector4};
+use utils::matrix;
+
+/// Part of a placed object, ready to render
+///
+/// `mesh_id` and `material_id` index the meshes and materials of the model
+/// list, `transform` places the part in the world.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PartInstance {
+    pub mesh_id: u16,
+    pub material_id: u16,
+    pub transform: Matrix4,
+}
+
+/// Object placed on a map with the parts of its model list definition
+///
+/// `transform` is the placement of the object, the transforms of the parts
+/// already include it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjectInstance {
+    pub object_id: u32,
+    pub parts: Vec<PartInstance>,
+    pub transform: Matrix4,
+}
+
+/// Resolve placed objects into their model list definitions
+///
+/// `objects` are the objects of an IFO block (e.g. `deco_objects`) and `zsc`
+/// the model list of that block. The transform of each part is its own
+/// position, rotation and scale, relative to its parent part if it has one,
+/// composed with the placement of the object. Positions are left in the
+/// units of the files.
+///
+/// Objects whose `object_id` is not in the model list are skipped with a
+/// warning.
+pub fn object_instances<'a>(objects: &'a [MapObject], zsc: &'a ZSC)
+    -> impl Iterator<Item = ObjectInstance> + 'a
+{
+    objects.iter().filter_map(move |object| {
+        let definition = match zsc.objects.get(object.object_id as usize) {
+            Some(definition) => definition,
+            None => {
+                warn!("Object {} ({}) is not in the model list of {} objects",
+                          object.object_id, object.name, zsc.objects.len());
+                return None;
+            }
+        };
+
+        let transform = matrix::compose(object.position, object.rotation, object.scale);
+
+        let mut parts: Vec<PartInstance> = Vec::with_capacity(definition.parts.len());
+        for part in &definition.parts {
+            let properties = &part.properties;
+            let local = matrix::compose(
+                properties.position.unwrap_or_else(Vector3::<f32>::new),
+                properties.rotation.unwrap_or(Vector4 { w: 1.0, x: 0.0, y: 0.0, z: 0.0 }),
+                properties.scale.unwrap_or(Vector3 { x: 1.0, y: 1.0, z: 1.0 }),
+            );
+
+            // Parents are 1-based indices of earlier parts, 0 is the object
+            let parent = match properties.parent {
+                Some(parent) if parent > 0 && (parent as usize) <= parts.len() => {
+                    parts[parent as usize - 1].transform
+                }
+                _ => transform,
+            };
+
+            parts.push(PartInstance {
+                mesh_id: part.mesh_id,
+                material_id: part.material_id,
+                transform: matrix::multiply(&parent, &local),
+            });
+        }
+
+        Some(ObjectInstance {
+            object_id: object.object_id,
+            parts,
+            transform,
+        })
+    })
+}
@@ -0,0 +1,44 @@
+//! Terrain tile textures
+use files::{TIL, ZON};
+use files::zon::ZoneTileRotation;
+
+/// Textures of a terrain tile
+///
+/// `layer1` is the base texture, blended tiles draw `layer2` over it.
+/// Paths are as stored in the zone, e.g. `3DDATA/TERRAIN/TILES/.../A.DDS`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ResolvedTile<'a> {
+    pub layer1: &'a str,
+    pub layer2: Option<&'a str>,
+    pub rotation: ZoneTileRotation,
+}
+
+/// Resolve the textures of the cell at `(x, y)` of a tilemap
+///
+/// The cell's `tile_id` is the index of a tile of the zone, whose layers
+/// index the zone textures after adding their offsets. `layer2` is only
+/// resolved for blended tiles.
+///
+/// Returns `None` if the cell is out of the tilemap or references a tile or
+/// base texture missing from the zone.
+pub fn resolve_tile<'a>(til: &TIL, zon: &'a ZON, x: usize, y: usize) -> Option<ResolvedTile<'a>> {
+    let cell = til.tiles.get(y)?.get(x)?;
+    if cell.tile_id < 0 {
+        return None;
+    }
+
+    let tile = zon.tiles.get(cell.tile_id as usize)?;
+    let texture = |layer: i32, offset: i32| {
+        let index = layer + offset;
+        if index < 0 {
+            return None;
+        }
+        zon.textures.get(index as usize).map(|t| t.as_str())
+    };
+
+    Some(ResolvedTile {
+        layer1: texture(tile.layer1, tile.offset1)?,
+        layer2: if tile.blend { texture(tile.layer2, tile.offset2) } else { None },
+        rotation: tile.rotation,
+    })
+}
@@ -0,0 +1,47 @@
+use failure::Error;
+use tables::{RowCells, Table, TableRow};
+
+const NAME: usize = 0;
+const ITEM_TYPE: usize = 4;
+const BASE_PRICE: usize = 5;
+const PRICE_RATE: usize = 6;
+const WEIGHT: usize = 7;
+const QUALITY: usize = 8;
+const ICON: usize = 9;
+const ATTACK_POWER: usize = 35;
+
+/// Item table (`LIST_WEAPON.STB`, `LIST_ARMS.STB`, ...)
+///
+/// All item tables share the same leading columns, `attack` is only set in
+/// weapon tables.
+pub type ItemTable<'a> = Table<'a, ItemRow>;
+
+/// Item table row
+#[derive(Debug, PartialEq)]
+pub struct ItemRow {
+    pub id: usize,
+    pub name: String,
+    pub item_type: i32,
+    pub base_price: i32,
+    pub price_rate: i32,
+    pub weight: i32,
+    pub quality: i32,
+    pub icon: i32,
+    pub attack: i32,
+}
+
+impl TableRow for ItemRow {
+    fn parse(cells: &RowCells) -> Result<ItemRow, Error> {
+        Ok(ItemRow {
+            id: cells.id(),
+            name: cells.name(NAME),
+            item_type: cells.int(ITEM_TYPE)?,
+            base_price: cells.int(BASE_PRICE)?,
+            price_rate: cells.int(PRICE_RATE)?,
+            weight: cells.int(WEIGHT)?,
+            quality: cells.int(QUALITY)?,
+            icon: cells.int(ICON)?,
+            attack: cells.int(ATTACK_POWER)?,
+        })
+    }
+}
@@ -0,0 +1,137 @@
+//! A module for typed access to well-known data tables
+//!
+//! Data tables only store string cells, the wrappers in this module parse
+//! the columns of common tables into typed rows and resolve their names
+//! through the matching string table.
+//!
+//! Column layouts are those of the 129 client, tables from other versions
+//! may have their columns in a different order.
+
+mod item;
+mod npc;
+
+pub use self::item::{ItemRow, ItemTable};
+pub use self::npc::{NpcRow, NpcTable};
+
+use std::marker::PhantomData;
+
+use errors::RoseError;
+use failure::Error;
+use files::{STB, STL};
+
+/// Typed data table, rows are parsed as `T`
+///
+/// Names are resolved through the string table if given, in language `0`
+/// unless set with `language`.
+pub struct Table<'a, T> {
+    stb: &'a STB,
+    stl: Option<&'a STL>,
+    language: usize,
+    rows: PhantomData<T>,
+}
+
+/// Row of a typed data table, see `Table`
+pub trait TableRow: Sized {
+    /// Parse a row from its cells
+    fn parse(cells: &RowCells) -> Result<Self, Error>;
+}
+
+/// Cells of a data table row being parsed, see `TableRow`
+pub struct RowCells<'a> {
+    stb: &'a STB,
+    stl: Option<&'a STL>,
+    language: usize,
+    id: usize,
+}
+
+impl<'a, T: TableRow> Table<'a, T> {
+    /// Wrap a data table, names are resolved through `stl` if given
+    pub fn new(stb: &'a STB, stl: Option<&'a STL>) -> Table<'a, T> {
+        Table {
+            stb,
+            stl,
+            language: 0,
+            rows: PhantomData,
+        }
+    }
+
+    /// Set the string table language used to resolve names
+    pub fn language(mut self, language: usize) -> Table<'a, T> {
+        self.language = language;
+        self
+    }
+
+    /// Get a row, `None` if there is no such row
+    ///
+    /// Fails if an integer cell of the row is malformed.
+    pub fn get(&self, id: usize) -> Result<Option<T>, Error> {
+        if id >= self.stb.row_count() {
+            return Ok(None);
+        }
+
+        let cells = RowCells {
+            stb: self.stb,
+            stl: self.stl,
+            language: self.language,
+            id,
+        };
+        T::parse(&cells).map(Some)
+    }
+
+    /// Iterate over all rows
+    pub fn rows(&self) -> impl Iterator<Item = Result<T, Error>> + '_ {
+        (0..self.stb.row_count()).filter_map(move |id| self.get(id).transpose())
+    }
+}
+
+impl<'a> RowCells<'a> {
+    /// Index of the row
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Integer cell of a column, see `int_cell`
+    pub fn int(&self, column: usize) -> Result<i32, Error> {
+        int_cell(self.stb, self.id, column)
+    }
+
+    /// Name of the row, see `name_cell`
+    pub fn name(&self, column: usize) -> String {
+        name_cell(self.stb, self.stl, self.language, self.id, column)
+    }
+}
+
+/// Parse an integer cell, empty or missing cells are read as 0
+///
+/// Fails with `RoseError::InvalidData` if the cell is not an integer.
+fn int_cell(stb: &STB, row: usize, column: usize) -> Result<i32, Error> {
+    let cell = stb.cell(row, column).unwrap_or("").trim();
+    if cell.is_empty() {
+        return Ok(0);
+    }
+
+    cell.parse().map_err(|_| {
+        RoseError::InvalidData {
+            format: "STB",
+            reason: format!("Invalid integer in row {} column {}: {:?}", row, column, cell),
+        }.into()
+    })
+}
+
+/// Resolve the name of a row
+///
+/// The last column of a table holds the row's key in the string table, the
+/// name column of the data table is used if there is no string table or no
+/// entry for the key.
+fn name_cell(stb: &STB, stl: Option<&STL>, language: usize, row: usize, column: usize) -> String {
+    let key = stb.column_count()
+        .checked_sub(1)
+        .and_then(|last| stb.cell(row, last))
+        .unwrap_or("");
+
+    if let Some(text) = stl.and_then(|s| s.text(key, language)) {
+        return String::from(text);
+    }
+
+    String::from(stb.cell(row, column).unwrap_or(""))
+}
@@ -0,0 +1,58 @@
+use failure::Error;
+use tables::{RowCells, Table, TableRow};
+
+const NAME: usize = 0;
+const WALK_SPEED: usize = 1;
+const RUN_SPEED: usize = 2;
+const SCALE: usize = 3;
+const LEVEL: usize = 6;
+const HEALTH: usize = 7;
+const ATTACK: usize = 8;
+const HIT: usize = 9;
+const DEFENCE: usize = 10;
+const RESISTANCE: usize = 11;
+const AVOID: usize = 12;
+const ATTACK_SPEED: usize = 13;
+
+/// NPC table (`LIST_NPC.STB`)
+///
+/// Holds both NPCs and monsters.
+pub type NpcTable<'a> = Table<'a, NpcRow>;
+
+/// NPC table row
+#[derive(Debug, PartialEq)]
+pub struct NpcRow {
+    pub id: usize,
+    pub name: String,
+    pub walk_speed: i32,
+    pub run_speed: i32,
+    pub scale: i32,
+    pub level: i32,
+    pub health: i32,
+    pub attack: i32,
+    pub hit: i32,
+    pub defence: i32,
+    pub resistance: i32,
+    pub avoid: i32,
+    pub attack_speed: i32,
+}
+
+impl TableRow for NpcRow {
+    fn parse(cells: &RowCells) -> Result<NpcRow, Error> {
+        Ok(NpcRow {
+            id: cells.id(),
+            name: cells.name(NAME),
+            walk_speed: cells.int(WALK_SPEED)?,
+            run_speed: cells.int(RUN_SPEED)?,
+            scale: cells.int(SCALE)?,
+            level: cells.int(LEVEL)?,
+            health: cells.int(HEALTH)?,
+            attack: cells.int(ATTACK)?,
+            hit: cells.int(HIT)?,
+            defence: cells.int(DEFENCE)?,
+            resistance: cells.int(RESISTANCE)?,
+            avoid: cells.int(AVOID)?,
+            attack_speed: cells.int(ATTACK_SPEED)?,
+        })
+    }
+}
@@ -42,12 +42,36 @@ impl Color4 {
             a: 0.0,
         }
     }
+
+    /// Convert to 8-bit RGBA, components outside of `[0, 1]` are clamped
+    pub fn to_rgba8(&self) -> [u8; 4] {
+        let byte = |c: f32| (c.max(0.0).min(1.0) * 255.0).round() as u8;
+        [byte(self.r), byte(self.g), byte(self.b), byte(self.a)]
+    }
+
+    /// Convert from 8-bit RGBA
+    pub fn from_rgba8(rgba: [u8; 4]) -> Color4 {
+        Color4 {
+            r: rgba[0] as f32 / 255.0,
+            g: rgba[1] as f32 / 255.0,
+            b: rgba[2] as f32 / 255.0,
+            a: rgba[3] as f32 / 255.0,
+        }
+    }
 }
 
 impl Vector2<f32> {
     pub fn new() -> Vector2<f32> {
         Vector2 { x: 0.0, y: 0.0 }
     }
+
+    /// Linear interpolation from `self` (`t = 0`) to `other` (`t = 1`)
+    pub fn lerp(&self, other: &Vector2<f32>, t: f32) -> Vector2<f32> {
+        Vector2 {
+            x: self.x + (other.x - self.x) * t,
+            y: self.y + (other.y - self.y) * t,
+        }
+    }
 }
 
 impl Vector2<i32> {
@@ -64,6 +88,26 @@ impl Vector3<f32> {
             z: 0.0,
         }
     }
+
+    /// Linear interpolation from `self` (`t = 0`) to `other` (`t = 1`)
+    pub fn lerp(&self, other: &Vector3<f32>, t: f32) -> Vector3<f32> {
+        Vector3 {
+            x: self.x + (other.x - self.x) * t,
+            y: self.y + (other.y - self.y) * t,
+            z: self.z + (other.z - self.z) * t,
+        }
+    }
+
+    /// Vector of unit length in the same direction, zero vectors are
+    /// returned as-is
+    pub fn normalize(&self) -> Vector3<f32> {
+        let length = (self.x * self.x + self.y * self.y + self.z * self.z).sqrt();
+        if length > 0.0 {
+            Vector3 { x: self.x / length, y: self.y / length, z: self.z / length }
+        } else {
+            *self
+        }
+    }
 }
 
 impl Vector3<i16> {
@@ -81,6 +125,42 @@ impl Vector4<f32> {
             z: 0.0,
         }
     }
+
+    /// Spherical linear interpolation of unit quaternions from `self`
+    /// (`t = 0`) to `other` (`t = 1`)
+    ///
+    /// Interpolates along the shortest path, falling back to a normalized
+    /// linear interpolation for nearly equal rotations.
+    pub fn slerp(&self, other: &Vector4<f32>, t: f32) -> Vector4<f32> {
+        let mut dot = self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z;
+        let mut other = *other;
+        if dot < 0.0 {
+            dot = -dot;
+            other = Vector4 { w: -other.w, x: -other.x, y: -other.y, z: -other.z };
+        }
+
+        let (a, b) = if dot > 0.9995 {
+            (1.0 - t, t)
+        } else {
+            let theta = dot.acos();
+            let sin_theta = theta.sin();
+            (((1.0 - t) * theta).sin() / sin_theta, (t * theta).sin() / sin_theta)
+        };
+
+        let q = Vector4 {
+            w: self.w * a + other.w * b,
+            x: self.x * a + other.x * b,
+            y: self.y * a + other.y * b,
+            z: self.z * a + other.z * b,
+        };
+
+        let length = (q.w * q.w + q.x * q.x + q.y * q.y + q.z * q.z).sqrt();
+        if length > 0.0 {
+            Vector4 { w: q.w / length, x: q.x / length, y: q.y / length, z: q.z / length }
+        } else {
+            q
+        }
+    }
 }
 
 impl Vector4<i16> {
@@ -93,3 +173,454 @@ impl Vector4<i16> {
         }
     }
 }
+
+impl<T: Copy + PartialOrd> BoundingBox<T> {
+    /// Smallest box containing all the points, `None` if there are none
+    pub fn from_points<I: IntoIterator<Item = Vector3<T>>>(points: I) -> Option<BoundingBox<T>> {
+        let mut points = points.into_iter();
+        let first = points.next()?;
+        let mut bounding_box = BoundingBox { min: first, max: first };
+        for p in points {
+            bounding_box.min = component_min(&bounding_box.min, &p);
+            bounding_box.max = component_max(&bounding_box.max, &p);
+        }
+        Some(bounding_box)
+    }
+
+    /// Whether a point is inside the box, points on its faces are inside
+    pub fn contains(&self, point: &Vector3<T>) -> bool {
+        self.min.x <= point.x && point.x <= self.max.x &&
+        self.min.y <= point.y && point.y <= self.max.y &&
+        self.min.z <= point.z && point.z <= self.max.z
+    }
+
+    /// Whether two boxes overlap, boxes sharing a face intersect
+    pub fn intersects(&self, other: &BoundingBox<T>) -> bool {
+        self.min.x <= other.max.x && other.min.x <= self.max.x &&
+        self.min.y <= other.max.y && other.min.y <= self.max.y &&
+        self.min.z <= other.max.z && other.min.z <= self.max.z
+    }
+
+    /// Smallest box containing both boxes
+    pub fn merge(&self, other: &BoundingBox<T>) -> BoundingBox<T> {
+        BoundingBox {
+            min: component_min(&self.min, &other.min),
+            max: component_max(&self.max, &other.max),
+        }
+    }
+}
+
+impl BoundingBox<f32> {
+    /// Center of the box
+    pub fn center(&self) -> Vector3<f32> {
+        self.min.lerp(&self.max, 0.5)
+    }
+
+    /// Extent of the box along each axis
+    pub fn size(&self) -> Vector3<f32> {
+        Vector3 {
+            x: self.max.x - self.min.x,
+            y: self.max.y - self.min.y,
+            z: self.max.z - self.min.z,
+        }
+    }
+}
+
+fn component_min<T: Copy + PartialOrd>(a: &Vector3<T>, b: &Vector3<T>) -> Vector3<T> {
+    let min = |a: T, b: T| if b < a { b } else { a };
+    Vector3 { x: min(a.x, b.x), y: min(a.y, b.y), z: min(a.z, b.z) }
+}
+
+fn component_max<T: Copy + PartialOrd>(a: &Vector3<T>, b: &Vector3<T>) -> Vector3<T> {
+    let max = |a: T, b: T| if b > a { b } else { a };
+    Vector3 { x: max(a.x, b.x), y: max(a.y, b.y), z: max(a.z, b.z) }
+}
+
+/// Comparison of floating point data within a tolerance
+///
+/// Floats are equal if they differ by at most `epsilon`, or are both NaN.
+/// Integer fields of implementing types are compared exactly.
+pub trait ApproxEq {
+    fn approx_eq(&self, other: &Self, epsilon: f32) -> bool;
+}
+
+impl ApproxEq for f32 {
+    fn approx_eq(&self, other: &f32, epsilon: f32) -> bool {
+        (self.is_nan() && other.is_nan()) || (self - other).abs() <= epsilon
+    }
+}
+
+impl<T: ApproxEq> ApproxEq for [T] {
+    fn approx_eq(&self, other: &[T], epsilon: f32) -> bool {
+        self.len() == other.len() &&
+        self.iter().zip(other).all(|(a, b)| a.approx_eq(b, epsilon))
+    }
+}
+
+impl<T: ApproxEq> ApproxEq for Vec<T> {
+    fn approx_eq(&self, other: &Vec<T>, epsilon: f32) -> bool {
+        self[..].approx_eq(&other[..], epsilon)
+    }
+}
+
+impl ApproxEq for Color4 {
+    fn approx_eq(&self, other: &Color4, epsilon: f32) -> bool {
+        self.r.approx_eq(&other.r, epsilon) &&
+        self.g.approx_eq(&other.g, epsilon) &&
+        self.b.approx_eq(&other.b, epsilon) &&
+        self.a.approx_eq(&other.a, epsilon)
+    }
+}
+
+impl ApproxEq for Vector2<f32> {
+    fn approx_eq(&self, other: &Vector2<f32>, epsilon: f32) -> bool {
+        self.x.approx_eq(&other.x, epsilon) && self.y.approx_eq(&other.y, epsilon)
+    }
+}
+
+impl ApproxEq for Vector3<f32> {
+    fn approx_eq(&self, other: &Vector3<f32>, epsilon: f32) -> bool {
+        self.x.approx_eq(&other.x, epsilon) &&
+        self.y.approx_eq(&other.y, epsilon) &&
+        self.z.approx_eq(&other.z, epsilon)
+    }
+}
+
+impl ApproxEq for Vector4<f32> {
+    fn approx_eq(&self, other: &Vector4<f32>, epsilon: f32) -> bool {
+        self.w.approx_eq(&other.w, epsilon) &&
+        self.x.approx_eq(&other.x, epsilon) &&
+        self.y.approx_eq(&other.y, epsilon) &&
+        self.z.approx_eq(&other.z, epsilon)
+    }
+}
+
+impl ApproxEq for BoundingBox<f32> {
+    fn approx_eq(&self, other: &BoundingBox<f32>, epsilon: f32) -> bool {
+        self.min.approx_eq(&other.min, epsilon) && self.max.approx_eq(&other.max, epsilon)
+    }
+}
+
+/// Default scale from ROSE units (centimeters) to meters
+pub const DEFAULT_SCALE: f32 = 0.01;
+
+/// Conversion between ROSE space and a Y-up right-handed space
+///
+/// ROSE is Z-up with positions in centimeters. Converting swaps the Y and Z
+/// axes and scales positions by `scale`. As swapping two axes mirrors the
+/// geometry, triangles wound clockwise in ROSE are wound counter-clockwise
+/// after the conversion, which is what most tools expect of front faces.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Transform {
+    pub scale: f32,
+}
+
+impl Transform {
+    /// Construct a transform scaling positions by `scale`
+    pub fn new(scale: f32) -> Transform {
+        Transform { scale }
+    }
+
+    /// Convert a ROSE position
+    pub fn position(&self, v: Vector3<f32>) -> Vector3<f32> {
+        Vector3 {
+            x: v.x * self.scale,
+            y: v.z * self.scale,
+            z: v.y * self.scale,
+        }
+    }
+
+    /// Convert a ROSE normal or direction, directions are not scaled
+    pub fn normal(&self, v: Vector3<f32>) -> Vector3<f32> {
+        Vector3 { x: v.x, y: v.z, z: v.y }
+    }
+
+    /// Convert a position back to ROSE space
+    pub fn inverse_position(&self, v: Vector3<f32>) -> Vector3<f32> {
+        Vector3 {
+            x: v.x / self.scale,
+            y: v.z / self.scale,
+            z: v.y / self.scale,
+        }
+    }
+
+    /// Convert a normal or direction back to ROSE space
+    pub fn inverse_normal(&self, v: Vector3<f32>) -> Vector3<f32> {
+        Vector3 { x: v.x, y: v.z, z: v.y }
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform::new(DEFAULT_SCALE)
+    }
+}
+
+/// (De)serialize a `f32` with `NaN` as `null`
+///
+/// JSON has no representation for `NaN`, `serde_json` writes it as `null`
+/// but fails to read it back. Use with `#[serde(with = "::utils::nan_as_null")]`
+/// on fields that can hold `NaN`.
+pub mod nan_as_null {
+    use std::f32;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &f32, serializer: S) -> Result<S::Ok, S::Error> {
+        if value.is_nan() {
+            serializer.serialize_none()
+        } else {
+            serializer.serialize_some(value)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+        Ok(Option::<f32>::deserialize(deserializer)?.unwrap_or(f32::NAN))
+    }
+}
+
+/// Maximum number of differences returned by `bytes_diff`
+pub const BYTES_DIFF_LIMIT: usize = 16;
+
+/// First differing bytes of two buffers as `(offset, a, b)`
+///
+/// At most `BYTES_DIFF_LIMIT` differences are returned. Only the bytes both
+/// buffers have are compared, use `bytes_diff_message` to also report a
+/// length mismatch.
+pub fn bytes_diff(a: &[u8], b: &[u8]) -> Vec<(usize, u8, u8)> {
+    a.iter()
+        .zip(b.iter())
+        .enumerate()
+        .filter(|&(_, (x, y))| x != y)
+        .map(|(offset, (&x, &y))| (offset, x, y))
+        .take(BYTES_DIFF_LIMIT)
+        .collect()
+}
+
+/// Describe the differences between two buffers, `None` if they are equal
+///
+/// Lists the lengths and the first differing offsets in hexadecimal, which
+/// is easier to read than the debug output of two large buffers.
+pub fn bytes_diff_message(a: &[u8], b: &[u8]) -> Option<String> {
+    let diff = bytes_diff(a, b);
+    if diff.is_empty() && a.len() == b.len() {
+        return None;
+    }
+
+    let mut message = format!("Buffers differ (lengths {} and {})", a.len(), b.len());
+    for (offset, x, y) in diff {
+        message += &format!("\n  0x{:08x}: {:02x} != {:02x}", offset, x, y);
+    }
+    if a.len() != b.len() {
+        message += &format!("\n  0x{:08x}: end of the shorter buffer", a.len().min(b.len()));
+    }
+    Some(message)
+}
+
+/// Assert that two byte buffers are equal, panicking with
+/// `bytes_diff_message` otherwise
+#[macro_export]
+macro_rules! assert_bytes_eq {
+    ($a:expr, $b:expr) => {
+        if let Some(message) = $crate::utils::bytes_diff_message(&$a[..], &$b[..]) {
+            panic!("{}", message);
+        }
+    };
+}
+
+/// Smallest and largest of some values as `(min, max)`, ignoring `NaN`s
+///
+/// Returns `None` if there are no values other than `NaN`s.
+///
+/// # Examples
+/// ```
+/// use roselib::utils::min_max;
+///
+/// assert_eq!(min_max(vec![2.0, std::f32::NAN, -1.0]), Some((-1.0, 2.0)));
+/// assert_eq!(min_max(vec![std::f32::NAN]), None);
+/// ```
+pub fn min_max<I: IntoIterator<Item = f32>>(values: I) -> Option<(f32, f32)> {
+    values.into_iter()
+        .filter(|v| !v.is_nan())
+        .fold(None, |range, v| match range {
+            Some((min, max)) => Some((v.min(min), v.max(max))),
+            None => Some((v, v)),
+        })
+}
+
+/// Render a grid of heights as a greyscale image
+///
+/// Heights are mapped from black at `min_height` to white at `max_height`,
+/// the image is black if both are equal.
+#[cfg(feature = "image")]
+pub fn heights_to_gray_image(heights: &[Vec<f32>], min_height: f32, max_height: f32) -> ::image::GrayImage {
+    let delta_height = max_height - min_height;
+    let width = heights.first().map(|row| row.len()).unwrap_or(0);
+
+    ::image::ImageBuffer::from_fn(width as u32, heights.len() as u32, |x, y| {
+        let height = heights[y as usize].get(x as usize).cloned().unwrap_or(min_height);
+        if delta_height > 0.0 {
+            let value = 255.0 * ((height - min_height) / delta_height);
+            ::image::Luma([value.max(0.0).min(255.0).round() as u8])
+        } else {
+            ::image::Luma([0])
+        }
+    })
+}
+
+/// Convert a greyscale image to a grid of heights
+///
+/// The inverse of `heights_to_gray_image`, black is `min_height` and white
+/// `max_height`.
+#[cfg(feature = "image")]
+pub fn gray_image_to_heights(image: &::image::GrayImage, min_height: f32, max_height: f32) -> Vec<Vec<f32>> {
+    let delta_height = max_height - min_height;
+
+    (0..image.height())
+        .map(|y| {
+            (0..image.width())
+                .map(|x| min_height + delta_height * (image.get_pixel(x, y)[0] as f32 / 255.0))
+                .collect()
+        })
+        .collect()
+}
+
+/// 4x4 matrix in row-major order, transforming column vectors
+pub type Matrix4 = [[f32; 4]; 4];
+
+/// Construction and application of `Matrix4` transforms
+///
+/// Matrices are combined with `multiply(a, b)`, which applies `b` first.
+pub mod matrix {
+    use super::{Matrix4, Vector3, Vector4};
+
+    /// Identity matrix
+    pub fn identity() -> Matrix4 {
+        [[1.0, 0.0, 0.0, 0.0],
+         [0.0, 1.0, 0.0, 0.0],
+         [0.0, 0.0, 1.0, 0.0],
+         [0.0, 0.0, 0.0, 1.0]]
+    }
+
+    /// Translation by `v`
+    pub fn translate(v: Vector3<f32>) -> Matrix4 {
+        let mut m = identity();
+        m[0][3] = v.x;
+        m[1][3] = v.y;
+        m[2][3] = v.z;
+        m
+    }
+
+    /// Scale by `v` along each axis
+    pub fn scale(v: Vector3<f32>) -> Matrix4 {
+        let mut m = identity();
+        m[0][0] = v.x;
+        m[1][1] = v.y;
+        m[2][2] = v.z;
+        m
+    }
+
+    /// Counter-clockwise rotation of `radians` around the Y axis
+    pub fn rotate_y(radians: f32) -> Matrix4 {
+        let (sin, cos) = radians.sin_cos();
+        let mut m = identity();
+        m[0][0] = cos;
+        m[0][2] = sin;
+        m[2][0] = -sin;
+        m[2][2] = cos;
+        m
+    }
+
+    /// Rotation by the unit quaternion `q`
+    pub fn rotate(q: Vector4<f32>) -> Matrix4 {
+        let (w, x, y, z) = (q.w, q.x, q.y, q.z);
+        let mut m = identity();
+        m[0][0] = 1.0 - 2.0 * (y * y + z * z);
+        m[0][1] = 2.0 * (x * y - w * z);
+        m[0][2] = 2.0 * (x * z + w * y);
+        m[1][0] = 2.0 * (x * y + w * z);
+        m[1][1] = 1.0 - 2.0 * (x * x + z * z);
+        m[1][2] = 2.0 * (y * z - w * x);
+        m[2][0] = 2.0 * (x * z - w * y);
+        m[2][1] = 2.0 * (y * z + w * x);
+        m[2][2] = 1.0 - 2.0 * (x * x + y * y);
+        m
+    }
+
+    /// Transform scaling by `scale`, then rotating by the quaternion
+    /// `rotation` and translating by `position`
+    pub fn compose(position: Vector3<f32>, rotation: Vector4<f32>, scale: Vector3<f32>) -> Matrix4 {
+        multiply(&translate(position), &multiply(&rotate(rotation), &self::scale(scale)))
+    }
+
+    /// Product `a * b`, the transform applying `b` then `a`
+    pub fn multiply(a: &Matrix4, b: &Matrix4) -> Matrix4 {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+            }
+        }
+        m
+    }
+
+    /// Transform a point, dividing by `w` for projective matrices
+    pub fn transform_point(m: &Matrix4, p: Vector3<f32>) -> Vector3<f32> {
+        let v = [p.x, p.y, p.z, 1.0];
+        let row = |i: usize| (0..4).map(|k| m[i][k] * v[k]).sum::<f32>();
+
+        let w = row(3);
+        let w = if w != 0.0 { w } else { 1.0 };
+        Vector3 { x: row(0) / w, y: row(1) / w, z: row(2) / w }
+    }
+
+    /// Transform a direction, the translation is ignored
+    pub fn transform_direction(m: &Matrix4, d: Vector3<f32>) -> Vector3<f32> {
+        let v = [d.x, d.y, d.z];
+        let row = |i: usize| (0..3).map(|k| m[i][k] * v[k]).sum::<f32>();
+        Vector3 { x: row(0), y: row(1), z: row(2) }
+    }
+
+    /// Inverse of a rigid transform (a rotation followed by a translation)
+    ///
+    /// The rotation is inverted by transposing it, the result is wrong for
+    /// matrices with a scale or shear.
+    pub fn inverse_rigid(m: &Matrix4) -> Matrix4 {
+        let mut inverse = identity();
+        for (i, row) in inverse.iter_mut().take(3).enumerate() {
+            for (j, value) in row.iter_mut().take(3).enumerate() {
+                *value = m[j][i];
+            }
+            row[3] = -(0..3).map(|k| m[k][i] * m[k][3]).sum::<f32>();
+        }
+        inverse
+    }
+
+    /// Matrix transforming normals, the inverse-transpose of the rotation
+    /// and scale of `m`
+    ///
+    /// Only the direction of transformed normals is meaningful, the matrix
+    /// is the cofactor matrix of `m` which is also defined for singular
+    /// matrices.
+    pub fn normal_matrix(m: &Matrix4) -> Matrix4 {
+        let c = |r0: usize, r1: usize, c0: usize, c1: usize| {
+            m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+        };
+
+        let cofactors = [[c(1, 2, 1, 2), -c(1, 2, 0, 2), c(1, 2, 0, 1)],
+                         [-c(0, 2, 1, 2), c(0, 2, 0, 2), -c(0, 2, 0, 1)],
+                         [c(0, 1, 1, 2), -c(0, 1, 0, 2), c(0, 1, 0, 1)]];
+
+        // The cofactor matrix is the inverse-transpose scaled by the
+        // determinant, keep the orientation of mirroring transforms
+        let determinant: f32 = (0..3).map(|k| m[0][k] * cofactors[0][k]).sum();
+        let sign = if determinant < 0.0 { -1.0 } else { 1.0 };
+
+        let mut n = identity();
+        for i in 0..3 {
+            for j in 0..3 {
+                n[i][j] = cofactors[i][j] * sign;
+            }
+        }
+        n
+    }
+}
@@ -0,0 +1,129 @@
+#[macro_use]
+extern crate roselib;
+
+use std::io::Cursor;
+
+use roselib::errors::RoseError;
+use roselib::files::AIP;
+use roselib::files::aip::*;
+use roselib::io::{RoseFile, WriteRoseExt};
+
+fn write_name(cursor: &mut Cursor<Vec<u8>>, name: &str) {
+    let mut bytes = name.as_bytes().to_vec();
+    bytes.resize(32, 0);
+    cursor.get_mut().extend_from_slice(&bytes);
+    cursor.set_position(cursor.get_ref().len() as u64);
+}
+
+fn write_record(cursor: &mut Cursor<Vec<u8>>, opcode: u32, data: &[u8]) {
+    cursor.write_u32(data.len() as u32 + 8).unwrap();
+    cursor.write_u32(opcode).unwrap();
+    cursor.get_mut().extend_from_slice(data);
+    cursor.set_position(cursor.get_ref().len() as u64);
+}
+
+/// Build a small monster pattern by hand
+fn monster_aip() -> Vec<u8> {
+    let mut cursor = Cursor::new(Vec::new());
+    cursor.write_i32(2).unwrap();
+    cursor.write_i32(5).unwrap();
+    cursor.write_i32(30).unwrap();
+    cursor.write_string_u32("Monster AI").unwrap();
+
+    // On idle: stop and say something
+    write_name(&mut cursor, "On Idle");
+    cursor.write_i32(1).unwrap();
+    write_name(&mut cursor, "Idle Event");
+    cursor.write_i32(1).unwrap();
+    write_record(&mut cursor, 0x0400_0001, &[]);
+    cursor.write_i32(2).unwrap();
+    write_record(&mut cursor, 0x0B00_0001, &[]);
+    write_record(&mut cursor, 0x0B00_0003, &[0x10, 0x00, 0x00, 0x00]);
+
+    // On damaged: one decoded and one unknown opcode
+    write_name(&mut cursor, "On Damaged");
+    cursor.write_i32(1).unwrap();
+    write_name(&mut cursor, "Damaged Event");
+    cursor.write_i32(2).unwrap();
+    write_record(&mut cursor, 0x0400_0002, &[0x64, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00]);
+    write_record(&mut cursor, 0x0400_0010, &[0xAA, 0xBB, 0xCC]);
+    cursor.write_i32(1).unwrap();
+    write_record(&mut cursor, 0x0B00_0020, &[0x01, 0x02, 0x03, 0x04, 0x05]);
+
+    cursor.into_inner()
+}
+
+#[test]
+fn read_aip() {
+    let mut cursor = Cursor::new(monster_aip());
+    let mut aip = AIP::new();
+    aip.read(&mut cursor).unwrap();
+
+    assert_eq!(aip.idle_interval, 5);
+    assert_eq!(aip.damage_rate, 30);
+    assert_eq!(aip.name, "Monster AI");
+    assert_eq!(aip.triggers.len(), 2);
+    assert_eq!(aip.triggers[0].name, "On Idle");
+    assert_eq!(aip.triggers[1].name, "On Damaged");
+    assert_eq!(aip.events().count(), 2);
+
+    let idle = &aip.triggers[0].events[0];
+    assert_eq!(idle.name, "Idle Event");
+    assert_eq!(idle.conditions, vec![AiCondition::FightOrDelay]);
+    assert_eq!(idle.actions, vec![AiAction::Stop, AiAction::Say { string_id: 16 }]);
+
+    let damaged = &aip.triggers[1].events[0];
+    assert_eq!(damaged.conditions[0], AiCondition::Damage { damage: 100, receive: 1 });
+    assert_eq!(damaged.conditions[1],
+               AiCondition::Unknown { opcode: 0x0400_0010, data: vec![0xAA, 0xBB, 0xCC] });
+    assert_eq!(damaged.actions[0],
+               AiAction::Unknown { opcode: 0x0B00_0020, data: vec![1, 2, 3, 4, 5] });
+}
+
+#[test]
+fn write_aip() {
+    let orig_bytes = monster_aip();
+
+    let mut cursor = Cursor::new(orig_bytes.clone());
+    let mut aip = AIP::new();
+    aip.read(&mut cursor).unwrap();
+
+    let mut new_cursor = Cursor::new(Vec::new());
+    aip.write(&mut new_cursor).unwrap();
+
+    assert_bytes_eq!(new_cursor.into_inner(), orig_bytes);
+}
+
+#[test]
+fn read_aip_record_size() {
+    // Size of the first condition record
+    let orig_bytes = monster_aip();
+    let offset = orig_bytes.windows(8)
+        .position(|w| w == [8, 0, 0, 0, 1, 0, 0, 4])
+        .unwrap();
+
+    for &(size, truncated) in [(4u32, false), (1 << 20, true), (0xFFFF_FFF0, false)].iter() {
+        let mut bytes = orig_bytes.clone();
+        bytes[offset..offset + 4].copy_from_slice(&size.to_le_bytes());
+
+        let err = AIP::from_reader(&mut Cursor::new(bytes)).unwrap_err();
+        match err.downcast_ref::<RoseError>() {
+            Some(&RoseError::TruncatedFile { .. }) => assert!(truncated),
+            Some(&RoseError::InvalidData { .. }) => assert!(!truncated),
+            e => panic!("unexpected error {:?}", e),
+        }
+    }
+}
+
+#[test]
+fn write_aip_full_name() {
+    // Names filling all 32 bytes have no terminator
+    let name = "0123456789ABCDEF0123456789ABCDEF";
+    let mut aip = AIP::from_reader(&mut Cursor::new(monster_aip())).unwrap();
+    aip.triggers[0].name = String::from(name);
+
+    let bytes = aip.to_bytes().unwrap();
+    let mut aip2 = AIP::from_reader(&mut Cursor::new(bytes.clone())).unwrap();
+    assert_eq!(aip2.triggers[0].name, name);
+    assert_bytes_eq!(aip2.to_bytes().unwrap(), bytes);
+}
@@ -0,0 +1,69 @@
+extern crate roselib;
+
+use std::env;
+use std::fs;
+use std::io::Cursor;
+
+use roselib::errors::RoseError;
+use roselib::files::DDS;
+use roselib::files::dds::{self, DdsFormat, DdsInfo};
+use roselib::io::RoseFile;
+
+fn header_bytes(header: &DdsInfo) -> Vec<u8> {
+    let mut cursor = Cursor::new(Vec::new());
+    header.write_header(&mut cursor).unwrap();
+    cursor.into_inner()
+}
+
+#[test]
+fn read_dds() {
+    let mut header = DDS::new();
+    header.width = 256;
+    header.height = 256;
+    header.mipmap_count = 9;
+    header.format = DdsFormat::DXT5;
+
+    // Header followed by (truncated) pixel data
+    let mut data = header_bytes(&header);
+    assert_eq!(data.len(), 128);
+    data.extend_from_slice(&[0xAB; 64]);
+
+    let path = env::temp_dir().join("roselib_read_dds_Object_256_1.dds");
+    fs::write(&path, data.as_slice()).unwrap();
+    let info = dds::info(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(info.width, 256);
+    assert_eq!(info.height, 256);
+    assert_eq!(info.mipmap_count, 9);
+    assert_eq!(info.format, DdsFormat::DXT5);
+}
+
+#[test]
+fn read_dds_formats() {
+    let formats = vec![
+        DdsFormat::DXT1,
+        DdsFormat::DXT3,
+        DdsFormat::Dxgi(28),
+        DdsFormat::FourCC(String::from("ATI2")),
+        DdsFormat::Uncompressed(32),
+    ];
+
+    for format in formats {
+        let mut header = DDS::new();
+        header.width = 64;
+        header.height = 32;
+        header.format = format.clone();
+
+        let bytes = header_bytes(&header);
+        let info = DDS::from_reader(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!(info, header);
+    }
+
+    // A header alone is not a texture
+    assert!(DDS::new().to_bytes().is_err());
+
+    let err = DDS::from_reader(&mut Cursor::new(b"PNG\0".to_vec())).unwrap_err();
+    assert_eq!(err.downcast_ref::<RoseError>(),
+               Some(&RoseError::InvalidMagic { format: "DDS", found: String::from("PNG") }));
+}
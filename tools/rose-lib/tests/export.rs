@@ -0,0 +1,149 @@
+extern crate roselib;
+
+use std::path::PathBuf;
+
+use roselib::export;
+use roselib::files::ZMS;
+use roselib::files::zsc::SceneMaterial;
+use roselib::io::RoseFile;
+use roselib::utils::{Transform, Vector3};
+
+fn sample_zms() -> ZMS {
+    let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    root.push("tests");
+    root.push("data");
+
+    ZMS::from_path(&root.join("CART01_ABILITY01.ZMS")).unwrap()
+}
+
+#[test]
+fn export_obj() {
+    let zms = sample_zms();
+
+    let mut buffer: Vec<u8> = Vec::new();
+    export::write_obj(&zms, &mut buffer, &Transform::default()).unwrap();
+    let obj = String::from_utf8(buffer).unwrap();
+
+    assert!(obj.starts_with("# Exported using roselib"));
+    assert_eq!(obj.lines().filter(|l| l.starts_with("v ")).count(), 544);
+    assert_eq!(obj.lines().filter(|l| l.starts_with("vt ")).count(), 544);
+    assert_eq!(obj.lines().filter(|l| l.starts_with("vn ")).count(), 544);
+    assert_eq!(obj.lines().filter(|l| l.starts_with("f ")).count(), 532);
+}
+
+#[test]
+fn export_obj_materials() {
+    let mut zms = sample_zms();
+    let triangle_count = zms.indices.len() as i16;
+    zms.materials = vec![100, triangle_count - 100];
+
+    let body = SceneMaterial::new("3DDATA\\PAT\\CART\\BODY.DDS");
+    let mut wheel = SceneMaterial::new("3DDATA\\PAT\\CART\\WHEEL.DDS");
+    wheel.alpha_enabled = true;
+    wheel.alpha = 0.5;
+
+    let (mut obj, mut mtl) = (Vec::new(), Vec::new());
+    export::write_obj_with_materials(&zms, &mut obj, &mut mtl, "cart.mtl", &[&body, &wheel],
+                                     &Transform::default()).unwrap();
+    let obj = String::from_utf8(obj).unwrap();
+    let mtl = String::from_utf8(mtl).unwrap();
+
+    assert!(obj.contains("\nmtllib cart.mtl\n"));
+    let groups: Vec<&str> = obj.lines().filter(|l| l.starts_with("usemtl ")).collect();
+    assert_eq!(groups, vec!["usemtl BODY", "usemtl WHEEL"]);
+    assert_eq!(obj.lines().filter(|l| l.starts_with("f ")).count(), 532);
+
+    // The first group has the first 100 triangles
+    let faces_before_wheel = obj.lines()
+        .take_while(|l| *l != "usemtl WHEEL")
+        .filter(|l| l.starts_with("f "))
+        .count();
+    assert_eq!(faces_before_wheel, 100);
+
+    let materials: Vec<&str> = mtl.lines().filter(|l| l.starts_with("newmtl ")).collect();
+    assert_eq!(materials, vec!["newmtl BODY", "newmtl WHEEL"]);
+    assert!(mtl.contains("map_Kd 3DDATA/PAT/CART/BODY.DDS\n"));
+    assert!(mtl.contains("d 0.5\nmap_Kd 3DDATA/PAT/CART/WHEEL.DDS\n"));
+
+    // Without a model list the materials get placeholder names
+    let (mut obj, mut mtl) = (Vec::new(), Vec::new());
+    export::write_obj_with_materials(&zms, &mut obj, &mut mtl, "cart.mtl", &[],
+                                     &Transform::default()).unwrap();
+    let mtl = String::from_utf8(mtl).unwrap();
+    let materials: Vec<&str> = mtl.lines().filter(|l| l.starts_with("newmtl ")).collect();
+    assert_eq!(materials, vec!["newmtl material_0", "newmtl material_1"]);
+    assert!(!mtl.contains("map_Kd"));
+}
+
+#[test]
+fn export_ply() {
+    let zms = sample_zms();
+
+    let mut buffer: Vec<u8> = Vec::new();
+    export::write_ply(&zms, &mut buffer, true, &Transform::default()).unwrap();
+    let ply = String::from_utf8(buffer).unwrap();
+
+    let header_end = ply.find("end_header\n").unwrap() + "end_header\n".len();
+    let header = &ply[..header_end];
+    assert!(header.starts_with("ply\nformat ascii 1.0\n"));
+    assert!(header.contains("element vertex 544\n"));
+    assert!(header.contains("element face 532\n"));
+    assert!(!header.contains("property uchar red"));
+    assert_eq!(ply[header_end..].lines().count(), 544 + 532);
+
+    // The V coordinate is flipped
+    let v = &zms.vertices[0];
+    let fields: Vec<f32> = ply[header_end..].lines().next().unwrap()
+        .split(' ')
+        .map(|f| f.parse().unwrap())
+        .collect();
+    assert_eq!(fields[6..8], [v.uv1.x, 1.0 - v.uv1.y]);
+
+    let mut buffer: Vec<u8> = Vec::new();
+    export::write_ply(&zms, &mut buffer, false, &Transform::default()).unwrap();
+
+    let marker = b"end_header\n";
+    let header_end = buffer.windows(marker.len())
+        .position(|w| w == marker)
+        .unwrap() + marker.len();
+    let header = String::from_utf8_lossy(&buffer[..header_end]);
+    assert!(header.starts_with("ply\nformat binary_little_endian 1.0\n"));
+
+    // position + normal + uv1 floats per vertex, count + 3 indices per face
+    let vertex_size = (3 + 3 + 2) * 4;
+    let face_size = 1 + 3 * 4;
+    assert_eq!(buffer.len() - header_end, 544 * vertex_size + 532 * face_size);
+}
+
+#[test]
+fn export_stl() {
+    let zms = sample_zms();
+
+    let mut buffer: Vec<u8> = Vec::new();
+    export::write_stl(&zms, &mut buffer, &Transform::default()).unwrap();
+
+    let count = buffer[80] as u32
+        | (buffer[81] as u32) << 8
+        | (buffer[82] as u32) << 16
+        | (buffer[83] as u32) << 24;
+    assert_eq!(count, 532);
+    assert_eq!(buffer.len(), 84 + 532 * 50);
+}
+
+#[test]
+fn export_transform() {
+    let transform = Transform::default();
+
+    let position = transform.position(Vector3 { x: 100.0, y: 200.0, z: 300.0 });
+    assert_eq!(position, Vector3 { x: 1.0, y: 3.0, z: 2.0 });
+    assert_eq!(transform.inverse_position(position), Vector3 { x: 100.0, y: 200.0, z: 300.0 });
+
+    let normal = transform.normal(Vector3 { x: 0.0, y: 0.0, z: 1.0 });
+    assert_eq!(normal, Vector3 { x: 0.0, y: 1.0, z: 0.0 });
+    assert_eq!(transform.inverse_normal(normal), Vector3 { x: 0.0, y: 0.0, z: 1.0 });
+
+    let transform = Transform::new(2.0);
+    let position = transform.position(Vector3 { x: 1.0, y: 2.0, z: 3.0 });
+    assert_eq!(position, Vector3 { x: 2.0, y: 6.0, z: 4.0 });
+    assert_eq!(transform.inverse_position(position), Vector3 { x: 1.0, y: 2.0, z: 3.0 });
+}
@@ -0,0 +1,54 @@
+extern crate roselib;
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use roselib::files::filetype::{RoseFileData, RoseFileType, detect_type};
+
+#[test]
+fn detect_file_type() {
+    let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    root.push("tests");
+    root.push("data");
+
+    let zms = fs::read(root.join("STONE014.ZMS")).unwrap();
+    assert_eq!(detect_type(&zms), Some(RoseFileType::ZMS));
+
+    // Lightmaps have no magic identifier
+    let lit = fs::read(root.join("OBJECTLIGHTMAPDATA.LIT")).unwrap();
+    assert_eq!(detect_type(&lit), None);
+    assert_eq!(RoseFileType::detect(&lit, Path::new("OBJECTLIGHTMAPDATA.LIT")),
+               Some(RoseFileType::LIT));
+
+    assert_eq!(detect_type(b"STB1\0\0\0\0"), Some(RoseFileType::STB));
+    assert_eq!(detect_type(b"\x06ITST01"), Some(RoseFileType::STL));
+    assert_eq!(detect_type(b"DDS |"), Some(RoseFileType::DDS));
+    assert_eq!(detect_type(b""), None);
+
+    assert_eq!(RoseFileType::from_extension("zon"), Some(RoseFileType::ZON));
+    assert_eq!(RoseFileType::from_extension("Him"), Some(RoseFileType::HIM));
+    assert_eq!(RoseFileType::from_extension("txt"), None);
+
+    // Content takes precedence over the extension
+    assert_eq!(RoseFileType::detect(&zms, Path::new("STONE014.LIT")), Some(RoseFileType::ZMS));
+}
+
+#[test]
+fn load_detected_file() {
+    let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    root.push("tests");
+    root.push("data");
+
+    match RoseFileData::from_path(&root.join("STONE014.ZMS")).unwrap() {
+        RoseFileData::ZMS(zms) => assert_eq!(zms.identifier, "ZMS0007"),
+        data => panic!("Unexpected file type: {:?}", data.file_type()),
+    }
+
+    let lit = RoseFileData::from_path(&root.join("OBJECTLIGHTMAPDATA.LIT")).unwrap();
+    assert_eq!(lit.file_type(), RoseFileType::LIT);
+
+    let zon = RoseFileData::from_path(&root.join("JGT01.ZON")).unwrap();
+    assert_eq!(zon.file_type(), RoseFileType::ZON);
+
+    assert!(RoseFileData::from_path(&root.join("31_30.MOV")).is_err());
+}
@@ -1,7 +1,14 @@
+#[macro_use]
 extern crate roselib;
+#[cfg(feature = "logging")]
+extern crate log;
 
+use std::f32;
+use std::fs::{self, File};
+use std::io::{BufReader, Cursor};
 use std::path::PathBuf;
-use roselib::files::HIM;
+use roselib::files::{HIM, ZMS};
+use roselib::files::him::HeightmapPatch;
 use roselib::io::RoseFile;
 
 #[test]
@@ -26,3 +33,254 @@ fn read_him() {
 
 }
 
+
+#[test]
+fn him_trailing_data() {
+    let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    root.push("tests");
+    root.push("data");
+
+    // The patch data after the heights is read
+    let path = root.join("33_30.HIM");
+    let mut reader = BufReader::new(File::open(&path).unwrap());
+    let mut him = HIM::new();
+    him.read_until_eof(&mut reader).unwrap();
+
+    // Unknown data after the heights is left unread
+    let mut data = Vec::new();
+    for &v in [1i32, 1, 4].iter() {
+        data.extend_from_slice(&v.to_le_bytes());
+    }
+    data.extend_from_slice(&250.0f32.to_le_bytes());
+    data.extend_from_slice(&42.0f32.to_le_bytes());
+    data.extend_from_slice(b"\x05other");
+    let mut him = HIM::new();
+    assert!(him.read_until_eof(&mut Cursor::new(data)).is_err());
+    assert!(him.patches.is_empty());
+
+    // Fully supported formats leave nothing behind
+    let mut reader = BufReader::new(File::open(root.join("STONE014.ZMS")).unwrap());
+    let mut zms = ZMS::new();
+    zms.read_until_eof(&mut reader).unwrap();
+}
+
+#[test]
+fn him_interpolation() {
+    // A 3x3 grid sloping up along x
+    let mut him = HIM::new();
+    him.width = 3;
+    him.height = 3;
+    him.scale = 10.0;
+    him.heights = vec![vec![0.0, 10.0, 20.0]; 3];
+
+    assert_eq!(him.height_at(0.0, 0.0), 0.0);
+    assert_eq!(him.height_at(1.0, 1.0), 10.0);
+    assert_eq!(him.height_at(0.5, 1.5), 5.0);
+    assert_eq!(him.height_at(1.25, 0.0), 12.5);
+
+    // Clamped to the edges
+    assert_eq!(him.height_at(-5.0, 1.0), 0.0);
+    assert_eq!(him.height_at(10.0, 10.0), 20.0);
+
+    // 45 degree slope
+    let n = him.normal_at(1.0, 1.0);
+    let expected = 1.0 / 2.0f32.sqrt();
+    assert!((n.x + expected).abs() < 1e-6);
+    assert!(n.y.abs() < 1e-6);
+    assert!((n.z - expected).abs() < 1e-6);
+
+    let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    root.push("tests");
+    root.push("data");
+
+    let him = HIM::from_path(&root.join("33_30.HIM")).unwrap();
+    let (x, y) = (10.5, 20.5);
+    let corners = [him.heights[20][10], him.heights[20][11], him.heights[21][10], him.heights[21][11]];
+    let min = corners.iter().cloned().fold(f32::MAX, f32::min);
+    let max = corners.iter().cloned().fold(f32::MIN, f32::max);
+    let height = him.height_at(x, y);
+    assert!(height >= min && height <= max);
+    assert_eq!(him.height_at(10.0, 20.0), him.heights[20][10]);
+
+    let n = him.normal_at(x, y);
+    assert!(((n.x * n.x + n.y * n.y + n.z * n.z).sqrt() - 1.0).abs() < 1e-5);
+    assert!(n.z > 0.0);
+}
+
+#[test]
+fn him_json_round_trip() {
+    // An empty heightmap has NaN min and max heights
+    let him = HIM::new();
+    let json = String::from_utf8(him.to_json_bytes().unwrap()).unwrap();
+    assert!(json.contains("\"min_height\": null"));
+
+    let him2 = HIM::from_json(&mut json.as_bytes()).unwrap();
+    assert!(him2.min_height.is_nan());
+    assert!(him2.max_height.is_nan());
+
+    let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    root.push("tests");
+    root.push("data");
+
+    let him = HIM::from_path(&root.join("33_30.HIM")).unwrap();
+    let json = him.to_json_bytes().unwrap();
+    let him2 = HIM::from_json(&mut Cursor::new(json)).unwrap();
+    assert_eq!(him2.heights, him.heights);
+    assert_eq!(him2.min_height, him.min_height);
+    assert_eq!(him2.max_height, him.max_height);
+}
+
+#[cfg(feature = "image")]
+#[test]
+fn him_gray_image() {
+    let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    root.push("tests");
+    root.push("data");
+
+    let him = HIM::from_path(&root.join("33_30.HIM")).unwrap();
+    let image = him.to_gray_image();
+    assert_eq!(image.width(), 65);
+    assert_eq!(image.height(), 65);
+
+    let him2 = HIM::from_gray_image(&image, him.min_height, him.max_height);
+    assert_eq!((him2.width, him2.height), (65, 65));
+
+    // Heights are quantized to 256 levels
+    let step = (him.max_height - him.min_height) / 255.0;
+    for (row, row2) in him.heights.iter().zip(him2.heights.iter()) {
+        for (height, height2) in row.iter().zip(row2.iter()) {
+            assert!((height - height2).abs() <= step / 2.0 + 1e-3);
+        }
+    }
+}
+
+#[cfg(feature = "logging")]
+#[test]
+fn him_trailing_data_warning() {
+    use std::sync::Mutex;
+    use log::{Level, Log, Metadata, Record};
+
+    struct Capture(Mutex<Vec<(Level, String)>>);
+
+    impl Log for Capture {
+        fn enabled(&self, _metadata: &Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &Record) {
+            self.0.lock().unwrap().push((record.level(), record.args().to_string()));
+        }
+
+        fn flush(&self) {}
+    }
+
+    let capture: &'static Capture = Box::leak(Box::new(Capture(Mutex::new(Vec::new()))));
+    log::set_logger(capture).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+
+    // 1x1 heightmap followed by 8 unknown bytes
+    let mut data = Vec::new();
+    for &v in [1i32, 1, 4].iter() {
+        data.extend_from_slice(&v.to_le_bytes());
+    }
+    data.extend_from_slice(&250.0f32.to_le_bytes());
+    data.extend_from_slice(&42.0f32.to_le_bytes());
+    data.extend_from_slice(&[0u8; 8]);
+
+    let him = HIM::from_reader(&mut Cursor::new(data)).unwrap();
+    assert_eq!(him.heights, vec![vec![42.0]]);
+
+    let records = capture.0.lock().unwrap();
+    assert!(records.contains(&(Level::Warn, String::from("HIM: 8 trailing bytes not parsed"))));
+}
+
+#[test]
+fn him_json_nan_range() {
+    // Heights set without updating the range
+    let mut him = HIM::new();
+    him.width = 2;
+    him.height = 1;
+    him.heights = vec![vec![-3.5, 12.0]];
+    assert!(him.min_height.is_nan());
+
+    let json = String::from_utf8(him.to_json_bytes().unwrap()).unwrap();
+    assert!(json.contains("\"max_height\": null"));
+
+    let him2 = HIM::from_json(&mut json.as_bytes()).unwrap();
+    assert_eq!(him2.heights, him.heights);
+    assert_eq!(him2.min_height, -3.5);
+    assert_eq!(him2.max_height, 12.0);
+}
+
+#[test]
+fn him_patches() {
+    let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    root.push("tests");
+    root.push("data");
+
+    let path = root.join("33_30.HIM");
+    let mut him = HIM::from_path(&path).unwrap();
+    assert_eq!(him.patches.len(), 256);
+    assert_eq!(him.quad_patches.len(), 85);
+    assert_eq!(him.quad_patches[0], HeightmapPatch { max: him.max_height, min: him.min_height });
+
+    // Rebuilding from unchanged heights gives the stored patches back
+    let (patches, quad_patches) = (him.patches.clone(), him.quad_patches.clone());
+    him.rebuild_patches();
+    assert_eq!(him.patches, patches);
+    assert_eq!(him.quad_patches, quad_patches);
+
+    let original = fs::read(&path).unwrap();
+    let bytes = him.to_bytes().unwrap();
+    assert!(bytes.len() <= him.size_hint());
+    assert_bytes_eq!(bytes, original);
+
+    // Raise a height of the bottom-left patch: the first patch, the root,
+    // the first node of each level and nothing else covers it
+    him.heights[63][1] = 9000.0;
+    him.rebuild_patches();
+    assert_eq!(him.patches[0].max, 9000.0);
+    assert_eq!(him.patches[1], patches[1]);
+    for &i in [0, 1, 5, 21].iter() {
+        assert_eq!(him.quad_patches[i].max, 9000.0);
+    }
+    assert_eq!(him.quad_patches[2], quad_patches[2]);
+    assert_eq!(him.quad_patches[22], quad_patches[22]);
+
+    let him2 = HIM::from_reader(&mut Cursor::new(him.to_bytes().unwrap())).unwrap();
+    assert_eq!(him2.patches, him.patches);
+    assert_eq!(him2.quad_patches, him.quad_patches);
+    assert_eq!(him2.heights[63][1], 9000.0);
+}
+
+#[test]
+fn him_from_heights() {
+    // Ramp rising along the columns
+    let heights: Vec<Vec<f32>> = (0..65).map(|_| (0..65).map(|x| x as f32 * 10.0).collect()).collect();
+    let mut him = HIM::from_heights(heights, 250.0).unwrap();
+    assert_eq!((him.width, him.height), (65, 65));
+    assert_eq!((him.grid_count, him.scale), (4, 250.0));
+    assert_eq!((him.min_height, him.max_height), (0.0, 640.0));
+    assert_eq!((him.patches.len(), him.quad_patches.len()), (256, 85));
+
+    let him2 = HIM::from_reader(&mut Cursor::new(him.to_bytes().unwrap())).unwrap();
+    assert_eq!(him2.heights, him.heights);
+    assert_eq!(him2.patches, him.patches);
+
+    let mut ragged = vec![vec![0.0; 65]; 65];
+    ragged[10].pop();
+    assert!(HIM::from_heights(ragged, 250.0).is_err());
+}
+
+#[test]
+fn him_invalid_size() {
+    for &(width, height) in [(-1i32, 65i32), (65, -1), (65, 65), (1 << 30, 1 << 30)].iter() {
+        let mut data = Vec::new();
+        for &v in [width, height, 4].iter() {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        data.extend_from_slice(&250.0f32.to_le_bytes());
+        data.extend_from_slice(&[0; 64]);
+        assert!(HIM::from_reader(&mut Cursor::new(data)).is_err(), "{}x{}", width, height);
+    }
+}
@@ -1,10 +1,16 @@
 extern crate roselib;
 
-use std::fs::File;
-use std::io::Cursor;
-use std::path::PathBuf;
+use std::env;
+use std::fs::{self, File};
+use std::io::{BufReader, Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
 
-use roselib::files::IDX;
+use roselib::errors::RoseError;
+use roselib::files::{IDX, ZMS};
+use roselib::files::idx::{Compression, VfsArchiveWriter, VfsDiff, VfsFileMetadata, VfsIndex,
+                          VfsMetadata, VfsReader, checksum, content_hash, diff};
 use roselib::io::RoseFile;
 
 #[test]
@@ -81,3 +87,784 @@ fn read_idx() {
     assert_eq!(map_vfs_last.filepath.to_str().unwrap(),
                "3DDATA/TERRAIN/TILES/ZONETYPEINFO.STB");
 }
+
+#[test]
+fn read_vfs_data() {
+    let mut file = VfsFileMetadata::new();
+    file.filepath = PathBuf::from("3DDATA/TEST.TXT");
+    file.offset = 4;
+    file.size = 5;
+
+    let mut vfs = Cursor::new(b"....hello....".to_vec());
+    assert_eq!(file.read_data(&mut vfs).unwrap(), b"hello");
+
+    file.size = 20;
+    match file.read_data(&mut vfs).unwrap_err().downcast_ref::<RoseError>() {
+        Some(&RoseError::TruncatedFile { .. }) => {}
+        _ => panic!("Expected TruncatedFile"),
+    }
+
+    // Corrupt sizes fail at the end of the blob without allocating them
+    file.size = i32::max_value();
+    match file.read_data(&mut vfs).unwrap_err().downcast_ref::<RoseError>() {
+        Some(&RoseError::TruncatedFile { .. }) => {}
+        _ => panic!("Expected TruncatedFile"),
+    }
+    file.size = -1;
+    match file.read_data(&mut vfs).unwrap_err().downcast_ref::<RoseError>() {
+        Some(&RoseError::InvalidData { .. }) => {}
+        _ => panic!("Expected InvalidData"),
+    }
+
+    file.size = 5;
+    file.is_encrypted = true;
+    assert!(file.read_data(&mut vfs).is_err());
+    assert_eq!(file.read_raw(&mut vfs).unwrap(), b"hello");
+}
+
+#[test]
+fn read_idx_progress() {
+    let mut idx_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    idx_path.push("tests");
+    idx_path.push("data");
+    idx_path.push("data.idx");
+
+    let mut reader = BufReader::new(File::open(&idx_path).unwrap());
+    let mut calls: Vec<(usize, usize)> = Vec::new();
+
+    let mut idx = IDX::new();
+    idx.read_with_progress(&mut reader, |done, total| calls.push((done, total))).unwrap();
+
+    assert_eq!(idx.file_systems.len(), 2);
+    assert_eq!(calls.len(), 3193 + 11053);
+    assert_eq!(calls[0], (1, 3193 + 11053));
+    assert_eq!(calls[calls.len() - 1], (3193 + 11053, 3193 + 11053));
+}
+
+#[test]
+fn extract_vfs_progress() {
+    let mut vfs = VfsMetadata::new();
+    for (i, name) in ["3DDATA/A.TXT", "3DDATA/SUB/B.TXT", "3DDATA/C.TXT"].iter().enumerate() {
+        let mut file = VfsFileMetadata::new();
+        file.filepath = PathBuf::from(name);
+        file.offset = i as i32 * 4;
+        file.size = 4;
+        file.is_deleted = i == 2;
+        vfs.files.push(file);
+    }
+
+    let out_dir = env::temp_dir().join("roselib_extract_vfs_progress");
+    let _ = fs::remove_dir_all(&out_dir);
+
+    let mut blob = Cursor::new(b"AAAABBBBCCCC".to_vec());
+    let mut calls: Vec<(usize, usize)> = Vec::new();
+    let extracted = vfs.extract_with_progress(&mut blob, &out_dir, |done, total| {
+        calls.push((done, total));
+    }).unwrap();
+
+    assert_eq!(extracted, 2);
+    assert_eq!(calls, vec![(1, 3), (2, 3), (3, 3)]);
+    assert_eq!(fs::read(out_dir.join("3DDATA/SUB/B.TXT")).unwrap(), b"BBBB");
+    assert!(!out_dir.join("3DDATA/C.TXT").exists());
+
+    fs::remove_dir_all(&out_dir).unwrap();
+}
+
+#[test]
+fn extract_vfs_encrypted() {
+    let mut vfs = VfsMetadata::new();
+    vfs.filename = PathBuf::from("TEST.VFS");
+    for (i, name) in ["3DDATA/A.TXT", "3DDATA/B.TXT"].iter().enumerate() {
+        let mut file = VfsFileMetadata::new();
+        file.filepath = PathBuf::from(name);
+        file.offset = i as i32 * 4;
+        file.size = 4;
+        file.is_encrypted = i == 0;
+        vfs.files.push(file);
+    }
+
+    let out_dir = env::temp_dir().join("roselib_extract_vfs_encrypted");
+    let _ = fs::remove_dir_all(&out_dir);
+    fs::create_dir_all(&out_dir).unwrap();
+
+    // Encrypted entries don't abort the extraction, they are written as
+    // stored
+    let mut blob = Cursor::new(b"AAAABBBB".to_vec());
+    assert_eq!(vfs.extract(&mut blob, &out_dir).unwrap(), 2);
+    assert_eq!(fs::read(out_dir.join("3DDATA/A.TXT")).unwrap(), b"AAAA");
+    assert_eq!(fs::read(out_dir.join("3DDATA/B.TXT")).unwrap(), b"BBBB");
+
+    fs::write(out_dir.join("TEST.VFS"), blob.get_ref()).unwrap();
+    let mut idx = VfsIndex::new();
+    idx.file_systems.push(vfs);
+    assert_eq!(idx.read_file(&out_dir, "3DDATA/A.TXT").unwrap(), b"AAAA");
+    let reader = VfsReader::new(idx, &out_dir);
+    assert_eq!(*reader.get("3DDATA/A.TXT").unwrap(), b"AAAA".to_vec());
+
+    fs::remove_dir_all(&out_dir).unwrap();
+}
+
+#[cfg(feature = "mmap")]
+#[test]
+fn read_vfs_mmap() {
+    use roselib::files::idx::VfsArchiveReader;
+
+    let vfs_path = env::temp_dir().join("roselib_read_vfs_mmap.vfs");
+    let blob: Vec<u8> = (0..255u8).cycle().take(4096).collect();
+    fs::write(&vfs_path, blob.as_slice()).unwrap();
+
+    let mut file = VfsFileMetadata::new();
+    file.filepath = PathBuf::from("3DDATA/TEST.BIN");
+    file.offset = 1000;
+    file.size = 300;
+
+    let expected = file.read_data(&mut File::open(&vfs_path).unwrap()).unwrap();
+
+    let archive = VfsArchiveReader::open(&vfs_path).unwrap();
+    assert_eq!(archive.len(), 4096);
+    assert_eq!(archive.read_file(&file).unwrap(), &expected[..]);
+    assert_eq!(file.read_data(&mut archive.cursor()).unwrap(), expected);
+
+    // Partial reads match a full read
+    assert_eq!(archive.read_range(&file, 0, 8).unwrap(), &expected[..8]);
+    assert_eq!(archive.read_range(&file, 290, 100).unwrap(), &expected[290..]);
+    assert!(archive.read_range(&file, 301, 1).is_err());
+
+    file.offset = 4000;
+    match archive.read_file(&file).unwrap_err().downcast_ref::<RoseError>() {
+        Some(&RoseError::TruncatedFile { .. }) => {}
+        _ => panic!("Expected TruncatedFile"),
+    }
+    assert!(archive.read_range(&file, 0, 8).is_err());
+
+    // Negative values are rejected before computing the end of the entry
+    file.offset = -1;
+    match archive.read_file(&file).unwrap_err().downcast_ref::<RoseError>() {
+        Some(&RoseError::InvalidData { .. }) => {}
+        _ => panic!("Expected InvalidData"),
+    }
+    file.offset = 1000;
+    file.size = -1;
+    match archive.read_file(&file).unwrap_err().downcast_ref::<RoseError>() {
+        Some(&RoseError::InvalidData { .. }) => {}
+        _ => panic!("Expected InvalidData"),
+    }
+    file.size = i32::max_value();
+    match archive.read_file(&file).unwrap_err().downcast_ref::<RoseError>() {
+        Some(&RoseError::TruncatedFile { .. }) => {}
+        _ => panic!("Expected TruncatedFile"),
+    }
+    file.size = 300;
+
+    file.offset = 0;
+    file.is_encrypted = true;
+    assert!(archive.read_file(&file).is_err());
+    file.is_encrypted = false;
+    file.is_compressed = true;
+    assert!(archive.read_range(&file, 0, 8).is_err());
+
+    drop(archive);
+    fs::remove_file(&vfs_path).unwrap();
+}
+
+#[test]
+fn idx_equivalence() {
+    let mut idx_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    idx_path.push("tests");
+    idx_path.push("data");
+    idx_path.push("data.idx");
+
+    let mut idx = IDX::from_path(&idx_path).unwrap();
+    let index = VfsIndex::from_path(&idx_path).unwrap();
+    assert_eq!(idx, index);
+
+    let bytes = idx.to_bytes().unwrap();
+    let reread = VfsIndex::from_reader(&mut Cursor::new(bytes)).unwrap();
+    assert_eq!(reread, index);
+}
+
+#[test]
+fn write_vfs_verified() {
+    let files: Vec<(&str, Vec<u8>)> = vec![
+        ("3DDATA/A.TXT", b"first file".to_vec()),
+        ("3DDATA/B.BIN", (0..200000u32).map(|i| (i % 251) as u8).collect()),
+        ("3DDATA/C.TXT", b"third file".to_vec()),
+    ];
+
+    let mut archive = VfsArchiveWriter::new(Cursor::new(Vec::new()), Path::new("TEST.VFS"));
+    for (path, data) in &files {
+        let file = archive.add_file(Path::new(path), &mut data.as_slice()).unwrap();
+        assert_eq!(file.size as usize, data.len());
+        assert_eq!(file.checksum, checksum(data));
+    }
+    let vfs = archive.finish().unwrap();
+    assert_eq!(vfs.filename, PathBuf::from("TEST.VFS"));
+    assert_eq!(vfs.files.len(), 3);
+    assert_eq!(vfs.files[1].offset, 10);
+    assert_eq!(vfs.files[2].offset, 200010);
+
+    // CRC-32 check value
+    assert_eq!(checksum(b"123456789") as u32, 0xCBF4_3926);
+
+    let mut blob: Vec<u8> = Vec::new();
+    for (_, data) in &files {
+        blob.extend_from_slice(data);
+    }
+    vfs.verify(&mut Cursor::new(blob.clone())).unwrap();
+
+    // Corrupt one byte of each of the last two files and truncate the blob
+    blob[12] ^= 0xFF;
+    blob[200012] ^= 0xFF;
+    let err = vfs.verify(&mut Cursor::new(blob.clone())).unwrap_err();
+    assert_eq!(err.downcast_ref::<RoseError>(),
+               Some(&RoseError::ChecksumMismatch {
+                   format: "VFS",
+                   files: vec![String::from("3DDATA/B.BIN"), String::from("3DDATA/C.TXT")],
+               }));
+
+    blob.truncate(100);
+    let err = vfs.verify(&mut Cursor::new(blob)).unwrap_err();
+    match err.downcast_ref::<RoseError>() {
+        Some(RoseError::ChecksumMismatch { files, .. }) => assert_eq!(files.len(), 2),
+        _ => panic!("Unexpected error: {}", err),
+    }
+}
+
+#[test]
+fn write_idx_empty_vfs() {
+    let mut idx = IDX::new();
+    idx.base_version = 129;
+    idx.current_version = 129;
+
+    let mut empty = VfsMetadata::new();
+    empty.filename = PathBuf::from("EMPTY.VFS");
+    idx.file_systems.push(empty);
+
+    let mut vfs = VfsMetadata::new();
+    vfs.filename = PathBuf::from("DATA.VFS");
+    for &(path, offset) in &[("3DDATA/B.TXT", 200), ("3DDATA/A.TXT", 100)] {
+        let mut file = VfsFileMetadata::new();
+        file.filepath = PathBuf::from(path);
+        file.offset = offset;
+        file.size = 50;
+        vfs.files.push(file);
+    }
+    idx.file_systems.push(vfs);
+
+    let bytes = idx.to_bytes().unwrap();
+    let new_idx = IDX::from_reader(&mut Cursor::new(bytes.clone())).unwrap();
+    assert_eq!(new_idx, idx);
+    assert!(new_idx.file_systems[0].files.is_empty());
+
+    // The start offset is the lowest file offset
+    let data_header = 12 + (2 + 10 + 4) + (2 + 9 + 4) + 12;
+    let start_offset = &bytes[data_header + 8..data_header + 12];
+    assert_eq!(start_offset, &100i32.to_le_bytes());
+}
+
+#[test]
+fn update_vfs_file() {
+    let mut archive = VfsArchiveWriter::new(Cursor::new(Vec::new()), Path::new("TEST.VFS"));
+    archive.add_file(Path::new("3DDATA/A.TXT"), &mut &b"first file"[..]).unwrap();
+    archive.add_file(Path::new("3DDATA/B.TXT"), &mut &b"second file"[..]).unwrap();
+    let vfs = archive.finish().unwrap();
+    let mut blob = Cursor::new(b"first filesecond file".to_vec());
+
+    let mut idx = VfsIndex::new();
+    idx.base_version = 129;
+    idx.current_version = 129;
+    idx.file_systems.push(vfs);
+
+    {
+        let file = idx.update_file(Path::new("TEST.VFS"),
+                                   &mut blob,
+                                   Path::new("3DDATA/a.txt"),
+                                   b"patched")
+            .unwrap();
+        assert_eq!(file.offset, 21);
+        assert_eq!(file.size, 7);
+        assert_eq!(file.version, 130);
+    }
+    assert_eq!(idx.current_version, 130);
+    assert_eq!(idx.base_version, 129);
+
+    let files = &idx.file_systems[0].files;
+    assert_eq!(files.len(), 3);
+    assert!(files[0].is_deleted);
+    assert!(!files[1].is_deleted);
+    assert!(!files[2].is_deleted);
+    assert_eq!(files[2].read_data(&mut blob).unwrap(), b"patched");
+    assert_eq!(files[1].read_data(&mut blob).unwrap(), b"second file");
+    assert_eq!(blob.get_ref().len(), 28);
+    idx.file_systems[0].verify(&mut blob).unwrap();
+
+    // The delete count is written to the index
+    let bytes = idx.to_bytes().unwrap();
+    let new_idx = IDX::from_reader(&mut Cursor::new(bytes)).unwrap();
+    assert_eq!(new_idx, idx);
+
+    assert!(idx.update_file(Path::new("MISSING.VFS"), &mut blob, Path::new("A.TXT"), b"")
+        .is_err());
+
+    // Stale entries in other file systems are deleted too
+    let mut other = VfsArchiveWriter::new(Cursor::new(Vec::new()), Path::new("OTHER.VFS"));
+    other.add_file(Path::new("3DDATA/B.TXT"), &mut &b"stale file"[..]).unwrap();
+    idx.file_systems.push(other.finish().unwrap());
+    assert!(!idx.file_systems[1].files[0].is_deleted);
+
+    idx.update_file(Path::new("TEST.VFS"), &mut blob, Path::new("3DDATA/B.TXT"), b"new")
+        .unwrap();
+    assert!(idx.file_systems[1].files[0].is_deleted);
+    assert!(idx.file_systems[0].files[1].is_deleted);
+    let (vfs, file) = idx.find_file("3DDATA/B.TXT").unwrap();
+    assert_eq!(vfs.filename, PathBuf::from("TEST.VFS"));
+    assert_eq!(file.read_data(&mut blob).unwrap(), b"new");
+}
+
+#[test]
+fn read_file_from_vfs() {
+    let data_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("data");
+    let zms_path = data_dir.join("STONE014.ZMS");
+    let mut zms_data = Vec::new();
+    File::open(&zms_path).unwrap().read_to_end(&mut zms_data).unwrap();
+
+    let vfs_dir = env::temp_dir().join("roselib_read_file_from_vfs");
+    fs::create_dir_all(&vfs_dir).unwrap();
+    let blob = File::create(vfs_dir.join("TEST.VFS")).unwrap();
+    let mut archive = VfsArchiveWriter::new(blob, Path::new("TEST.VFS"));
+    archive.add_file(Path::new("3DDATA/README.TXT"), &mut &b"not a mesh"[..]).unwrap();
+    archive.add_file(Path::new("3DDATA/JUNON/STONE014.ZMS"), &mut &zms_data[..]).unwrap();
+
+    let mut idx = VfsIndex::new();
+    idx.file_systems.push(archive.finish().unwrap());
+
+    let zms = ZMS::from_vfs(&idx, &vfs_dir, "3ddata\\junon\\stone014.zms").unwrap();
+    assert_eq!(zms, ZMS::from_path(&zms_path).unwrap());
+
+    let err = ZMS::from_vfs(&idx, &vfs_dir, "3DDATA/MISSING.ZMS").unwrap_err();
+    assert_eq!(err.downcast_ref::<RoseError>(),
+               Some(&RoseError::FileNotFound {
+                   format: "IDX",
+                   path: String::from("3DDATA/MISSING.ZMS"),
+               }));
+
+    // Found in the index but not a mesh
+    let err = ZMS::from_vfs(&idx, &vfs_dir, "3DDATA/README.TXT").unwrap_err();
+    if let Some(&RoseError::FileNotFound { .. }) = err.downcast_ref::<RoseError>() {
+        panic!("expected a parse error");
+    }
+}
+
+#[test]
+fn sort_vfs_files() {
+    let inputs = [("3DDATA/b.txt", &b"b"[..]),
+                  ("3DDATA/A.TXT", &b"a"[..]),
+                  ("3DDATA\\C\\D.TXT", &b"d"[..]),
+                  ("3DDATA/C.TXT", &b"c"[..])];
+
+    let pack = |order: &[usize]| {
+        let mut archive = VfsArchiveWriter::new(Cursor::new(Vec::new()), Path::new("TEST.VFS"));
+        let files = order.iter()
+            .map(|&i| (PathBuf::from(inputs[i].0), inputs[i].1))
+            .collect();
+        archive.add_files(files, true).unwrap();
+        let vfs = archive.finish().unwrap();
+
+        let mut idx = VfsIndex::new();
+        idx.file_systems.push(vfs);
+        idx.to_bytes().unwrap()
+    };
+
+    let bytes = pack(&[0, 1, 2, 3]);
+    assert_eq!(bytes, pack(&[3, 1, 0, 2]));
+    assert_eq!(bytes, pack(&[2, 3, 1, 0]));
+
+    let idx = IDX::from_reader(&mut Cursor::new(bytes)).unwrap();
+    let paths: Vec<_> = idx.file_systems[0].files.iter().map(|f| f.filepath.clone()).collect();
+    assert_eq!(paths,
+               vec![PathBuf::from("3DDATA/A.TXT"),
+                    PathBuf::from("3DDATA/b.txt"),
+                    PathBuf::from("3DDATA/C.TXT"),
+                    PathBuf::from("3DDATA/C/D.TXT")]);
+
+    // Deleted entries are moved to the end
+    let mut idx = idx;
+    idx.file_systems[0].files[0].is_deleted = true;
+    idx.file_systems[0].files.reverse();
+    idx.sort();
+    let files = &idx.file_systems[0].files;
+    assert_eq!(files[0].filepath, PathBuf::from("3DDATA/b.txt"));
+    assert_eq!(files[3].filepath, PathBuf::from("3DDATA/A.TXT"));
+    assert!(files[3].is_deleted);
+}
+
+#[test]
+fn vfs_file_flags() {
+    let mut file = VfsFileMetadata::new();
+    file.filepath = PathBuf::from("3DDATA/A.TXT");
+    file.size = 12;
+    assert_eq!(file.flags_string(), "---");
+
+    file.is_compressed = true;
+    assert_eq!(file.flags_string(), "-C-");
+    assert_eq!(file.to_string(), "3DDATA/A.TXT (12 bytes) [-C-]");
+
+    file.is_deleted = true;
+    file.is_encrypted = true;
+    assert_eq!(file.flags_string(), "DCE");
+}
+
+#[test]
+fn write_vfs_compressed() {
+    // Pseudo-random bytes don't compress
+    let mut seed: u32 = 12345;
+    let noise: Vec<u8> = (0..4096)
+        .map(|_| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 16) as u8
+        })
+        .collect();
+    let text = b"ROSE Online ".repeat(100);
+
+    let mut archive = VfsArchiveWriter::new(Cursor::new(Vec::new()), Path::new("TEST.VFS"));
+    archive.add_file_compressed(Path::new("NOISE.BIN"), &mut &noise[..], Compression::Auto(6))
+        .unwrap();
+    archive.add_file_compressed(Path::new("TEXT.TXT"), &mut &text[..], Compression::Auto(6))
+        .unwrap();
+    archive.add_file_compressed(Path::new("ALWAYS.BIN"), &mut &noise[..], Compression::Always(9))
+        .unwrap();
+    archive.add_file_compressed(Path::new("NONE.TXT"), &mut &text[..], Compression::None)
+        .unwrap();
+    let vfs = archive.finish_verified().unwrap();
+
+    let files = &vfs.files;
+    assert!(!files[0].is_compressed);
+    assert_eq!(files[0].size, 4096);
+    assert!(files[1].is_compressed);
+    assert!((files[1].size as usize) < text.len());
+    assert!(files[2].is_compressed);
+    assert!(!files[3].is_compressed);
+    assert_eq!(files[3].size as usize, text.len());
+    assert_eq!(files[1].checksum, checksum(&text));
+}
+
+#[test]
+fn read_vfs_compressed() {
+    let text = b"ROSE Online ".repeat(100);
+    let noise: Vec<u8> = (0..255u8).cycle().take(1000).map(|b| b.wrapping_mul(167)).collect();
+
+    let mut blob = Cursor::new(Vec::new());
+    let vfs = {
+        let mut archive = VfsArchiveWriter::new(&mut blob, Path::new("TEST.VFS"));
+        archive.add_file_compressed(Path::new("TEXT.TXT"), &mut &text[..], Compression::Always(6))
+            .unwrap();
+        archive.add_file_compressed(Path::new("NOISE.BIN"), &mut &noise[..], Compression::Auto(1))
+            .unwrap();
+        archive.finish().unwrap()
+    };
+
+    assert_eq!(vfs.files[0].read_data(&mut blob).unwrap(), text);
+    assert_eq!(vfs.files[1].read_data(&mut blob).unwrap(), noise);
+
+    // Corrupt compressed data is reported
+    let mut file = VfsFileMetadata::new();
+    file.size = 4;
+    file.is_compressed = true;
+    let err = file.read_data(&mut Cursor::new(vec![1, 2, 3, 4])).unwrap_err();
+    match err.downcast_ref::<RoseError>() {
+        Some(&RoseError::InvalidData { .. }) => {}
+        _ => panic!("expected invalid data, got {}", err),
+    }
+}
+
+#[test]
+fn diff_vfs_content() {
+    let vfs_dir = env::temp_dir().join("roselib_diff_vfs_content");
+    let _ = fs::remove_dir_all(&vfs_dir);
+    fs::create_dir_all(&vfs_dir).unwrap();
+
+    let mut blob = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(vfs_dir.join("TEST.VFS"))
+        .unwrap();
+    let mut idx = VfsIndex::new();
+    {
+        let mut archive = VfsArchiveWriter::new(&mut blob, Path::new("TEST.VFS"));
+        archive.add_file(Path::new("3DDATA/A.TXT"), &mut &b"first file"[..]).unwrap();
+        archive.add_file(Path::new("3DDATA/B.TXT"), &mut &b"second file"[..]).unwrap();
+        archive.add_file_compressed(Path::new("3DDATA/C.TXT"),
+                                    &mut &b"third file third file"[..],
+                                    Compression::Always(6))
+            .unwrap();
+        idx.file_systems.push(archive.finish().unwrap());
+    }
+
+    let old = idx.content_hashes(&vfs_dir).unwrap();
+    assert_eq!(old.len(), 3);
+    assert_eq!(old["3DDATA/A.TXT"], content_hash(b"first file"));
+    assert_eq!(old["3DDATA/C.TXT"], content_hash(b"third file third file"));
+    assert_eq!(diff(&old, &old), VfsDiff::default());
+
+    // Only the patched entry changed
+    idx.update_file(Path::new("TEST.VFS"), &mut blob, Path::new("3ddata\\b.txt"), b"patched")
+        .unwrap();
+    let new = idx.content_hashes(&vfs_dir).unwrap();
+    assert_eq!(diff(&old, &new),
+               VfsDiff {
+                   added: vec![],
+                   removed: vec![],
+                   changed: vec![String::from("3DDATA/B.TXT")],
+               });
+
+    let mut new = new;
+    new.remove("3DDATA/A.TXT");
+    new.insert(String::from("3DDATA/D.TXT"), 0);
+    let changes = diff(&old, &new);
+    assert_eq!(changes.added, vec![String::from("3DDATA/D.TXT")]);
+    assert_eq!(changes.removed, vec![String::from("3DDATA/A.TXT")]);
+    assert_eq!(changes.changed, vec![String::from("3DDATA/B.TXT")]);
+}
+
+#[test]
+fn merge_vfs_index() {
+    fn index(base_version: i32, current_version: i32, vfs: &str, files: &[(&str, i32)]) -> VfsIndex {
+        let mut idx = VfsIndex::new();
+        idx.base_version = base_version;
+        idx.current_version = current_version;
+
+        let mut metadata = VfsMetadata::new();
+        metadata.filename = PathBuf::from(vfs);
+        for &(path, version) in files {
+            let mut file = VfsFileMetadata::new();
+            file.filepath = PathBuf::from(path);
+            file.version = version;
+            metadata.files.push(file);
+        }
+        idx.file_systems.push(metadata);
+        idx
+    }
+
+    let mut idx = index(1, 2, "DATA.VFS", &[("3DDATA/A.TXT", 1), ("3DDATA/B.TXT", 2)]);
+    let patch = index(1, 3, "PATCH.VFS", &[("3ddata\\a.txt", 3), ("3DDATA/B.TXT", 0), ("3DDATA/C.TXT", 3)]);
+    assert!(idx.is_compatible_with(&patch));
+    idx.merge(patch).unwrap();
+
+    assert_eq!(idx.current_version, 4);
+    assert_eq!(idx.file_systems.len(), 2);
+
+    // The higher version of A wins, the lower version of B is dropped
+    let (vfs, file) = idx.find_file("3DDATA/A.TXT").unwrap();
+    assert_eq!((vfs.filename.to_str().unwrap(), file.version), ("PATCH.VFS", 3));
+    assert!(idx.file_systems[0].files[0].is_deleted);
+
+    let (vfs, file) = idx.find_file("3DDATA/B.TXT").unwrap();
+    assert_eq!((vfs.filename.to_str().unwrap(), file.version), ("DATA.VFS", 2));
+    assert!(idx.find_file("3DDATA/C.TXT").is_some());
+    assert_eq!(idx.file_systems[1].files.len(), 2);
+
+    let other = index(2, 1, "DATA.VFS", &[]);
+    assert!(!idx.is_compatible_with(&other));
+    assert!(idx.merge(other).is_err());
+}
+
+#[test]
+fn read_idx_offset_out_of_range() {
+    let mut idx = VfsIndex::new();
+    let mut vfs = VfsMetadata::new();
+    vfs.filename = PathBuf::from("DATA.VFS");
+    idx.file_systems.push(vfs);
+    let mut bytes = idx.to_bytes().unwrap();
+    let size = bytes.len();
+
+    // The offset of the first file system follows the versions, the count and its
+    // null terminated name
+    let offset_pos = 4 + 4 + 4 + 2 + "DATA.VFS".len() + 1;
+    bytes[offset_pos..offset_pos + 4].copy_from_slice(&1000i32.to_le_bytes());
+
+    let err = VfsIndex::from_reader(&mut Cursor::new(bytes)).unwrap_err();
+    match err.downcast_ref::<RoseError>() {
+        Some(&RoseError::InvalidData { format: "IDX", ref reason }) => {
+            assert_eq!(reason, &format!("index offset 1000 beyond file size {}", size));
+        }
+        _ => panic!("expected invalid data, got {}", err),
+    }
+}
+
+#[test]
+fn read_idx_lenient() {
+    let mut idx_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    idx_path.push("tests");
+    idx_path.push("data");
+    idx_path.push("data.idx");
+    let bytes = fs::read(&idx_path).unwrap();
+    let full = IDX::from_path(&idx_path).unwrap();
+
+    let (idx, warning) = VfsIndex::load_lenient(&mut Cursor::new(&bytes));
+    assert_eq!(idx, full);
+    assert_eq!(warning, None);
+
+    // Cut the index in the middle of the entries of the last file system
+    let truncated = &bytes[..bytes.len() - 1000];
+    assert!(IDX::from_reader(&mut Cursor::new(truncated)).is_err());
+
+    let (idx, warning) = VfsIndex::load_lenient(&mut Cursor::new(truncated));
+    assert_eq!(idx.file_systems.len(), 2);
+    assert_eq!(idx.file_systems[0], full.file_systems[0]);
+
+    let files = &idx.file_systems[1].files;
+    assert!(!files.is_empty() && files.len() < full.file_systems[1].files.len());
+    assert_eq!(files[..], full.file_systems[1].files[..files.len()]);
+
+    let warning = warning.unwrap();
+    assert!(warning.contains(&format!("after {} files", 3193 + files.len())), "{}", warning);
+    assert!(warning.contains("offset"), "{}", warning);
+}
+
+#[test]
+fn write_idx_flag_bytes() {
+    let mut idx = VfsIndex::new();
+    let mut vfs = VfsMetadata::new();
+    vfs.filename = PathBuf::from("DATA.VFS");
+    let mut file = VfsFileMetadata::new();
+    file.filepath = PathBuf::from("3DDATA/A.TXT");
+    vfs.files.push(file);
+    idx.file_systems.push(vfs);
+
+    // The flags of the last entry are before its version and checksum
+    let mut bytes = idx.to_bytes().unwrap();
+    let flags = bytes.len() - 8 - 3;
+    bytes[flags + 1] = 2;
+
+    let mut idx = VfsIndex::from_reader(&mut Cursor::new(&bytes)).unwrap();
+    let file = &idx.file_systems[0].files[0];
+    assert!(file.is_compressed && !file.is_deleted);
+    assert_eq!(file.flag_bytes, [0, 2, 0]);
+    assert_eq!(idx.to_bytes().unwrap(), bytes);
+
+    // Changed flags are written as 0 or 1
+    idx.file_systems[0].files[0].is_deleted = true;
+    let new_bytes = idx.to_bytes().unwrap();
+    assert_eq!(&new_bytes[flags..flags + 3], &[1, 2, 0]);
+
+    idx.file_systems[0].files[0].is_compressed = false;
+    let new_bytes = idx.to_bytes().unwrap();
+    assert_eq!(&new_bytes[flags..flags + 3], &[1, 0, 0]);
+}
+
+#[test]
+fn write_idx_euc_kr_path() {
+    let path = "3DDATA/\u{BAAC}\u{C2A4}\u{D130}/A.ZMS";
+    let euc_kr = b"3DDATA\\\xB8\xF3\xBD\xBA\xC5\xCD\\A.ZMS\0";
+
+    let mut idx = VfsIndex::new();
+    let mut vfs = VfsMetadata::new();
+    vfs.filename = PathBuf::from("DATA.VFS");
+    let mut file = VfsFileMetadata::new();
+    file.filepath = PathBuf::from(path);
+    vfs.files.push(file);
+    idx.file_systems.push(vfs);
+
+    let bytes = idx.to_bytes().unwrap();
+    assert!(bytes.windows(euc_kr.len()).any(|w| w == &euc_kr[..]));
+
+    let mut idx = VfsIndex::from_reader(&mut Cursor::new(&bytes)).unwrap();
+    assert_eq!(idx.file_systems[0].files[0].filepath, PathBuf::from(path));
+    assert_eq!(idx.to_bytes().unwrap(), bytes);
+}
+
+#[test]
+fn write_idx_exact() {
+    let mut idx_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    idx_path.push("tests");
+    idx_path.push("data");
+    idx_path.push("data.idx");
+    let original = fs::read(&idx_path).unwrap();
+
+    let mut idx = IDX::from_reader(&mut Cursor::new(&original)).unwrap();
+    assert!(idx.trailing_data.is_empty());
+    assert_eq!(idx.to_bytes().unwrap(), original);
+
+    // Bytes after the last file system are kept
+    let mut trailing = original;
+    trailing.extend_from_slice(&[1, 0, 0, 0, 0x81, 0, 0, 0]);
+    let mut idx = IDX::from_reader(&mut Cursor::new(&trailing)).unwrap();
+    assert_eq!(idx.trailing_data, vec![1, 0, 0, 0, 0x81, 0, 0, 0]);
+    assert_eq!(idx.file_systems[1].files.len(), 11053);
+    assert_eq!(idx.to_bytes().unwrap(), trailing);
+}
+
+#[test]
+fn rename_vfs_file() {
+    let mut idx = VfsIndex::new();
+    let mut vfs = VfsMetadata::new();
+    vfs.filename = PathBuf::from("DATA.VFS");
+    for &(path, offset) in &[("3DDATA/A.TXT", 0), ("3DDATA/B.TXT", 10)] {
+        let mut file = VfsFileMetadata::new();
+        file.filepath = PathBuf::from(path);
+        file.offset = offset;
+        vfs.files.push(file);
+    }
+    idx.file_systems.push(vfs);
+
+    idx.rename("3ddata\\a.txt", "3DDATA\\MOD\\A.TXT").unwrap();
+    assert!(idx.find_file("3DDATA/A.TXT").is_none());
+    let (_, file) = idx.find_file("3DDATA/MOD/A.TXT").unwrap();
+    assert_eq!(file.filepath, PathBuf::from("3DDATA/MOD/A.TXT"));
+    assert_eq!(file.offset, 0);
+
+    // Changing only the case is allowed
+    idx.rename("3DDATA/MOD/A.TXT", "3DDATA/MOD/a.txt").unwrap();
+    assert_eq!(idx.file_systems[0].files[0].filepath, PathBuf::from("3DDATA/MOD/a.txt"));
+
+    assert!(idx.rename("3DDATA/MOD/A.TXT", "3DDATA/b.txt").is_err());
+    let err = idx.rename("3DDATA/A.TXT", "3DDATA/C.TXT").unwrap_err();
+    match err.downcast_ref::<RoseError>() {
+        Some(&RoseError::FileNotFound { format: "IDX", ref path }) => assert_eq!(path, "3DDATA/A.TXT"),
+        _ => panic!("expected file not found, got {}", err),
+    }
+    assert_eq!(idx.file_systems[0].files[1].filepath, PathBuf::from("3DDATA/B.TXT"));
+}
+
+#[test]
+fn vfs_reader_cache() {
+    let vfs_dir = env::temp_dir().join("roselib_vfs_reader_cache");
+    fs::create_dir_all(&vfs_dir).unwrap();
+    let blob = File::create(vfs_dir.join("TEST.VFS")).unwrap();
+    let mut archive = VfsArchiveWriter::new(blob, Path::new("TEST.VFS"));
+    archive.add_file(Path::new("3DDATA/A.TXT"), &mut &[b'a'; 64][..]).unwrap();
+    archive.add_file(Path::new("3DDATA/B.TXT"), &mut &[b'b'; 64][..]).unwrap();
+
+    let mut idx = VfsIndex::new();
+    idx.file_systems.push(archive.finish().unwrap());
+
+    // Room for one of the files
+    let reader = Arc::new(VfsReader::with_cache(idx, &vfs_dir, 100));
+    let first = reader.get("3DDATA/A.TXT").unwrap();
+    let second = reader.get("3ddata\\a.txt").unwrap();
+    assert_eq!(*first, vec![b'a'; 64]);
+    assert!(Arc::ptr_eq(&first, &second));
+    assert_eq!((reader.cache_hits(), reader.cache_misses()), (1, 1));
+    assert_eq!(reader.cache_size(), 64);
+
+    // Evicts A
+    let handle = {
+        let reader = reader.clone();
+        thread::spawn(move || reader.get("3DDATA/B.TXT").unwrap())
+    };
+    assert_eq!(*handle.join().unwrap(), vec![b'b'; 64]);
+    assert_eq!(reader.cache_size(), 64);
+    assert_eq!(*reader.get("3DDATA/A.TXT").unwrap(), vec![b'a'; 64]);
+    assert_eq!((reader.cache_hits(), reader.cache_misses()), (1, 3));
+
+    let err = reader.get("3DDATA/MISSING.TXT").unwrap_err();
+    assert_eq!(err.downcast_ref::<RoseError>(),
+               Some(&RoseError::FileNotFound {
+                   format: "IDX",
+                   path: String::from("3DDATA/MISSING.TXT"),
+               }));
+
+    let uncached = VfsReader::new(VfsIndex::new(), &vfs_dir);
+    assert_eq!(uncached.cache_size(), 0);
+}
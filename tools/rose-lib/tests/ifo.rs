@@ -0,0 +1,92 @@
+#[macro_use]
+extern crate roselib;
+
+use std::fs::File;
+use std::io::{Cursor, Read};
+use std::path::PathBuf;
+
+use roselib::files::IFO;
+use roselib::files::ifo::*;
+use roselib::io::RoseFile;
+use roselib::utils::Vector2;
+
+fn ifo_path() -> PathBuf {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("tests");
+    path.push("data");
+    path.push("31_30.IFO");
+    path
+}
+
+#[test]
+fn read_ifo() {
+    let ifo = IFO::from_path(&ifo_path()).unwrap();
+    assert_eq!(ifo.name, "31_30");
+    assert_eq!(ifo.map_position, Vector2 { x: 31, y: 30 });
+    assert_eq!((ifo.width, ifo.height), (16, 16));
+    assert_eq!(ifo.world_matrix[12], -16000.0);
+    assert_eq!(ifo.world_matrix[14], 32000.0);
+
+    assert_eq!(ifo.deco_objects.len(), 73);
+    assert_eq!(ifo.deco_objects[0].object_id, 4);
+    assert_eq!(ifo.deco_objects[0].map_position, Vector2 { x: 51, y: 28 });
+
+    assert_eq!(ifo.npcs.len(), 1);
+    assert_eq!(ifo.npcs[0].object.object_id, 1030);
+    assert_eq!(ifo.npcs[0].quest_file, "EM20-001.con");
+    assert_eq!(ifo.construction_objects.len(), 1);
+    assert_eq!(ifo.sounds.len(), 0);
+    assert_eq!(ifo.effects.len(), 1);
+    assert_eq!(ifo.effects[0].path, "3DData\\Effect\\bandy_01.eft");
+    assert_eq!(ifo.animated_objects.len(), 0);
+
+    assert_eq!(ifo.water_patches.len(), 16);
+    assert!(ifo.water_patches.iter().all(|row| row.len() == 16));
+    assert_eq!(ifo.water_size, 2000.0);
+    assert_eq!(ifo.water_planes.len(), 0);
+
+    assert_eq!(ifo.monster_spawns.len(), 35);
+    let spawn = &ifo.monster_spawns[0];
+    assert_eq!(spawn.name, "Untitled");
+    assert_eq!(spawn.basic_monsters.len(), 5);
+    assert_eq!(spawn.basic_monsters[0].name, "Mini-Jelly Bean");
+    assert_eq!(spawn.basic_monsters[0].monster_id, 1);
+    assert_eq!(spawn.tactic_monsters.len(), 2);
+    assert_eq!((spawn.interval, spawn.limit, spawn.range, spawn.tactic_points),
+               (21, 1, 5, 200));
+
+    assert_eq!(ifo.warps.len(), 0);
+    assert_eq!(ifo.collision_objects.len(), 3);
+
+    assert_eq!(ifo.events.len(), 1);
+    assert_eq!(ifo.events[0].object.event_id, 1);
+    assert_eq!(ifo.events[0].quest_trigger, "Tutorial_1");
+    assert_eq!(ifo.events[0].script_function, "EMPTY");
+}
+
+#[test]
+fn write_ifo() {
+    let mut original = Vec::new();
+    File::open(ifo_path()).unwrap().read_to_end(&mut original).unwrap();
+
+    let mut ifo = IFO::from_reader(&mut Cursor::new(&original)).unwrap();
+    assert_bytes_eq!(ifo.to_bytes().unwrap(), original);
+}
+
+#[test]
+fn ifo_warps() {
+    let mut ifo = IFO::from_path(&ifo_path()).unwrap();
+    for &(name, warp_id) in [("Warp to Zant", 12), ("Warp to Junon", 3)].iter() {
+        let mut warp = MapObject::new();
+        warp.name = String::from(name);
+        warp.warp_id = warp_id;
+        warp.object_type = MapBlockType::Warps as u32;
+        ifo.warps.push(warp);
+    }
+
+    let bytes = ifo.to_bytes().unwrap();
+    let ifo = IFO::from_reader(&mut Cursor::new(bytes)).unwrap();
+    let warps: Vec<(&str, u16)> = ifo.warps.iter().map(|w| (w.name.as_str(), w.warp_id)).collect();
+    assert_eq!(warps, vec![("Warp to Zant", 12), ("Warp to Junon", 3)]);
+    assert_eq!(ifo.events[0].quest_trigger, "Tutorial_1");
+}
@@ -0,0 +1,177 @@
+extern crate roselib;
+
+use std::env;
+use std::fs;
+use std::io::Cursor;
+use std::path::PathBuf;
+
+use roselib::errors::RoseError;
+use roselib::files::{IDX, ZMS};
+use roselib::files::idx::VFS_BUFFER_CAPACITY;
+use roselib::io::{ReadRoseExt, RoseFile, WriteRoseExt, decode_euc_kr, encode_euc_kr, f16_to_f32,
+                  f32_to_f16, resolve_path};
+use roselib::utils::{Vector2, Vector4};
+
+#[test]
+fn string_fixed() {
+    let mut cursor = Cursor::new(Vec::new());
+    cursor.write_string_fixed("STONE", 16).unwrap();
+    cursor.write_u8(0xFF).unwrap();
+
+    let bytes = cursor.get_ref().clone();
+    assert_eq!(bytes.len(), 17);
+    assert_eq!(&bytes[..6], b"STONE\0");
+    assert!(bytes[5..16].iter().all(|&b| b == 0));
+
+    cursor.set_position(0);
+    assert_eq!(cursor.read_string(16).unwrap(), "STONE");
+    assert_eq!(cursor.read_u8().unwrap(), 0xFF);
+
+    // Strings filling the field are written without their terminator
+    let mut cursor = Cursor::new(Vec::new());
+    assert!(cursor.write_string_fixed("0123456789ABCDEFG", 16).is_err());
+    cursor.write_string_fixed("0123456789ABCDEF", 16).unwrap();
+    assert_eq!(cursor.get_ref().len(), 16);
+    cursor.set_position(0);
+    assert_eq!(cursor.read_string(16).unwrap(), "0123456789ABCDEF");
+}
+
+#[test]
+fn vector2_integers() {
+    let mut cursor = Cursor::new(Vec::new());
+    cursor.write_vector2_i16(&Vector2 { x: -2i16, y: 300 }).unwrap();
+    cursor.write_vector2_i32(&Vector2 { x: -70000i32, y: 5 }).unwrap();
+    cursor.write_vector2_u32(&Vector2 { x: 0xFFFF_FFFFu32, y: 1 }).unwrap();
+
+    let bytes = cursor.get_ref().clone();
+    assert_eq!(bytes.len(), 4 + 8 + 8);
+    // Components are written x first
+    assert_eq!(&bytes[..4], &[0xFE, 0xFF, 0x2C, 0x01]);
+
+    cursor.set_position(0);
+    assert_eq!(cursor.read_vector2_i16().unwrap(), Vector2 { x: -2, y: 300 });
+    assert_eq!(cursor.read_vector2_i32().unwrap(), Vector2 { x: -70000, y: 5 });
+    assert_eq!(cursor.read_vector2_u32().unwrap(), Vector2 { x: 0xFFFF_FFFF, y: 1 });
+}
+
+#[test]
+fn vector4_order() {
+    // 4 component vectors are stored w first
+    let mut cursor = Cursor::new(Vec::new());
+    cursor.write_vector4_i16(&Vector4 { w: 1, x: 2, y: 3, z: 4 }).unwrap();
+    assert_eq!(cursor.get_ref().as_slice(), &[1, 0, 2, 0, 3, 0, 4, 0]);
+
+    cursor.set_position(0);
+    assert_eq!(cursor.read_vector4_i16().unwrap(), Vector4 { w: 1, x: 2, y: 3, z: 4 });
+}
+
+#[test]
+fn half_floats() {
+    let smallest_subnormal = 2.0f32.powi(-24);
+    let values = [0.0, 1.0, -2.5, 65504.0, smallest_subnormal, 3.0 * smallest_subnormal,
+                  f32::INFINITY, f32::NEG_INFINITY];
+
+    let mut cursor = Cursor::new(Vec::new());
+    for &v in values.iter() {
+        cursor.write_f16(v).unwrap();
+    }
+    assert_eq!(cursor.get_ref().len(), values.len() * 2);
+    assert_eq!(&cursor.get_ref()[2..4], &[0x00, 0x3c]);
+
+    cursor.set_position(0);
+    for &v in values.iter() {
+        assert_eq!(cursor.read_f16().unwrap(), v);
+    }
+
+    assert_eq!(f32_to_f16(-0.0), 0x8000);
+    assert_eq!(f32_to_f16(smallest_subnormal), 0x0001);
+    assert!(f16_to_f32(f32_to_f16(f32::NAN)).is_nan());
+
+    // Rounded to nearest, ties to even
+    assert_eq!(f16_to_f32(f32_to_f16(1.0 + 2.0f32.powi(-11))), 1.0);
+    assert_eq!(f16_to_f32(f32_to_f16(1.0 + 3.0 * 2.0f32.powi(-11))), 1.0 + 2.0f32.powi(-9));
+    assert_eq!(f16_to_f32(f32_to_f16(0.1)), 0.099975586);
+    assert_eq!(f32_to_f16(65520.0), 0x7c00);
+    assert_eq!(f32_to_f16(smallest_subnormal / 4.0), 0);
+}
+
+#[test]
+fn path_any_case() {
+    let dir = env::temp_dir().join("roselib_path_any_case");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    let mut data = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    data.push("tests");
+    data.push("data");
+    fs::copy(data.join("STONE014.ZMS"), dir.join("STONE014.zms")).unwrap();
+    fs::copy(data.join("STONE014.ZMS"), dir.join("stone015.zms")).unwrap();
+
+    let zms = ZMS::from_path_any_case(&dir.join("STONE014.ZMS")).unwrap();
+    assert_eq!(zms, ZMS::from_path(&data.join("STONE014.ZMS")).unwrap());
+    assert_eq!(resolve_path(&dir.join("STONE015.ZMS")).unwrap(), dir.join("stone015.zms"));
+
+    let missing = dir.join("STONE016.ZMS");
+    let e = ZMS::from_path_any_case(&missing).unwrap_err();
+    assert_eq!(e.downcast_ref::<RoseError>(),
+               Some(&RoseError::PathNotFound {
+                   tried: vec![missing.display().to_string(),
+                               dir.join("STONE016.zms").display().to_string()],
+               }));
+}
+
+#[test]
+fn counted_vec() {
+    let mut cursor = Cursor::new(Vec::new());
+    cursor.write_u16(3).unwrap();
+    for &v in &[-2i16, 0, 300] {
+        cursor.write_i16(v).unwrap();
+    }
+
+    cursor.set_position(0);
+    let values = cursor.read_vec_u16("test", 2, |r| r.read_i16()).unwrap();
+    assert_eq!(values, vec![-2, 0, 300]);
+
+    // The count is checked against the remaining bytes
+    cursor.set_position(0);
+    let e = cursor.read_vec_u16("test", 4, |r| r.read_i32()).unwrap_err();
+    assert_eq!(e.downcast_ref::<RoseError>(), Some(&RoseError::TruncatedFile { format: "test" }));
+
+    let mut cursor = Cursor::new(vec![0xFF, 0xFF, 0xFF, 0xFF]);
+    assert!(cursor.read_vec_i32("test", 1, |r| r.read_u8()).is_err());
+}
+
+#[test]
+fn euc_kr_strings() {
+    let korean = [0xB8, 0xF3, 0xBD, 0xBA, 0xC5, 0xCD];
+    assert_eq!(decode_euc_kr(&korean), "몬스터");
+    assert_eq!(encode_euc_kr("몬스터").unwrap(), korean);
+    assert_eq!(decode_euc_kr(b"3DDATA/NPC"), "3DDATA/NPC");
+
+    // Strings that are not EUC-KR are read as UTF-8
+    assert_eq!(decode_euc_kr("日本".as_bytes()), "日本");
+    assert!(encode_euc_kr("\u{1F600}").is_err());
+
+    let mut writer = Cursor::new(Vec::new());
+    writer.write_string_u16_euc_kr("몬스터.ZMS").unwrap();
+    assert_eq!(writer.get_ref().len(), 2 + 10);
+    writer.set_position(0);
+    assert_eq!(writer.read_string_u16_euc_kr().unwrap(), "몬스터.ZMS");
+}
+
+#[test]
+fn read_with_capacity() {
+    let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    root.push("tests");
+    root.push("data");
+
+    let path = root.join("STONE014.ZMS");
+    let zms = ZMS::from_path(&path).unwrap();
+    assert_eq!(ZMS::from_path_with_capacity(&path, 16).unwrap(), zms);
+    assert_eq!(ZMS::from_file_with_capacity(&fs::File::open(&path).unwrap(), 1 << 20).unwrap(),
+               zms);
+
+    let path = root.join("data.idx");
+    assert_eq!(IDX::from_path_with_capacity(&path, VFS_BUFFER_CAPACITY).unwrap(),
+               IDX::from_path(&path).unwrap());
+}
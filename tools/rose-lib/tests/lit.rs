@@ -1,3 +1,4 @@
+#[macro_use]
 extern crate roselib;
 
 use std::fs::File;
@@ -5,8 +6,16 @@ use std::io::Cursor;
 use std::path::PathBuf;
 
 use roselib::files::LIT;
+use roselib::files::lit::{LightmapObject, LightmapPart};
 use roselib::io::RoseFile;
 
+fn data_dir() -> PathBuf {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("tests");
+    path.push("data");
+    path
+}
+
 #[test]
 fn read_lit() {
     let mut lit_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -101,3 +110,93 @@ fn write_lit() {
     assert_eq!(last_part.parts_per_width, 16);
     assert_eq!(last_part.part_position, 52);
 }
+
+#[test]
+fn lit_from_reader() {
+    let mut lit_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    lit_path.push("tests");
+    lit_path.push("data");
+    lit_path.push("OBJECTLIGHTMAPDATA.LIT");
+
+    let mut orig_lit = LIT::from_path(&lit_path).unwrap();
+    let bytes = orig_lit.to_bytes().unwrap();
+
+    let mut new_lit = LIT::from_reader(&mut Cursor::new(bytes.clone())).unwrap();
+    assert_eq!(new_lit.objects.len(), orig_lit.objects.len());
+    assert_eq!(new_lit.filenames, orig_lit.filenames);
+    assert_bytes_eq!(new_lit.to_bytes().unwrap(), bytes);
+}
+
+#[test]
+fn lit_atlas_rect() {
+    let lit = LIT::from_path(&data_dir().join("OBJECTLIGHTMAPDATA.LIT")).unwrap();
+
+    let first_part = &lit.objects[0].parts[0];
+    assert_eq!(first_part.atlas_rect(), Some((0, 256, 256, 256)));
+    assert_eq!(lit.filename_for(first_part), Some("Object_256_1.dds"));
+
+    let last_obj = &lit.objects[lit.objects.len() - 1];
+    let last_part = &last_obj.parts[last_obj.parts.len() - 1];
+    assert_eq!(last_part.atlas_rect(), Some((4 * 32, 3 * 32, 32, 32)));
+    assert_eq!(lit.filename_for(last_part), Some("Object_32_0.dds"));
+
+    assert_eq!(LightmapPart::new().atlas_rect(), None);
+    assert_eq!(LIT::new().filename_for(&LightmapPart::new()), None);
+}
+
+#[cfg(feature = "image")]
+#[test]
+fn lit_composite() {
+    use roselib::image::{Rgba, RgbaImage};
+
+    let part = |lightmap_index, pixels_per_part, parts_per_width, part_position| {
+        let mut part = LightmapPart::new();
+        part.lightmap_index = lightmap_index;
+        part.pixels_per_part = pixels_per_part;
+        part.parts_per_width = parts_per_width;
+        part.part_position = part_position;
+        part
+    };
+
+    let mut lit = LIT::new();
+    lit.filenames = vec![String::from("A.dds"), String::from("B.dds"), String::from("C.dds")];
+    let mut object = LightmapObject::new();
+    object.parts.push(part(0, 16, 2, 1));
+    object.parts.push(part(1, 32, 1, 0));
+    object.parts.push(part(2, 32, 1, 0));
+    lit.objects.push(object);
+
+    let red = Rgba([255, 0, 0, 255]);
+    let green = Rgba([0, 255, 0, 255]);
+    let composite = lit.composite(|filename| {
+        match filename {
+            "A.dds" => Some(RgbaImage::from_pixel(32, 32, red)),
+            "B.dds" => Some(RgbaImage::from_pixel(32, 32, green)),
+            _ => None,
+        }
+    });
+
+    assert_eq!(composite.dimensions(), (96, 32));
+    assert_eq!(*composite.get_pixel(20, 5), red);
+    assert_eq!(*composite.get_pixel(5, 5), Rgba([0, 0, 0, 0]));
+    assert_eq!(*composite.get_pixel(20, 20), Rgba([0, 0, 0, 0]));
+    assert_eq!(*composite.get_pixel(32 + 31, 31), green);
+    assert_eq!(*composite.get_pixel(64 + 5, 5), Rgba([0, 0, 0, 0]));
+}
+
+#[test]
+fn lit_single_layout() {
+    let mut lit_path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    lit_path.push("tests");
+    lit_path.push("data");
+    lit_path.push("OBJECTLIGHTMAPDATA.LIT");
+
+    let mut data = std::fs::read(&lit_path).unwrap();
+    let mut lit = LIT::new();
+    lit.read_until_eof(&mut Cursor::new(&data)).unwrap();
+
+    // Extra part fields of another build would leave bytes unread
+    data.extend_from_slice(&[0; 8]);
+    let mut lit = LIT::new();
+    assert!(lit.read_until_eof(&mut Cursor::new(&data)).is_err());
+}
@@ -0,0 +1,249 @@
+extern crate roselib;
+
+use std::env;
+use std::f32::consts::FRAC_1_SQRT_2;
+use std::fs;
+use std::io::Cursor;
+use std::path::Path;
+
+use roselib::files::{IFO, TIL, ZON, ZSC};
+use roselib::files::ifo::MapObject;
+use roselib::files::til::Tile;
+use roselib::files::zon::{ZoneTile, ZoneTileRotation};
+use roselib::io::{RoseFile, WriteRoseExt};
+use roselib::map::{self, MapHeightmap, ResolvedTile};
+use roselib::utils::{ApproxEq, Vector2, Vector3, Vector4, matrix};
+
+fn write_him(path: &Path, height: f32) {
+    write_him_sized(path, 65, height);
+}
+
+fn write_him_sized(path: &Path, size: i32, height: f32) {
+    let mut data: Vec<u8> = Vec::new();
+    for v in &[size, size, 4] {
+        data.extend_from_slice(&v.to_le_bytes());
+    }
+    data.extend_from_slice(&250.0f32.to_le_bytes());
+    for _ in 0..size * size {
+        data.extend_from_slice(&height.to_le_bytes());
+    }
+    fs::write(path, data.as_slice()).unwrap();
+}
+
+#[test]
+fn map_heightmap_from_tiles() {
+    let root = env::temp_dir().join("roselib_map_heightmap");
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+
+    write_him(&root.join("31_30.HIM"), 10.0);
+    write_him(&root.join("32_30.HIM"), 20.0);
+    write_him(&root.join("31_31.HIM"), 30.0);
+    write_him(&root.join("32_31.HIM"), 40.0);
+    fs::write(root.join("notes.txt"), b"ignored").unwrap();
+
+    let tiles = map::tile_files(&root, "HIM").unwrap();
+    let coords: Vec<_> = tiles.iter().map(|t| t.0).collect();
+    assert_eq!(coords, vec![(31, 30), (32, 30), (31, 31), (32, 31)]);
+
+    let heightmap = MapHeightmap::from_tiles(&root).unwrap();
+    assert_eq!((heightmap.x_min, heightmap.x_max), (31, 32));
+    assert_eq!((heightmap.y_min, heightmap.y_max), (30, 31));
+    assert_eq!(heightmap.width, 130);
+    assert_eq!(heightmap.height, 130);
+    assert_eq!(heightmap.heights.len(), 130);
+    assert!(heightmap.heights.iter().all(|row| row.len() == 130));
+
+    assert_eq!(heightmap.min_height, 10.0);
+    assert_eq!(heightmap.max_height, 40.0);
+    assert_eq!(heightmap.heights[0][0], 10.0);
+    assert_eq!(heightmap.heights[0][129], 20.0);
+    assert_eq!(heightmap.heights[129][0], 30.0);
+    assert_eq!(heightmap.heights[129][129], 40.0);
+    assert!(heightmap.warnings.is_empty());
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn map_heightmap_mixed_sizes() {
+    let root = env::temp_dir().join("roselib_map_heightmap_mixed");
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+
+    write_him_sized(&root.join("30_30.HIM"), 65, 10.0);
+    write_him_sized(&root.join("31_30.HIM"), 33, 20.0);
+
+    let heightmap = MapHeightmap::from_tiles(&root).unwrap();
+    assert_eq!(heightmap.width, 65 + 33);
+    assert_eq!(heightmap.height, 65);
+    assert_eq!(heightmap.min_height, 10.0);
+    assert_eq!(heightmap.max_height, 20.0);
+
+    assert_eq!(heightmap.heights[64][64], 10.0);
+    assert_eq!(heightmap.heights[0][65], 20.0);
+    assert_eq!(heightmap.heights[32][97], 20.0);
+    // Below the smaller tile
+    assert_eq!(heightmap.heights[33][65], 0.0);
+
+    assert_eq!(heightmap.warnings.len(), 1);
+    assert!(heightmap.warnings[0].contains("(31, 30)"));
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn map_heightmap_no_tiles() {
+    let root = env::temp_dir().join("roselib_map_heightmap_empty");
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+
+    assert!(MapHeightmap::from_tiles(&root).is_err());
+
+    fs::remove_dir_all(&root).unwrap();
+}
+
+#[test]
+fn resolve_tile_textures() {
+    let mut zon = ZON::new();
+    zon.textures = vec![String::from("GRASS.DDS"),
+                        String::from("SAND.DDS"),
+                        String::from("ROCK.DDS")];
+    zon.tiles.push(ZoneTile {
+        layer1: 0,
+        layer2: 1,
+        offset1: 1,
+        offset2: 0,
+        blend: false,
+        rotation: ZoneTileRotation::None,
+        tile_type: 0,
+    });
+    zon.tiles.push(ZoneTile {
+        layer1: 0,
+        layer2: 0,
+        offset1: 0,
+        offset2: 2,
+        blend: true,
+        rotation: ZoneTileRotation::Clockwise90,
+        tile_type: 0,
+    });
+
+    let cell = |tile_id| Tile { brush_id: 0, tile_idx: 0, tile_set: 0, tile_id };
+    let mut til = TIL::new();
+    til.width = 2;
+    til.height = 2;
+    til.tiles = vec![vec![cell(0), cell(1)], vec![cell(5), cell(-1)]];
+
+    assert_eq!(map::resolve_tile(&til, &zon, 0, 0),
+               Some(ResolvedTile {
+                   layer1: "SAND.DDS",
+                   layer2: None,
+                   rotation: ZoneTileRotation::None,
+               }));
+    assert_eq!(map::resolve_tile(&til, &zon, 1, 0),
+               Some(ResolvedTile {
+                   layer1: "GRASS.DDS",
+                   layer2: Some("ROCK.DDS"),
+                   rotation: ZoneTileRotation::Clockwise90,
+               }));
+
+    // Missing zone tiles and cells outside the tilemap
+    assert_eq!(map::resolve_tile(&til, &zon, 0, 1), None);
+    assert_eq!(map::resolve_tile(&til, &zon, 1, 1), None);
+    assert_eq!(map::resolve_tile(&til, &zon, 2, 0), None);
+}
+
+/// Model list with a single object made of a base and a part on top of it
+fn tower_zsc() -> ZSC {
+    let mut cursor = Cursor::new(Vec::new());
+    cursor.write_u16(1).unwrap();
+    cursor.write_cstring("3DDATA\\JUNON\\TOWER.ZMS").unwrap();
+
+    cursor.write_u16(1).unwrap();
+    cursor.write_cstring("3DDATA\\JUNON\\TOWER.DDS").unwrap();
+    for &n in [0, 0, 0, 0, 128, 1, 1, 0, 0].iter() {
+        cursor.write_u16(n).unwrap();
+    }
+    cursor.write_f32(1.0).unwrap();
+    cursor.write_u16(0).unwrap();
+    for _ in 0..3 {
+        cursor.write_f32(0.0).unwrap();
+    }
+
+    cursor.write_u16(0).unwrap();
+
+    cursor.write_u16(1).unwrap();
+    cursor.write_i32(100).unwrap();
+    cursor.write_i32(0).unwrap();
+    cursor.write_i32(0).unwrap();
+    cursor.write_u16(2).unwrap();
+    for &(parent, x, z) in [(0, 0.0, 50.0), (1, 10.0, 20.0)].iter() {
+        cursor.write_u16(0).unwrap();
+        cursor.write_u16(0).unwrap();
+        cursor.write_u8(1).unwrap();
+        cursor.write_u8(12).unwrap();
+        for &n in [x, 0.0, z].iter() {
+            cursor.write_f32(n).unwrap();
+        }
+        if parent > 0 {
+            cursor.write_u8(7).unwrap();
+            cursor.write_u8(2).unwrap();
+            cursor.write_u16(parent).unwrap();
+        }
+        cursor.write_u8(0).unwrap();
+    }
+    cursor.write_u16(0).unwrap();
+    for &n in [-100.0, -100.0, 0.0, 100.0, 100.0, 100.0].iter() {
+        cursor.write_f32(n).unwrap();
+    }
+
+    cursor.set_position(0);
+    ZSC::from_reader(&mut cursor).unwrap()
+}
+
+#[test]
+fn map_object_instances() {
+    let zsc = tower_zsc();
+
+    let mut ifo = IFO::new();
+    for &object_id in [0, 7].iter() {
+        let mut object = MapObject::new();
+        object.object_id = object_id;
+        object.position = Vector3 { x: 5200.0, y: 5100.0, z: 0.0 };
+        // Quarter turn around Z
+        object.rotation = Vector4 { w: FRAC_1_SQRT_2, x: 0.0, y: 0.0, z: FRAC_1_SQRT_2 };
+        object.scale = Vector3 { x: 2.0, y: 2.0, z: 2.0 };
+        ifo.deco_objects.push(object);
+    }
+
+    // The object missing from the model list is skipped
+    let instances: Vec<_> = map::object_instances(&ifo.deco_objects, &zsc).collect();
+    assert_eq!(instances.len(), 1);
+    assert_eq!(instances[0].object_id, 0);
+    assert_eq!(instances[0].parts.len(), 2);
+
+    let origin = Vector3 { x: 0.0, y: 0.0, z: 0.0 };
+    let position = |i: usize| matrix::transform_point(&instances[0].parts[i].transform, origin);
+    assert!(position(0).approx_eq(&Vector3 { x: 5200.0, y: 5100.0, z: 100.0 }, 1e-3));
+    assert!(position(1).approx_eq(&Vector3 { x: 5200.0, y: 5120.0, z: 140.0 }, 1e-3));
+}
+
+#[test]
+fn map_grid_to_world() {
+    assert_eq!(map::TIL_CELL_GRID_SIZE, 4);
+    assert_eq!(map::GRID_CELL_WORLD_SIZE, 250.0);
+
+    // Top-left and bottom-right samples of tile 31_30
+    assert_eq!(map::grid_to_world((31, 30), 0, 0), Vector2 { x: 496000.0, y: 560000.0 });
+    assert_eq!(map::grid_to_world((31, 30), map::HIM_GRID_SIZE - 1, map::HIM_GRID_SIZE - 1),
+               Vector2 { x: 512000.0, y: 544000.0 });
+
+    // The first deco object of 31_30 is in the minimap cell (51, 28)
+    let ifo = IFO::from_path(&Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data/31_30.IFO")).unwrap();
+    let object = &ifo.deco_objects[0];
+    let cell = map::grid_to_world((31, 30), object.map_position.x as usize, object.map_position.y as usize);
+    let x = object.position.x + map::IFO_POSITION_OFFSET;
+    let y = object.position.y + map::IFO_POSITION_OFFSET;
+    assert!(x >= cell.x && x < cell.x + map::GRID_CELL_WORLD_SIZE);
+    assert!(y <= cell.y && y > cell.y - map::GRID_CELL_WORLD_SIZE);
+}
@@ -0,0 +1,85 @@
+#[macro_use]
+extern crate roselib;
+
+use std::io::Cursor;
+
+use roselib::errors::RoseError;
+use roselib::files::STB;
+use roselib::io::{RoseFile, WriteRoseExt};
+
+/// Build a 2x3 data table by hand
+fn sample_stb() -> Vec<u8> {
+    let mut cursor = Cursor::new(b"STB1".to_vec());
+    cursor.set_position(4);
+    cursor.write_u32(0).unwrap();
+    cursor.write_i32(3).unwrap();
+    cursor.write_i32(4).unwrap();
+    cursor.write_i32(20).unwrap();
+
+    for &width in [50, 100, 60, 60, 60].iter() {
+        cursor.write_i16(width).unwrap();
+    }
+
+    for name in ["", "ID", "NAME", "PRICE", "WEIGHT"].iter() {
+        cursor.write_string_u16(name).unwrap();
+    }
+
+    cursor.write_string_u16("1").unwrap();
+    cursor.write_string_u16("2").unwrap();
+
+    let data_offset = cursor.position();
+    for cell in ["Sword", "100", "20", "Shield", "250", ""].iter() {
+        cursor.write_string_u16(cell).unwrap();
+    }
+
+    cursor.set_position(4);
+    cursor.write_u32(data_offset as u32).unwrap();
+    cursor.into_inner()
+}
+
+#[test]
+fn read_stb() {
+    let mut cursor = Cursor::new(sample_stb());
+    let mut stb = STB::new();
+    stb.read(&mut cursor).unwrap();
+
+    assert_eq!(stb.identifier, "STB1");
+    assert_eq!(stb.row_height, 20);
+    assert_eq!(stb.row_count(), 2);
+    assert_eq!(stb.column_count(), 3);
+    assert_eq!(stb.column_names[2], "PRICE");
+    assert_eq!(stb.row_names, vec!["1", "2"]);
+    assert_eq!(stb.cell(0, 0), Some("Sword"));
+    assert_eq!(stb.cell(1, 1), Some("250"));
+    assert_eq!(stb.cell(1, 2), Some(""));
+    assert_eq!(stb.cell(2, 0), None);
+}
+
+#[test]
+fn write_stb() {
+    let orig_bytes = sample_stb();
+
+    let mut cursor = Cursor::new(orig_bytes.clone());
+    let mut stb = STB::new();
+    stb.read(&mut cursor).unwrap();
+    assert_eq!(stb.size_hint(), orig_bytes.len());
+
+    let mut new_cursor = Cursor::new(Vec::new());
+    stb.write(&mut new_cursor).unwrap();
+
+    assert_bytes_eq!(new_cursor.into_inner(), orig_bytes);
+}
+
+#[test]
+fn read_stb_identifier() {
+    let mut bytes = sample_stb();
+    bytes[..4].copy_from_slice(b"STB2");
+    let err = STB::from_reader(&mut Cursor::new(bytes.clone())).unwrap_err();
+    assert_eq!(err.downcast_ref::<RoseError>(),
+               Some(&RoseError::UnsupportedVersion { format: "STB", found: String::from("STB2") }));
+
+    bytes[..4].copy_from_slice(b"ZMS0");
+    let err = STB::from_reader(&mut Cursor::new(bytes)).unwrap_err();
+    assert_eq!(err.downcast_ref::<RoseError>(),
+               Some(&RoseError::InvalidMagic { format: "STB", found: String::from("ZMS0") }));
+}
@@ -0,0 +1,76 @@
+extern crate roselib;
+
+use std::io::Cursor;
+
+use roselib::errors::RoseError;
+use roselib::files::STL;
+use roselib::files::stl::*;
+use roselib::io::RoseFile;
+
+fn item_stl() -> STL {
+    let mut stl = STL::new();
+    stl.identifier = String::from("ITST01");
+
+    let long_description = "A very long description. ".repeat(10);
+    for (id, key) in ["WP001", "WP002"].iter().enumerate() {
+        stl.keys.push(StringTableKey { key: String::from(*key), id: id as i32 });
+    }
+
+    let mut english = vec![StringTableEntry::new("Wooden Sword"), StringTableEntry::new("Iron Sword")];
+    english[1].description = long_description;
+    let korean = vec![StringTableEntry::new("KR1"), StringTableEntry::new("KR2")];
+    stl.languages.push(english);
+    stl.languages.push(korean);
+    stl
+}
+
+#[test]
+fn write_stl() {
+    let mut stl = item_stl();
+
+    let mut cursor = Cursor::new(Vec::new());
+    stl.write(&mut cursor).unwrap();
+
+    // Strings longer than 127 bytes use a two byte length
+    let bytes = cursor.get_ref().clone();
+    let description = stl.languages[0][1].description.as_bytes();
+    let position = bytes.windows(description.len())
+        .position(|w| w == description)
+        .unwrap();
+    assert_eq!(&bytes[position - 2..position], &[0xFA, 0x01]);
+
+    cursor.set_position(0);
+    let mut new_stl = STL::new();
+    new_stl.read(&mut cursor).unwrap();
+    assert_eq!(new_stl, stl);
+}
+
+#[test]
+fn stl_lookup() {
+    let stl = item_stl();
+
+    assert_eq!(stl.index("WP002"), Some(1));
+    assert_eq!(stl.text("WP001", 0), Some("Wooden Sword"));
+    assert_eq!(stl.text("WP001", 1), Some("KR1"));
+    assert_eq!(stl.text("WP001", 2), None);
+    assert_eq!(stl.text("WP999", 0), None);
+    assert!(stl.entry("WP002", 0).unwrap().description.starts_with("A very long"));
+}
+
+#[test]
+fn read_stl_identifier() {
+    for &(identifier, supported_type) in [("ITST02", true), ("ZMS008", false)].iter() {
+        let mut stl = item_stl();
+        stl.identifier = String::from(identifier);
+        let bytes = stl.to_bytes().unwrap();
+
+        let err = STL::from_reader(&mut Cursor::new(bytes)).unwrap_err();
+        let found = String::from(identifier);
+        let expected = if supported_type {
+            RoseError::UnsupportedVersion { format: "STL", found }
+        } else {
+            RoseError::InvalidMagic { format: "STL", found }
+        };
+        assert_eq!(err.downcast_ref::<RoseError>(), Some(&expected));
+    }
+}
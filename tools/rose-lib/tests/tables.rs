@@ -0,0 +1,82 @@
+extern crate roselib;
+
+use roselib::files::{STB, STL};
+use roselib::files::stl::{StringTableEntry, StringTableKey};
+use roselib::io::RoseFile;
+use roselib::tables::{ItemTable, NpcTable};
+
+fn table(rows: &[&[&str]]) -> STB {
+    let columns = rows[0].len();
+
+    let mut stb = STB::new();
+    for i in 0..columns + 1 {
+        stb.column_names.push(format!("COL{}", i));
+        stb.column_widths.push(50);
+    }
+    for (i, row) in rows.iter().enumerate() {
+        stb.row_names.push(format!("{}", i));
+        stb.rows.push(row.iter().map(|c| String::from(*c)).collect());
+    }
+    stb
+}
+
+fn item_row(name: &str, price: &str, weight: &str, key: &str) -> Vec<String> {
+    let mut row = vec![String::new(); 40];
+    row[0] = String::from(name);
+    row[4] = String::from("1");
+    row[5] = String::from(price);
+    row[7] = String::from(weight);
+    row[35] = String::from("42");
+    row[39] = String::from(key);
+    row
+}
+
+#[test]
+fn item_table() {
+    let rows = [item_row("", "", "", ""),
+                item_row("KR_SWORD", "1200", "15", "WP001"),
+                item_row("Dagger", " 300 ", "", ""),
+                item_row("Broken", "100", "x", "")];
+    let rows: Vec<Vec<&str>> = rows.iter()
+        .map(|r| r.iter().map(|c| c.as_str()).collect())
+        .collect();
+    let rows: Vec<&[&str]> = rows.iter().map(|r| &r[..]).collect();
+    let stb = table(&rows);
+
+    let mut stl = STL::new();
+    stl.keys.push(StringTableKey { key: String::from("WP001"), id: 0 });
+    stl.languages.push(vec![StringTableEntry::new("Wooden Sword")]);
+
+    let items = ItemTable::new(&stb, Some(&stl));
+    let sword = items.get(1).unwrap().unwrap();
+    assert_eq!(sword.name, "Wooden Sword");
+    assert_eq!(sword.base_price, 1200);
+    assert_eq!(sword.weight, 15);
+    assert_eq!(sword.attack, 42);
+
+    let dagger = items.get(2).unwrap().unwrap();
+    assert_eq!(dagger.name, "Dagger");
+    assert_eq!(dagger.base_price, 300);
+    assert_eq!(dagger.weight, 0);
+
+    // Malformed cells are not read as 0
+    assert!(items.get(3).is_err());
+
+    assert_eq!(items.rows().count(), 4);
+    assert_eq!(items.rows().filter(|r| r.is_err()).count(), 1);
+    assert!(items.get(4).unwrap().is_none());
+}
+
+#[test]
+fn npc_table() {
+    let stb = table(&[&["Jelly Bean", "100", "250", "80", "", "", "5", "120", "30", "", "12", "", "", "90", "NPC001"]]);
+
+    let npcs = NpcTable::new(&stb, None);
+    let jelly = npcs.get(0).unwrap().unwrap();
+    assert_eq!(jelly.name, "Jelly Bean");
+    assert_eq!(jelly.run_speed, 250);
+    assert_eq!(jelly.level, 5);
+    assert_eq!(jelly.health, 120);
+    assert_eq!(jelly.defence, 12);
+    assert_eq!(jelly.attack_speed, 90);
+}
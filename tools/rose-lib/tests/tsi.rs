@@ -0,0 +1,74 @@
+#[macro_use]
+extern crate roselib;
+
+use std::io::Cursor;
+
+use roselib::files::TSI;
+use roselib::io::{RoseFile, WriteRoseExt};
+
+fn write_sprite(cursor: &mut Cursor<Vec<u8>>, texture_id: u16, rect: [i32; 4], name: &str) {
+    cursor.write_u16(texture_id).unwrap();
+    for n in rect.iter() {
+        cursor.write_i32(*n).unwrap();
+    }
+    cursor.write_u32(0xFFFF_FFFF).unwrap();
+
+    let mut bytes = name.as_bytes().to_vec();
+    bytes.resize(32, 0);
+    cursor.get_mut().extend_from_slice(&bytes);
+    cursor.set_position(cursor.get_ref().len() as u64);
+}
+
+/// Build a small UI sprite sheet by hand
+fn ui_tsi() -> Vec<u8> {
+    let mut cursor = Cursor::new(Vec::new());
+    cursor.write_u16(2).unwrap();
+    cursor.write_string_u16("3DDATA/CONTROL/RES/UI00.DDS").unwrap();
+    cursor.write_u32(0).unwrap();
+    cursor.write_string_u16("3DDATA/CONTROL/RES/UI01.DDS").unwrap();
+    cursor.write_u32(0xFF00_FF00).unwrap();
+
+    cursor.write_u16(3).unwrap();
+    cursor.write_u16(2).unwrap();
+    write_sprite(&mut cursor, 0, [0, 0, 64, 32], "UI00_BTN_OK");
+    write_sprite(&mut cursor, 0, [64, 0, 128, 32], "UI00_BTN_CANCEL");
+    cursor.write_u16(1).unwrap();
+    write_sprite(&mut cursor, 1, [10, 20, 210, 120], "UI01_DLG_CHAT");
+
+    cursor.into_inner()
+}
+
+#[test]
+fn read_tsi() {
+    let mut cursor = Cursor::new(ui_tsi());
+    let mut tsi = TSI::new();
+    tsi.read(&mut cursor).unwrap();
+
+    assert_eq!(tsi.textures.len(), 2);
+    assert_eq!(tsi.textures[0].path, "3DDATA/CONTROL/RES/UI00.DDS");
+    assert_eq!(tsi.textures[1].color_key, 0xFF00_FF00);
+    assert_eq!(tsi.textures[0].sprites.len(), 2);
+    assert_eq!(tsi.textures[1].sprites.len(), 1);
+
+    let chat = tsi.sprite("UI01_DLG_CHAT").unwrap();
+    assert_eq!(chat.texture_id, 1);
+    assert_eq!((chat.left, chat.top, chat.right, chat.bottom), (10, 20, 210, 120));
+    assert_eq!((chat.width(), chat.height()), (200, 100));
+
+    assert!(tsi.sprite("UI99_MISSING").is_none());
+}
+
+#[test]
+fn write_tsi() {
+    let orig_bytes = ui_tsi();
+
+    let mut cursor = Cursor::new(orig_bytes.clone());
+    let mut tsi = TSI::new();
+    tsi.read(&mut cursor).unwrap();
+    assert_eq!(tsi.size_hint(), orig_bytes.len());
+
+    let mut new_cursor = Cursor::new(Vec::new());
+    tsi.write(&mut new_cursor).unwrap();
+
+    assert_bytes_eq!(new_cursor.into_inner(), orig_bytes);
+}
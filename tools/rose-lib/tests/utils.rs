@@ -0,0 +1,77 @@
+#[macro_use]
+extern crate roselib;
+
+use roselib::utils::{BYTES_DIFF_LIMIT, BoundingBox, Vector3, bytes_diff, bytes_diff_message,
+                     min_max};
+
+#[test]
+fn bytes_diff_offsets() {
+    let a = [0x00, 0x01, 0x02, 0x03, 0x04, 0x05];
+    let b = [0x00, 0xFF, 0x02, 0x03, 0xEE, 0x05, 0x06];
+
+    assert_eq!(bytes_diff(&a, &b), vec![(1, 0x01, 0xFF), (4, 0x04, 0xEE)]);
+    assert!(bytes_diff(&a, &a).is_empty());
+
+    let message = bytes_diff_message(&a, &b).unwrap();
+    assert!(message.contains("lengths 6 and 7"));
+    assert!(message.contains("0x00000001: 01 != ff"));
+    assert!(message.contains("0x00000004: 04 != ee"));
+    assert!(message.contains("0x00000006: end of the shorter buffer"));
+
+    // Only the length differs
+    assert!(bytes_diff(&a, &a[..5]).is_empty());
+    assert!(bytes_diff_message(&a, &a[..5]).is_some());
+    assert!(bytes_diff_message(&a, &a).is_none());
+
+    let zeros = vec![0u8; 100];
+    let ones = vec![1u8; 100];
+    let diff = bytes_diff(&zeros, &ones);
+    assert_eq!(diff.len(), BYTES_DIFF_LIMIT);
+    assert_eq!(diff[BYTES_DIFF_LIMIT - 1], (BYTES_DIFF_LIMIT - 1, 0, 1));
+
+    assert_bytes_eq!(zeros, vec![0u8; 100]);
+}
+
+#[test]
+#[should_panic(expected = "0x00000002: 02 != 00")]
+fn assert_bytes_eq_message() {
+    assert_bytes_eq!(vec![0u8, 1, 2], vec![0u8, 1, 0]);
+}
+
+#[test]
+fn bounding_box() {
+    let v = |x: f32, y: f32, z: f32| Vector3 { x, y, z };
+
+    let a = BoundingBox::from_points(vec![v(0.0, 0.0, 0.0), v(1.0, 2.0, -1.0), v(0.5, 1.0, 1.0)])
+        .unwrap();
+    assert_eq!(a, BoundingBox { min: v(0.0, 0.0, -1.0), max: v(1.0, 2.0, 1.0) });
+    assert_eq!(a.size(), v(1.0, 2.0, 2.0));
+    assert_eq!(a.center(), v(0.5, 1.0, 0.0));
+    assert!(BoundingBox::<f32>::from_points(vec![]).is_none());
+
+    // Points on the faces are inside
+    assert!(a.contains(&v(1.0, 1.0, 0.0)));
+    assert!(a.contains(&v(0.0, 2.0, -1.0)));
+    assert!(!a.contains(&v(1.0001, 1.0, 0.0)));
+
+    let b = BoundingBox { min: v(3.0, 3.0, 3.0), max: v(4.0, 5.0, 6.0) };
+    assert!(!a.intersects(&b));
+    let merged = a.merge(&b);
+    assert_eq!(merged, BoundingBox { min: v(0.0, 0.0, -1.0), max: v(4.0, 5.0, 6.0) });
+    assert!(merged.intersects(&a) && merged.intersects(&b));
+    assert!(merged.contains(&v(2.0, 2.5, 2.0)));
+
+    // Integer boxes
+    let c = BoundingBox { min: Vector3 { x: 0, y: 0, z: 0 }, max: Vector3 { x: 2, y: 2, z: 2 } };
+    let d = BoundingBox { min: Vector3 { x: 2, y: 1, z: 0 }, max: Vector3 { x: 3, y: 3, z: 3 } };
+    assert!(c.intersects(&d));
+}
+
+#[test]
+fn min_max_ignores_nan() {
+    let nan = ::std::f32::NAN;
+    assert_eq!(min_max(vec![nan, 3.0, -2.5, nan, 1.0]), Some((-2.5, 3.0)));
+    assert_eq!(min_max(vec![4.0, nan]), Some((4.0, 4.0)));
+    assert_eq!(min_max(vec![nan, nan]), None);
+    assert_eq!(min_max(Vec::new()), None);
+}
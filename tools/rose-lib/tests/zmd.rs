@@ -0,0 +1,90 @@
+extern crate roselib;
+
+use std::f32::consts::FRAC_1_SQRT_2;
+use std::io::{Cursor, Write};
+
+use roselib::errors::RoseError;
+use roselib::files::ZMD;
+use roselib::files::filetype::{RoseFileType, detect_type};
+use roselib::files::zmd::Bone;
+use roselib::io::{RoseFile, WriteRoseExt};
+use roselib::utils::{ApproxEq, Vector3, Vector4, matrix};
+
+fn bone(name: &str, parent: u32, y: f32) -> Bone {
+    let mut bone = Bone::new();
+    bone.name = String::from(name);
+    bone.parent = parent;
+    bone.position = Vector3 { x: 0.0, y, z: 0.0 };
+    bone
+}
+
+#[test]
+fn write_zmd() {
+    let mut zmd = ZMD::new();
+    zmd.bones.push(bone("b1_pelvis", 0, 0.0));
+    zmd.bones.push(bone("b1_spine", 0, 10.0));
+    let mut dummy = bone("p_00", 1, 5.0);
+    dummy.rotation = Vector4 { w: 0.0, x: 1.0, y: 0.0, z: 0.0 };
+    zmd.dummies.push(dummy);
+
+    let bytes = zmd.to_bytes().unwrap();
+    assert_eq!(&bytes[..7], b"ZMD0003");
+    assert_eq!(detect_type(&bytes), Some(RoseFileType::ZMD));
+    assert!(bytes.len() <= zmd.size_hint());
+
+    let new_zmd = ZMD::from_reader(&mut Cursor::new(bytes)).unwrap();
+    assert_eq!(new_zmd, zmd);
+    assert_eq!(new_zmd.node_count(), 3);
+
+    // Version 2 dummies have no rotation
+    zmd.identifier = String::from("ZMD0002");
+    let bytes = zmd.to_bytes().unwrap();
+    let new_zmd = ZMD::from_reader(&mut Cursor::new(bytes)).unwrap();
+    assert_eq!(new_zmd.dummies[0].rotation, Vector4 { w: 1.0, x: 0.0, y: 0.0, z: 0.0 });
+    assert_eq!(new_zmd.bones, zmd.bones);
+}
+
+#[test]
+fn zmd_invalid() {
+    let mut cursor = Cursor::new(Vec::new());
+    cursor.write_all(b"ZMD0004").unwrap();
+    cursor.write_u32(0).unwrap();
+    let err = ZMD::from_reader(&mut Cursor::new(cursor.into_inner())).unwrap_err();
+    assert_eq!(err.downcast_ref::<RoseError>(),
+               Some(&RoseError::UnsupportedVersion {
+                   format: "ZMD",
+                   found: String::from("ZMD0004"),
+               }));
+}
+
+#[test]
+fn zmd_bind_pose() {
+    let mut zmd = ZMD::new();
+    let mut root = bone("root", 0, 0.0);
+    root.position = Vector3 { x: 1.0, y: 2.0, z: 3.0 };
+    // Quarter turn around Z
+    root.rotation = Vector4 { w: FRAC_1_SQRT_2, x: 0.0, y: 0.0, z: FRAC_1_SQRT_2 };
+    zmd.bones.push(root);
+    let mut child = bone("child", 0, 0.0);
+    child.position = Vector3 { x: 10.0, y: 0.0, z: 0.0 };
+    zmd.bones.push(child);
+
+    let matrices = zmd.bind_pose_matrices().unwrap();
+    assert_eq!(matrices.len(), 2);
+
+    // The child is at the root plus its offset turned by the root rotation
+    let origin = Vector3 { x: 0.0, y: 0.0, z: 0.0 };
+    let position = matrix::transform_point(&matrices[1], origin);
+    assert!(position.approx_eq(&Vector3 { x: 1.0, y: 12.0, z: 3.0 }, 1e-5));
+
+    let inverses = zmd.inverse_bind_matrices().unwrap();
+    let back = matrix::transform_point(&inverses[1], position);
+    assert!(back.approx_eq(&origin, 1e-5));
+
+    // Parents must come first
+    zmd.bones[0].parent = 1;
+    match zmd.bind_pose_matrices().unwrap_err().downcast_ref::<RoseError>() {
+        Some(&RoseError::InvalidData { format: "ZMD", .. }) => {}
+        e => panic!("Unexpected error: {:?}", e),
+    }
+}
@@ -0,0 +1,161 @@
+extern crate roselib;
+
+use std::env;
+use std::fs;
+use std::io::Cursor;
+
+use roselib::errors::RoseError;
+use roselib::files::{ZMD, ZMO};
+use roselib::files::zmd::Bone;
+use roselib::files::zmo::*;
+use roselib::io::{RoseFile, WriteRoseExt};
+use roselib::utils::{ApproxEq, Vector3, Vector4};
+
+/// Build a motion moving and turning one bone over 3 frames at 10 fps
+fn motion() -> ZMO {
+    let rotation = |angle: f32| {
+        let half = angle.to_radians() / 2.0;
+        ChannelValue::Rotation(Vector4 { w: half.cos(), x: 0.0, y: 0.0, z: half.sin() })
+    };
+
+    let mut zmo = ZMO::new();
+    zmo.fps = 10;
+    zmo.frame_count = 3;
+    zmo.channels.push(MotionChannel {
+        channel_type: ChannelType::Position,
+        index: 0,
+        frames: vec![
+            ChannelValue::Position(Vector3 { x: 0.0, y: 0.0, z: 0.0 }),
+            ChannelValue::Position(Vector3 { x: 10.0, y: 0.0, z: 0.0 }),
+            ChannelValue::Position(Vector3 { x: 10.0, y: 20.0, z: 0.0 }),
+        ],
+    });
+    zmo.channels.push(MotionChannel {
+        channel_type: ChannelType::Rotation,
+        index: 0,
+        frames: vec![rotation(0.0), rotation(90.0), rotation(180.0)],
+    });
+    zmo.channels.push(MotionChannel {
+        channel_type: ChannelType::Scale,
+        index: 1,
+        frames: vec![ChannelValue::Scale(1.0), ChannelValue::Scale(2.0), ChannelValue::Scale(3.0)],
+    });
+    zmo
+}
+
+fn position(value: &ChannelValue) -> Vector3<f32> {
+    match *value {
+        ChannelValue::Position(v) => v,
+        ref v => panic!("Expected a position, found {:?}", v),
+    }
+}
+
+fn rotation(value: &ChannelValue) -> Vector4<f32> {
+    match *value {
+        ChannelValue::Rotation(v) => v,
+        ref v => panic!("Expected a rotation, found {:?}", v),
+    }
+}
+
+#[test]
+fn write_zmo() {
+    let mut zmo = motion();
+    let bytes = zmo.to_bytes().unwrap();
+    assert_eq!(bytes.len(), zmo.size_hint());
+    assert_eq!(&bytes[..8], b"ZMO0002\0");
+
+    let new_zmo = ZMO::from_reader(&mut Cursor::new(bytes)).unwrap();
+    assert_eq!(new_zmo, zmo);
+}
+
+#[test]
+fn zmo_invalid() {
+    let mut cursor = Cursor::new(Vec::new());
+    cursor.write_cstring("ZMO0003").unwrap();
+    let err = ZMO::from_reader(&mut Cursor::new(cursor.into_inner())).unwrap_err();
+    assert_eq!(err.downcast_ref::<RoseError>(),
+               Some(&RoseError::UnsupportedVersion {
+                   format: "ZMO",
+                   found: String::from("ZMO0003"),
+               }));
+
+    let mut cursor = Cursor::new(Vec::new());
+    cursor.write_cstring("ZMO0002").unwrap();
+    cursor.write_u32(30).unwrap();
+    cursor.write_i32(1).unwrap();
+    cursor.write_i32(1).unwrap();
+    cursor.write_i32(3).unwrap();
+    cursor.write_u32(0).unwrap();
+    let err = ZMO::from_reader(&mut Cursor::new(cursor.into_inner())).unwrap_err();
+    match err.downcast_ref::<RoseError>() {
+        Some(&RoseError::InvalidData { format: "ZMO", .. }) => {}
+        e => panic!("Unexpected error: {:?}", e),
+    }
+}
+
+#[test]
+fn zmo_sample() {
+    let zmo = motion();
+    assert_eq!(zmo.duration(), 0.3);
+
+    // On a keyframe the frame is returned verbatim
+    let frame1 = zmo.sample(0.1, false);
+    assert_eq!(frame1.len(), 3);
+    assert_eq!(frame1[0], zmo.channels[0].frames[1]);
+    assert!(rotation(&frame1[1]).approx_eq(&rotation(&zmo.channels[1].frames[1]), 1e-6));
+    assert_eq!(frame1[2], ChannelValue::Scale(2.0));
+    assert_eq!(zmo.sample(0.0, true)[0], zmo.channels[0].frames[0]);
+
+    // Halfway between the first two frames
+    let middle = zmo.sample(0.05, false);
+    assert!(position(&middle[0]).approx_eq(&Vector3 { x: 5.0, y: 0.0, z: 0.0 }, 1e-5));
+    let half = 45f32.to_radians() / 2.0;
+    let expected = Vector4 { w: half.cos(), x: 0.0, y: 0.0, z: half.sin() };
+    assert!(rotation(&middle[1]).approx_eq(&expected, 1e-5));
+    match middle[2] {
+        ChannelValue::Scale(s) => assert!(s.approx_eq(&1.5, 1e-6)),
+        ref v => panic!("Expected a scale, found {:?}", v),
+    }
+
+    // Clamped past the end
+    assert_eq!(zmo.sample(1.0, false)[0], zmo.channels[0].frames[2]);
+    assert_eq!(zmo.sample(-1.0, false)[0], zmo.channels[0].frames[0]);
+
+    // Looping blends the last frame back into the first one
+    let wrapped = zmo.sample(0.25, true);
+    assert!(position(&wrapped[0]).approx_eq(&Vector3 { x: 5.0, y: 10.0, z: 0.0 }, 1e-5));
+    let wrapped = zmo.sample(0.4, true);
+    assert_eq!(wrapped[0], zmo.channels[0].frames[1]);
+}
+
+#[test]
+fn zmo_animation_set() {
+    let mut skeleton = ZMD::new();
+    skeleton.bones.push(Bone::new());
+    skeleton.bones.push(Bone::new());
+
+    let root = env::temp_dir().join("roselib_zmo_animation_set");
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+
+    let mut walk = motion();
+    fs::write(root.join("WALK.ZMO"), walk.to_bytes().unwrap()).unwrap();
+    let mut run = motion();
+    run.fps = 30;
+    fs::write(root.join("run.zmo"), run.to_bytes().unwrap()).unwrap();
+    fs::write(root.join("notes.txt"), b"not a motion").unwrap();
+
+    let set = AnimationSet::from_dir(&skeleton, &root).unwrap();
+    assert_eq!(set.names().collect::<Vec<_>>(), vec!["WALK", "run"]);
+    assert_eq!(set.get("WALK").unwrap().fps, 10);
+    assert_eq!(set.get("run").unwrap().fps, 30);
+    assert!(set.get("IDLE").is_none());
+
+    // The scale channel animates bone 1, a single bone skeleton can't play it
+    skeleton.bones.pop();
+    let err = AnimationSet::from_paths(&skeleton, &[root.join("WALK.ZMO")]).unwrap_err();
+    assert_eq!(err.downcast_ref::<RoseError>(),
+               Some(&RoseError::IndexOutOfRange { kind: "ZMO bone", index: 1, count: 1 }));
+
+    fs::remove_dir_all(&root).unwrap();
+}
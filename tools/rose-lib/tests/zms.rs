@@ -1,11 +1,37 @@
 extern crate roselib;
 
-use std::fs::File;
+use std::env;
+use std::fs::{self, File};
 use std::io::Cursor;
 use std::path::PathBuf;
 
+use roselib::errors::RoseError;
+use roselib::export;
 use roselib::files::ZMS;
-use roselib::io::RoseFile;
+use roselib::files::zms::*;
+use roselib::io::{RoseFile, WriteRoseExt};
+use roselib::utils::{ApproxEq, Color4, Transform, Vector2, Vector3, Vector4};
+use roselib::utils::matrix;
+
+/// Build a unit quad in the XY plane at the given offset
+fn quad(x: f32, y: f32) -> ZMS {
+    let mut zms = ZMS::new();
+    zms.identifier = String::from("ZMS0008");
+    zms.format = VertexFormat::Position as i32 | VertexFormat::UV1 as i32;
+
+    let corners = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)];
+    for &(cx, cy) in corners.iter() {
+        let mut v = Vertex::new();
+        v.position = Vector3 { x: x + cx, y: y + cy, z: 0.0 };
+        v.uv1 = Vector2 { x: cx, y: cy };
+        zms.vertices.push(v);
+    }
+
+    zms.indices.push(Vector3 { x: 0, y: 1, z: 2 });
+    zms.indices.push(Vector3 { x: 0, y: 2, z: 3 });
+    zms.recalculate_bounding_box();
+    zms
+}
 
 #[test]
 fn read_zms() {
@@ -105,11 +131,1178 @@ fn write_zms() {
         let mut new_zms = ZMS::new();
         new_zms.read(&mut cursor).unwrap();
 
-        if orig_zms.identifier.as_str() == "ZMS0007" {
-            orig_zms.identifier = String::from("ZMS0008");
+        assert!(orig_zms.eq_ignoring_version(&new_zms));
+    }
+}
+
+
+#[test]
+fn zms_size_hint() {
+    let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    root.push("tests");
+    root.push("data");
+
+    let file1 = root.join("HEADBAD01.ZMS");
+    let file2 = root.join("STONE014.ZMS");
+    let file3 = root.join("CART01_ABILITY01.ZMS");
+
+    for zms_file in [file1, file2, file3].iter() {
+        let mut zms = ZMS::from_path(zms_file).unwrap();
+        let hint = zms.size_hint();
+        let bytes = zms.to_bytes().unwrap();
+
+        assert!(hint >= bytes.len(), "{} < {}", hint, bytes.len());
+    }
+}
+
+#[test]
+fn merge_zms() {
+    let mut a = quad(0.0, 0.0);
+    let mut b = quad(2.0, 0.0);
+    b.materials.push(2);
+
+    a.merge(&b).unwrap();
+
+    assert_eq!(a.vertices.len(), 8);
+    assert_eq!(a.indices.len(), 4);
+    assert_eq!(a.materials, vec![2, 2]);
+    for index in &a.indices {
+        for &i in [index.x, index.y, index.z].iter() {
+            assert!((i as usize) < a.vertices.len());
+        }
+    }
+    assert_eq!(a.indices[2], Vector3 { x: 4, y: 5, z: 6 });
+    assert_eq!(a.bounding_box.min, Vector3 { x: 0.0, y: 0.0, z: 0.0 });
+    assert_eq!(a.bounding_box.max, Vector3 { x: 3.0, y: 1.0, z: 0.0 });
+
+    let mut skinned = quad(0.0, 0.0);
+    skinned.format |= VertexFormat::BoneWeight as i32 | VertexFormat::BoneIndex as i32;
+    assert!(skinned.merge(&b).is_err());
+}
+
+#[test]
+fn merge_zms_overflow() {
+    let skin = VertexFormat::BoneWeight as i32 | VertexFormat::BoneIndex as i32;
+
+    // Bone palettes too large to be indexed
+    let mut a = quad(0.0, 0.0);
+    a.format |= skin;
+    a.bones = vec![0; i16::max_value() as usize];
+    let mut b = quad(2.0, 0.0);
+    b.format |= skin;
+    b.bones = vec![0];
+    assert!(a.merge(&b).is_err());
+    assert_eq!(a.vertices.len(), 4);
+
+    // Out of range indices fail without modifying the mesh
+    let mut a = quad(0.0, 0.0);
+    let mut b = quad(2.0, 0.0);
+    b.indices[0].x = i16::max_value();
+    assert!(a.merge(&b).is_err());
+    assert_eq!(a, quad(0.0, 0.0));
+
+    let mut b = quad(2.0, 0.0);
+    b.strips = vec![i16::max_value()];
+    assert!(a.merge(&b).is_err());
+    assert_eq!(a, quad(0.0, 0.0));
+}
+
+#[test]
+fn merge_zms_strips() {
+    let mut a = quad(0.0, 0.0);
+    let mut b = quad(2.0, 0.0);
+    a.strips = vec![1, 2, 0, 3];
+    b.strips = vec![1, 2, 0, 3];
+
+    a.merge(&b).unwrap();
+
+    // Both quads and no triangle bridging them
+    let triangles = strip_to_triangles(&a.strips);
+    assert_eq!(triangles.len(), 4);
+    for t in &triangles {
+        let quads: Vec<bool> = [t.x, t.y, t.z].iter().map(|&i| i < 4).collect();
+        assert!(quads.iter().all(|&q| q == quads[0]), "{:?}", t);
+    }
+    let mut expected = a.indices.clone();
+    let mut triangles = triangles;
+    for t in expected.iter_mut().chain(triangles.iter_mut()) {
+        // Rotate to start at the smallest index to compare with the same winding
+        while t.x > t.y.min(t.z) {
+            *t = Vector3 { x: t.y, y: t.z, z: t.x };
+        }
+    }
+    expected.sort_by_key(|t| (t.x, t.y, t.z));
+    triangles.sort_by_key(|t| (t.x, t.y, t.z));
+    assert_eq!(triangles, expected);
+}
+
+#[test]
+fn zms_triangle_strips() {
+    let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    root.push("tests");
+    root.push("data");
+
+    // Rotate each triangle so it starts at its smallest index, keeping winding
+    fn normalize(indices: &[Vector3<i16>]) -> Vec<[i16; 3]> {
+        let mut triangles: Vec<[i16; 3]> = indices.iter().map(|i| {
+            let t = [i.x, i.y, i.z];
+            let m = (0..3).min_by_key(|&k| t[k]).unwrap();
+            [t[m], t[(m + 1) % 3], t[(m + 2) % 3]]
+        }).collect();
+        triangles.sort();
+        triangles
+    }
+
+    for name in ["HEADBAD01.ZMS", "CART01_ABILITY01.ZMS"].iter() {
+        let zms = ZMS::from_path(&root.join(name)).unwrap();
+
+        let strips = zms.to_triangle_strips();
+        assert!(strips.len() < zms.indices.len());
+
+        let mut separate = ZMS::new();
+        separate.set_triangle_strips(&strips);
+        assert_eq!(normalize(&separate.indices), normalize(&zms.indices));
+
+        let stitched = vec![stitch_strips(&strips)];
+        let mut single = ZMS::new();
+        single.set_triangle_strips(&stitched);
+        assert_eq!(normalize(&single.indices), normalize(&zms.indices));
+    }
+}
+
+#[test]
+fn zms_from_obj() {
+    let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    root.push("tests");
+    root.push("data");
+
+    let mut orig = ZMS::from_path(&root.join("CART01_ABILITY01.ZMS")).unwrap();
+    orig.recalculate_bounding_box();
+
+    let mut obj: Vec<u8> = Vec::new();
+    export::write_obj(&orig, &mut obj, &Transform::default()).unwrap();
+
+    let zms = ZMS::from_obj(&mut Cursor::new(obj), &Transform::default()).unwrap();
+    assert_eq!(zms.identifier, "ZMS0008");
+    assert_eq!(zms.vertices.len(), orig.vertices.len());
+    assert_eq!(zms.indices.len(), orig.indices.len());
+    assert!(zms.positions_enabled());
+    assert!(zms.normals_enabled());
+    assert!(zms.uv1_enabled());
+    assert!(!zms.bones_enabled());
+    assert_eq!(zms.bounding_box, orig.bounding_box);
+
+    // Quads are triangulated as a fan
+    let quad = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n";
+    let zms = ZMS::from_obj(&mut Cursor::new(quad), &Transform::new(1.0)).unwrap();
+    assert_eq!(zms.vertices.len(), 4);
+    assert_eq!(zms.indices, vec![Vector3 { x: 0, y: 1, z: 2 }, Vector3 { x: 0, y: 2, z: 3 }]);
+    assert!(!zms.uv1_enabled());
+}
+
+#[test]
+fn zms_unsupported_version() {
+    let mut cursor = Cursor::new(b"ZMS0099\0".to_vec());
+    let mut zms = ZMS::new();
+    let err = zms.read(&mut cursor).unwrap_err();
+
+    assert_eq!(err.downcast_ref::<RoseError>(),
+               Some(&RoseError::UnsupportedVersion {
+                   format: "ZMS",
+                   found: String::from("ZMS0099"),
+               }));
+
+    let mut cursor = Cursor::new(b"PNG\0".to_vec());
+    let mut zms = ZMS::new();
+    let err = zms.read(&mut cursor).unwrap_err();
+
+    match err.downcast_ref::<RoseError>() {
+        Some(&RoseError::InvalidMagic { .. }) => {}
+        _ => panic!("Expected InvalidMagic, got: {}", err),
+    }
+}
+
+#[test]
+fn zms_weld_vertices() {
+    // Two quads sharing an edge, each with its own copy of the shared corners
+    let mut zms = quad(0.0, 0.0);
+    let mut right = quad(1.0, 0.0);
+    for v in &mut right.vertices {
+        v.uv1 = Vector2 { x: v.position.x, y: v.position.y };
+    }
+    for v in &mut zms.vertices {
+        v.uv1 = Vector2 { x: v.position.x, y: v.position.y };
+    }
+    zms.merge(&right).unwrap();
+    zms.vertices[4].position.x += 0.0001;
+
+    let triangles = |m: &ZMS| -> Vec<[(i32, i32); 3]> {
+        m.indices.iter().map(|t| {
+            let p = |i: i16| {
+                let v = &m.vertices[i as usize].position;
+                ((v.x * 100.0).round() as i32, (v.y * 100.0).round() as i32)
+            };
+            [p(t.x), p(t.y), p(t.z)]
+        }).collect()
+    };
+
+    let before = triangles(&zms);
+    assert_eq!(zms.vertices.len(), 8);
+
+    // Each quad in its own material group
+    let mut grouped = ZMS::from_reader(&mut Cursor::new(zms.to_bytes().unwrap())).unwrap();
+    grouped.materials = vec![2, 2];
+    grouped.weld_vertices(0.001);
+    assert_eq!(grouped.vertices.len(), 8);
+    assert_eq!(grouped.materials, vec![2, 2]);
+
+    zms.weld_vertices(0.001);
+
+    assert_eq!(zms.vertices.len(), 6);
+    assert_eq!(zms.indices.len(), 4);
+    assert_eq!(triangles(&zms), before);
+    assert_eq!(zms.bounding_box.max.x, 2.0);
+
+    // Vertices with different UVs are kept apart
+    let mut seam = quad(0.0, 0.0);
+    seam.merge(&quad(1.0, 0.0)).unwrap();
+    seam.weld_vertices(0.001);
+    assert_eq!(seam.vertices.len(), 8);
+}
+
+#[test]
+fn zms_invalid_count() {
+    let mut cursor = Cursor::new(Vec::new());
+    cursor.write_cstring("ZMS0008").unwrap();
+    cursor.write_i32(VertexFormat::Position as i32).unwrap();
+    for _ in 0..6 {
+        cursor.write_f32(0.0).unwrap();
+    }
+    cursor.write_i16(0).unwrap();
+    cursor.write_i16(i16::max_value()).unwrap();
+    cursor.write_f32(1.0).unwrap();
+
+    cursor.set_position(0);
+    let mut zms = ZMS::new();
+    let err = zms.read(&mut cursor).unwrap_err();
+
+    assert_eq!(err.downcast_ref::<RoseError>(),
+               Some(&RoseError::TruncatedFile { format: "ZMS" }));
+    assert!(zms.vertices.is_empty());
+
+    let mut cursor = Cursor::new(Vec::new());
+    cursor.write_cstring("ZMS0008").unwrap();
+    cursor.write_i32(VertexFormat::Position as i32).unwrap();
+    for _ in 0..6 {
+        cursor.write_f32(0.0).unwrap();
+    }
+    cursor.write_i16(-5).unwrap();
+
+    cursor.set_position(0);
+    let mut zms = ZMS::new();
+    match zms.read(&mut cursor).unwrap_err().downcast_ref::<RoseError>() {
+        Some(&RoseError::InvalidData { .. }) => {}
+        _ => panic!("Expected InvalidData"),
+    }
+}
+
+#[test]
+fn zms_collision() {
+    let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    root.push("tests");
+    root.push("data");
+
+    let zms = ZMS::from_path(&root.join("CART01_ABILITY01.ZMS")).unwrap();
+    assert!(!zms.is_collision());
+
+    let triangles = zms.to_triangles();
+    assert_eq!(triangles.len(), zms.indices.len());
+    let first = zms.indices[0];
+    assert_eq!(triangles[0][0], zms.vertices[first.x as usize].position);
+    assert_eq!(triangles[0][2], zms.vertices[first.z as usize].position);
+
+    let collision = zms.to_collision();
+    assert!(collision.is_collision());
+    assert!(!collision.normals_enabled());
+    assert!(!collision.uv1_enabled());
+    assert_eq!(collision.vertices.len(), zms.vertices.len());
+    assert_eq!(collision.to_triangles(), triangles);
+    assert!(collision.materials.is_empty());
+
+    // Round trips through the file format
+    let mut buffer = Cursor::new(Vec::new());
+    let mut written = zms.to_collision();
+    written.write(&mut buffer).unwrap();
+    buffer.set_position(0);
+    let mut read = ZMS::new();
+    read.read(&mut buffer).unwrap();
+    assert!(read.is_collision());
+    assert_eq!(read.to_triangles(), triangles);
+}
+
+#[test]
+fn zms_legacy_version() {
+    let positions = [(0.0, 0.0, 0.0), (100.0, 0.0, 0.0), (0.0, 200.0, 50.0)];
+
+    let mut cursor = Cursor::new(Vec::new());
+    cursor.write_cstring("ZMS0006").unwrap();
+    cursor.write_i32(VertexFormat::Position as i32 | VertexFormat::UV1 as i32).unwrap();
+    cursor.write_vector3_f32(&Vector3 { x: 0.0, y: 0.0, z: 0.0 }).unwrap();
+    cursor.write_vector3_f32(&Vector3 { x: 100.0, y: 200.0, z: 50.0 }).unwrap();
+    cursor.write_i32(0).unwrap();
+
+    cursor.write_i32(positions.len() as i32).unwrap();
+    for (i, &(x, y, z)) in positions.iter().enumerate() {
+        cursor.write_i32(i as i32).unwrap();
+        cursor.write_vector3_f32(&Vector3 { x, y, z }).unwrap();
+    }
+    for i in 0..positions.len() {
+        cursor.write_i32(i as i32).unwrap();
+        cursor.write_vector2_f32(&Vector2 { x: i as f32, y: 0.5 }).unwrap();
+    }
+
+    cursor.write_i32(1).unwrap();
+    for &n in &[0, 0, 1, 2] {
+        cursor.write_i32(n).unwrap();
+    }
+    cursor.write_i32(1).unwrap();
+    cursor.write_i32(1).unwrap();
+
+    cursor.set_position(0);
+    let mut zms = ZMS::new();
+    zms.read(&mut cursor).unwrap();
+
+    assert_eq!(zms.identifier, "ZMS0006");
+    assert_eq!(zms.vertices.len(), 3);
+    assert_eq!(zms.vertices[1].position, Vector3 { x: 100.0, y: 0.0, z: 0.0 });
+    assert_eq!(zms.vertices[2].position, Vector3 { x: 0.0, y: 200.0, z: 50.0 });
+    assert_eq!(zms.vertices[2].uv1, Vector2 { x: 2.0, y: 0.5 });
+    assert_eq!(zms.indices, vec![Vector3 { x: 0, y: 1, z: 2 }]);
+    assert_eq!(zms.materials, vec![1]);
+
+    // Legacy meshes are written as ZMS0008
+    let bytes = zms.to_bytes().unwrap();
+    let written = ZMS::from_reader(&mut Cursor::new(bytes)).unwrap();
+    assert_eq!(written.identifier, "ZMS0008");
+    assert_eq!(written.vertices, zms.vertices);
+    assert_eq!(written.indices, zms.indices);
+}
+
+#[test]
+fn zms_triangles_edges() {
+    let zms = quad(2.0, 3.0);
+
+    let cross = |a: Vector3<f32>, b: Vector3<f32>| Vector3 {
+        x: a.y * b.z - a.z * b.y,
+        y: a.z * b.x - a.x * b.z,
+        z: a.x * b.y - a.y * b.x,
+    };
+    let sub = |a: Vector3<f32>, b: Vector3<f32>| Vector3 { x: a.x - b.x, y: a.y - b.y, z: a.z - b.z };
+
+    let area: f32 = zms.triangles()
+        .map(|[a, b, c]| {
+            let n = cross(sub(b.position, a.position), sub(c.position, a.position));
+            0.5 * (n.x * n.x + n.y * n.y + n.z * n.z).sqrt()
+        })
+        .sum();
+    assert_eq!(area, 1.0);
+
+    assert_eq!(zms.edges().count(), 6);
+    let (a, b) = zms.edges().next().unwrap();
+    assert_eq!(a, &zms.vertices[0]);
+    assert_eq!(b, &zms.vertices[1]);
+
+    // The diagonal is shared by both triangles
+    let diagonal = zms.edges()
+        .filter(|&(a, b)| {
+            (a == &zms.vertices[0] && b == &zms.vertices[2]) ||
+            (a == &zms.vertices[2] && b == &zms.vertices[0])
+        })
+        .count();
+    assert_eq!(diagonal, 2);
+
+    let empty = ZMS::new();
+    assert_eq!(empty.triangles().count(), 0);
+    assert_eq!(empty.edges().count(), 0);
+}
+
+#[test]
+fn zms_load_lod() {
+    let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    root.push("tests");
+    root.push("data");
+
+    let dir = env::temp_dir().join("roselib_zms_load_lod");
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::copy(root.join("STONE014.ZMS"), dir.join("STONE014.ZMS")).unwrap();
+    fs::copy(root.join("CART01_ABILITY01.ZMS"), dir.join("STONE014_LOD1.ZMS")).unwrap();
+
+    let base = dir.join("STONE014.ZMS");
+    assert_eq!(lod_path(&base, 0), base);
+    assert_eq!(lod_path(&base, 1), dir.join("STONE014_LOD1.ZMS"));
+    assert_eq!(lod_path(&dir.join("stone014.zms"), 2), dir.join("stone014_lod2.zms"));
+
+    let stone = ZMS::from_path(&root.join("STONE014.ZMS")).unwrap();
+    let cart = ZMS::from_path(&root.join("CART01_ABILITY01.ZMS")).unwrap();
+
+    assert_eq!(load_model_lod(&base, 0).unwrap().vertices.len(), stone.vertices.len());
+    assert_eq!(load_model_lod(&base, 1).unwrap().vertices.len(), cart.vertices.len());
+    assert_eq!(load_model_lod(&base, 2).unwrap().vertices.len(), stone.vertices.len());
+    assert!(load_model_lod(&dir.join("MISSING.ZMS"), 1).is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn zms_approx_eq() {
+    let a = quad(0.0, 0.0);
+    let mut b = quad(0.0, 0.0);
+    b.vertices[2].position.x += 1e-7;
+    b.vertices[2].uv1.y -= 1e-7;
+
+    assert!(a != b);
+    assert!(a.approx_eq(&b, 1e-6));
+    assert!(!a.approx_eq(&b, 0.0));
+
+    // Integer fields are compared exactly
+    let mut c = quad(0.0, 0.0);
+    c.indices[0].z = 3;
+    assert!(!a.approx_eq(&c, 1.0));
+
+    let mut d = quad(0.0, 0.0);
+    d.vertices.pop();
+    assert!(!a.approx_eq(&d, 1.0));
+}
+
+#[test]
+fn zms_write_version() {
+    let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    root.push("tests");
+    root.push("data");
+
+    let mut zms = ZMS::from_path(&root.join("CART01_ABILITY01.ZMS")).unwrap();
+    zms.pool = 2;
+
+    let mut v8 = Cursor::new(Vec::new());
+    zms.write_version(&mut v8, 8).unwrap();
+    let mut v7 = Cursor::new(Vec::new());
+    zms.write_version(&mut v7, 7).unwrap();
+
+    // Same data without the trailing pool
+    let (v7, v8) = (v7.into_inner(), v8.into_inner());
+    assert_eq!(v7.len() + 2, v8.len());
+    assert_eq!(&v8[..8], b"ZMS0008\0");
+    assert_eq!(&v7[..8], b"ZMS0007\0");
+
+    let read = ZMS::from_reader(&mut Cursor::new(v7)).unwrap();
+    assert_eq!(read.identifier, "ZMS0007");
+    assert_eq!(read.pool, 0);
+    assert_eq!(read.vertices, zms.vertices);
+    assert_eq!(read.indices, zms.indices);
+
+    let err = zms.write_version(&mut Cursor::new(Vec::new()), 6).unwrap_err();
+    assert_eq!(err.downcast_ref::<RoseError>(),
+               Some(&RoseError::UnsupportedVersion {
+                   format: "ZMS",
+                   found: String::from("ZMS0006"),
+               }));
+}
+
+#[test]
+fn zms_json_round_trip() {
+    let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    root.push("tests");
+    root.push("data");
+
+    let zms = ZMS::from_path(&root.join("HEADBAD01.ZMS")).unwrap();
+    let json = zms.to_json_bytes().unwrap();
+    let zms2 = ZMS::from_json(&mut Cursor::new(json)).unwrap();
+    assert_eq!(zms, zms2);
+
+    // Edited JSON is written back as binary
+    let json = String::from_utf8(quad(0.0, 0.0).to_json_bytes().unwrap()).unwrap();
+    let json = json.replace("\"ZMS0008\"", "\"ZMS0007\"");
+    let mut zms3 = ZMS::from_json(&mut Cursor::new(json.into_bytes())).unwrap();
+    assert_eq!(zms3.identifier, "ZMS0007");
+
+    let bytes = zms3.to_bytes().unwrap();
+    let zms4 = ZMS::from_reader(&mut Cursor::new(bytes)).unwrap();
+    assert_eq!(zms4.vertices, quad(0.0, 0.0).vertices);
+}
+
+#[test]
+fn zms_vertex_color_rgba8() {
+    let mut v = Vertex::new();
+    v.color = Color4 { r: 1.0, g: 0.5, b: 0.0, a: 1.0 };
+    assert_eq!(v.color_rgba8(), [255, 128, 0, 255]);
+
+    // Out of range components are clamped
+    v.color = Color4 { r: 2.0, g: -1.0, b: 0.25, a: 1.5 };
+    assert_eq!(v.color_rgba8(), [255, 0, 64, 255]);
+
+    v.set_color_rgba8([255, 128, 0, 64]);
+    assert!(v.color.approx_eq(&Color4 { r: 1.0, g: 0.5, b: 0.0, a: 0.25 }, 1.0 / 255.0));
+    assert_eq!(v.color_rgba8(), [255, 128, 0, 64]);
+}
+
+#[test]
+fn zms_transform() {
+    let orig = quad(0.0, 0.0);
+
+    let mut zms = quad(0.0, 0.0);
+    zms.transform(matrix::translate(Vector3 { x: 10.0, y: -5.0, z: 2.0 }));
+    for (v, o) in zms.vertices.iter().zip(orig.vertices.iter()) {
+        assert!(v.position.approx_eq(&Vector3 {
+                                          x: o.position.x + 10.0,
+                                          y: o.position.y - 5.0,
+                                          z: o.position.z + 2.0,
+                                      },
+                                      1e-6));
+        assert_eq!(v.uv1, o.uv1);
+    }
+
+    // The shape is preserved
+    let edges: Vec<f32> = zms.edges()
+        .map(|(a, b)| Vector3 {
+                x: a.position.x - b.position.x,
+                y: a.position.y - b.position.y,
+                z: a.position.z - b.position.z,
+            })
+        .map(|d| (d.x * d.x + d.y * d.y + d.z * d.z).sqrt())
+        .collect();
+    let orig_edges: Vec<f32> = orig.edges()
+        .map(|(a, b)| Vector3 {
+                x: a.position.x - b.position.x,
+                y: a.position.y - b.position.y,
+                z: a.position.z - b.position.z,
+            })
+        .map(|d| (d.x * d.x + d.y * d.y + d.z * d.z).sqrt())
+        .collect();
+    assert!(edges.approx_eq(&orig_edges, 1e-5));
+    assert!(zms.bounding_box.min.approx_eq(&Vector3 { x: 10.0, y: -5.0, z: 2.0 }, 1e-6));
+    assert!(zms.bounding_box.max.approx_eq(&Vector3 { x: 11.0, y: -4.0, z: 2.0 }, 1e-6));
+
+    // Rotating a quarter turn around Y
+    let mut zms = quad(0.0, 0.0);
+    zms.transform(matrix::rotate_y(std::f32::consts::FRAC_PI_2));
+    assert!(zms.vertices[1].position.approx_eq(&Vector3 { x: 0.0, y: 0.0, z: -1.0 }, 1e-6));
+
+    // Non-uniform scale keeps normals perpendicular to the surface
+    let mut zms = ZMS::new();
+    zms.format = VertexFormat::Position as i32 | VertexFormat::Normal as i32;
+    let normal = Vector3 { x: 1.0, y: 1.0, z: 0.0 }.normalize();
+    for p in &[(1.0, 0.0, 0.0), (0.0, 1.0, 0.0), (1.0, 0.0, 1.0)] {
+        let mut v = Vertex::new();
+        v.position = Vector3 { x: p.0, y: p.1, z: p.2 };
+        v.normal = normal;
+        zms.vertices.push(v);
+    }
+    zms.indices.push(Vector3 { x: 0, y: 1, z: 2 });
+
+    let m = matrix::multiply(&matrix::translate(Vector3 { x: 1.0, y: 2.0, z: 3.0 }),
+                             &matrix::scale(Vector3 { x: 4.0, y: 1.0, z: 1.0 }));
+    zms.transform(m);
+    let p: Vec<Vector3<f32>> = zms.vertices.iter().map(|v| v.position).collect();
+    let n = zms.vertices[0].normal;
+    let dot = |a: Vector3<f32>, b: Vector3<f32>| a.x * b.x + a.y * b.y + a.z * b.z;
+    let edge1 = Vector3 { x: p[1].x - p[0].x, y: p[1].y - p[0].y, z: p[1].z - p[0].z };
+    let edge2 = Vector3 { x: p[2].x - p[0].x, y: p[2].y - p[0].y, z: p[2].z - p[0].z };
+    assert!(dot(n, edge1).abs() < 1e-5);
+    assert!(dot(n, edge2).abs() < 1e-5);
+    assert!((dot(n, n) - 1.0).abs() < 1e-5);
+}
+
+#[test]
+fn zms_bone_weight_order() {
+    let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    root.push("tests");
+    root.push("data");
+
+    // The first vertex of the file stores the weights
+    // [0.98843414, 0.011565873, 0.0, 0.0] and the indices [0, 1, 0, 0]
+    let mut zms = ZMS::from_path(&root.join("HEADBAD01.ZMS")).unwrap();
+    assert!(zms.bones_enabled());
+
+    let v = &zms.vertices[0];
+    assert_eq!(v.bone_weights.x, 0.98843414);
+    assert_eq!(v.bone_weights.y, 0.011565873);
+    assert_eq!(v.bone_weights.z, 0.0);
+    assert_eq!(v.bone_weights.w, 0.0);
+    assert_eq!(v.bone_indices, Vector4 { x: 0, y: 1, z: 0, w: 0 });
+
+    // Written back in the same order
+    let bytes = zms.to_bytes().unwrap();
+    let zms2 = ZMS::from_reader(&mut Cursor::new(bytes)).unwrap();
+    assert_eq!(zms2.vertices[0].bone_weights, zms.vertices[0].bone_weights);
+    assert_eq!(zms2.vertices[0].bone_indices, zms.vertices[0].bone_indices);
+}
+
+#[test]
+fn zms_stats() {
+    let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    root.push("tests");
+    root.push("data");
+
+    let zms = ZMS::from_path(&root.join("HEADBAD01.ZMS")).unwrap();
+    let stats = zms.stats();
+    assert_eq!(stats.vertex_count, 336);
+    assert_eq!(stats.triangle_count, 578);
+    assert_eq!(stats.material_count, zms.materials.len());
+    assert_eq!(stats.unreferenced_vertices, 0);
+    assert_eq!(stats.bad_weight_vertices, 0);
+    let (min, max) = stats.bone_weight_sum_range.unwrap();
+    assert!(min.approx_eq(&1.0, BONE_WEIGHT_EPSILON));
+    assert!(max.approx_eq(&1.0, BONE_WEIGHT_EPSILON));
+
+    // Authoring errors are reported
+    let mut zms = quad(0.0, 0.0);
+    zms.vertices.push(Vertex::new());
+    let stats = zms.stats();
+    assert_eq!(stats.triangle_count, 2);
+    assert_eq!(stats.material_count, 1);
+    assert_eq!(stats.unreferenced_vertices, 1);
+    assert_eq!(stats.bone_weight_sum_range, None);
+
+    zms.format |= VertexFormat::BoneWeight as i32 | VertexFormat::BoneIndex as i32;
+    for v in &mut zms.vertices {
+        v.bone_weights.x = 1.0;
+    }
+    zms.vertices[0].bone_weights.y = 0.5;
+    let stats = zms.stats();
+    assert_eq!(stats.bad_weight_vertices, 1);
+    assert_eq!(stats.bone_weight_sum_range, Some((1.0, 1.5)));
+}
+
+#[test]
+fn zms_uv_channels() {
+    let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    root.push("tests");
+    root.push("data");
+
+    let original = ZMS::from_path(&root.join("STONE014.ZMS")).unwrap();
+    assert!(original.uv1_enabled() && original.uv2_enabled());
+
+    let mut zms = ZMS::from_path(&root.join("STONE014.ZMS")).unwrap();
+    zms.keep_uv_channels(&[1, 3]).unwrap();
+    assert!(zms.uv1_enabled());
+    assert!(!zms.uv2_enabled());
+    assert!(!zms.uv3_enabled());
+    assert_eq!(zms.vertices[0].uv1, original.vertices[0].uv1);
+    assert!(zms.vertices.iter().all(|v| v.uv2.x == 0.0 && v.uv2.y == 0.0));
+
+    // The dropped channel is not written
+    let bytes = zms.to_bytes().unwrap();
+    let zms = ZMS::from_reader(&mut Cursor::new(bytes)).unwrap();
+    assert!(!zms.uv2_enabled());
+    assert_eq!(zms.vertices.len(), original.vertices.len());
+
+    // Promote uv2 into uv1
+    let mut zms = ZMS::from_path(&root.join("STONE014.ZMS")).unwrap();
+    zms.move_uv(2, 1).unwrap();
+    assert!(zms.uv1_enabled());
+    assert!(!zms.uv2_enabled());
+    assert_eq!(zms.vertices[0].uv1, original.vertices[0].uv2);
+
+    assert!(zms.move_uv(2, 1).is_err());
+    assert!(zms.move_uv(1, 5).is_err());
+    assert!(zms.keep_uv_channels(&[0]).is_err());
+    assert!(zms.uv1_enabled());
+}
+
+#[test]
+fn zms_repair() {
+    let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    root.push("tests");
+    root.push("data");
+
+    // Consistent meshes are left unchanged
+    let mut zms = ZMS::from_path(&root.join("HEADBAD01.ZMS")).unwrap();
+    assert!(!zms.repair());
+    assert_eq!(zms, ZMS::from_path(&root.join("HEADBAD01.ZMS")).unwrap());
+
+    let mut zms = quad(0.0, 0.0);
+    assert!(zms.materials.is_empty());
+    assert!(zms.repair());
+    assert_eq!(zms.materials, vec![2]);
+    assert_eq!(zms.stats().material_count, 1);
+    assert!(!zms.repair());
+
+    zms.materials = vec![1, 5];
+    zms.strips = vec![0, 1, 7];
+    assert!(zms.repair());
+    assert_eq!(zms.materials, vec![2]);
+    assert!(zms.strips.iter().all(|i| (0..4).contains(i)));
+    assert_eq!(strip_to_triangles(&zms.strips).len(), 2);
+}
+
+#[test]
+fn zms_split_by_material() {
+    let mut zms = quad(0.0, 0.0);
+    zms.materials.push(2);
+    let mut other = quad(5.0, 0.0);
+    other.materials.push(2);
+    zms.merge(&other).unwrap();
+    assert_eq!(zms.materials, vec![2, 2]);
+
+    let meshes = zms.split_by_material();
+    assert_eq!(meshes.len(), 2);
+    let triangles: usize = meshes.iter().map(|m| m.indices.len()).sum();
+    assert_eq!(triangles, zms.indices.len());
+
+    for (i, mesh) in meshes.iter().enumerate() {
+        assert_eq!(mesh.format, zms.format);
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.materials, vec![2]);
+        assert!(mesh.indices.iter().all(|t| t.x < 4 && t.y < 4 && t.z < 4));
+        assert_eq!(mesh.bounding_box.min.x, i as f32 * 5.0);
+        assert_eq!(mesh.bounding_box.max.x, i as f32 * 5.0 + 1.0);
+    }
+    assert_eq!(meshes[1].to_triangles(), zms.to_triangles()[2..].to_vec());
+
+    // Meshes without materials are returned whole
+    let zms = quad(0.0, 0.0);
+    let meshes = zms.split_by_material();
+    assert_eq!(meshes.len(), 1);
+    assert_eq!(meshes[0], zms);
+
+    let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    root.push("tests");
+    root.push("data");
+    let zms = ZMS::from_path(&root.join("HEADBAD01.ZMS")).unwrap();
+    let meshes = zms.split_by_material();
+    assert_eq!(meshes.len(), zms.materials.len());
+    let triangles: usize = meshes.iter().map(|m| m.indices.len()).sum();
+    assert_eq!(triangles, zms.indices.len());
+}
+
+#[test]
+fn zms_global_bone_index() {
+    let mut zms = quad(0.0, 0.0);
+    zms.format |= VertexFormat::BoneWeight as i32 | VertexFormat::BoneIndex as i32;
+    zms.bones = vec![4, 7, 2];
+    zms.vertices[0].bone_indices = Vector4 { x: 1, y: 2, z: 0, w: 0 };
+
+    let v = &zms.vertices[0];
+    assert_eq!(zms.global_bone_index(v.bone_indices.x), Some(7));
+    assert_eq!(zms.global_bone_index(v.bone_indices.y), Some(2));
+    assert_eq!(zms.global_bone_index(v.bone_indices.z), Some(4));
+    assert_eq!(zms.global_bone_index(3), None);
+    assert_eq!(zms.global_bone_index(-1), None);
+}
+
+#[test]
+fn zms_optimize_vertex_cache() {
+    // Triangle positions of each material, sorted so triangles compare as sets
+    fn triangle_sets(zms: &ZMS) -> Vec<Vec<Vec<[u32; 3]>>> {
+        let mut start = 0;
+        zms.materials.iter().map(|&count| {
+            let end = start + count as usize;
+            let mut triangles: Vec<Vec<[u32; 3]>> = zms.indices[start..end].iter().map(|t| {
+                [t.x, t.y, t.z].iter().map(|&i| {
+                    let p = zms.vertices[i as usize].position;
+                    [p.x.to_bits(), p.y.to_bits(), p.z.to_bits()]
+                }).collect()
+            }).collect();
+            triangles.sort();
+            start = end;
+            triangles
+        }).collect()
+    }
+
+    // Vertex cache misses with a 16 entry LRU cache
+    fn cache_misses(zms: &ZMS) -> usize {
+        let mut cache: Vec<i16> = Vec::new();
+        let mut misses = 0;
+        for t in &zms.indices {
+            for &i in [t.x, t.y, t.z].iter() {
+                if !cache.contains(&i) {
+                    misses += 1;
+                }
+                cache.retain(|&c| c != i);
+                cache.insert(0, i);
+                cache.truncate(16);
+            }
+        }
+        misses
+    }
+
+    let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    root.push("tests");
+    root.push("data");
+
+    let original = ZMS::from_path(&root.join("HEADBAD01.ZMS")).unwrap();
+    let mut zms = ZMS::from_path(&root.join("HEADBAD01.ZMS")).unwrap();
+    zms.optimize_vertex_cache();
+    assert_eq!(zms.vertices.len(), original.vertices.len());
+    assert_eq!(zms.materials, original.materials);
+    assert_eq!(triangle_sets(&zms), triangle_sets(&original));
+
+    // Vertices are referenced in ascending order
+    let mut next = 0;
+    for t in &zms.indices {
+        for &i in [t.x, t.y, t.z].iter() {
+            assert!(i <= next);
+            if i == next {
+                next += 1;
+            }
         }
+    }
 
-        assert_eq!(orig_zms, new_zms);
+    // A scrambled 16x16 grid is reordered into a cache friendly order
+    let mut grid = ZMS::new();
+    grid.format = VertexFormat::Position as i32;
+    for y in 0..17 {
+        for x in 0..17 {
+            let mut v = Vertex::new();
+            v.position = Vector3 { x: x as f32, y: y as f32, z: 0.0 };
+            grid.vertices.push(v);
+        }
+    }
+    let mut triangles = Vec::new();
+    for y in 0..16 {
+        for x in 0..16 {
+            let i = y * 17 + x;
+            triangles.push(Vector3 { x: i, y: i + 1, z: i + 18 });
+            triangles.push(Vector3 { x: i, y: i + 18, z: i + 17 });
+        }
     }
+    for k in 0..triangles.len() {
+        grid.indices.push(triangles[(k * 97) % triangles.len()]);
+    }
+    grid.materials.push(grid.indices.len() as i16);
+
+    let scrambled = grid.indices.clone();
+    let misses = cache_misses(&grid);
+    let expected = triangle_sets(&grid);
+    grid.optimize_vertex_cache();
+    assert_eq!(triangle_sets(&grid), expected);
+    assert!(cache_misses(&grid) * 2 < misses);
+    assert_ne!(grid.indices, scrambled);
+}
+
+#[test]
+fn zms_normalize_bone_weights() {
+    let mut zms = quad(0.0, 0.0);
+    zms.vertices[0].bone_weights = Vector4 { x: 0.65, y: 0.39, z: 0.26, w: 0.0 };
+    zms.vertices[1].bone_weights = Vector4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 };
+
+    // Bones are not enabled
+    zms.normalize_bone_weights();
+    assert_eq!(zms.vertices[0].bone_weights.x, 0.65);
+
+    zms.format |= VertexFormat::BoneWeight as i32 | VertexFormat::BoneIndex as i32;
+    zms.normalize_bone_weights();
+
+    let w = zms.vertices[0].bone_weights;
+    assert!((w.x + w.y + w.z + w.w - 1.0).abs() < 1e-6);
+    assert!((w.x - 0.5).abs() < 1e-6);
+    assert!((w.y - 0.3).abs() < 1e-6);
+    assert!((w.z - 0.2).abs() < 1e-6);
+    assert_eq!(zms.vertices[1].bone_weights, Vector4 { x: 0.0, y: 0.0, z: 0.0, w: 0.0 });
+    assert_eq!(zms.stats().bad_weight_vertices, 3);
+}
+
+#[test]
+fn zms_vertex_attributes() {
+    // Single UV channel
+    let mut zms = quad(0.0, 0.0);
+    assert_eq!(zms.uv1(2), Some(Vector2 { x: 1.0, y: 1.0 }));
+    assert_eq!(zms.uv2(2), None);
+    assert_eq!(zms.uv1(4), None);
+    assert_eq!(zms.color(0), None);
+    assert_eq!(zms.tangent(0), None);
+    assert_eq!(zms.bone_indices(0), None);
+
+    // Two UV channels
+    zms.format |= VertexFormat::UV2 as i32;
+    zms.vertices[2].uv2 = Vector2 { x: 0.5, y: 0.25 };
+    assert_eq!(zms.uv2(2), Some(Vector2 { x: 0.5, y: 0.25 }));
+
+    let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    root.push("tests");
+    root.push("data");
+    let zms = ZMS::from_path(&root.join("HEADBAD01.ZMS")).unwrap();
+    assert_eq!(zms.bone_indices(0), Some(Vector4 { x: 0, y: 1, z: 0, w: 0 }));
+    assert_eq!(zms.bone_weights(0).map(|w| w.x), Some(0.98843414));
 }
 
+#[test]
+fn zms_read_positions_only() {
+    let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    root.push("tests");
+    root.push("data");
+
+    for name in &["STONE014.ZMS", "HEADBAD01.ZMS"] {
+        let zms = ZMS::from_path(&root.join(name)).unwrap();
+        let expected: Vec<Vector3<f32>> = zms.vertices.iter().map(|v| v.position).collect();
+
+        let bytes = fs::read(root.join(name)).unwrap();
+        let positions = ZMS::read_positions_only(&mut Cursor::new(bytes)).unwrap();
+        assert_eq!(positions, expected);
+    }
+
+    // Legacy meshes index each attribute and store 4 byte bones
+    let mut cursor = Cursor::new(Vec::new());
+    cursor.write_cstring("ZMS0005").unwrap();
+    cursor.write_i32(VertexFormat::Position as i32).unwrap();
+    cursor.write_vector3_f32(&Vector3 { x: 0.0, y: 0.0, z: 0.0 }).unwrap();
+    cursor.write_vector3_f32(&Vector3 { x: 1.0, y: 2.0, z: 3.0 }).unwrap();
+    cursor.write_i32(2).unwrap();
+    cursor.write_i32(4).unwrap();
+    cursor.write_i32(9).unwrap();
+    cursor.write_i32(2).unwrap();
+    for &(i, x) in &[(0, 0.0), (1, 1.0)] {
+        cursor.write_i32(i).unwrap();
+        cursor.write_vector3_f32(&Vector3 { x, y: 2.0, z: 3.0 }).unwrap();
+    }
+
+    cursor.set_position(0);
+    assert_eq!(ZMS::read_positions_only(&mut cursor).unwrap(),
+               vec![Vector3 { x: 0.0, y: 2.0, z: 3.0 }, Vector3 { x: 1.0, y: 2.0, z: 3.0 }]);
+}
+
+#[test]
+fn zms_vertex_pool() {
+    let mut zms = ZMS::new();
+    let pools = [(0, VertexPool::Static), (1, VertexPool::Dynamic), (2, VertexPool::System)];
+    for &(value, pool) in pools.iter() {
+        zms.pool = value;
+        assert_eq!(zms.pool_kind(), pool);
+        assert_eq!(i16::from(pool), value);
+    }
+
+    zms.pool = 7;
+    assert_eq!(zms.pool_kind(), VertexPool::Static);
+    assert_eq!(VertexPool::from(-1), VertexPool::Static);
+}
+
+#[test]
+fn zms_validate() {
+    let mut zms = quad(0.0, 0.0);
+    assert!(zms.validate().is_empty());
+
+    zms.vertices[1].position.y = f32::NAN;
+    zms.strips = vec![0, 1, 9];
+    zms.materials = vec![1];
+    zms.format |= VertexFormat::BoneWeight as i32 | VertexFormat::BoneIndex as i32;
+    for v in &mut zms.vertices {
+        v.bone_weights.x = 1.0;
+    }
+    zms.vertices[2].bone_weights.y = 0.3;
+
+    assert_eq!(zms.validate(),
+               vec![String::from("1 strip indices out of range (4 vertices)"),
+                    String::from("1 vertex positions are not finite"),
+                    String::from("1 vertices have bone weights not summing to 1.0"),
+                    String::from("Materials cover 1 triangles of 2")]);
+}
+
+#[test]
+fn zms_interleaved_buffer() {
+    let zms = quad(2.0, 3.0);
+    let buffer = zms.interleaved_buffer(&[VertexFormat::Position, VertexFormat::UV1]).unwrap();
+
+    let stride = 20;
+    assert_eq!(buffer.len(), stride * zms.vertices.len());
+
+    let mut first = Vec::new();
+    for &value in &[2.0f32, 3.0, 0.0, 0.0, 0.0] {
+        first.extend_from_slice(&value.to_le_bytes());
+    }
+    assert_eq!(&buffer[..stride], &first[..]);
+
+    // The second vertex has its UV in front of its position
+    let buffer = zms.interleaved_buffer(&[VertexFormat::UV1, VertexFormat::Position]).unwrap();
+    assert_eq!(&buffer[stride..stride + 4], &1.0f32.to_le_bytes());
+    assert_eq!(&buffer[stride + 8..stride + 12], &3.0f32.to_le_bytes());
+
+    assert!(zms.interleaved_buffer(&[VertexFormat::Position, VertexFormat::Normal]).is_err());
+
+    assert_eq!(zms.index_buffer(), vec![0, 0, 1, 0, 2, 0, 0, 0, 2, 0, 3, 0]);
+}
+
+#[test]
+fn zms_truncated_offset() {
+    let mut bytes = quad(0.0, 0.0).to_bytes().unwrap();
+    let size = bytes.len();
+    bytes.truncate(size - 1);
+
+    // The pool is the last value of the file
+    let err = ZMS::from_reader(&mut Cursor::new(bytes)).unwrap_err();
+    let offset = (size - 2) as u64;
+    assert_eq!(err.downcast_ref::<RoseError>(), Some(&RoseError::UnexpectedEof { offset }));
+    assert!(err.to_string().contains(&format!("offset {:#X}", offset)));
+}
+
+#[test]
+fn zms_write_too_many_vertices() {
+    let mut zms = quad(0.0, 0.0);
+    zms.vertices.resize(i16::MAX as usize, Vertex::new());
+    assert!(zms.to_bytes().is_ok());
+
+    zms.vertices.push(Vertex::new());
+    let err = zms.to_bytes().unwrap_err();
+    assert_eq!(err.to_string(), "ZMS has too many vertices to be written: 32768 (at most 32767)");
+
+    // Nothing is written
+    let mut cursor = Cursor::new(Vec::new());
+    assert!(zms.write(&mut cursor).is_err());
+    assert!(cursor.into_inner().is_empty());
+}
+
+#[test]
+fn zms_flip_winding() {
+    let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    root.push("tests");
+    root.push("data");
+
+    let original = ZMS::from_path(&root.join("STONE014.ZMS")).unwrap();
+    assert!(original.normals_enabled());
+
+    let mut zms = ZMS::from_path(&root.join("STONE014.ZMS")).unwrap();
+    zms.flip_winding(true);
+    let (a, b) = (original.indices[0], zms.indices[0]);
+    assert_eq!((b.x, b.y, b.z), (a.x, a.z, a.y));
+    assert_eq!(zms.vertices[0].normal.x, -original.vertices[0].normal.x);
+
+    zms.flip_winding(true);
+    assert_eq!(zms.indices, original.indices);
+    assert_eq!(zms.vertices, original.vertices);
+
+    // Normals are kept unless asked
+    zms.flip_winding(false);
+    assert_eq!(zms.vertices, original.vertices);
+}
+
+#[test]
+fn zms_flip_uv_v() {
+    let mut zms = quad(0.0, 0.0);
+    zms.vertices[1].uv1.y = 0.25;
+    zms.flip_uv_v(1).unwrap();
+
+    let v: Vec<f32> = zms.vertices.iter().map(|v| v.uv1.y).collect();
+    assert_eq!(v, vec![1.0, 0.75, 0.0, 0.0]);
+    assert_eq!(zms.vertices[1].uv1.x, 1.0);
+
+    assert!(zms.flip_uv_v(2).is_err());
+    assert!(zms.flip_uv_v(5).is_err());
+}
+
+#[test]
+fn zms_bounding_sphere() {
+    let mut zms = ZMS::new();
+    assert_eq!(zms.bounding_sphere(), (Vector3 { x: 0.0, y: 0.0, z: 0.0 }, 0.0));
+
+    // Unit cube
+    for i in 0..8 {
+        let mut v = Vertex::new();
+        v.position = Vector3 { x: (i & 1) as f32, y: (i >> 1 & 1) as f32, z: (i >> 2) as f32 };
+        zms.vertices.push(v);
+    }
+
+    let (center, radius) = zms.bounding_sphere();
+    assert!(center.approx_eq(&Vector3 { x: 0.5, y: 0.5, z: 0.5 }, 1e-5));
+    assert!((radius - 3f32.sqrt() / 2.0).abs() < 1e-3);
+}
+
+#[test]
+fn zms_eq_ignoring_version() {
+    let a = quad(0.0, 0.0);
+    let mut b = quad(0.0, 0.0);
+    b.identifier = String::from("ZMS0007");
+    assert!(a.eq_ignoring_version(&b));
+    assert_ne!(a, b);
+
+    b.vertices[0].uv1.x = 0.5;
+    assert!(!a.eq_ignoring_version(&b));
+}
+
+#[test]
+fn zms_uses_strips() {
+    let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    root.push("tests");
+    root.push("data");
+
+    // None of the test models have a strip, build one from the triangles
+    let mut zms = ZMS::from_path(&root.join("STONE014.ZMS")).unwrap();
+    assert!(!zms.uses_strips());
+    assert_eq!(zms.draw_triangles(), zms.indices);
+
+    zms.strips = stitch_strips(&zms.to_triangle_strips());
+    assert!(zms.uses_strips());
+
+    // Same triangles and windings, starting from their lowest index
+    fn sorted(triangles: &[Vector3<i16>]) -> Vec<[i16; 3]> {
+        let mut sorted: Vec<[i16; 3]> = triangles.iter()
+            .map(|t| {
+                let mut t = [t.x, t.y, t.z];
+                let min = (0..3).min_by_key(|&i| t[i]).unwrap();
+                t.rotate_left(min);
+                t
+            })
+            .collect();
+        sorted.sort();
+        sorted
+    }
+    assert_eq!(sorted(&zms.draw_triangles()), sorted(&zms.indices));
+
+    let mut obj = Vec::new();
+    export::write_obj(&zms, &mut obj, &Transform::default()).unwrap();
+    let faces = String::from_utf8(obj).unwrap().lines().filter(|l| l.starts_with("f ")).count();
+    assert_eq!(faces, zms.indices.len());
+}
+
+#[test]
+fn zms_simplify() {
+    // A 21x21 vertex grid bent into a gentle dome, 800 triangles
+    let size = 21;
+    let mut zms = ZMS::new();
+    zms.format = VertexFormat::Position as i32 | VertexFormat::Normal as i32
+        | VertexFormat::UV1 as i32;
+    for y in 0..size {
+        for x in 0..size {
+            let (u, v) = (x as f32 / (size - 1) as f32, y as f32 / (size - 1) as f32);
+            let mut vertex = Vertex::new();
+            vertex.position = Vector3 {
+                x: u * 10.0,
+                y: v * 10.0,
+                z: (u * (1.0 - u) + v * (1.0 - v)) * 2.0,
+            };
+            vertex.normal = Vector3 { x: 0.0, y: 0.0, z: 1.0 };
+            vertex.uv1 = Vector2 { x: u, y: v };
+            zms.vertices.push(vertex);
+        }
+    }
+    for y in 0..size - 1 {
+        for x in 0..size - 1 {
+            let i = (y * size + x) as i16;
+            let s = size as i16;
+            zms.indices.push(Vector3 { x: i, y: i + 1, z: i + s + 1 });
+            zms.indices.push(Vector3 { x: i, y: i + s + 1, z: i + s });
+        }
+    }
+    zms.materials = vec![800];
+    zms.recalculate_bounding_box();
+    let original = zms.bounding_box;
+
+    zms.simplify(0.5);
+    let triangles = zms.indices.len();
+    assert!((360..=400).contains(&triangles), "{} triangles", triangles);
+    assert_eq!(zms.materials, vec![triangles as i16]);
+    assert!(zms.vertices.len() < 441);
+    assert_eq!(zms.validate(), Vec::<String>::new());
+
+    // The border doesn't move and the dome keeps most of its height
+    assert!(zms.bounding_box.min.approx_eq(&original.min, 1e-5));
+    assert!((zms.bounding_box.max.x - original.max.x).abs() < 1e-5);
+    assert!((zms.bounding_box.max.y - original.max.y).abs() < 1e-5);
+    assert!(zms.bounding_box.max.z > original.max.z * 0.8);
+
+    // Every triangle still faces up
+    for t in zms.triangles() {
+        let (a, b, c) = (t[0].position, t[1].position, t[2].position);
+        let normal_z = (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x);
+        assert!(normal_z > 0.0);
+    }
+}
@@ -1,6 +1,10 @@
+#[macro_use]
 extern crate roselib;
 
+use std::fs::File;
+use std::io::{Cursor, Read};
 use std::path::PathBuf;
+use roselib::errors::RoseError;
 use roselib::files::ZON;
 use roselib::files::zon::*;
 use roselib::io::RoseFile;
@@ -40,3 +44,48 @@ fn read_zon() {
     assert_eq!(zon.background_music, "button1");
     assert_eq!(zon.sky, "button2");
 }
+
+#[test]
+fn write_zon() {
+    let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    root.push("tests");
+    root.push("data");
+
+    let mut original = Vec::new();
+    File::open(root.join("JGT01.ZON")).unwrap().read_to_end(&mut original).unwrap();
+
+    let mut zon = ZON::from_reader(&mut Cursor::new(&original)).unwrap();
+    assert_eq!(zon.tiles[0].rotation, ZoneTileRotation::None);
+    assert_bytes_eq!(zon.to_bytes().unwrap(), original);
+}
+
+#[test]
+fn read_zon_invalid_enum() {
+    let mut root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    root.push("tests");
+    root.push("data");
+
+    let mut original = Vec::new();
+    File::open(root.join("JGT01.ZON")).unwrap().read_to_end(&mut original).unwrap();
+    let block_offset = |block_type: i32| -> usize {
+        let count = i32::from_le_bytes([original[0], original[1], original[2], original[3]]);
+        (0..count as usize)
+            .map(|i| 4 + i * 8)
+            .find(|&o| original[o..o + 4] == block_type.to_le_bytes())
+            .map(|o| u32::from_le_bytes([original[o + 4], original[o + 5], original[o + 6], original[o + 7]]))
+            .unwrap() as usize
+    };
+
+    // Zone type of the basic info and rotation of the first tile
+    let zone_type = block_offset(ZoneBlockType::BasicInfo as i32);
+    let rotation = block_offset(ZoneBlockType::Tiles as i32) + 4 + 20;
+    for &offset in [zone_type, rotation].iter() {
+        let mut data = original.clone();
+        data[offset..offset + 4].copy_from_slice(&99i32.to_le_bytes());
+        let err = ZON::from_reader(&mut Cursor::new(data)).unwrap_err();
+        match err.downcast_ref::<RoseError>() {
+            Some(&RoseError::InvalidData { format: "ZON", .. }) => {}
+            e => panic!("unexpected error {:?}", e),
+        }
+    }
+}
@@ -0,0 +1,148 @@
+#[macro_use]
+extern crate roselib;
+
+use std::io::{Cursor, Write};
+
+use roselib::errors::RoseError;
+use roselib::files::ZSC;
+use roselib::io::{RoseFile, WriteRoseExt};
+
+fn write_material(cursor: &mut Cursor<Vec<u8>>, path: &str, alpha_enabled: u16) {
+    cursor.write_cstring(path).unwrap();
+    for &n in [0, alpha_enabled, 0, 0, 128, 1, 1, 0, 0].iter() {
+        cursor.write_u16(n).unwrap();
+    }
+    cursor.write_f32(1.0).unwrap();
+    cursor.write_u16(0).unwrap();
+    for _ in 0..3 {
+        cursor.write_f32(0.0).unwrap();
+    }
+}
+
+/// Build a small model list by hand
+fn cart_zsc() -> Vec<u8> {
+    let mut cursor = Cursor::new(Vec::new());
+    cursor.write_u16(2).unwrap();
+    cursor.write_cstring("3DDATA\\PAT\\CART\\BODY.ZMS").unwrap();
+    cursor.write_cstring("3DDATA\\PAT\\CART\\WHEEL.ZMS").unwrap();
+
+    cursor.write_u16(2).unwrap();
+    write_material(&mut cursor, "3DDATA\\PAT\\CART\\BODY.DDS", 0);
+    write_material(&mut cursor, "3DDATA\\PAT\\CART\\WHEEL.DDS", 1);
+
+    cursor.write_u16(1).unwrap();
+    cursor.write_cstring("3DDATA\\EFFECT\\SMOKE.EFT").unwrap();
+
+    cursor.write_u16(2).unwrap();
+
+    // Cart: a body and two wheels sharing a mesh and material
+    cursor.write_i32(150).unwrap();
+    cursor.write_i32(0).unwrap();
+    cursor.write_i32(0).unwrap();
+    cursor.write_u16(3).unwrap();
+
+    cursor.write_u16(0).unwrap();
+    cursor.write_u16(0).unwrap();
+    cursor.write_u8(1).unwrap();
+    cursor.write_u8(12).unwrap();
+    for &n in [0.0, 0.0, 50.0].iter() {
+        cursor.write_f32(n).unwrap();
+    }
+    cursor.write_u8(0).unwrap();
+
+    for &parent in [1, 1].iter() {
+        cursor.write_u16(1).unwrap();
+        cursor.write_u16(1).unwrap();
+        cursor.write_u8(7).unwrap();
+        cursor.write_u8(2).unwrap();
+        cursor.write_u16(parent).unwrap();
+        cursor.write_u8(30).unwrap();
+        cursor.write_string_u8("3DDATA\\MOTION\\WHEEL.ZMO").unwrap();
+        cursor.write_u8(99).unwrap();
+        cursor.write_u8(3).unwrap();
+        cursor.write_all(&[1, 2, 3]).unwrap();
+        cursor.write_u8(0).unwrap();
+    }
+
+    cursor.write_u16(1).unwrap();
+    cursor.write_u16(0).unwrap();
+    cursor.write_u16(0).unwrap();
+    cursor.write_u8(7).unwrap();
+    cursor.write_u8(2).unwrap();
+    cursor.write_u16(1).unwrap();
+    cursor.write_u8(0).unwrap();
+
+    for &n in [-100.0, -100.0, 0.0, 100.0, 100.0, 120.0].iter() {
+        cursor.write_f32(n).unwrap();
+    }
+
+    // Empty object
+    cursor.write_i32(0).unwrap();
+    cursor.write_i32(0).unwrap();
+    cursor.write_i32(0).unwrap();
+    cursor.write_u16(0).unwrap();
+
+    cursor.into_inner()
+}
+
+#[test]
+fn read_zsc() {
+    let mut cursor = Cursor::new(cart_zsc());
+    let mut zsc = ZSC::new();
+    zsc.read(&mut cursor).unwrap();
+
+    assert_eq!(zsc.meshes.len(), 2);
+    assert_eq!(zsc.materials.len(), 2);
+    assert_eq!(zsc.effects, vec![String::from("3DDATA\\EFFECT\\SMOKE.EFT")]);
+    assert_eq!(zsc.objects.len(), 2);
+
+    let cart = &zsc.objects[0];
+    assert_eq!(cart.radius, 150);
+    assert_eq!(cart.parts.len(), 3);
+    assert_eq!(cart.parts[0].properties.position.unwrap().z, 50.0);
+    assert_eq!(cart.parts[1].properties.parent, Some(1));
+    assert_eq!(cart.parts[1].properties.animation,
+               Some(String::from("3DDATA\\MOTION\\WHEEL.ZMO")));
+    assert_eq!(cart.parts[1].properties.unknown, vec![(99, vec![1, 2, 3])]);
+    assert_eq!(cart.effects.len(), 1);
+    assert_eq!(cart.bounding_box.max.z, 120.0);
+
+    assert!(zsc.objects[1].parts.is_empty());
+    assert!(zsc.materials[1].alpha_enabled);
+    assert_eq!(zsc.materials[0].alpha_ref, 128);
+}
+
+#[test]
+fn write_zsc() {
+    let orig_bytes = cart_zsc();
+
+    let mut cursor = Cursor::new(orig_bytes.clone());
+    let mut zsc = ZSC::new();
+    zsc.read(&mut cursor).unwrap();
+
+    let mut new_cursor = Cursor::new(Vec::new());
+    zsc.write(&mut new_cursor).unwrap();
+
+    assert_bytes_eq!(new_cursor.into_inner(), orig_bytes);
+}
+
+#[test]
+fn zsc_resolve_materials() {
+    let mut cursor = Cursor::new(cart_zsc());
+    let mut zsc = ZSC::new();
+    zsc.read(&mut cursor).unwrap();
+
+    let wheel = zsc.mesh_id("3ddata/pat/cart/wheel.zms").unwrap();
+    assert_eq!(wheel, 1);
+
+    let materials = zsc.resolve_materials(wheel).unwrap();
+    assert_eq!(materials.len(), 1);
+    assert_eq!(materials[0].path, "3DDATA\\PAT\\CART\\WHEEL.DDS");
+
+    zsc.objects[0].parts[0].material_id = 5;
+    let body = zsc.mesh_id("3DDATA\\PAT\\CART\\BODY.ZMS").unwrap();
+    match zsc.resolve_materials(body).unwrap_err().downcast_ref::<RoseError>() {
+        Some(&RoseError::IndexOutOfRange { index: 5, count: 2, .. }) => {}
+        _ => panic!("Expected IndexOutOfRange"),
+    }
+}
@@ -4,10 +4,11 @@ extern crate roselib;
 
 use std::ffi::OsStr;
 use std::fs::{File, create_dir_all};
-use std::io::{Read, Write, Seek, SeekFrom};
+use std::io::{BufReader, Write};
 use std::path::{Path, PathBuf};
 use std::process::exit;
 use roselib::files::IDX;
+use roselib::files::idx::VFS_BUFFER_CAPACITY;
 use roselib::io::RoseFile;
 
 fn main() {
@@ -37,7 +38,7 @@ fn main() {
         }
     };
 
-    let idx = match IDX::from_file(&idx_file) {
+    let idx = match IDX::from_file_with_capacity(&idx_file, VFS_BUFFER_CAPACITY) {
         Ok(i) => i,
         Err(e) => {
             println!("Error reading idx file: {}", e);
@@ -55,7 +56,7 @@ fn main() {
         vfs_path.push(&fs.filename);
 
         let mut vfs = match File::open(vfs_path) { 
-            Ok(f) => f,
+            Ok(f) => BufReader::with_capacity(VFS_BUFFER_CAPACITY, f),
             Err(e) => {
                 println!("Unable to open {}: {}",
                          &fs.filename.to_str().unwrap_or(""),
@@ -108,21 +109,17 @@ fn main() {
                         }
                     };
 
-                    let mut buffer: Vec<u8> = Vec::new();
-                    buffer.resize(file.size as usize, 0u8);
-                    if let Err(e) = vfs.seek(SeekFrom::Start(file.offset as u64)) {
-                        println!("Error reading data from {}: {}",
-                                 fs.filename.to_str().unwrap(),
-                                 e);
-                        continue;
-                    }
-
-                    if let Err(e) = vfs.read_exact(&mut buffer) {
-                        println!("Error reading data from {}: {}",
-                                 fs.filename.to_str().unwrap(),
-                                 e);
-                        continue;
-                    }
+                    // Encrypted entries can't be decrypted, they are
+                    // extracted as stored
+                    let buffer = match file.read_data_or_raw(&mut vfs) {
+                        Ok(data) => data,
+                        Err(e) => {
+                            println!("Error reading data from {}: {}",
+                                     fs.filename.to_str().unwrap(),
+                                     e);
+                            continue;
+                        }
+                    };
 
                     if let Err(e) = out_file.write_all(&buffer) {
                         println!("Error writing file {}: {}",